@@ -0,0 +1,380 @@
+//! Per-session cgroups (Linux, cgroup v2).
+//!
+//! Each session's process group runs in its own cgroup, created under a
+//! `treebeard` cgroup inside the user's systemd manager
+//! (`user@<uid>.service`), which systemd delegates to the user. When running
+//! as root the cgroup root is used instead. `[limits]` values are written to
+//! the session cgroup's `memory.max`, `cpu.max` and `pids.max`, and `list`
+//! reads usage back from `cpu.stat` and `memory.stat`.
+//!
+//! As with the Linux sandbox, everything is prepared in the parent by
+//! [`SessionCgroup::prepare`]. The child only calls [`SessionCgroup::join`]
+//! between `fork` and `exec`, which writes to an already open `cgroup.procs`.
+
+use crate::config::LimitsConfig;
+use crate::error::{Result, TreebeardError};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the cgroup that holds every session cgroup.
+const TREEBEARD_CGROUP: &str = "treebeard";
+
+/// Controllers enabled for session cgroups: limits need all three, and
+/// `memory.stat` only exists with the memory controller.
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+
+/// Returns where the unified (v2) hierarchy is mounted, if it is.
+fn cgroup2_mount() -> Option<PathBuf> {
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let _device = fields.next()?;
+        let mount_point = fields.next()?;
+        (fields.next()? == "cgroup2").then(|| PathBuf::from(mount_point))
+    })
+}
+
+/// Returns the part of `own_cgroup` (as listed in `/proc/self/cgroup`) that
+/// `uid` may create cgroups under, relative to the hierarchy root.
+///
+/// For a regular user that is their systemd manager, `user@<uid>.service`.
+/// Processes in a login session scope (e.g. over SSH) are outside it and
+/// cannot be moved into it, so there is no usable subtree.
+fn delegated_subtree(own_cgroup: &str, uid: u32) -> Option<PathBuf> {
+    if uid == 0 {
+        return Some(PathBuf::new());
+    }
+
+    let manager = format!("user@{}.service", uid);
+    let mut subtree = PathBuf::new();
+    for component in own_cgroup.trim_start_matches('/').split('/') {
+        subtree.push(component);
+        if component == manager {
+            return Some(subtree);
+        }
+    }
+    None
+}
+
+/// Returns the `treebeard` cgroup that session cgroups are created in, or
+/// `None` if cgroup v2 is not available or not delegated to this user.
+pub fn treebeard_cgroup_root() -> Option<PathBuf> {
+    let mount = cgroup2_mount()?;
+    let own = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let own = own.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let subtree = delegated_subtree(own, nix::unistd::geteuid().as_raw())?;
+    Some(mount.join(subtree).join(TREEBEARD_CGROUP))
+}
+
+/// Cgroup name for the session mounted at `mount_path`.
+///
+/// The mount path is unique per repository and branch, so it is hashed in to
+/// keep same-named branches of different repositories apart.
+fn session_cgroup_name(mount_path: &Path, branch_name: &str) -> String {
+    let hash = fxhash::hash64(mount_path.as_os_str().as_encoded_bytes()) as u32;
+    let branch: String = branch_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}-{:08x}", branch, hash)
+}
+
+/// Returns the cgroup of the session mounted at `mount_path`, if it exists.
+pub fn session_cgroup_path(mount_path: &Path, branch_name: &str) -> Option<PathBuf> {
+    let path = treebeard_cgroup_root()?.join(session_cgroup_name(mount_path, branch_name));
+    path.is_dir().then_some(path)
+}
+
+/// Enables `CONTROLLERS` for the children of `cgroup`, as far as `cgroup`
+/// itself has them. Controllers that cannot be enabled are left out.
+fn enable_controllers(cgroup: &Path) {
+    let available = fs::read_to_string(cgroup.join("cgroup.controllers")).unwrap_or_default();
+    for controller in CONTROLLERS {
+        if !available.split_whitespace().any(|c| c == controller) {
+            continue;
+        }
+        if let Err(e) = fs::write(
+            cgroup.join("cgroup.subtree_control"),
+            format!("+{}", controller),
+        ) {
+            tracing::debug!(
+                "Failed to enable {} controller in {}: {}",
+                controller,
+                cgroup.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Writes a limit to `file` in `cgroup`, or warns if the controller behind
+/// it is not available.
+fn write_limit(cgroup: &Path, file: &str, value: &str) -> Result<()> {
+    let path = cgroup.join(file);
+    if !path.exists() {
+        tracing::warn!(
+            "{} is not available in {}; limits.{} will not be enforced",
+            file,
+            cgroup.display(),
+            file.replace('.', "_")
+        );
+        return Ok(());
+    }
+
+    fs::write(&path, value).map_err(|e| {
+        TreebeardError::Config(format!(
+            "Failed to set {} to '{}' (check [limits] in your config): {}",
+            file, value, e
+        ))
+    })
+}
+
+/// A session cgroup with its limits applied, ready to be joined in a forked
+/// child.
+#[derive(Debug)]
+pub struct SessionCgroup {
+    path: PathBuf,
+    procs: OwnedFd,
+}
+
+impl SessionCgroup {
+    /// Creates (or reuses) the cgroup for the session mounted at
+    /// `mount_path` and applies `config`'s limits.
+    ///
+    /// Returns `None` with a warning if cgroup v2 is not delegated to this
+    /// user, in which case the session runs without limits.
+    pub fn prepare(
+        config: &LimitsConfig,
+        mount_path: &Path,
+        branch_name: &str,
+    ) -> Result<Option<Self>> {
+        let Some(root) = treebeard_cgroup_root() else {
+            tracing::warn!(
+                "cgroup v2 is not available to this user; session limits will not be enforced"
+            );
+            return Ok(None);
+        };
+
+        if let Some(parent) = root.parent() {
+            enable_controllers(parent);
+        }
+        match Self::create_in(&root, &session_cgroup_name(mount_path, branch_name), config) {
+            Ok(cgroup) => Ok(Some(cgroup)),
+            Err(TreebeardError::Io(e)) => {
+                tracing::warn!(
+                    "Failed to create session cgroup in {}; session limits will not be enforced: {}",
+                    root.display(),
+                    e
+                );
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Creates `name` under `root` and writes the limits. Limits not set in
+    /// `config` are reset to `max`, since the cgroup may be left over from
+    /// an earlier run of the session.
+    fn create_in(root: &Path, name: &str, config: &LimitsConfig) -> Result<Self> {
+        fs::create_dir_all(root).map_err(TreebeardError::Io)?;
+        enable_controllers(root);
+
+        let path = root.join(name);
+        match fs::create_dir(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(TreebeardError::Io(e)),
+        }
+
+        let pids_max = config.pids_max.map(|p| p.to_string());
+        for (file, value) in [
+            ("memory.max", config.memory_max.as_deref()),
+            ("cpu.max", config.cpu_max.as_deref()),
+            ("pids.max", pids_max.as_deref()),
+        ] {
+            let value = value.unwrap_or("max");
+            if value == "max" && !path.join(file).exists() {
+                continue;
+            }
+            write_limit(&path, file, value)?;
+        }
+
+        let procs = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open(path.join("cgroup.procs"))
+            .map_err(TreebeardError::Io)?;
+
+        Ok(Self {
+            path,
+            procs: procs.into(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the current process into the cgroup.
+    ///
+    /// Intended to be called from `pre_exec`: writing "0" to `cgroup.procs`
+    /// moves the writer, and `write` is async-signal-safe.
+    pub fn join(&self) -> io::Result<()> {
+        // SAFETY: the fd is owned by self and the buffer outlives the call.
+        let written = unsafe { libc::write(self.procs.as_raw_fd(), b"0".as_ptr().cast(), 1) };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Removes the cgroup of the session mounted at `mount_path` once its last
+/// process has exited. A cgroup that still has processes (e.g. an attached
+/// shell) is left alone.
+pub fn remove_session_cgroup(mount_path: &Path, branch_name: &str) {
+    let Some(path) = session_cgroup_path(mount_path, branch_name) else {
+        return;
+    };
+    if let Err(e) = fs::remove_dir(&path) {
+        tracing::debug!("Session cgroup {} not removed: {}", path.display(), e);
+    }
+}
+
+/// Live resource usage of a session cgroup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CgroupUsage {
+    /// CPU use over the sampling interval; 100.0 is one full core
+    pub cpu_percent: f64,
+    /// Anonymous memory charged to the cgroup, which is what makes up RSS
+    pub rss_bytes: u64,
+}
+
+/// Reads `key` from a flat-keyed cgroup file such as `cpu.stat`.
+fn read_stat(path: &Path, key: &str) -> Option<u64> {
+    fs::read_to_string(path).ok()?.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        (k == key).then(|| v.trim().parse().ok()).flatten()
+    })
+}
+
+fn cpu_usage_usec(cgroup: &Path) -> Option<u64> {
+    read_stat(&cgroup.join("cpu.stat"), "usage_usec")
+}
+
+/// Samples CPU and memory usage of each cgroup in `cgroups` over `interval`.
+///
+/// All cgroups are sampled together, so the total wait is one interval no
+/// matter how many sessions there are. A cgroup that cannot be read yields
+/// `None`.
+pub fn sample_usage(cgroups: &[PathBuf], interval: Duration) -> Vec<Option<CgroupUsage>> {
+    let before: Vec<Option<u64>> = cgroups.iter().map(|c| cpu_usage_usec(c)).collect();
+    if before.iter().any(Option::is_some) {
+        std::thread::sleep(interval);
+    }
+
+    cgroups
+        .iter()
+        .zip(before)
+        .map(|(cgroup, before)| {
+            let after = cpu_usage_usec(cgroup)?;
+            let used = after.saturating_sub(before?) as f64;
+            Some(CgroupUsage {
+                cpu_percent: used / interval.as_micros() as f64 * 100.0,
+                rss_bytes: read_stat(&cgroup.join("memory.stat"), "anon").unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delegated_subtree() {
+        assert_eq!(
+            delegated_subtree(
+                "/user.slice/user-1000.slice/user@1000.service/app.slice/vte.scope",
+                1000
+            ),
+            Some(PathBuf::from(
+                "user.slice/user-1000.slice/user@1000.service"
+            ))
+        );
+        assert_eq!(
+            delegated_subtree("/user.slice/user-1000.slice/session-3.scope", 1000),
+            None
+        );
+        assert_eq!(
+            delegated_subtree("/system.slice/x.service", 0),
+            Some(PathBuf::new())
+        );
+    }
+
+    #[test]
+    fn test_session_cgroup_name() {
+        let name = session_cgroup_name(Path::new("/mnt/repo/feature/x"), "feature/x");
+        assert!(name.starts_with("feature-x-"));
+        assert_ne!(
+            name,
+            session_cgroup_name(Path::new("/mnt/other/feature/x"), "feature/x")
+        );
+    }
+
+    #[test]
+    fn test_create_in_writes_limits() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("session");
+        fs::create_dir(&path).unwrap();
+        for file in ["memory.max", "cpu.max", "pids.max", "cgroup.procs"] {
+            fs::write(path.join(file), "").unwrap();
+        }
+
+        let config = LimitsConfig {
+            memory_max: Some("8G".to_string()),
+            pids_max: Some(512),
+            ..Default::default()
+        };
+        let cgroup = SessionCgroup::create_in(root.path(), "session", &config).unwrap();
+
+        assert_eq!(cgroup.path(), path);
+        assert_eq!(fs::read_to_string(path.join("memory.max")).unwrap(), "8G");
+        assert_eq!(fs::read_to_string(path.join("cpu.max")).unwrap(), "max");
+        assert_eq!(fs::read_to_string(path.join("pids.max")).unwrap(), "512");
+    }
+
+    #[test]
+    fn test_sample_usage() {
+        let cgroup = tempfile::tempdir().unwrap();
+        fs::write(
+            cgroup.path().join("cpu.stat"),
+            "usage_usec 1000\nuser_usec 800\nsystem_usec 200\n",
+        )
+        .unwrap();
+        fs::write(cgroup.path().join("memory.stat"), "anon 4096\nfile 8192\n").unwrap();
+
+        let usage = sample_usage(
+            &[cgroup.path().to_path_buf(), cgroup.path().join("missing")],
+            Duration::from_millis(1),
+        );
+        assert_eq!(
+            usage[0],
+            Some(CgroupUsage {
+                cpu_percent: 0.0,
+                rss_bytes: 4096
+            })
+        );
+        assert_eq!(usage[1], None);
+    }
+}
@@ -1,14 +1,15 @@
-use crate::config::Config;
+use crate::config::{Config, SyncPolicy};
 use crate::error::{Result, TreebeardError};
 use crate::git::GitRepo;
 use crate::hooks::{self, HookContext};
-use crate::overlay::MutationType;
-use crate::session::remove_active_session;
+use crate::overlay::{MutationJournal, MutationType};
+use crate::session::{get_mutation_journal_path, load_base_snapshots, remove_active_session};
 use crate::sync;
+use crate::watcher::AutoCommitFailures;
 use std::collections::HashMap;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::sync::{atomic::AtomicUsize, Arc, Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 use tokio::signal;
 
 pub struct SquashContext<'a> {
@@ -17,11 +18,15 @@ pub struct SquashContext<'a> {
     pub on_exit: &'a crate::config::OnExitBehavior,
     pub squash_message: String,
     pub base_commit: String,
+    /// Whether the user can be asked; if not, prompts take their default
+    pub interactive: bool,
 }
 
 pub struct WorktreeCleanupContext {
     pub worktree_path: std::path::PathBuf,
     pub main_repo_path: std::path::PathBuf,
+    /// Whether the user can be asked; if not, the worktree is kept
+    pub interactive: bool,
 }
 
 pub struct SyncContext<'a> {
@@ -40,10 +45,16 @@ pub struct CleanupContext {
     pub config: Config,
     pub mutations: HashMap<PathBuf, MutationType>,
     pub base_commit: String,
-    pub auto_commit_failure_count: Arc<AtomicUsize>,
+    pub auto_commit_failures: Arc<AutoCommitFailures>,
 }
 
 impl CleanupContext {
+    /// Cleanup prompts only when the sync policy does. A non-interactive
+    /// policy runs it unattended, answering every prompt with its default.
+    pub fn is_interactive(&self) -> bool {
+        self.config.sync.get_sync_policy() == SyncPolicy::Prompt
+    }
+
     pub fn as_squash_context(&self) -> SquashContext<'_> {
         SquashContext {
             repo: self.repo.clone(),
@@ -55,6 +66,7 @@ impl CleanupContext {
                 .get_squash_commit_message()
                 .replace("{branch}", &self.branch_name),
             base_commit: self.base_commit.clone(),
+            interactive: self.is_interactive(),
         }
     }
 
@@ -62,6 +74,7 @@ impl CleanupContext {
         WorktreeCleanupContext {
             worktree_path: self.worktree_path.clone(),
             main_repo_path: self.main_repo_path.clone(),
+            interactive: self.is_interactive(),
         }
     }
 
@@ -132,7 +145,7 @@ pub fn perform_fuse_cleanup_with_output(mount_path: &Path) {
     }
 }
 
-fn squash_branch_commits(ctx: &SquashContext) -> Result<()> {
+pub fn squash_branch_commits(ctx: &SquashContext) -> Result<()> {
     let commit_count = match ctx
         .repo
         .get_commit_count_since(&ctx.branch_name, &ctx.base_commit)
@@ -172,6 +185,13 @@ fn squash_branch_commits(ctx: &SquashContext) -> Result<()> {
                 }
                 false
             }
+            crate::config::OnExitBehavior::Prompt if !ctx.interactive => {
+                println!(
+                    "Keeping {} auto-commit(s) on branch '{}' (not asking with a non-interactive sync policy)",
+                    commit_count, ctx.branch_name
+                );
+                false
+            }
             crate::config::OnExitBehavior::Prompt => {
                 if commit_count == 1 {
                     println!(
@@ -202,7 +222,7 @@ fn squash_branch_commits(ctx: &SquashContext) -> Result<()> {
 
     if let Err(e) = ctx
         .repo
-        .squash_commits(&ctx.branch_name, &ctx.squash_message)
+        .squash_commits(&ctx.base_commit, &ctx.squash_message)
     {
         eprintln!("Warning: Failed to squash commits: {}", e);
     } else {
@@ -227,6 +247,15 @@ fn handle_worktree_cleanup(
         return Ok(());
     }
 
+    if !ctx.interactive {
+        println!(
+            "Worktree left at: {} (not asking with a non-interactive sync policy)",
+            ctx.worktree_path.display()
+        );
+        println!("Remove it with: treebeard cleanup --yes <branch>");
+        return Ok(());
+    }
+
     // Check for uncommitted changes before prompting for deletion
     let worktree_repo = GitRepo::from_path(&ctx.worktree_path);
     if let Ok(wt_repo) = worktree_repo {
@@ -290,15 +319,20 @@ pub async fn perform_cleanup(ctx: &CleanupContext) -> Result<()> {
         drop(already_running);
     }
 
-    let failure_count = ctx
-        .auto_commit_failure_count
-        .load(std::sync::atomic::Ordering::Relaxed);
+    let failure_count = ctx.auto_commit_failures.count();
     if failure_count > 0 {
         eprintln!(
             "Warning: {} auto-commit(s) failed during this session",
             failure_count
         );
     }
+    let signing_failures = ctx.auto_commit_failures.signing_count();
+    if signing_failures > 0 {
+        eprintln!(
+            "Warning: {} of them couldn't be signed. Check your signing key, or set [commit] signing = \"final-only\" to leave auto-commits unsigned",
+            signing_failures
+        );
+    }
 
     // Pre-cleanup hooks
     if !ctx.config.hooks.pre_cleanup.is_empty() {
@@ -336,11 +370,13 @@ pub async fn perform_cleanup(ctx: &CleanupContext) -> Result<()> {
     // Sync modified ignored files (may cancel)
     let sync_ctx = ctx.as_sync_context();
     let (was_cancelled, git_check_failed) = if !ctx.mutations.is_empty() {
+        let bases = load_base_snapshots(&ctx.main_repo_path, &ctx.branch_name);
         match sync::run_sync_flow(
             sync_ctx.mutations,
             sync_ctx.main_repo_path,
             sync_ctx.worktree_path,
             &sync_ctx.config.sync,
+            &bases,
         ) {
             Ok(sync::SyncResult::Synced(count)) => {
                 tracing::info!("Synced {} files to main repo", count);
@@ -378,6 +414,18 @@ pub async fn perform_cleanup(ctx: &CleanupContext) -> Result<()> {
         (false, false)
     };
 
+    // The journal is only needed until the sync flow has run to completion
+    if !was_cancelled && !git_check_failed {
+        match get_mutation_journal_path(&ctx.main_repo_path, &ctx.branch_name) {
+            Ok(journal_path) => {
+                if let Err(e) = MutationJournal::remove(&journal_path) {
+                    tracing::warn!("{}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to locate mutation journal: {}", e),
+        }
+    }
+
     // Worktree deletion (skip if cancelled, extra confirmation if git check failed)
     let worktree_cleanup_ctx = ctx.as_worktree_cleanup_context();
     if let Err(e) = handle_worktree_cleanup(&worktree_cleanup_ctx, was_cancelled, git_check_failed)
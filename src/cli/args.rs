@@ -1,3 +1,4 @@
+use crate::config::{SyncConfig, SyncPolicy};
 use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
@@ -18,9 +19,133 @@ pub enum Commands {
         branch_name: String,
         #[arg(long, hide = true, help = "Skip spawning shell (for testing)")]
         no_shell: bool,
+        #[arg(
+            long,
+            help = "Run the command in the background under the treebeard supervisor"
+        )]
+        detach: bool,
+        #[arg(
+            long,
+            value_name = "REV",
+            help = "Start a new branch from this commit, branch or tag instead of HEAD"
+        )]
+        from: Option<String>,
+        #[arg(
+            long,
+            value_name = "NUMBER",
+            conflicts_with = "from",
+            help = "Start a new branch from an already fetched pull request head"
+        )]
+        from_pr: Option<u64>,
+        #[command(flatten)]
+        sync: SyncArgs,
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         command: Vec<String>,
     },
+    #[command(about = "Open a shell or run a command inside a running session")]
+    Attach {
+        #[arg(help = "Branch of the running session")]
+        branch_name: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    #[command(about = "Resume a crashed session using its existing worktree")]
+    Resume {
+        #[arg(help = "Branch of the crashed session")]
+        branch_name: String,
+        #[command(flatten)]
+        sync: SyncArgs,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    #[command(about = "Show changes a session has made to ignored files")]
+    Diff {
+        #[arg(help = "Branch name")]
+        branch_name: String,
+
+        #[arg(long, conflicts_with_all = ["name_status", "json"], help = "Show a diffstat instead of the patch")]
+        stat: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "json",
+            help = "Show only the status and name of changed files"
+        )]
+        name_status: bool,
+
+        #[arg(long, help = "JSON output")]
+        json: bool,
+    },
+    #[command(about = "Sync changed ignored files to the main repo without ending the session")]
+    Sync {
+        #[arg(help = "Branch of the session")]
+        branch_name: String,
+
+        #[arg(help = "Only offer these files, directories or globs (relative to the repo root)")]
+        paths: Vec<String>,
+
+        #[arg(
+            long,
+            help = "Remove the session's copies of synced files so they read through to the main repo again"
+        )]
+        passthrough: bool,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
+    #[command(about = "Show the output of a detached session")]
+    Logs {
+        #[arg(help = "Branch of the detached session")]
+        branch_name: String,
+
+        #[arg(short, long, help = "Keep printing output until the session exits")]
+        follow: bool,
+    },
+    #[command(about = "Stop a detached session")]
+    Stop {
+        #[arg(help = "Branch of the detached session")]
+        branch_name: String,
+    },
+    #[command(about = "Show overlay statistics for a running session")]
+    Stats {
+        #[arg(help = "Branch of the session")]
+        branch_name: String,
+
+        #[arg(long, help = "JSON output")]
+        json: bool,
+    },
+    #[command(about = "Apply changed passthrough patterns to a running session")]
+    Reload {
+        #[arg(help = "Branch of the session")]
+        branch_name: String,
+
+        #[arg(
+            long,
+            help = "Reload even if worktree copies of newly passthrough files would be hidden"
+        )]
+        force: bool,
+    },
+    #[command(about = "List a session's checkpoints (its auto-commits)")]
+    Log {
+        #[arg(help = "Branch of the session")]
+        branch_name: String,
+    },
+    #[command(about = "Reset a running session's worktree to one of its checkpoints")]
+    Rollback {
+        #[arg(help = "Branch of the session")]
+        branch_name: String,
+
+        #[arg(help = "Checkpoint to roll back to, as listed by treebeard log")]
+        checkpoint: String,
+    },
+    #[command(about = "Sync and clean up a detached session after it has exited")]
+    Finish {
+        #[arg(help = "Branch of the detached session")]
+        branch_name: String,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
     #[command(about = "Manage configuration")]
     Config {
         #[command(subcommand)]
@@ -63,7 +188,41 @@ pub enum Commands {
 
         #[arg(long, help = "Clean up stale FUSE mounts from crashed sessions")]
         stale: bool,
+
+        #[command(flatten)]
+        sync: SyncArgs,
     },
+    #[command(hide = true, about = "Run the detached session supervisor")]
+    Supervise,
+}
+
+/// Command-line overrides for the `[sync]` policy used at cleanup.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct SyncArgs {
+    #[arg(
+        long = "sync",
+        value_name = "POLICY",
+        help = "Sync changed ignored files without prompting: all, none, patterns or prompt"
+    )]
+    pub policy: Option<SyncPolicy>,
+
+    #[arg(
+        long = "sync-report",
+        value_name = "PATH",
+        help = "Write a JSON report of synced, skipped and failed files to PATH"
+    )]
+    pub report: Option<String>,
+}
+
+impl SyncArgs {
+    pub fn apply(&self, sync_config: &mut SyncConfig) {
+        if let Some(policy) = self.policy {
+            sync_config.sync_policy = Some(policy);
+        }
+        if let Some(report) = &self.report {
+            sync_config.sync_report = Some(report.clone());
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
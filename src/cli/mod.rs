@@ -1,5 +1,7 @@
 pub mod args;
 pub mod validation;
 
-pub use args::{Args, Commands, ConfigAction};
-pub use validation::{check_tty_requirement_for_command, validate_branch_name};
+pub use args::{Args, Commands, ConfigAction, SyncArgs};
+pub use validation::{
+    check_tty_requirement_for_command, require_terminal_for_sync, validate_branch_name,
+};
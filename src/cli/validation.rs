@@ -1,4 +1,5 @@
-use crate::cli::Commands;
+use crate::cli::{Commands, SyncArgs};
+use crate::config::{load_config, SyncPolicy};
 use crate::error::{Result, TreebeardError};
 use std::io::IsTerminal;
 
@@ -54,28 +55,86 @@ pub fn validate_branch_name(branch_name: &str) -> Result<()> {
     Ok(())
 }
 
+fn require_terminal() -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        return Err(TreebeardError::Config(
+            "This command requires an interactive terminal (TTY). \
+             Cannot run with piped input."
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Require a terminal if cleanup would prompt under `policy`.
+///
+/// For commands that only learn their sync policy after they start, such as
+/// `finish`, which uses the policy the detached session was started with.
+pub fn require_terminal_for_sync(policy: SyncPolicy) -> Result<()> {
+    if policy == SyncPolicy::Prompt && std::env::var("TREEBEARD_TEST_MODE").is_err() {
+        return require_terminal();
+    }
+    Ok(())
+}
+
+/// Whether cleanup can run without prompting: the policy given with `--sync`,
+/// or else the configured one, isn't `prompt`.
+fn sync_is_non_interactive(sync: &SyncArgs) -> bool {
+    let policy = sync.policy.unwrap_or_else(|| {
+        load_config()
+            .map(|config| config.sync.get_sync_policy())
+            .unwrap_or_default()
+    });
+    policy != SyncPolicy::Prompt
+}
+
 pub fn check_tty_requirement_for_command(command: &Commands) -> Result<()> {
     let is_test_mode = std::env::var("TREEBEARD_TEST_MODE").is_ok();
 
     match command {
-        Commands::Branch { no_shell, .. } => {
-            if *no_shell || is_test_mode {
+        Commands::Branch {
+            no_shell,
+            detach,
+            sync,
+            command,
+            ..
+        } => {
+            if *no_shell || *detach || is_test_mode {
                 return Ok(());
             }
-            if !std::io::stdin().is_terminal() {
-                return Err(TreebeardError::Config(
-                    "This command requires an interactive terminal (TTY). \
-                     Cannot run with piped input."
-                        .to_string(),
-                ));
+            if !command.is_empty() && sync_is_non_interactive(sync) {
+                return Ok(());
+            }
+            require_terminal()
+        }
+        Commands::Resume { sync, command, .. } => {
+            if is_test_mode || (!command.is_empty() && sync_is_non_interactive(sync)) {
+                return Ok(());
+            }
+            require_terminal()
+        }
+        Commands::Attach { command, .. } => {
+            if is_test_mode || !command.is_empty() {
+                return Ok(());
             }
-            Ok(())
+            require_terminal()
         }
+        // Checked by `finish` once it knows the session's sync policy
+        Commands::Finish { .. } => Ok(()),
         Commands::Config { .. }
         | Commands::Doctor
         | Commands::List { .. }
         | Commands::Path { .. }
-        | Commands::Cleanup { .. } => Ok(()),
+        | Commands::Cleanup { .. }
+        | Commands::Diff { .. }
+        | Commands::Sync { .. }
+        | Commands::Logs { .. }
+        | Commands::Stop { .. }
+        | Commands::Stats { .. }
+        | Commands::Reload { .. }
+        | Commands::Log { .. }
+        | Commands::Rollback { .. }
+        | Commands::Supervise => Ok(()),
     }
 }
 
@@ -0,0 +1,60 @@
+use crate::cli::validate_branch_name;
+use crate::config::load_config;
+use crate::error::{Result, TreebeardError};
+use crate::git::GitRepo;
+use crate::session::{find_active_session, run_shell_session};
+use std::path::PathBuf;
+
+/// Open a shell (or run a command) inside the mount of a running session.
+///
+/// The subprocess gets the same sandbox and `TREEBEARD_*` environment as the
+/// session's own shell. It is registered as attached for as long as it runs,
+/// so the session owner waits for it before cleaning up.
+pub async fn attach_to_session(branch_name: &str, command: Vec<String>) -> Result<i32> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    let session = find_active_session(repo.workdir(), branch_name)?.ok_or_else(|| {
+        TreebeardError::Config(format!(
+            "No active session for branch '{}'. Start one with: treebeard branch {}",
+            branch_name, branch_name
+        ))
+    })?;
+
+    if !session.is_healthy() {
+        return Err(TreebeardError::Config(format!(
+            "Session for '{}' is stale (mount is gone). Run: treebeard cleanup {}",
+            branch_name, branch_name
+        )));
+    }
+
+    let config = load_config()?;
+    let mount_path = PathBuf::from(&session.mount_path);
+
+    println!(
+        "Attaching to session '{}' at {}",
+        branch_name,
+        mount_path.display()
+    );
+    if !command.is_empty() {
+        println!("Running: {}", command.join(" "));
+    }
+    println!();
+
+    let command = if command.is_empty() {
+        None
+    } else {
+        Some(command.as_slice())
+    };
+
+    run_shell_session(
+        &mount_path,
+        branch_name,
+        command,
+        Some(&config.sandbox),
+        Some(&config.limits),
+        Some(&mount_path),
+        Some(repo.workdir()),
+    )
+    .await
+}
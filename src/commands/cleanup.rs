@@ -1,9 +1,11 @@
 use crate::cleanup;
-use crate::cli::validate_branch_name;
-use crate::config::{get_mount_dir, get_worktree_dir};
+use crate::cli::{validate_branch_name, SyncArgs};
+use crate::config::{get_mount_dir, get_worktree_dir, load_config, SyncPolicy};
 use crate::error::Result;
 use crate::git::GitRepo;
-use crate::overlay;
+use crate::overlay::{self, MutationJournal};
+use crate::session::{get_mutation_journal_path, load_base_snapshots};
+use crate::sync::{self, SyncResult};
 use std::path::Path;
 
 /// Print a warning to stderr if the given repo has uncommitted changes.
@@ -30,6 +32,7 @@ pub fn cleanup_branch(
     yes: bool,
     force: bool,
     stale: bool,
+    sync: &SyncArgs,
 ) -> Result<()> {
     for branch_name in &branch_names {
         validate_branch_name(branch_name)?;
@@ -75,7 +78,7 @@ pub fn cleanup_branch(
     for branch_name in &branches_to_cleanup {
         println!("\n--- Cleaning up '{}' ---", branch_name);
 
-        if let Err(e) = cleanup_single_branch(&repo, branch_name, delete_branch, yes, force) {
+        if let Err(e) = cleanup_single_branch(&repo, branch_name, delete_branch, yes, force, sync) {
             eprintln!("Error cleaning up '{}': {}", branch_name, e);
         }
     }
@@ -111,6 +114,73 @@ fn delete_worktree_directory(repo: &GitRepo, worktree_path: &Path, force: bool)
     println!("Worktree removed.");
 }
 
+/// Offer to sync changes recorded in the mutation journal by a session that
+/// never reached its own cleanup (e.g. treebeard crashed or was killed).
+///
+/// Returns `false` if the worktree should be preserved.
+fn sync_journaled_mutations(
+    repo: &GitRepo,
+    branch_name: &str,
+    worktree_path: &Path,
+    yes: bool,
+    sync: &SyncArgs,
+) -> Result<bool> {
+    let journal_path = get_mutation_journal_path(repo.workdir(), branch_name)?;
+    let mutations = MutationJournal::load(&journal_path)?;
+    if mutations.is_empty() {
+        return Ok(true);
+    }
+
+    println!(
+        "Found {} file change(s) recorded by an unfinished session.",
+        mutations.len()
+    );
+
+    let mut config = load_config()?;
+    sync.apply(&mut config.sync);
+
+    if yes && config.sync.get_sync_policy() == SyncPolicy::Prompt {
+        eprintln!("Warning: Skipping sync of recorded changes (--yes was given without --sync)");
+        return Ok(true);
+    }
+
+    let bases = load_base_snapshots(repo.workdir(), branch_name);
+    match sync::run_sync_flow(
+        &mutations,
+        repo.workdir(),
+        worktree_path,
+        &config.sync,
+        &bases,
+    )? {
+        SyncResult::Cancelled => {
+            println!(
+                "Sync cancelled. Worktree left at: {}",
+                worktree_path.display()
+            );
+            return Ok(false);
+        }
+        SyncResult::GitCheckFailed => {
+            eprintln!("WARNING: Could not check which files are gitignored.");
+            eprintln!("         Recorded changes were not shown for sync.");
+            eprintln!("         If you delete the worktree, these changes will be lost.");
+            eprintln!();
+            if !cleanup::prompt_destructive_action("delete")? {
+                println!("Worktree preserved at: {}", worktree_path.display());
+                return Ok(false);
+            }
+        }
+        SyncResult::Partial(progress) => {
+            for (path, error) in &progress.failed_files {
+                eprintln!("Warning: Failed to sync {}: {}", path.display(), error);
+            }
+        }
+        SyncResult::Synced(_) | SyncResult::Skipped => {}
+    }
+
+    MutationJournal::remove(&journal_path)?;
+    Ok(true)
+}
+
 fn prompt_and_delete_worktree(worktree_path: &Path, yes: bool) -> bool {
     if yes {
         return true;
@@ -150,9 +220,13 @@ fn cleanup_single_branch(
     delete_branch: bool,
     yes: bool,
     force: bool,
+    sync: &SyncArgs,
 ) -> Result<()> {
+    let journal_path = get_mutation_journal_path(repo.workdir(), branch_name)?;
+
     if !repo.worktree_exists(branch_name) {
         println!("Worktree for '{}' does not exist.", branch_name);
+        MutationJournal::remove(&journal_path)?;
         return Ok(());
     }
 
@@ -164,12 +238,18 @@ fn cleanup_single_branch(
 
     cleanup_fuse_mount(&mount_path);
 
+    if !sync_journaled_mutations(repo, branch_name, &worktree_path, yes, sync)? {
+        println!("Skipping '{}'.", branch_name);
+        return Ok(());
+    }
+
     if !prompt_and_delete_worktree(&worktree_path, yes) {
         println!("Skipping '{}'.", branch_name);
         return Ok(());
     }
 
     delete_worktree_directory(repo, &worktree_path, force);
+    MutationJournal::remove(&journal_path)?;
     prompt_and_delete_branch(repo, branch_name, delete_branch, yes, force);
 
     Ok(())
@@ -182,7 +262,15 @@ mod tests {
     #[test]
     fn test_cleanup_stale_only() {
         std::env::set_var("TREEBEARD_TEST_MODE", "1");
-        let result = cleanup_branch(vec![], false, false, true, false, true);
+        let result = cleanup_branch(
+            vec![],
+            false,
+            false,
+            true,
+            false,
+            true,
+            &SyncArgs::default(),
+        );
         std::env::remove_var("TREEBEARD_TEST_MODE");
         assert!(result.is_ok());
     }
@@ -31,13 +31,39 @@ pub fn handle_config_command(action: Option<ConfigAction>) -> Result<()> {
                 "    squash_commit_message: {}",
                 config.commit.get_squash_commit_message()
             );
+            let signing = config.commit.get_signing();
+            println!("    signing: {}", signing.policy);
+            if let Some(key) = &signing.key {
+                println!("    signing_key: {}", key);
+            }
+            if let Some(format) = signing.format {
+                println!("    signing_format: {}", format);
+            }
+            if let Some(author) = &config.commit.author {
+                println!("    author: {}", author);
+            }
+            if let Some(committer) = &config.commit.committer {
+                println!("    committer: {}", committer);
+            }
+            println!("    trailers: {}", config.commit.get_trailers());
             println!("  Auto Commit Timing:");
             println!(
                 "    auto_commit_debounce_ms: {}",
                 config.auto_commit_timing.get_debounce_ms()
             );
+            println!("  Inode Cache:");
+            println!("    capacity: {}", config.inode_cache.get_capacity());
+            println!(
+                "    pin_referenced: {}",
+                config.inode_cache.get_pin_referenced()
+            );
             println!("  Other:");
             println!("    fuse_ttl_secs: {}", config.get_fuse_ttl_secs());
+            println!("    overlay_backend: {}", config.get_overlay_backend());
+            println!("    sync_policy: {}", config.sync.get_sync_policy());
+            if let Some(report) = &config.sync.sync_report {
+                println!("    sync_report: {}", report);
+            }
             if !config.sync.get_sync_always_skip().is_empty() {
                 println!(
                     "    sync_always_skip: {:?}",
@@ -50,6 +76,25 @@ pub fn handle_config_command(action: Option<ConfigAction>) -> Result<()> {
                     config.sync.get_sync_always_include()
                 );
             }
+            if config.limits.is_enabled() {
+                let limits = &config.limits;
+                println!("  Limits:");
+                println!(
+                    "    memory_max: {}",
+                    limits.memory_max.as_deref().unwrap_or("max")
+                );
+                println!(
+                    "    cpu_max: {}",
+                    limits.cpu_max.as_deref().unwrap_or("max")
+                );
+                println!(
+                    "    pids_max: {}",
+                    limits
+                        .pids_max
+                        .map(|p| p.to_string())
+                        .unwrap_or_else(|| "max".to_string())
+                );
+            }
         }
         Some(ConfigAction::Edit) => {
             let config_path = get_config_path();
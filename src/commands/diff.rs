@@ -0,0 +1,390 @@
+use crate::cli::validate_branch_name;
+use crate::error::{Result, TreebeardError};
+use crate::git::GitRepo;
+use crate::overlay::{MutationJournal, MutationType};
+use crate::session::get_mutation_journal_path;
+use crate::sync::aggregation::get_gitignored_files;
+use crate::sync::display::format_diff;
+use crate::sync::files::{detect_binary, should_skip_diff};
+use crate::sync::types::ChangeType;
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Longest `+`/`-` bar printed by `--stat`.
+const STAT_BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Patch,
+    Stat,
+    NameStatus,
+    Json,
+}
+
+impl DiffFormat {
+    pub fn from_flags(stat: bool, name_status: bool, json: bool) -> Self {
+        if json {
+            DiffFormat::Json
+        } else if name_status {
+            DiffFormat::NameStatus
+        } else if stat {
+            DiffFormat::Stat
+        } else {
+            DiffFormat::Patch
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FileDiff {
+    path: PathBuf,
+    status: &'static str,
+    binary: bool,
+    /// Too large to diff; `insertions` and `deletions` are zero
+    too_large: bool,
+    insertions: usize,
+    deletions: usize,
+    old_size: u64,
+    new_size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+    #[serde(skip)]
+    change_type: ChangeType,
+}
+
+#[derive(Serialize)]
+struct DiffOutput<'a> {
+    branch: &'a str,
+    files: &'a [FileDiff],
+}
+
+fn read_side(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(TreebeardError::Config(format!(
+            "Failed to read file {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Compares the main repo's copy of `path` with the worktree's. Returns
+/// `None` if the two are identical (e.g. a file opened for writing but left
+/// unchanged) or missing on both sides.
+fn diff_file(
+    path: &Path,
+    mutation: &MutationType,
+    repo_path: &Path,
+    worktree_path: &Path,
+) -> Result<Option<FileDiff>> {
+    let repo_file = repo_path.join(path);
+    let worktree_file = worktree_path.join(path);
+    let change_type = ChangeType::from(mutation.clone());
+
+    let mut file_diff = FileDiff {
+        path: path.to_path_buf(),
+        status: change_type.as_str(),
+        binary: false,
+        too_large: false,
+        insertions: 0,
+        deletions: 0,
+        old_size: file_size(&repo_file),
+        new_size: file_size(&worktree_file),
+        diff: None,
+        change_type,
+    };
+
+    if should_skip_diff(&repo_file, &worktree_file)
+        || (change_type == ChangeType::Deleted
+            && file_diff.old_size > crate::sync::MAX_DIFF_FILE_SIZE)
+    {
+        file_diff.too_large = true;
+        return Ok(Some(file_diff));
+    }
+
+    let old = read_side(&repo_file)?;
+    let new = match change_type {
+        ChangeType::Deleted => None,
+        _ => read_side(&worktree_file)?,
+    };
+    if old == new {
+        return Ok(None);
+    }
+
+    let old = old.unwrap_or_default();
+    let new = new.unwrap_or_default();
+    let (old_text, new_text) = match (String::from_utf8(old), String::from_utf8(new)) {
+        (Ok(o), Ok(n)) if !detect_binary(o.as_bytes()) && !detect_binary(n.as_bytes()) => (o, n),
+        _ => {
+            file_diff.binary = true;
+            return Ok(Some(file_diff));
+        }
+    };
+
+    let text_diff = TextDiff::from_lines(&old_text, &new_text);
+    for change in text_diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => file_diff.insertions += 1,
+            ChangeTag::Delete => file_diff.deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    file_diff.diff = Some(format_diff(&old_text, &new_text));
+
+    Ok(Some(file_diff))
+}
+
+/// Builds the diffs of every changed ignored file in `mutations`, sorted by
+/// path. Tracked files are left to `git diff` in the worktree.
+fn collect_diffs(
+    mutations: &HashMap<PathBuf, MutationType>,
+    repo_path: &Path,
+    worktree_path: &Path,
+) -> Result<Vec<FileDiff>> {
+    let candidates: Vec<PathBuf> = mutations
+        .keys()
+        .filter(|path| {
+            !worktree_path
+                .join(path)
+                .symlink_metadata()
+                .is_ok_and(|m| m.file_type().is_symlink())
+        })
+        .cloned()
+        .collect();
+
+    let ignored = get_gitignored_files(repo_path, &candidates)
+        .map_err(|e| TreebeardError::Git(e.to_string()))?;
+
+    let mut diffs = Vec::new();
+    for path in candidates.iter().filter(|p| ignored.contains(*p)) {
+        if let Some(file_diff) = diff_file(path, &mutations[path], repo_path, worktree_path)? {
+            diffs.push(file_diff);
+        }
+    }
+    diffs.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(diffs)
+}
+
+fn status_letter(change_type: ChangeType) -> char {
+    match change_type {
+        ChangeType::Added => 'A',
+        ChangeType::Modified => 'M',
+        ChangeType::Deleted => 'D',
+        ChangeType::Conflicted => 'C',
+    }
+}
+
+fn print_patch(diffs: &[FileDiff]) {
+    for (idx, file) in diffs.iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+        let path = file.path.display();
+        if file.too_large {
+            println!(
+                "{} — too large to diff ({} -> {} bytes)",
+                path, file.old_size, file.new_size
+            );
+            continue;
+        }
+        if file.binary {
+            println!(
+                "Binary file {} differs ({} -> {} bytes)",
+                path, file.old_size, file.new_size
+            );
+            continue;
+        }
+
+        match file.change_type {
+            ChangeType::Added => println!("--- /dev/null"),
+            _ => println!("--- {} (main repo)", path),
+        }
+        match file.change_type {
+            ChangeType::Deleted => println!("+++ /dev/null"),
+            _ => println!("+++ {} (worktree)", path),
+        }
+        if let Some(diff) = &file.diff {
+            print!("{}", diff);
+            if !diff.ends_with('\n') {
+                println!();
+            }
+        }
+    }
+}
+
+fn format_stat(diffs: &[FileDiff]) -> String {
+    let name_width = diffs
+        .iter()
+        .map(|f| f.path.to_string_lossy().chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_changes = diffs
+        .iter()
+        .map(|f| f.insertions + f.deletions)
+        .max()
+        .unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+
+    let mut out = String::new();
+    let (mut insertions, mut deletions) = (0, 0);
+    for file in diffs {
+        let name = file.path.to_string_lossy();
+        if file.binary || file.too_large {
+            out.push_str(&format!(
+                " {:name_width$} | Bin {} -> {} bytes\n",
+                name, file.old_size, file.new_size
+            ));
+            continue;
+        }
+
+        let total = file.insertions + file.deletions;
+        let (plus, minus) = if max_changes > STAT_BAR_WIDTH {
+            let scale = |n: usize| (n * STAT_BAR_WIDTH).div_ceil(max_changes);
+            (scale(file.insertions), scale(file.deletions))
+        } else {
+            (file.insertions, file.deletions)
+        };
+        out.push_str(&format!(
+            " {:name_width$} | {:>count_width$} {}{}\n",
+            name,
+            total,
+            "+".repeat(plus),
+            "-".repeat(minus)
+        ));
+        insertions += file.insertions;
+        deletions += file.deletions;
+    }
+
+    out.push_str(&format!(
+        " {} file{} changed, {} insertion{}(+), {} deletion{}(-)\n",
+        diffs.len(),
+        if diffs.len() == 1 { "" } else { "s" },
+        insertions,
+        if insertions == 1 { "" } else { "s" },
+        deletions,
+        if deletions == 1 { "" } else { "s" },
+    ));
+    out
+}
+
+/// Show the ignored files a session has changed so far.
+///
+/// Changes are read from the session's mutation journal, which the overlay
+/// appends to on every write, so this works while the session is running
+/// (attached or detached) as well as after a crash.
+pub fn show_diff(branch_name: &str, format: DiffFormat) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    let worktree_path = repo
+        .get_worktree_path(branch_name)
+        .ok()
+        .filter(|p| p.exists())
+        .ok_or_else(|| {
+            TreebeardError::Config(format!("No worktree found for branch '{}'", branch_name))
+        })?;
+
+    let journal_path = get_mutation_journal_path(repo.workdir(), branch_name)?;
+    let mutations = MutationJournal::load(&journal_path)?;
+    let diffs = collect_diffs(&mutations, repo.workdir(), &worktree_path)?;
+
+    match format {
+        DiffFormat::Json => {
+            let output = DiffOutput {
+                branch: branch_name,
+                files: &diffs,
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        DiffFormat::NameStatus => {
+            for file in &diffs {
+                println!(
+                    "{}\t{}",
+                    status_letter(file.change_type),
+                    file.path.display()
+                );
+            }
+        }
+        DiffFormat::Stat | DiffFormat::Patch if diffs.is_empty() => {
+            println!("No ignored files changed in '{}'", branch_name);
+        }
+        DiffFormat::Stat => print!("{}", format_stat(&diffs)),
+        DiffFormat::Patch => print_patch(&diffs),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_collect_diffs() {
+        let repo = tempfile::tempdir().unwrap();
+        let worktree = tempfile::tempdir().unwrap();
+        Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(repo.path())
+            .status()
+            .unwrap();
+        fs::write(repo.path().join(".gitignore"), "*.env\n*.bin\n").unwrap();
+
+        fs::write(repo.path().join("app.env"), "A=1\nB=2\n").unwrap();
+        fs::write(worktree.path().join("app.env"), "A=1\nB=3\nC=4\n").unwrap();
+        fs::write(worktree.path().join("new.env"), "X=1\n").unwrap();
+        fs::write(repo.path().join("gone.env"), "Y=1\n").unwrap();
+        fs::write(repo.path().join("same.env"), "Z=1\n").unwrap();
+        fs::write(worktree.path().join("same.env"), "Z=1\n").unwrap();
+        fs::write(worktree.path().join("data.bin"), [0u8, 1, 2]).unwrap();
+        fs::write(worktree.path().join("tracked.txt"), "not ignored\n").unwrap();
+
+        let mutations = HashMap::from([
+            (PathBuf::from("app.env"), MutationType::CopiedUp),
+            (PathBuf::from("new.env"), MutationType::Created),
+            (PathBuf::from("gone.env"), MutationType::Deleted),
+            (PathBuf::from("same.env"), MutationType::CopiedUp),
+            (PathBuf::from("data.bin"), MutationType::Created),
+            (PathBuf::from("tracked.txt"), MutationType::Created),
+        ]);
+
+        let diffs = collect_diffs(&mutations, repo.path(), worktree.path()).unwrap();
+        let summary: Vec<(String, char, usize, usize, bool)> = diffs
+            .iter()
+            .map(|f| {
+                (
+                    f.path.to_string_lossy().to_string(),
+                    status_letter(f.change_type),
+                    f.insertions,
+                    f.deletions,
+                    f.binary,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("app.env".to_string(), 'M', 2, 1, false),
+                ("data.bin".to_string(), 'A', 0, 0, true),
+                ("gone.env".to_string(), 'D', 0, 1, false),
+                ("new.env".to_string(), 'A', 1, 0, false),
+            ]
+        );
+        assert_eq!(diffs[0].diff.as_deref(), Some(" A=1\n-B=2\n+B=3\n+C=4\n"));
+
+        let stat = format_stat(&diffs);
+        assert!(stat.contains(" app.env  | 3 ++-\n"));
+        assert!(stat.contains(" data.bin | Bin 0 -> 3 bytes\n"));
+        assert!(stat.ends_with(" 4 files changed, 3 insertions(+), 2 deletions(-)\n"));
+    }
+}
@@ -1,7 +1,12 @@
+#[cfg(target_os = "macos")]
+use crate::config::get_macos_version;
 use crate::config::validate_config;
-use crate::config::{get_config_path, get_macos_version, Config};
+use crate::config::{get_config_path, get_worktree_dir, Config};
 use crate::error::Result;
+use crate::git::GitRepo;
+use crate::overlay::OverlayFs;
 use crate::session::load_active_sessions;
+use std::os::unix::fs::MetadataExt;
 
 struct DiagnosticCheck {
     name: String,
@@ -86,6 +91,15 @@ pub fn run_doctor() -> Result<()> {
     }
     checks.push(disk_check);
 
+    let (copy_up_check, separate_filesystems) = check_copy_up();
+    if separate_filesystems {
+        suggestions.push(
+            "Set paths.worktree_dir to a directory on the same filesystem as your repos"
+                .to_string(),
+        );
+    }
+    checks.push(copy_up_check);
+
     let session_check = check_active_sessions();
     checks.push(session_check);
 
@@ -376,6 +390,74 @@ fn check_disk_space() -> DiagnosticCheck {
     }
 }
 
+/// Reports how copy-up will copy files from the current repo into its
+/// worktrees: by reflink when both are on one filesystem that supports them,
+/// otherwise by copying file contents. The flag is set when worktrees are on
+/// another filesystem, which moving `worktree_dir` would fix.
+fn check_copy_up() -> (DiagnosticCheck, bool) {
+    let repo = match GitRepo::discover() {
+        Ok(repo) => repo,
+        Err(_) => {
+            return (
+                DiagnosticCheck::ok("Copy-up", "run inside a repository to check"),
+                false,
+            )
+        }
+    };
+
+    let worktree_dir = match get_worktree_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            return (
+                DiagnosticCheck::warning("Copy-up", format!("no worktree dir: {}", e)),
+                false,
+            )
+        }
+    };
+    if let Err(e) = std::fs::create_dir_all(&worktree_dir) {
+        return (
+            DiagnosticCheck::warning(
+                "Copy-up",
+                format!("could not create {}: {}", worktree_dir.display(), e),
+            ),
+            false,
+        );
+    }
+
+    let same_filesystem = match (
+        std::fs::metadata(repo.workdir()),
+        std::fs::metadata(&worktree_dir),
+    ) {
+        (Ok(repo_meta), Ok(worktree_meta)) => repo_meta.dev() == worktree_meta.dev(),
+        _ => false,
+    };
+    if !same_filesystem {
+        return (
+            DiagnosticCheck::warning(
+                "Copy-up",
+                format!(
+                    "worktrees ({}) are on a different filesystem from the repo, so copy-up copies whole files",
+                    worktree_dir.display()
+                ),
+            ),
+            true,
+        );
+    }
+
+    let check = match OverlayFs::reflink_supported(&worktree_dir) {
+        Ok(true) => DiagnosticCheck::ok(
+            "Copy-up",
+            "reflinks supported, copied-up files share data with the repo",
+        ),
+        Ok(false) => DiagnosticCheck::ok(
+            "Copy-up",
+            "filesystem doesn't support reflinks, copy-up copies file contents",
+        ),
+        Err(e) => DiagnosticCheck::warning("Copy-up", format!("could not check reflinks: {}", e)),
+    };
+    (check, false)
+}
+
 fn check_active_sessions() -> DiagnosticCheck {
     let sessions = match load_active_sessions() {
         Ok(s) => s,
@@ -0,0 +1,84 @@
+use crate::cleanup::{self, CleanupContext};
+use crate::cli::{require_terminal_for_sync, validate_branch_name, SyncArgs};
+use crate::config::{load_config, OnExitBehavior};
+use crate::error::{Result, TreebeardError};
+use crate::git::{CommitSession, GitRepo};
+use crate::overlay::MutationJournal;
+use crate::session::get_mutation_journal_path;
+use crate::supervisor::client;
+use crate::supervisor::protocol::{DetachedState, Request};
+use crate::watcher::AutoCommitFailures;
+use std::sync::Arc;
+
+/// Finish a detached session whose command has exited.
+///
+/// Runs the parts of cleanup that the supervisor deferred because they need
+/// the user: syncing changed ignored files from the mutation journal, the
+/// squash prompt when `on_exit = "prompt"`, and worktree removal.
+pub async fn finish_session(branch_name: &str, sync: &SyncArgs) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    let session = client::get_session(repo.workdir(), branch_name)?;
+    let code = match session.state {
+        DetachedState::Running => {
+            return Err(TreebeardError::Config(format!(
+                "Session for '{}' is still running. Stop it first with: treebeard stop {}",
+                branch_name, branch_name
+            )))
+        }
+        DetachedState::Exited { code } => code,
+    };
+
+    // The sync policy given when the session was started applies unless
+    // overridden here
+    let mut config = load_config()?;
+    config.sync = session.sync.clone();
+    sync.apply(&mut config.sync);
+    require_terminal_for_sync(config.sync.get_sync_policy())?;
+
+    println!(
+        "Finishing detached session '{}' (exited with status {})",
+        branch_name, code
+    );
+
+    if session.squashed {
+        println!("Auto-commits were squashed when the session exited");
+        config.cleanup.on_exit = OnExitBehavior::Keep;
+    }
+
+    let journal_path = get_mutation_journal_path(repo.workdir(), branch_name)?;
+    let mutations = MutationJournal::load(&journal_path)?;
+    let commit_session = CommitSession {
+        id: session.session_id.clone(),
+        branch: branch_name.to_string(),
+        command: session.command.clone(),
+        base_commit: session.base_commit.clone(),
+    };
+    let worktree_repo = GitRepo::from_path(&session.worktree_path)?
+        .with_signing(config.commit.get_signing())
+        .with_attribution(config.commit.get_attribution(&commit_session));
+
+    let ctx = CleanupContext {
+        mount_path: None,
+        worktree_path: session.worktree_path.clone(),
+        main_repo_path: repo.workdir().to_path_buf(),
+        repo: worktree_repo,
+        branch_name: branch_name.to_string(),
+        config,
+        mutations,
+        base_commit: session.base_commit.clone(),
+        auto_commit_failures: Arc::new(AutoCommitFailures::from_counts(
+            session.auto_commit_failures,
+            session.auto_commit_signing_failures,
+        )),
+    };
+    cleanup::perform_cleanup(&ctx).await?;
+
+    client::send_request(&Request::Forget {
+        repo_path: repo.workdir().to_path_buf(),
+        branch_name: branch_name.to_string(),
+    })?;
+
+    Ok(())
+}
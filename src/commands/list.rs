@@ -1,6 +1,8 @@
 use crate::error::Result;
 use crate::git::GitRepo;
-use crate::session::{load_active_sessions, SessionDisplay, SessionStatus};
+use crate::session::{load_active_sessions, SessionDisplay};
+use crate::supervisor::client;
+use crate::supervisor::protocol::{DetachedSession, DetachedState};
 use std::path::Path;
 use std::time::Duration;
 
@@ -12,7 +14,7 @@ fn get_worktree_dirty_files_count(worktree_path: &Path) -> usize {
         .unwrap_or(0)
 }
 
-fn format_age(duration: Duration) -> String {
+pub(crate) fn format_age(duration: Duration) -> String {
     let total_secs = duration.as_secs();
     let hours = total_secs / 3600;
     let minutes = (total_secs % 3600) / 60;
@@ -27,10 +29,73 @@ fn format_age(duration: Duration) -> String {
     }
 }
 
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+/// How long `list` samples CPU usage of sessions that run in a cgroup.
+#[cfg(target_os = "linux")]
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy)]
+struct SessionUsage {
+    cpu_percent: f64,
+    rss_bytes: u64,
+}
+
+/// Reads live CPU and RSS usage from each session's cgroup. Sessions
+/// without one (`[limits]` disabled, or not on Linux) get `None`.
+fn sample_session_usage(sessions: &[crate::session::ActiveSession]) -> Vec<Option<SessionUsage>> {
+    #[cfg(target_os = "linux")]
+    {
+        let cgroups: Vec<Option<std::path::PathBuf>> = sessions
+            .iter()
+            .map(|s| crate::cgroup::session_cgroup_path(Path::new(&s.mount_path), &s.branch_name))
+            .collect();
+        let paths: Vec<std::path::PathBuf> = cgroups.iter().flatten().cloned().collect();
+        let mut samples = crate::cgroup::sample_usage(&paths, CPU_SAMPLE_INTERVAL).into_iter();
+
+        cgroups
+            .iter()
+            .map(|cgroup| {
+                cgroup.as_ref()?;
+                samples.next().flatten().map(|usage| SessionUsage {
+                    cpu_percent: usage.cpu_percent,
+                    rss_bytes: usage.rss_bytes,
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    vec![None; sessions.len()]
+}
+
 struct SessionInfo<'a> {
     session: &'a crate::session::ActiveSession,
     is_mounted: bool,
     dirty_files_count: usize,
+    detached: bool,
+    usage: Option<SessionUsage>,
+}
+
+/// Exit code of a detached session that has exited and awaits `finish`.
+fn exited_code(session: &DetachedSession) -> Option<i32> {
+    match session.state {
+        DetachedState::Exited { code } => Some(code),
+        DetachedState::Running => None,
+    }
 }
 
 pub fn list_active_sessions(porcelain: bool, json: bool) -> Result<()> {
@@ -46,17 +111,32 @@ pub fn list_active_sessions(porcelain: bool, json: bool) -> Result<()> {
         .filter(|s| s.repo_path == repo_path_str)
         .collect();
 
+    let detached_sessions = client::list_sessions(repo.workdir()).unwrap_or_else(|e| {
+        tracing::warn!("Failed to query supervisor: {}", e);
+        Vec::new()
+    });
+    let finished_sessions: Vec<_> = detached_sessions
+        .iter()
+        .filter_map(|s| exited_code(s).map(|code| (s, code)))
+        .collect();
+
+    let usages = sample_session_usage(&repo_sessions);
     let session_infos: Vec<_> = repo_sessions
         .iter()
-        .map(|session| SessionInfo {
+        .zip(usages)
+        .map(|(session, usage)| SessionInfo {
             session,
             is_mounted: Path::new(&session.mount_path).exists(),
             dirty_files_count: get_worktree_dirty_files_count(Path::new(&session.worktree_path)),
+            detached: detached_sessions
+                .iter()
+                .any(|d| d.branch_name == session.branch_name && d.is_running()),
+            usage,
         })
         .collect();
 
     if json {
-        let sessions_display: Vec<_> = session_infos
+        let mut sessions_display: Vec<_> = session_infos
             .iter()
             .map(|info| {
                 serde_json::json!({
@@ -64,9 +144,26 @@ pub fn list_active_sessions(porcelain: bool, json: bool) -> Result<()> {
                     "mount_path": info.session.mount_path,
                     "status": if info.is_mounted { "mounted" } else { "unmounted" },
                     "dirty_files": info.dirty_files_count,
+                    "state": info.session.status().as_str(),
+                    "attached": info.session.ref_count(),
+                    "detached": info.detached,
+                    "cpu_percent": info.usage.map(|u| (u.cpu_percent * 10.0).round() / 10.0),
+                    "rss_bytes": info.usage.map(|u| u.rss_bytes),
                 })
             })
             .collect();
+        sessions_display.extend(finished_sessions.iter().map(|(session, code)| {
+            serde_json::json!({
+                "branch": session.branch_name,
+                "mount_path": session.mount_path,
+                "status": "unmounted",
+                "dirty_files": get_worktree_dirty_files_count(&session.worktree_path),
+                "state": "exited",
+                "attached": 0,
+                "detached": true,
+                "exit_code": code,
+            })
+        }));
 
         println!("{}", serde_json::to_string(&sessions_display)?);
     } else if porcelain {
@@ -90,17 +187,14 @@ pub fn list_active_sessions(porcelain: bool, json: bool) -> Result<()> {
             println!("Active sessions for: {}", repo_name);
             println!();
             println!("  (No active worktrees)");
+            print_finished_sessions(&finished_sessions);
             return Ok(());
         }
 
         let sessions_display: Vec<SessionDisplay> = session_infos
             .iter()
             .map(|info| {
-                let status = if info.session.is_healthy() {
-                    SessionStatus::Idle
-                } else {
-                    SessionStatus::Stale
-                };
+                let status = info.session.status();
 
                 let now = chrono::Utc::now();
                 let age = now.signed_duration_since(info.session.start_time);
@@ -116,6 +210,9 @@ pub fn list_active_sessions(porcelain: bool, json: bool) -> Result<()> {
                     },
                     dirty_files: info.dirty_files_count,
                     age: age_duration,
+                    usage: info
+                        .usage
+                        .map(|u| (format!("{:.0}%", u.cpu_percent), format_bytes(u.rss_bytes))),
                 }
             })
             .collect();
@@ -134,13 +231,23 @@ pub fn list_active_sessions(porcelain: bool, json: bool) -> Result<()> {
         let files_header_width = 8;
         let age_header_width = 10;
 
+        // CPU and RSS are only known for sessions running in a cgroup
+        let show_usage = sessions_display.iter().any(|s| s.usage.is_some());
+        let usage_header_width = if show_usage { 16 } else { 0 };
+        let usage_header = if show_usage {
+            format!("  {:>6}{:>8}", "CPU", "RSS")
+        } else {
+            String::new()
+        };
+
         println!(
-            "{:<width_branch$}{:<width_status$}{:<width_mount$}{:<width_files$}{:<width_age$}",
+            "{:<width_branch$}{:<width_status$}{:<width_mount$}{:<width_files$}{:<width_age$}{}",
             "BRANCH",
             "STATUS",
             "MOUNT",
             "FILES",
             "AGE",
+            usage_header,
             width_branch = branch_header_width,
             width_status = status_header_width,
             width_mount = mount_header_width,
@@ -152,19 +259,26 @@ pub fn list_active_sessions(porcelain: bool, json: bool) -> Result<()> {
             + status_header_width
             + mount_header_width
             + files_header_width
-            + age_header_width;
+            + age_header_width
+            + usage_header_width;
         println!("{}", "─".repeat(separator_width));
 
         for session in &sessions_display {
             let age_str = format_age(session.age);
+            let usage_str = match (&session.usage, show_usage) {
+                (Some((cpu, rss)), true) => format!("  {:>6}{:>8}", cpu, rss),
+                (None, true) => format!("  {:>6}{:>8}", "-", "-"),
+                (_, false) => String::new(),
+            };
             println!(
-                "{:<width_branch$}{} {:<width_status$}{:<width_mount$}{:<width_files$}{:>width_age$}",
+                "{:<width_branch$}{} {:<width_status$}{:<width_mount$}{:<width_files$}{:>width_age$}{}",
                 session.branch,
                 session.status.symbol(),
                 session.status.as_str(),
                 session.mount_status,
                 session.dirty_files,
                 age_str,
+                usage_str,
                 width_branch = branch_header_width,
                 width_status = status_header_width - 2,
                 width_mount = mount_header_width,
@@ -174,8 +288,41 @@ pub fn list_active_sessions(porcelain: bool, json: bool) -> Result<()> {
         }
 
         println!();
-        println!("● active (shell running)  ○ idle (mounted, no shell)  ↯ stale (needs cleanup)");
+        println!(
+            "● active (shell running)  ○ idle (mounted, no shell)  ↯ stale (resume or clean up)"
+        );
+        print_finished_sessions(&finished_sessions);
     }
 
     Ok(())
 }
+
+/// Print detached sessions whose command has exited but which still need
+/// `treebeard finish` to sync and remove the worktree.
+fn print_finished_sessions(sessions: &[(&DetachedSession, i32)]) {
+    if sessions.is_empty() {
+        return;
+    }
+
+    println!();
+    println!("Detached sessions awaiting finish:");
+    for (session, code) in sessions {
+        println!(
+            "  {}  (exited with status {}; run: treebeard finish {})",
+            session.branch_name, code, session.branch_name
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512 * 1024), "512K");
+        assert_eq!(format_bytes(1536 * 1024), "1.5M");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0G");
+        assert_eq!(format_bytes(100), "0.1K");
+    }
+}
@@ -0,0 +1,79 @@
+use crate::cli::validate_branch_name;
+use crate::error::{Result, TreebeardError};
+use crate::git::{Checkpoint, GitRepo};
+use crate::session::find_active_session;
+use std::path::Path;
+
+/// How many changed paths to list per checkpoint before summarizing the rest.
+const MAX_LISTED_PATHS: usize = 5;
+
+/// List a session's checkpoints, newest first, down to its base commit.
+pub fn show_log(branch_name: &str) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    let session = find_active_session(repo.workdir(), branch_name)?.ok_or_else(|| {
+        TreebeardError::Config(format!(
+            "No active session for branch '{}'. Start one with: treebeard branch {}",
+            branch_name, branch_name
+        ))
+    })?;
+    let base_commit = session.base_commit.ok_or_else(|| {
+        TreebeardError::Config(format!(
+            "No base commit recorded for '{}', so its checkpoints can't be told apart from the branch's history",
+            branch_name
+        ))
+    })?;
+
+    let worktree_repo = GitRepo::from_path(Path::new(&session.worktree_path))?;
+    let checkpoints = worktree_repo.list_checkpoints(&base_commit)?;
+    let base = worktree_repo.checkpoint(&base_commit)?;
+
+    if checkpoints.is_empty() {
+        println!("No checkpoints yet for '{}'", branch_name);
+    } else {
+        println!(
+            "{} checkpoint(s) for '{}', newest first:",
+            checkpoints.len(),
+            branch_name
+        );
+    }
+    println!();
+    for checkpoint in &checkpoints {
+        print_checkpoint(checkpoint, None);
+    }
+    print_checkpoint(&base, Some("(base)"));
+
+    if !checkpoints.is_empty() {
+        println!();
+        println!(
+            "Roll back with: treebeard rollback {} <checkpoint>",
+            branch_name
+        );
+    }
+    Ok(())
+}
+
+fn print_checkpoint(checkpoint: &Checkpoint, label: Option<&str>) {
+    println!(
+        "  {}  {}  {}{}",
+        short_id(&checkpoint.id),
+        checkpoint.time.format("%Y-%m-%d %H:%M:%S"),
+        label.map(|l| format!("{} ", l)).unwrap_or_default(),
+        checkpoint.subject
+    );
+    for (status, path) in checkpoint.changes.iter().take(MAX_LISTED_PATHS) {
+        println!("             {} {}", status, path.display());
+    }
+    if checkpoint.changes.len() > MAX_LISTED_PATHS {
+        println!(
+            "             ... and {} more",
+            checkpoint.changes.len() - MAX_LISTED_PATHS
+        );
+    }
+}
+
+/// The abbreviated form of a commit id shown to users.
+pub(crate) fn short_id(id: &str) -> &str {
+    &id[..id.len().min(7)]
+}
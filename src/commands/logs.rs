@@ -0,0 +1,72 @@
+use crate::cli::validate_branch_name;
+use crate::error::{Result, TreebeardError};
+use crate::git::GitRepo;
+use crate::supervisor::{client, get_session_log_path};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// How often `logs --follow` checks for new output.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Print the output of a detached session.
+///
+/// With `follow`, keeps printing new output until the session exits or the
+/// user presses Ctrl+C.
+pub async fn show_logs(branch_name: &str, follow: bool) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    // Prefer the path reported by the supervisor; fall back to the default
+    // location so logs stay readable after the supervisor has exited.
+    let log_path = match client::get_session(repo.workdir(), branch_name) {
+        Ok(session) => session.log_path,
+        Err(_) => get_session_log_path(repo.workdir(), branch_name)?,
+    };
+
+    let mut file = std::fs::File::open(&log_path).map_err(|_| {
+        TreebeardError::Config(format!("No logs found for branch '{}'", branch_name))
+    })?;
+
+    copy_to_stdout(&mut file)?;
+    if !follow {
+        return Ok(());
+    }
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(FOLLOW_POLL_INTERVAL) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+
+        // The log is recreated when a new session starts on the same branch
+        if truncated(&file, &log_path) {
+            file.seek(SeekFrom::Start(0)).map_err(TreebeardError::Io)?;
+        }
+        copy_to_stdout(&mut file)?;
+
+        let running = client::get_session(repo.workdir(), branch_name)
+            .map(|s| s.is_running())
+            .unwrap_or(false);
+        if !running {
+            copy_to_stdout(&mut file)?;
+            return Ok(());
+        }
+    }
+}
+
+fn copy_to_stdout(file: &mut std::fs::File) -> Result<()> {
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).map_err(TreebeardError::Io)?;
+    let mut stdout = std::io::stdout();
+    stdout.write_all(&buf).map_err(TreebeardError::Io)?;
+    stdout.flush().map_err(TreebeardError::Io)
+}
+
+fn truncated(file: &std::fs::File, log_path: &Path) -> bool {
+    let Ok(len) = std::fs::metadata(log_path).map(|m| m.len()) else {
+        return false;
+    };
+    let mut file = file;
+    file.stream_position().map(|pos| len < pos).unwrap_or(false)
+}
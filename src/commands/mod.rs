@@ -1,11 +1,33 @@
+pub mod attach;
 pub mod cleanup;
 pub mod config;
+pub mod diff;
 pub mod doctor;
+pub mod finish;
 pub mod list;
+pub mod log;
+pub mod logs;
 pub mod path;
+pub mod reload;
+pub mod resume;
+pub mod rollback;
+pub mod stats;
+pub mod stop;
+pub mod sync;
 
+pub use attach::attach_to_session;
 pub use cleanup::cleanup_branch;
 pub use config::handle_config_command;
+pub use diff::{show_diff, DiffFormat};
 pub use doctor::run_doctor;
+pub use finish::finish_session;
 pub use list::list_active_sessions;
+pub use log::show_log;
+pub use logs::show_logs;
 pub use path::print_path;
+pub use reload::reload_session;
+pub use resume::resume_session;
+pub use rollback::rollback_session;
+pub use stats::show_stats;
+pub use stop::stop_session;
+pub use sync::{sync_session, AfterSync};
@@ -0,0 +1,61 @@
+use crate::cli::validate_branch_name;
+use crate::config::load_config;
+use crate::error::{Result, TreebeardError};
+use crate::git::GitRepo;
+use crate::overlay::stats::reload_passthrough;
+use crate::session::find_active_session;
+use std::path::{Path, PathBuf};
+
+/// How many hidden files to list before summarizing the rest.
+const MAX_LISTED_FILES: usize = 20;
+
+/// Apply the configured passthrough patterns to a running session's overlay
+/// without remounting it.
+pub fn reload_session(branch_name: &str, force: bool) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    let session = find_active_session(repo.workdir(), branch_name)?.ok_or_else(|| {
+        TreebeardError::Config(format!(
+            "No active session for branch '{}'. Start one with: treebeard branch {}",
+            branch_name, branch_name
+        ))
+    })?;
+
+    let patterns = load_config()?.paths.get_passthrough();
+    let reload = reload_passthrough(Path::new(&session.mount_path), patterns.clone(), force)?;
+
+    if !reload.applied {
+        eprintln!("These worktree files match the new passthrough patterns and would be hidden:");
+        print_files(&reload.shadowed);
+        return Err(TreebeardError::Config(format!(
+            "Passthrough patterns not reloaded. Re-run with --force to show the main repo's copies instead: treebeard reload {} --force",
+            branch_name
+        )));
+    }
+
+    if patterns.is_empty() {
+        println!("Reloaded '{}': no passthrough patterns", branch_name);
+    } else {
+        println!(
+            "Reloaded '{}' with passthrough patterns: {}",
+            branch_name,
+            patterns.join(", ")
+        );
+    }
+    if !reload.shadowed.is_empty() {
+        eprintln!("Warning: these worktree files are now hidden by the main repo's copies:");
+        print_files(&reload.shadowed);
+    }
+
+    Ok(())
+}
+
+fn print_files(files: &[PathBuf]) {
+    for file in files.iter().take(MAX_LISTED_FILES) {
+        eprintln!("  {}", file.display());
+    }
+    if files.len() > MAX_LISTED_FILES {
+        eprintln!("  ... and {} more", files.len() - MAX_LISTED_FILES);
+    }
+}
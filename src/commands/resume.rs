@@ -0,0 +1,181 @@
+use crate::cli::{validate_branch_name, SyncArgs};
+use crate::config::load_config;
+use crate::error::{Result, TreebeardError};
+use crate::git::{CommitSession, GitRepo};
+use crate::overlay::{perform_fuse_cleanup, setup_overlay_and_watcher};
+use crate::session::{
+    add_active_session, find_active_session, new_session_id, remove_active_session,
+    run_shell_and_cleanup,
+};
+use crate::supervisor::client;
+use crate::watcher::AutoCommitFailures;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Returns true if `path` is a mount point left behind by a crashed FUSE
+/// process. Such mounts fail `stat` with an error other than "not found"
+/// (typically ENOTCONN) and must be unmounted before remounting.
+fn is_dead_mount(path: &Path) -> bool {
+    match std::fs::metadata(path) {
+        Ok(_) => false,
+        Err(e) => e.kind() != std::io::ErrorKind::NotFound,
+    }
+}
+
+/// Resume a session whose treebeard process has died.
+///
+/// The existing worktree (which is also the overlay's upper layer) is reused
+/// as-is: the overlay is remounted over it, mutations are recovered from the
+/// journal, and the watcher resumes auto-committing on top of the current
+/// HEAD. The session's original base commit is kept so squash-on-exit still
+/// covers everything done on the branch.
+pub async fn resume_session(
+    branch_name: &str,
+    sync: &SyncArgs,
+    command: Vec<String>,
+) -> Result<i32> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    if let Ok(detached) = client::get_session(repo.workdir(), branch_name) {
+        return Err(TreebeardError::Config(if detached.is_running() {
+            format!(
+                "'{}' is running as a detached session. Use: treebeard attach {}",
+                branch_name, branch_name
+            )
+        } else {
+            format!(
+                "'{}' is a detached session awaiting finish. Run: treebeard finish {}",
+                branch_name, branch_name
+            )
+        }));
+    }
+
+    let previous = find_active_session(repo.workdir(), branch_name)?;
+    if let Some(session) = &previous {
+        if session.is_healthy() {
+            return Err(TreebeardError::Config(format!(
+                "Session for '{}' is still running. Use: treebeard attach {}",
+                branch_name, branch_name
+            )));
+        }
+    }
+
+    let worktree_path = repo
+        .get_worktree_path(branch_name)
+        .ok()
+        .filter(|p| p.exists())
+        .ok_or_else(|| {
+            TreebeardError::Config(format!(
+                "No worktree found for branch '{}'. Start a new session with: treebeard branch {}",
+                branch_name, branch_name
+            ))
+        })?;
+    let worktree_repo = GitRepo::from_path(&worktree_path)?;
+
+    let base_commit = match previous.as_ref().and_then(|s| s.base_commit.clone()) {
+        Some(commit) => commit,
+        None => {
+            eprintln!(
+                "Warning: No base commit recorded for '{}'; squash on exit will only cover commits from now on",
+                branch_name
+            );
+            worktree_repo.get_head()?
+        }
+    };
+
+    let mut config = load_config()?;
+    sync.apply(&mut config.sync);
+    let commit_session = CommitSession {
+        id: previous
+            .as_ref()
+            .and_then(|s| s.session_id.clone())
+            .unwrap_or_else(new_session_id),
+        branch: branch_name.to_string(),
+        command: command.clone(),
+        base_commit: base_commit.clone(),
+    };
+    let worktree_repo = worktree_repo
+        .with_signing(config.commit.get_signing())
+        .with_attribution(config.commit.get_attribution(&commit_session));
+
+    if let Some(session) = &previous {
+        let old_mount = Path::new(&session.mount_path);
+        if is_dead_mount(old_mount) {
+            println!("Unmounting stale mount at {}", old_mount.display());
+            perform_fuse_cleanup(old_mount);
+        }
+        if let Err(e) = remove_active_session(repo.workdir(), branch_name) {
+            tracing::warn!("Failed to remove stale session state: {}", e);
+        }
+    }
+
+    // The previous watcher may have died inside its debounce window
+    if worktree_repo.has_uncommitted_changes()? {
+        println!("Committing changes left uncommitted by the previous session...");
+        if let Err(e) = worktree_repo.stage_and_commit(&config.commit.get_auto_commit_message()) {
+            eprintln!("Warning: Failed to commit leftover changes: {}", e);
+        }
+    }
+
+    let failure_count = Arc::new(AutoCommitFailures::default());
+    let overlay = setup_overlay_and_watcher(
+        &commit_session,
+        &worktree_path,
+        repo.workdir(),
+        &config,
+        failure_count.clone(),
+    )?;
+
+    if let Err(e) = add_active_session(
+        repo.workdir(),
+        &worktree_path,
+        &overlay.mount_path,
+        &commit_session,
+    ) {
+        tracing::warn!("Failed to save session state: {}", e);
+    }
+
+    println!("Resumed session '{}'", branch_name);
+    if command.is_empty() {
+        println!("[treebeard will clean up the ephemeral environment when the shell terminates]");
+    } else {
+        println!("Running: {}", command.join(" "));
+        println!(
+            "[treebeard will clean up the ephemeral environment when the subprocess terminates]"
+        );
+    }
+    println!();
+
+    run_shell_and_cleanup(
+        &overlay.mount_path,
+        &worktree_path,
+        branch_name,
+        &config,
+        &repo,
+        overlay.mutations,
+        Some(overlay.mount_path.clone()),
+        if command.is_empty() {
+            None
+        } else {
+            Some(&command)
+        },
+        &overlay.worktree_repo,
+        &base_commit,
+        failure_count,
+        overlay.watcher_handle,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_dead_mount() {
+        let temp = tempfile::tempdir().unwrap();
+        assert!(!is_dead_mount(temp.path()));
+        assert!(!is_dead_mount(&temp.path().join("missing")));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::cli::validate_branch_name;
+use crate::commands::log::short_id;
+use crate::config::load_config;
+use crate::error::{Result, TreebeardError};
+use crate::git::GitRepo;
+use crate::overlay::check_upper_changes_allowed;
+use crate::overlay::stats::{read_stats, refresh_paths};
+use crate::session::find_active_session;
+use std::path::Path;
+
+/// Reset a session's worktree to one of its checkpoints while the session
+/// keeps running.
+///
+/// Uncommitted changes are committed first, so that everything after the
+/// checkpoint can still be recovered by rolling forward to the old HEAD.
+/// The mount's process is told which paths changed, so this needs a session
+/// whose backend allows changing its worktree while it's mounted.
+pub fn rollback_session(branch_name: &str, checkpoint: &str) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    let session = find_active_session(repo.workdir(), branch_name)?.ok_or_else(|| {
+        TreebeardError::Config(format!(
+            "No active session for branch '{}'. Start one with: treebeard branch {}",
+            branch_name, branch_name
+        ))
+    })?;
+    let base_commit = session.base_commit.clone().ok_or_else(|| {
+        TreebeardError::Config(format!(
+            "No base commit recorded for '{}', so its checkpoints can't be told apart from the branch's history",
+            branch_name
+        ))
+    })?;
+
+    // Resetting the worktree changes the upper layer underneath the mount,
+    // which has to be told about it afterwards
+    let mount_path = Path::new(&session.mount_path);
+    check_upper_changes_allowed(mount_path, "rollback")?;
+    read_stats(mount_path)?;
+
+    let config = load_config()?;
+    let worktree_repo = GitRepo::from_path(Path::new(&session.worktree_path))?
+        .with_signing(config.commit.get_signing())
+        .with_attribution(config.commit.get_attribution(&session.commit_session()));
+
+    if worktree_repo.has_uncommitted_changes()? {
+        worktree_repo.stage_and_commit(&config.commit.get_auto_commit_message())?;
+    }
+    let previous_head = worktree_repo.get_head()?;
+
+    let (target, changed) = worktree_repo.rollback_to(&base_commit, checkpoint)?;
+    let target = worktree_repo.checkpoint(&target)?;
+
+    let refreshed = if changed.is_empty() {
+        Ok(0)
+    } else {
+        refresh_paths(mount_path, changed.clone(), true)
+    };
+
+    println!(
+        "Rolled back '{}' to {} ({}, {})",
+        branch_name,
+        short_id(&target.id),
+        target.subject,
+        target.time.format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("  {} path(s) changed", changed.len());
+    println!("  Files ignored by git aren't part of checkpoints and were left as they are");
+    if previous_head != target.id {
+        println!(
+            "  Undo with: treebeard rollback {} {}",
+            branch_name,
+            short_id(&previous_head)
+        );
+    }
+
+    refreshed.map(|_| ()).map_err(|e| {
+        TreebeardError::Overlay(format!(
+            "The worktree was rolled back, but the mount may show stale files until the session is restarted: {}",
+            e
+        ))
+    })
+}
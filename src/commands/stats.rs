@@ -0,0 +1,73 @@
+use crate::cli::validate_branch_name;
+use crate::commands::list::{format_age, format_bytes};
+use crate::error::{Result, TreebeardError};
+use crate::git::GitRepo;
+use crate::overlay::stats::{read_stats, OverlayStats};
+use crate::session::find_active_session;
+use std::path::Path;
+use std::time::Duration;
+
+/// Print the runtime counters of a running session's overlay.
+pub fn show_stats(branch_name: &str, json: bool) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    let session = find_active_session(repo.workdir(), branch_name)?.ok_or_else(|| {
+        TreebeardError::Config(format!(
+            "No active session for branch '{}'. Start one with: treebeard branch {}",
+            branch_name, branch_name
+        ))
+    })?;
+    let stats = read_stats(Path::new(&session.mount_path))?;
+
+    if json {
+        println!("{}", serde_json::to_string(&stats)?);
+    } else {
+        print_stats(branch_name, &stats);
+    }
+    Ok(())
+}
+
+fn print_stats(branch_name: &str, stats: &OverlayStats) {
+    let cache = &stats.inode_cache;
+    let passthrough = &stats.passthrough;
+
+    println!(
+        "Overlay stats for '{}' (mounted {})",
+        branch_name,
+        format_age(Duration::from_secs(stats.uptime_secs))
+    );
+    println!();
+    println!("  Requests:         {}", stats.requests);
+    println!(
+        "  Lookups:          {} ({} cache hits, {})",
+        stats.lookups,
+        cache.hits,
+        percent(cache.hits, stats.lookups)
+    );
+    println!(
+        "  Inode cache:      {} pinned, {} cached, {} evicted",
+        cache.pinned, cache.cached, cache.evictions
+    );
+    println!(
+        "  Copy-ups:         {} ({} copied)",
+        stats.copy_ups,
+        format_bytes(stats.bytes_copied)
+    );
+    println!("  Whiteouts:        {}", stats.whiteouts);
+    println!(
+        "  Passthrough:      {} of {} paths ({} pattern checks, {} cached)",
+        passthrough.hits,
+        passthrough.checks,
+        passthrough.pattern_evaluations,
+        passthrough.cached_paths
+    );
+}
+
+fn percent(part: u64, total: u64) -> String {
+    if total == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}%", part as f64 * 100.0 / total as f64)
+    }
+}
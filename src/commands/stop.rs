@@ -0,0 +1,26 @@
+use crate::cli::validate_branch_name;
+use crate::error::Result;
+use crate::git::GitRepo;
+use crate::supervisor::client;
+use crate::supervisor::protocol::Request;
+
+/// Ask the supervisor to terminate a running detached session.
+///
+/// The command's process group receives SIGTERM. Once it exits the
+/// supervisor unmounts the overlay; the session then waits for `finish`.
+pub fn stop_session(branch_name: &str) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    // Resolve the session first so a missing supervisor reports the branch
+    client::get_session(repo.workdir(), branch_name)?;
+    client::send_request(&Request::Stop {
+        repo_path: repo.workdir().to_path_buf(),
+        branch_name: branch_name.to_string(),
+    })?;
+
+    println!("Stopping detached session '{}'", branch_name);
+    println!("Once it has exited, run: treebeard finish {}", branch_name);
+
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use crate::cli::{validate_branch_name, SyncArgs};
+use crate::config::load_config;
+use crate::error::{Result, TreebeardError};
+use crate::git::GitRepo;
+use crate::overlay::stats::refresh_paths;
+use crate::overlay::whiteout::Whiteout;
+use crate::overlay::{check_upper_changes_allowed, BaseStore, MutationJournal, MutationType};
+use crate::session::{find_active_session, get_mutation_journal_path, load_base_snapshots};
+use crate::sync::aggregation::get_gitignored_files;
+use crate::sync::files::CompiledPatterns;
+use crate::sync::{self, SyncResult};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What happens to a session's copy of a file once it matches the main repo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AfterSync {
+    /// Leave the copy in the upper layer. Its recorded base is moved to the
+    /// synced content so a later sync doesn't report it as a conflict.
+    Keep,
+    /// Remove the copy so the session reads the main repo's file again, and
+    /// drop it from the journal. The next write copies it up afresh.
+    Passthrough,
+}
+
+/// Keeps the mutations whose path matches one of `filters`. A filter matches
+/// the path itself, anything below it, or is treated as a glob.
+fn filter_mutations(
+    mutations: HashMap<PathBuf, MutationType>,
+    filters: &[String],
+) -> HashMap<PathBuf, MutationType> {
+    if filters.is_empty() {
+        return mutations;
+    }
+
+    let prefixes: Vec<PathBuf> = filters
+        .iter()
+        .map(|f| PathBuf::from(f.trim_start_matches("./").trim_end_matches('/')))
+        .collect();
+    let globs = CompiledPatterns::new(filters);
+
+    mutations
+        .into_iter()
+        .filter(|(path, _)| prefixes.iter().any(|p| path.starts_with(p)) || globs.matches(path))
+        .collect()
+}
+
+fn same_content(a: &Path, b: &Path) -> bool {
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(ma), Ok(mb)) if ma.is_file() && mb.is_file() && ma.len() == mb.len() => {
+            matches!((fs::read(a), fs::read(b)), (Ok(x), Ok(y)) if x == y)
+        }
+        _ => false,
+    }
+}
+
+/// Applies `after` to every file in `mutations` that now matches the main
+/// repo, whether it was just synced or the user kept the main repo version
+/// of a conflict. Returns the paths settled.
+///
+/// Only gitignored files are touched: a tracked file's upper copy is the
+/// worktree checkout itself and must never be removed.
+fn settle_synced_files(
+    mutations: &HashMap<PathBuf, MutationType>,
+    repo_path: &Path,
+    worktree_path: &Path,
+    journal_path: &Path,
+    after: AfterSync,
+) -> Result<Vec<PathBuf>> {
+    let paths: Vec<PathBuf> = mutations.keys().cloned().collect();
+    let ignored = get_gitignored_files(repo_path, &paths)
+        .map_err(|e| TreebeardError::Config(e.to_string()))?;
+
+    let journal = MutationJournal::open(journal_path)?;
+    let base_store = BaseStore::open(&BaseStore::dir_for_journal(journal_path))?;
+    let mut settled = Vec::new();
+
+    let mut paths: Vec<&PathBuf> = paths.iter().filter(|p| ignored.contains(*p)).collect();
+    paths.sort();
+
+    for path in paths {
+        let worktree_file = worktree_path.join(path);
+        let repo_file = repo_path.join(path);
+
+        if same_content(&worktree_file, &repo_file) {
+            match after {
+                AfterSync::Keep => {
+                    let repo_metadata = fs::metadata(&repo_file).map_err(TreebeardError::Io)?;
+                    base_store.record(path, &repo_metadata, &worktree_file)?
+                }
+                AfterSync::Passthrough => {
+                    // Compared just above, so a write that raced the sync is
+                    // kept rather than thrown away
+                    fs::remove_file(&worktree_file).map_err(|e| {
+                        TreebeardError::Config(format!(
+                            "Failed to remove {}: {}",
+                            worktree_file.display(),
+                            e
+                        ))
+                    })?;
+                    journal.forget(path)?;
+                }
+            }
+            settled.push(path.clone());
+        } else if mutations[path] == MutationType::Deleted
+            && (worktree_file.symlink_metadata().is_err()
+                || Whiteout::is_kernel_whiteout(&worktree_file))
+            && repo_file.symlink_metadata().is_err()
+        {
+            if after == AfterSync::Passthrough {
+                if let (Some(parent), Some(name)) = (worktree_file.parent(), path.file_name()) {
+                    // The overlayfs backend leaves the whiteout in place of the file
+                    let marker = if Whiteout::is_kernel_whiteout(&worktree_file) {
+                        worktree_file.clone()
+                    } else {
+                        parent.join(Whiteout::marker_name(name))
+                    };
+                    match fs::remove_file(marker) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(e) => {
+                            return Err(TreebeardError::Config(format!(
+                                "Failed to remove whiteout for {}: {}",
+                                path.display(),
+                                e
+                            )))
+                        }
+                    }
+                }
+                journal.forget(path)?;
+            }
+            settled.push(path.clone());
+        }
+    }
+
+    Ok(settled)
+}
+
+/// Sync changed ignored files of a session back to the main repo without
+/// ending it.
+///
+/// Runs the same selection flow as cleanup over the session's mutation
+/// journal, restricted to `filters` if any are given. The session keeps
+/// running; `after` decides what happens to its copies of the synced files.
+pub fn sync_session(
+    branch_name: &str,
+    filters: &[String],
+    after: AfterSync,
+    sync_args: &SyncArgs,
+) -> Result<()> {
+    validate_branch_name(branch_name)?;
+    let repo = GitRepo::discover()?;
+
+    let worktree_path = repo
+        .get_worktree_path(branch_name)
+        .ok()
+        .filter(|p| p.exists())
+        .ok_or_else(|| {
+            TreebeardError::Config(format!("No worktree found for branch '{}'", branch_name))
+        })?;
+
+    let session = find_active_session(repo.workdir(), branch_name)?;
+    if let (AfterSync::Passthrough, Some(session)) = (after, &session) {
+        check_upper_changes_allowed(Path::new(&session.mount_path), "sync --passthrough")?;
+    }
+
+    let journal_path = get_mutation_journal_path(repo.workdir(), branch_name)?;
+    let mutations = filter_mutations(MutationJournal::load(&journal_path)?, filters);
+    if mutations.is_empty() {
+        if filters.is_empty() {
+            println!("No ignored files were modified.");
+        } else {
+            println!("No changed files match {}", filters.join(", "));
+        }
+        return Ok(());
+    }
+
+    let mut config = load_config()?;
+    sync_args.apply(&mut config.sync);
+    let bases = load_base_snapshots(repo.workdir(), branch_name);
+
+    match sync::run_sync_flow(
+        &mutations,
+        repo.workdir(),
+        &worktree_path,
+        &config.sync,
+        &bases,
+    )? {
+        SyncResult::Cancelled => {
+            println!("Sync cancelled.");
+            return Ok(());
+        }
+        SyncResult::GitCheckFailed => {
+            return Err(TreebeardError::Config(
+                "Could not determine which files are gitignored; nothing was synced".to_string(),
+            ));
+        }
+        SyncResult::Partial(progress) => {
+            for (path, error) in &progress.failed_files {
+                eprintln!("Warning: Failed to sync {}: {}", path.display(), error);
+            }
+        }
+        SyncResult::Synced(_) | SyncResult::Skipped => {}
+    }
+
+    let settled = settle_synced_files(
+        &mutations,
+        repo.workdir(),
+        &worktree_path,
+        &journal_path,
+        after,
+    )?;
+    if after == AfterSync::Passthrough && !settled.is_empty() {
+        let count = settled.len();
+        // The running mount still caches the removed copies
+        if let Some(session) = &session {
+            if let Err(e) = refresh_paths(Path::new(&session.mount_path), settled, false) {
+                eprintln!(
+                    "Warning: The mount may show the session's old copies until it's remounted: {}",
+                    e
+                );
+            }
+        }
+        println!(
+            "{} file(s) now read through to the main repo; the session copies were removed.",
+            count
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_filter_mutations() {
+        let mutations = HashMap::from([
+            (PathBuf::from(".env"), MutationType::CopiedUp),
+            (PathBuf::from("gen/api.rs"), MutationType::Created),
+            (PathBuf::from("gen/old.rs"), MutationType::Deleted),
+            (PathBuf::from("general.txt"), MutationType::Created),
+            (PathBuf::from("logs/a.log"), MutationType::Created),
+        ]);
+
+        let mut kept: Vec<PathBuf> = filter_mutations(
+            mutations.clone(),
+            &["gen/".to_string(), "*.log".to_string()],
+        )
+        .into_keys()
+        .collect();
+        kept.sort();
+        assert_eq!(
+            kept,
+            vec![
+                PathBuf::from("gen/api.rs"),
+                PathBuf::from("gen/old.rs"),
+                PathBuf::from("logs/a.log"),
+            ]
+        );
+
+        assert_eq!(filter_mutations(mutations.clone(), &[]).len(), 5);
+        assert!(filter_mutations(mutations, &["missing".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn test_settle_synced_files() {
+        let repo = tempfile::tempdir().unwrap();
+        let worktree = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let journal_path = state.path().join("branch.jsonl");
+
+        Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(repo.path())
+            .status()
+            .unwrap();
+        fs::write(repo.path().join(".gitignore"), "*.env\n").unwrap();
+
+        // Synced, not yet synced, synced deletion, and a tracked file
+        fs::write(repo.path().join("a.env"), "A=2\n").unwrap();
+        fs::write(worktree.path().join("a.env"), "A=2\n").unwrap();
+        fs::write(repo.path().join("b.env"), "B=1\n").unwrap();
+        fs::write(worktree.path().join("b.env"), "B=2\n").unwrap();
+        fs::write(worktree.path().join(".wh.c.env"), "").unwrap();
+        fs::write(repo.path().join("tracked.txt"), "same\n").unwrap();
+        fs::write(worktree.path().join("tracked.txt"), "same\n").unwrap();
+
+        let journal = MutationJournal::open(&journal_path).unwrap();
+        let mut mutations = HashMap::new();
+        for (path, mutation) in [
+            ("a.env", MutationType::CopiedUp),
+            ("b.env", MutationType::CopiedUp),
+            ("c.env", MutationType::Deleted),
+            ("tracked.txt", MutationType::CopiedUp),
+        ] {
+            journal.append(Path::new(path), &mutation).unwrap();
+            mutations.insert(PathBuf::from(path), mutation);
+        }
+
+        let settled = settle_synced_files(
+            &mutations,
+            repo.path(),
+            worktree.path(),
+            &journal_path,
+            AfterSync::Keep,
+        )
+        .unwrap();
+        assert_eq!(
+            settled,
+            vec![PathBuf::from("a.env"), PathBuf::from("c.env")]
+        );
+        assert!(worktree.path().join("a.env").exists());
+        let bases = crate::overlay::BaseSnapshots::load(&BaseStore::dir_for_journal(&journal_path))
+            .unwrap();
+        assert_eq!(bases.content(Path::new("a.env")), Some(b"A=2\n".to_vec()));
+
+        let settled = settle_synced_files(
+            &mutations,
+            repo.path(),
+            worktree.path(),
+            &journal_path,
+            AfterSync::Passthrough,
+        )
+        .unwrap();
+        assert_eq!(settled.len(), 2);
+        assert!(!worktree.path().join("a.env").exists());
+        assert!(!worktree.path().join(".wh.c.env").exists());
+        assert!(worktree.path().join("b.env").exists());
+        assert!(worktree.path().join("tracked.txt").exists());
+
+        let mut remaining: Vec<PathBuf> = MutationJournal::load(&journal_path)
+            .unwrap()
+            .into_keys()
+            .collect();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            vec![PathBuf::from("b.env"), PathBuf::from("tracked.txt")]
+        );
+    }
+}
@@ -37,3 +37,7 @@ fn default_sync_always_include() -> Vec<String> {
 fn default_fuse_ttl_secs() -> u64 {
     1
 }
+
+fn default_inode_cache_capacity() -> usize {
+    10000
+}
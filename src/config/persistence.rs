@@ -107,6 +107,12 @@ fn merge_configs(base: Config, overlay: Config) -> Config {
                 .commit
                 .squash_commit_message
                 .or(base.commit.squash_commit_message),
+            signing: overlay.commit.signing.or(base.commit.signing),
+            signing_key: overlay.commit.signing_key.or(base.commit.signing_key),
+            signing_format: overlay.commit.signing_format.or(base.commit.signing_format),
+            author: overlay.commit.author.or(base.commit.author),
+            committer: overlay.commit.committer.or(base.commit.committer),
+            trailers: overlay.commit.trailers.or(base.commit.trailers),
         },
         sync: SyncConfig {
             sync_always_skip: overlay.sync.sync_always_skip.or(base.sync.sync_always_skip),
@@ -114,6 +120,8 @@ fn merge_configs(base: Config, overlay: Config) -> Config {
                 .sync
                 .sync_always_include
                 .or(base.sync.sync_always_include),
+            sync_policy: overlay.sync.sync_policy.or(base.sync.sync_policy),
+            sync_report: overlay.sync.sync_report.or(base.sync.sync_report),
         },
         cleanup: CleanupConfig {
             on_exit: overlay.cleanup.on_exit,
@@ -131,7 +139,21 @@ fn merge_configs(base: Config, overlay: Config) -> Config {
             commit_message: overlay.hooks.commit_message.or(base.hooks.commit_message),
         },
         fuse_ttl_secs: overlay.fuse_ttl_secs.or(base.fuse_ttl_secs),
+        overlay_backend: overlay.overlay_backend.or(base.overlay_backend),
+        inode_cache: InodeCacheConfig {
+            capacity: overlay.inode_cache.capacity.or(base.inode_cache.capacity),
+            pin_referenced: overlay
+                .inode_cache
+                .pin_referenced
+                .or(base.inode_cache.pin_referenced),
+        },
         sandbox: overlay.sandbox,
+        limits: LimitsConfig {
+            enabled: overlay.limits.enabled.or(base.limits.enabled),
+            memory_max: overlay.limits.memory_max.or(base.limits.memory_max),
+            cpu_max: overlay.limits.cpu_max.or(base.limits.cpu_max),
+            pids_max: overlay.limits.pids_max.or(base.limits.pids_max),
+        },
     }
 }
 
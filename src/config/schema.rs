@@ -36,6 +36,41 @@ impl std::str::FromStr for OnExitBehavior {
     }
 }
 
+/// Which implementation provides a session's copy-on-write mount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlayBackendKind {
+    /// The userspace overlay, served over FUSE (macFUSE on macOS)
+    #[default]
+    Fuse,
+    /// Kernel overlayfs in an unprivileged user namespace (Linux 5.11+)
+    Overlayfs,
+}
+
+impl std::fmt::Display for OverlayBackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverlayBackendKind::Fuse => write!(f, "fuse"),
+            OverlayBackendKind::Overlayfs => write!(f, "overlayfs"),
+        }
+    }
+}
+
+impl std::str::FromStr for OverlayBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fuse" => Ok(OverlayBackendKind::Fuse),
+            "overlayfs" => Ok(OverlayBackendKind::Overlayfs),
+            _ => Err(format!(
+                "Invalid overlay backend '{}'. Must be one of: fuse, overlayfs",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PathsConfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -66,12 +101,145 @@ impl PathsConfig {
     }
 }
 
+/// Which of a session's commits are signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigningPolicy {
+    /// Never sign, whatever git's `commit.gpgSign` says
+    None,
+    /// Sign whenever git's `commit.gpgSign` says to
+    #[default]
+    Auto,
+    /// Sign the squash commit but not the auto-commits
+    FinalOnly,
+}
+
+impl std::fmt::Display for SigningPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SigningPolicy::None => write!(f, "none"),
+            SigningPolicy::Auto => write!(f, "auto"),
+            SigningPolicy::FinalOnly => write!(f, "final-only"),
+        }
+    }
+}
+
+impl std::str::FromStr for SigningPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(SigningPolicy::None),
+            "auto" => Ok(SigningPolicy::Auto),
+            "final-only" => Ok(SigningPolicy::FinalOnly),
+            _ => Err(format!(
+                "Invalid signing policy '{}'. Must be one of: none, auto, final-only",
+                s
+            )),
+        }
+    }
+}
+
+/// Signature format, as for git's `gpg.format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    Gpg,
+    Ssh,
+}
+
+impl std::fmt::Display for SigningFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SigningFormat::Gpg => write!(f, "gpg"),
+            SigningFormat::Ssh => write!(f, "ssh"),
+        }
+    }
+}
+
+impl std::str::FromStr for SigningFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gpg" => Ok(SigningFormat::Gpg),
+            "ssh" => Ok(SigningFormat::Ssh),
+            _ => Err(format!(
+                "Invalid signing format '{}'. Must be one of: gpg, ssh",
+                s
+            )),
+        }
+    }
+}
+
+/// A git identity, written `Name <email>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CommitIdentity {
+    pub name: String,
+    pub email: String,
+}
+
+impl std::fmt::Display for CommitIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} <{}>", self.name, self.email)
+    }
+}
+
+impl std::str::FromStr for CommitIdentity {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("Invalid identity '{}'. Must look like: Name <email>", s);
+        let (name, rest) = s.split_once('<').ok_or_else(invalid)?;
+        let email = rest.trim_end().strip_suffix('>').ok_or_else(invalid)?;
+        let name = name.trim();
+        if name.is_empty() || email.contains(['<', '>']) {
+            return Err(invalid());
+        }
+        Ok(CommitIdentity {
+            name: name.to_string(),
+            email: email.trim().to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for CommitIdentity {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<CommitIdentity> for String {
+    fn from(identity: CommitIdentity) -> Self {
+        identity.to_string()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct CommitConfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub auto_commit_message: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub squash_commit_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing: Option<SigningPolicy>,
+    /// Key to sign with, as for git's `user.signingKey`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_format: Option<SigningFormat>,
+    /// Author of the session's commits instead of git's configured identity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<CommitIdentity>,
+    /// Committer of the session's commits instead of git's configured identity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub committer: Option<CommitIdentity>,
+    /// Whether commits carry `Treebeard-*` trailers describing the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailers: Option<bool>,
 }
 
 impl CommitConfig {
@@ -86,6 +254,78 @@ impl CommitConfig {
             .clone()
             .unwrap_or_else(super::default_squash_commit_message)
     }
+
+    pub fn get_signing(&self) -> crate::git::CommitSigning {
+        crate::git::CommitSigning {
+            policy: self.signing.unwrap_or_default(),
+            key: self.signing_key.clone(),
+            format: self.signing_format,
+        }
+    }
+
+    pub fn get_trailers(&self) -> bool {
+        self.trailers.unwrap_or(true)
+    }
+
+    /// Who the commits made in `session` are attributed to, and the
+    /// trailers they carry.
+    pub fn get_attribution(
+        &self,
+        session: &crate::git::CommitSession,
+    ) -> crate::git::CommitAttribution {
+        crate::git::CommitAttribution {
+            author: self.author.clone(),
+            committer: self.committer.clone(),
+            trailers: if self.get_trailers() {
+                session.trailers()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+/// How changed ignored files are synced back to the main repo at cleanup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncPolicy {
+    /// Ask interactively (requires a terminal)
+    #[default]
+    Prompt,
+    /// Sync everything not matched by `sync_always_skip`
+    All,
+    /// Sync nothing
+    None,
+    /// Sync only what matches `sync_always_include`
+    Patterns,
+}
+
+impl std::fmt::Display for SyncPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncPolicy::Prompt => write!(f, "prompt"),
+            SyncPolicy::All => write!(f, "all"),
+            SyncPolicy::None => write!(f, "none"),
+            SyncPolicy::Patterns => write!(f, "patterns"),
+        }
+    }
+}
+
+impl std::str::FromStr for SyncPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "prompt" => Ok(SyncPolicy::Prompt),
+            "all" => Ok(SyncPolicy::All),
+            "none" => Ok(SyncPolicy::None),
+            "patterns" => Ok(SyncPolicy::Patterns),
+            _ => Err(format!(
+                "Invalid sync policy '{}'. Must be one of: prompt, all, none, patterns",
+                s
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -94,6 +334,11 @@ pub struct SyncConfig {
     pub sync_always_skip: Option<Vec<String>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sync_always_include: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_policy: Option<SyncPolicy>,
+    /// File to write a JSON report to when a non-interactive policy runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_report: Option<String>,
 }
 
 impl SyncConfig {
@@ -108,6 +353,14 @@ impl SyncConfig {
             .clone()
             .unwrap_or_else(super::default_sync_always_include)
     }
+
+    pub fn get_sync_policy(&self) -> SyncPolicy {
+        self.sync_policy.unwrap_or_default()
+    }
+
+    pub fn get_sync_report_path(&self) -> Option<std::path::PathBuf> {
+        self.sync_report.as_deref().map(crate::config::expand_tilde)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -144,6 +397,57 @@ impl AutoCommitTimingConfig {
     }
 }
 
+/// Sizing of the FUSE overlay's in-memory inode table.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct InodeCacheConfig {
+    /// Inodes kept in the LRU, on top of those that are pinned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<usize>,
+    /// Never evict inodes the kernel still references (default: true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin_referenced: Option<bool>,
+}
+
+impl InodeCacheConfig {
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+            .unwrap_or_else(super::default_inode_cache_capacity)
+    }
+
+    pub fn get_pin_referenced(&self) -> bool {
+        self.pin_referenced.unwrap_or(true)
+    }
+}
+
+/// Per-session resource limits, enforced with a cgroup v2 per session on
+/// Linux. Values are written to the cgroup files of the same name.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LimitsConfig {
+    /// Create the session cgroup even without limits, so `list` can report
+    /// usage. Defaults to true when any limit is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// `memory.max`, e.g. "8G" or "max"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_max: Option<String>,
+    /// `cpu.max` as "QUOTA PERIOD" in microseconds, e.g. "200000 100000" for two CPUs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_max: Option<String>,
+    /// `pids.max`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids_max: Option<u64>,
+}
+
+impl LimitsConfig {
+    pub fn has_limits(&self) -> bool {
+        self.memory_max.is_some() || self.cpu_max.is_some() || self.pids_max.is_some()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or_else(|| self.has_limits())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
     #[serde(default)]
@@ -160,8 +464,14 @@ pub struct Config {
     pub hooks: HooksConfig,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub fuse_ttl_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay_backend: Option<OverlayBackendKind>,
+    #[serde(default)]
+    pub inode_cache: InodeCacheConfig,
     #[serde(default)]
     pub sandbox: SandboxConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
 }
 
 impl Config {
@@ -169,6 +479,10 @@ impl Config {
         self.fuse_ttl_secs
             .unwrap_or_else(super::default_fuse_ttl_secs)
     }
+
+    pub fn get_overlay_backend(&self) -> OverlayBackendKind {
+        self.overlay_backend.unwrap_or_default()
+    }
 }
 
 pub fn validate_config(config: &Config) -> Result<()> {
@@ -190,6 +504,10 @@ pub fn validate_config(config: &Config) -> Result<()> {
         );
     }
 
+    if config.inode_cache.get_capacity() == 0 {
+        eprintln!("Warning: inode_cache.capacity is 0. An LRU of one inode will be used.");
+    }
+
     Ok(())
 }
 
@@ -242,7 +560,7 @@ pub struct SandboxConfig {
 }
 
 fn default_sandbox_enabled() -> bool {
-    cfg!(target_os = "macos")
+    cfg!(any(target_os = "macos", target_os = "linux"))
 }
 
 fn default_deny_read() -> Vec<String> {
@@ -11,6 +11,9 @@ pub enum TreebeardError {
     #[error("Git error: {0}")]
     Git(String),
 
+    #[error("Signing error: {0}")]
+    Signing(String),
+
     #[error("Config error: {0}")]
     Config(String),
 
@@ -20,6 +23,9 @@ pub enum TreebeardError {
     #[error("FUSE error: {0}")]
     Fuse(String),
 
+    #[error("Overlay error: {0}")]
+    Overlay(String),
+
     #[error("Worktree already exists: {0}")]
     WorktreeAlreadyExists(String),
 
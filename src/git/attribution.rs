@@ -0,0 +1,132 @@
+//! Who a session's commits are attributed to, and the trailers recording
+//! the session they were made in.
+//!
+//! Commits carry the user's git identity unless `[commit] author` or
+//! `committer` say otherwise, so that work done by an agent can be told apart
+//! from the person who kept it. Unless `[commit] trailers` is off, every
+//! auto-commit and the squash commit end with `Treebeard-*` trailers naming
+//! the session, its branch, the command it ran and the commit it started
+//! from.
+
+use crate::config::CommitIdentity;
+
+/// The session a commit is made in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitSession {
+    /// Identifies the session, kept across resumes
+    pub id: String,
+    pub branch: String,
+    /// The command the session runs, empty for an interactive shell
+    pub command: Vec<String>,
+    /// HEAD of the branch when the session was created
+    pub base_commit: String,
+}
+
+impl CommitSession {
+    /// Trailers describing the session. The command is left out of shell
+    /// sessions, and the id out of sessions recorded without one.
+    pub fn trailers(&self) -> Vec<(String, String)> {
+        let mut trailers = Vec::new();
+        if !self.id.is_empty() {
+            trailers.push(("Treebeard-Session".to_string(), self.id.clone()));
+        }
+        trailers.push(("Treebeard-Branch".to_string(), self.branch.clone()));
+        if !self.command.is_empty() {
+            // A trailer is a single line
+            let command = self.command.join(" ").replace(['\n', '\r'], " ");
+            trailers.push(("Treebeard-Command".to_string(), command));
+        }
+        trailers.push(("Treebeard-Base".to_string(), self.base_commit.clone()));
+        trailers
+    }
+}
+
+/// Identities and trailers for a session's commits.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitAttribution {
+    /// Author instead of git's configured identity
+    pub author: Option<CommitIdentity>,
+    /// Committer instead of git's configured identity
+    pub committer: Option<CommitIdentity>,
+    pub trailers: Vec<(String, String)>,
+}
+
+impl CommitAttribution {
+    /// `message` with the trailers appended as its last paragraph.
+    pub fn message(&self, message: &str) -> String {
+        if self.trailers.is_empty() {
+            return message.to_string();
+        }
+
+        let mut message = message.trim_end().to_string();
+        message.push_str("\n\n");
+        for (key, value) in &self.trailers {
+            message.push_str(&format!("{}: {}\n", key, value));
+        }
+        message
+    }
+
+    /// `-c` options for `git` that set the author and committer.
+    pub fn git_config_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (role, identity) in [("author", &self.author), ("committer", &self.committer)] {
+            if let Some(identity) = identity {
+                args.extend([
+                    "-c".to_string(),
+                    format!("{}.name={}", role, identity.name),
+                    "-c".to_string(),
+                    format!("{}.email={}", role, identity.email),
+                ]);
+            }
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(command: &[&str]) -> CommitSession {
+        CommitSession {
+            id: "20261017T120000Z-1a2b3c4d".to_string(),
+            branch: "feature".to_string(),
+            command: command.iter().map(|s| s.to_string()).collect(),
+            base_commit: "abc123".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_message_appends_trailers() {
+        let attribution = CommitAttribution {
+            trailers: session(&["cargo", "test"]).trailers(),
+            ..Default::default()
+        };
+        assert_eq!(
+            attribution.message("treebeard: auto-save\n"),
+            "treebeard: auto-save\n\n\
+             Treebeard-Session: 20261017T120000Z-1a2b3c4d\n\
+             Treebeard-Branch: feature\n\
+             Treebeard-Command: cargo test\n\
+             Treebeard-Base: abc123\n"
+        );
+
+        // Shell sessions have no command, and no trailers leave the message as is
+        assert_eq!(session(&[]).trailers().len(), 3);
+        assert_eq!(CommitAttribution::default().message("msg"), "msg");
+    }
+
+    #[test]
+    fn test_git_config_args() {
+        assert!(CommitAttribution::default().git_config_args().is_empty());
+
+        let attribution = CommitAttribution {
+            author: Some("agent <agent@local>".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            attribution.git_config_args(),
+            ["-c", "author.name=agent", "-c", "author.email=agent@local"]
+        );
+    }
+}
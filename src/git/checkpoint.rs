@@ -0,0 +1,213 @@
+//! A session's auto-commits as checkpoints.
+//!
+//! Every debounced auto-commit records the worktree at that moment, so the
+//! commits between a session's base and HEAD form a timeline of its work.
+//! `treebeard log` lists them and `treebeard rollback` resets the worktree to
+//! one of them.
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, TimeZone};
+
+use super::{run_git, with_retry, GitRepo, WHITEOUT_PATHSPEC};
+use crate::error::{Result, TreebeardError};
+
+/// Starts each commit's header in `git log` output.
+const HEADER_START: char = '\x1e';
+/// Separates the fields of a commit's header.
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// A commit a session can be rolled back to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub id: String,
+    pub time: DateTime<Local>,
+    pub subject: String,
+    /// Paths changed since the previous checkpoint, with git's status letter
+    /// (`A`, `M`, `D` or `T`)
+    pub changes: Vec<(char, PathBuf)>,
+}
+
+impl GitRepo {
+    /// Checkpoints made since `base_commit`, newest first.
+    pub fn list_checkpoints(&self, base_commit: &str) -> Result<Vec<Checkpoint>> {
+        let range = format!("{}..HEAD", base_commit);
+        self.log_checkpoints(&["--name-status", &range])
+    }
+
+    /// The checkpoint `rev` names, without its changes.
+    pub fn checkpoint(&self, rev: &str) -> Result<Checkpoint> {
+        self.log_checkpoints(&["-1", rev])?
+            .pop()
+            .ok_or_else(|| TreebeardError::Git(format!("No commit found for '{}'", rev)))
+    }
+
+    fn log_checkpoints(&self, args: &[&str]) -> Result<Vec<Checkpoint>> {
+        let format = format!(
+            "--format={}%H{}%ct{}%s",
+            HEADER_START, FIELD_SEPARATOR, FIELD_SEPARATOR
+        );
+        let mut log_args = vec!["log", "-z", "--no-renames", &format];
+        log_args.extend(args);
+        let output = run_git(&self.workdir, &log_args, "Failed to list checkpoints")?;
+        parse_checkpoints(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Reset the worktree to `checkpoint`, which must be `base_commit` or a
+    /// commit descending from it. Returns the full id of the checkpoint and the paths
+    /// the reset changed, relative to the worktree.
+    ///
+    /// Uncommitted changes are discarded, and files ignored by git are left
+    /// as they are.
+    pub fn rollback_to(
+        &self,
+        base_commit: &str,
+        checkpoint: &str,
+    ) -> Result<(String, Vec<PathBuf>)> {
+        let target = self.resolve_commit(checkpoint)?;
+        let base = self.resolve_commit(base_commit)?;
+        // Commits made since the base, including ones a rollback left behind
+        let in_session = run_git(
+            &self.workdir,
+            &["merge-base", &base, &target],
+            "Failed to compare the checkpoint with the session's base",
+        )
+        // Unrelated histories have no merge base
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == base);
+        if !in_session {
+            return Err(TreebeardError::Git(format!(
+                "'{}' isn't a checkpoint of this session. See: treebeard log",
+                checkpoint
+            )));
+        }
+
+        // Paths differing between the checkpoint and the worktree, tracked
+        // or not, are the ones the reset touches
+        let output = run_git(
+            &self.workdir,
+            &["diff", "--no-renames", "--name-only", "-z", &target],
+            "Failed to diff against the checkpoint",
+        )?;
+        let mut changed = split_paths(&output.stdout);
+        let output = run_git(
+            &self.workdir,
+            &[
+                "ls-files",
+                "--others",
+                "--exclude-standard",
+                "-z",
+                "--",
+                ".",
+                WHITEOUT_PATHSPEC,
+            ],
+            "Failed to list untracked files",
+        )?;
+        let untracked = split_paths(&output.stdout);
+
+        // The watcher may be committing at the same time
+        with_retry(
+            || {
+                run_git(
+                    &self.workdir,
+                    &["reset", "--hard", &target],
+                    "Failed to reset the worktree",
+                )
+            },
+            "rollback_to",
+        )?;
+        // `reset --hard` leaves untracked files, which the checkpoint didn't have
+        for path in &untracked {
+            if let Err(e) = std::fs::remove_file(self.workdir.join(path)) {
+                tracing::warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+
+        changed.extend(untracked);
+        changed.sort();
+        changed.dedup();
+        Ok((target, changed))
+    }
+}
+
+fn split_paths(output: &[u8]) -> Vec<PathBuf> {
+    output
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+        .collect()
+}
+
+/// Parse `git log -z --name-status` output whose format starts each header
+/// with [`HEADER_START`].
+fn parse_checkpoints(output: &str) -> Result<Vec<Checkpoint>> {
+    let mut checkpoints: Vec<Checkpoint> = Vec::new();
+    let mut tokens = output.split('\0');
+    while let Some(token) = tokens.next() {
+        // A commit's changes start on the line after its header
+        let token = token.trim_start_matches('\n');
+        if token.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = token.strip_prefix(HEADER_START) {
+            let mut fields = header.splitn(3, FIELD_SEPARATOR);
+            let (Some(id), Some(time), Some(subject)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(TreebeardError::Git(format!(
+                    "Unexpected git log output: {:?}",
+                    header
+                )));
+            };
+            let time = time
+                .parse()
+                .ok()
+                .and_then(|secs| Local.timestamp_opt(secs, 0).single())
+                .ok_or_else(|| {
+                    TreebeardError::Git(format!("Unexpected commit time in git log: {}", time))
+                })?;
+            checkpoints.push(Checkpoint {
+                id: id.to_string(),
+                time,
+                subject: subject.to_string(),
+                changes: Vec::new(),
+            });
+        } else if let (Some(checkpoint), Some(path)) = (checkpoints.last_mut(), tokens.next()) {
+            let status = token.chars().next().unwrap_or('M');
+            checkpoint.changes.push((status, PathBuf::from(path)));
+        }
+    }
+    Ok(checkpoints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checkpoints() {
+        let output = "\x1eb33\x1f1792205114\x1fempty\0\
+                      \x1e1f0\x1f1792205114\x1ftwo\0\nD\0a\0\
+                      \x1e0b5\x1f1792205000\x1fone: with \x1f in it\0\nM\0a\0A\0dir/b c\0";
+        let checkpoints = parse_checkpoints(output).unwrap();
+
+        assert_eq!(checkpoints.len(), 3);
+        assert_eq!(checkpoints[0].id, "b33");
+        assert!(checkpoints[0].changes.is_empty());
+        assert_eq!(checkpoints[1].changes, vec![('D', PathBuf::from("a"))]);
+        assert_eq!(checkpoints[2].subject, "one: with \x1f in it");
+        assert_eq!(checkpoints[2].time.timestamp(), 1792205000);
+        assert_eq!(
+            checkpoints[2].changes,
+            vec![('M', PathBuf::from("a")), ('A', PathBuf::from("dir/b c"))]
+        );
+    }
+
+    #[test]
+    fn test_parse_checkpoints_rejects_bad_header() {
+        assert!(parse_checkpoints("\x1eb33\x1fnot-a-time\x1fsubject\0").is_err());
+        assert!(parse_checkpoints("\x1eb33\0").is_err());
+    }
+}
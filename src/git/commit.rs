@@ -0,0 +1,596 @@
+//! Auto-commits made in-process with gix.
+//!
+//! The watcher knows exactly which paths the overlay changed, so there's no
+//! need to have `git add -A` rescan the whole worktree for every debounced
+//! commit. Each path is hashed into the object database, applied to HEAD's
+//! tree and updated in the index, and the tree is committed, all without
+//! spawning git. Squashing a session's commits is done in-process too, by
+//! committing HEAD's tree on top of the base.
+//!
+//! `git commit` is still used when it has work to do that gix can't: running
+//! commit hooks and signing commits. Submodules, unresolved conflicts and
+//! sparse indexes are left to it as well.
+
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use gix::bstr::{BStr, BString, ByteSlice};
+use gix::filter::plumbing::pipeline::convert::ToGitOutcome;
+use gix::index::entry::{Flags, Mode, Stat};
+use gix::objs::tree::EntryKind;
+use gix::ObjectId;
+
+use super::{CommitKind, GitRepo};
+use crate::config::CommitIdentity;
+use crate::error::{Result, TreebeardError};
+use crate::overlay::whiteout::Whiteout;
+
+/// Hooks `git commit` runs.
+const COMMIT_HOOKS: &[&str] = &[
+    "pre-commit",
+    "prepare-commit-msg",
+    "commit-msg",
+    "post-commit",
+];
+
+/// How one path changes in the commit and the index.
+enum PathChange {
+    Upsert {
+        path: BString,
+        kind: EntryKind,
+        id: ObjectId,
+        stat: Stat,
+    },
+    Remove(BString),
+}
+
+impl PathChange {
+    fn path(&self) -> &BStr {
+        match self {
+            PathChange::Upsert { path, .. } | PathChange::Remove(path) => path.as_bstr(),
+        }
+    }
+}
+
+fn gix_error(context: &str, e: impl std::fmt::Display) -> TreebeardError {
+    TreebeardError::Git(format!("{}: {}", context, e))
+}
+
+/// `identity` signing now if one is configured, otherwise git's identity
+/// for `role`.
+fn signature(
+    identity: Option<&CommitIdentity>,
+    configured: Option<std::result::Result<gix::actor::SignatureRef<'_>, gix::config::time::Error>>,
+    role: &str,
+) -> Result<gix::actor::Signature> {
+    if let Some(identity) = identity {
+        return Ok(gix::actor::Signature {
+            name: identity.name.as_str().into(),
+            email: identity.email.as_str().into(),
+            time: gix::date::Time::now_local_or_utc(),
+        });
+    }
+    match configured {
+        Some(Ok(signature)) => Ok(signature.to_owned()),
+        Some(Err(e)) => Err(gix_error(&format!("Failed to read the {} date", role), e)),
+        None => Err(TreebeardError::Git(format!(
+            "No {} identity configured",
+            role
+        ))),
+    }
+}
+
+impl GitRepo {
+    /// Commit the current content of `paths`, relative to the worktree.
+    ///
+    /// Only these paths are staged. Deleted files, including those kernel
+    /// overlayfs replaced with whiteouts, are removed, and ignored files that
+    /// aren't tracked are skipped, as `git add` would. Falls back to
+    /// [`stage_and_commit`](Self::stage_and_commit), which stages everything,
+    /// when the commit needs `git commit` or can't be made in-process.
+    pub fn commit_paths(&self, paths: &[PathBuf], message: &str) -> Result<()> {
+        tracing::debug!(
+            "commit_paths() for {} path(s) in directory: {:?}",
+            paths.len(),
+            self.workdir
+        );
+
+        match self.try_commit_paths(paths, message) {
+            Ok(true) => Ok(()),
+            Ok(false) => self.stage_and_commit(message),
+            Err(e) => {
+                tracing::debug!("In-process commit failed, committing with git: {}", e);
+                self.stage_and_commit(message)
+            }
+        }
+    }
+
+    /// Returns Ok(false), having changed nothing, if the commit has to be
+    /// made by `git commit`.
+    fn try_commit_paths(&self, paths: &[PathBuf], message: &str) -> Result<bool> {
+        let repo = self.open_gix()?;
+        let signs = self.signing.signs(CommitKind::AutoSave);
+        if let Some(reason) = needs_git_commit(&repo, &self.workdir, signs) {
+            tracing::debug!("Committing with git: {}", reason);
+            return Ok(false);
+        }
+
+        let Ok(head) = repo.head_commit() else {
+            tracing::debug!("Committing with git: HEAD has no commit yet");
+            return Ok(false);
+        };
+        let head_tree = head
+            .tree_id()
+            .map_err(|e| gix_error("Failed to read HEAD's tree", e))?
+            .detach();
+        let mut index = repo
+            .open_index()
+            .map_err(|e| gix_error("Failed to read the index", e))?;
+        if index.is_sparse() {
+            tracing::debug!("Committing with git: the index is sparse");
+            return Ok(false);
+        }
+
+        let Some(changes) = self.path_changes(&repo, &index, paths)? else {
+            return Ok(false);
+        };
+        if changes.is_empty() {
+            return Ok(true);
+        }
+
+        let mut editor = repo
+            .edit_tree(head_tree)
+            .map_err(|e| gix_error("Failed to edit HEAD's tree", e))?;
+        for change in &changes {
+            let edit = match change {
+                PathChange::Upsert { path, kind, id, .. } => {
+                    editor.upsert(path.clone(), *kind, *id)
+                }
+                PathChange::Remove(path) => editor.remove(path.clone()),
+            };
+            edit.map_err(|e| gix_error(&format!("Failed to stage {}", change.path()), e))?;
+        }
+        let tree = editor
+            .write()
+            .map_err(|e| gix_error("Failed to write tree", e))?
+            .detach();
+
+        if tree != head_tree {
+            let author = signature(self.attribution.author.as_ref(), repo.author(), "author")?;
+            let committer = signature(
+                self.attribution.committer.as_ref(),
+                repo.committer(),
+                "committer",
+            )?;
+            // `git commit -m` ends the message with a newline
+            let message = format!("{}\n", self.attribution.message(message).trim_end());
+            repo.commit_as(&committer, &author, "HEAD", message, tree, [head.id])
+                .map_err(|e| gix_error("Failed to commit", e))?;
+        }
+
+        apply_to_index(&mut index, &changes);
+        // The cached trees no longer match the entries, so git rebuilds them
+        let options = gix::index::write::Options {
+            extensions: gix::index::write::Extensions::Given {
+                tree_cache: false,
+                end_of_index_entry: true,
+            },
+            skip_hash: false,
+        };
+        index
+            .write(options)
+            .map_err(|e| gix_error("Failed to write the index", e))?;
+
+        Ok(true)
+    }
+
+    /// Replace every commit from `base_commit` to `original_head` with one
+    /// commit of HEAD's tree, as `git reset --soft` and `git commit` would.
+    ///
+    /// Returns Ok(false), having changed nothing, if the squash has to be
+    /// made by `git commit`, including when the index has changes HEAD
+    /// doesn't.
+    pub(super) fn try_squash_commits(
+        &self,
+        base_commit: &str,
+        original_head: &str,
+        message: &str,
+    ) -> Result<bool> {
+        use gix::refs::transaction::{Change, LogChange, PreviousValue, RefEdit, RefLog};
+        use gix::refs::Target;
+
+        let repo = self.open_gix()?;
+        let signs = self.signing.signs(CommitKind::Squash);
+        if let Some(reason) = needs_git_commit(&repo, &self.workdir, signs) {
+            tracing::debug!("Squashing with git: {}", reason);
+            return Ok(false);
+        }
+
+        let parse_id = |id: &str| {
+            ObjectId::from_hex(id.as_bytes())
+                .map_err(|e| gix_error(&format!("Invalid commit id '{}'", id), e))
+        };
+        let base = parse_id(base_commit)?;
+        let head = parse_id(original_head)?;
+        let tree = repo
+            .find_commit(head)
+            .map_err(|e| gix_error("Failed to read HEAD", e))?
+            .tree_id()
+            .map_err(|e| gix_error("Failed to read HEAD's tree", e))?
+            .detach();
+
+        let index = repo
+            .open_index()
+            .map_err(|e| gix_error("Failed to read the index", e))?;
+        let head_index = repo
+            .index_from_tree(&tree)
+            .map_err(|e| gix_error("Failed to read HEAD's tree", e))?;
+        if index.is_sparse() || !same_entries(&index, &head_index) {
+            tracing::debug!("Squashing with git: the index has staged changes");
+            return Ok(false);
+        }
+
+        let author = signature(self.attribution.author.as_ref(), repo.author(), "author")?;
+        let committer = signature(
+            self.attribution.committer.as_ref(),
+            repo.committer(),
+            "committer",
+        )?;
+        // `git commit -m` ends the message with a newline
+        let message = format!("{}\n", self.attribution.message(message).trim_end());
+        let reflog_message = format!(
+            "commit (squash): {}",
+            message.lines().next().unwrap_or_default()
+        );
+        let commit = gix::objs::Commit {
+            message: message.into(),
+            tree,
+            author,
+            committer,
+            encoding: None,
+            parents: [base].into_iter().collect(),
+            extra_headers: Vec::new(),
+        };
+        let squashed = repo
+            .write_object(&commit)
+            .map_err(|e| gix_error("Failed to write the squash commit", e))?
+            .detach();
+
+        repo.edit_reference(RefEdit {
+            change: Change::Update {
+                log: LogChange {
+                    mode: RefLog::AndReference,
+                    force_create_reflog: false,
+                    message: reflog_message.into(),
+                },
+                expected: PreviousValue::MustExistAndMatch(Target::Object(head)),
+                new: Target::Object(squashed),
+            },
+            name: "HEAD"
+                .try_into()
+                .map_err(|e| gix_error("Invalid reference name", e))?,
+            deref: true,
+        })
+        .map_err(|e| gix_error("Failed to update HEAD", e))?;
+
+        Ok(true)
+    }
+
+    /// How each of `paths` changes, or None if one can only be staged by git.
+    fn path_changes(
+        &self,
+        repo: &gix::Repository,
+        index: &gix::index::File,
+        paths: &[PathBuf],
+    ) -> Result<Option<Vec<PathChange>>> {
+        let mut excludes = repo
+            .excludes(
+                index,
+                None,
+                gix::worktree::stack::state::ignore::Source::WorktreeThenIdMappingIfNotSkipped,
+            )
+            .map_err(|e| gix_error("Failed to read ignore rules", e))?;
+        let (mut pipeline, _) = repo
+            .filter_pipeline(None)
+            .map_err(|e| gix_error("Failed to set up filters", e))?;
+        let trust_executable_bit = repo
+            .config_snapshot()
+            .boolean("core.fileMode")
+            .unwrap_or(true);
+
+        let mut changes: Vec<PathChange> = Vec::new();
+        let mut files: Vec<(PathBuf, fs::Metadata)> = Vec::new();
+        for path in paths {
+            let Some(rela_path) = worktree_relative(path) else {
+                continue;
+            };
+            let git_path = gix::path::into_bstr(&rela_path).into_owned();
+            if index
+                .prefixed_entries(git_path.as_bstr())
+                .unwrap_or_default()
+                .iter()
+                .filter(|entry| {
+                    let entry_path = entry.path(index);
+                    entry_path == git_path || is_inside(entry_path, git_path.as_bstr())
+                })
+                .any(|entry| entry.stage_raw() != 0 || entry.mode.is_submodule())
+            {
+                tracing::debug!(
+                    "Committing with git: {} is conflicted or a submodule",
+                    git_path
+                );
+                return Ok(None);
+            }
+
+            let full_path = self.workdir.join(&rela_path);
+            let metadata = match fs::symlink_metadata(&full_path) {
+                Ok(metadata) if !Whiteout::is_kernel_whiteout(&full_path) => metadata,
+                Ok(_) => {
+                    changes.push(PathChange::Remove(git_path));
+                    continue;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    changes.push(PathChange::Remove(git_path));
+                    continue;
+                }
+                Err(e) => {
+                    return Err(TreebeardError::Git(format!(
+                        "Failed to read {}: {}",
+                        full_path.display(),
+                        e
+                    )))
+                }
+            };
+
+            if !metadata.is_dir() {
+                files.push((rela_path, metadata));
+                continue;
+            }
+
+            // A new or renamed directory: take everything under it, and drop
+            // tracked files that are gone from it
+            if is_ignored(&mut excludes, &rela_path, true)? {
+                continue;
+            }
+            let mut walk = walkdir::WalkDir::new(&full_path)
+                .min_depth(1)
+                .sort_by_file_name()
+                .into_iter();
+            while let Some(entry) = walk.next() {
+                let entry = entry.map_err(|e| gix_error("Failed to walk worktree", e))?;
+                let name = entry.file_name();
+                if Whiteout::is_whiteout_marker(name) || Whiteout::is_opaque_marker(name) {
+                    continue;
+                }
+                let entry_rela = rela_path.join(entry.path().strip_prefix(&full_path).unwrap());
+                if entry.file_type().is_dir() {
+                    if name == ".git" {
+                        tracing::debug!("Committing with git: {} holds a repository", git_path);
+                        return Ok(None);
+                    }
+                    if is_ignored(&mut excludes, &entry_rela, true)? {
+                        walk.skip_current_dir();
+                    }
+                    continue;
+                }
+                let metadata = entry
+                    .metadata()
+                    .map_err(|e| gix_error("Failed to walk worktree", e))?;
+                files.push((entry_rela, metadata));
+            }
+            let prefix = format!("{}/", git_path);
+            for entry in index
+                .prefixed_entries(prefix.as_bytes().as_bstr())
+                .unwrap_or_default()
+            {
+                let entry_path = entry.path(index);
+                let full_path = self.workdir.join(gix::path::from_bstr(entry_path));
+                if fs::symlink_metadata(&full_path).is_err()
+                    || Whiteout::is_kernel_whiteout(&full_path)
+                {
+                    changes.push(PathChange::Remove(entry_path.to_owned()));
+                }
+            }
+        }
+
+        for (rela_path, metadata) in files {
+            let git_path = gix::path::into_bstr(&rela_path).into_owned();
+            let tracked = index.entry_by_path(git_path.as_bstr());
+            if tracked.is_none() && is_ignored(&mut excludes, &rela_path, false)? {
+                continue;
+            }
+
+            let full_path = self.workdir.join(&rela_path);
+            // Taken before the content is read, as git does, so a write in
+            // between leaves the index entry stale rather than the change lost
+            let stat = gix::index::fs::Metadata::from_path_no_follow(&full_path)
+                .ok()
+                .and_then(|metadata| Stat::from_fs(&metadata).ok())
+                .unwrap_or_default();
+            let (kind, content) = if metadata.file_type().is_symlink() {
+                let target = fs::read_link(&full_path).map_err(|e| {
+                    gix_error(&format!("Failed to read {}", full_path.display()), e)
+                })?;
+                (
+                    EntryKind::Link,
+                    gix::path::into_bstr(target).into_owned().into(),
+                )
+            } else if metadata.is_file() {
+                let executable = if trust_executable_bit {
+                    metadata.permissions().mode() & 0o111 != 0
+                } else {
+                    tracked.is_some_and(|entry| entry.mode == Mode::FILE_EXECUTABLE)
+                };
+                let kind = if executable {
+                    EntryKind::BlobExecutable
+                } else {
+                    EntryKind::Blob
+                };
+                let file = fs::File::open(&full_path).map_err(|e| {
+                    gix_error(&format!("Failed to read {}", full_path.display()), e)
+                })?;
+                let mut content = Vec::with_capacity(metadata.len() as usize);
+                let read = match pipeline
+                    .convert_to_git(file, &rela_path, index)
+                    .map_err(|e| gix_error(&format!("Failed to filter {}", git_path), e))?
+                {
+                    ToGitOutcome::Unchanged(mut file) => file.read_to_end(&mut content).map(|_| ()),
+                    ToGitOutcome::Process(mut stream) => {
+                        stream.read_to_end(&mut content).map(|_| ())
+                    }
+                    ToGitOutcome::Buffer(buffer) => {
+                        content.extend_from_slice(buffer);
+                        Ok(())
+                    }
+                };
+                read.map_err(|e| gix_error(&format!("Failed to read {}", git_path), e))?;
+                (kind, content)
+            } else {
+                // Git can't record FIFOs, sockets or devices
+                continue;
+            };
+
+            let id = repo
+                .write_blob(&content)
+                .map_err(|e| gix_error(&format!("Failed to store {}", git_path), e))?
+                .detach();
+            changes.push(PathChange::Upsert {
+                path: git_path,
+                kind,
+                id,
+                stat,
+            });
+        }
+
+        Ok(Some(changes))
+    }
+}
+
+/// Why the commit must be made by `git commit`, if it must. `signs` is what
+/// the signing policy says, None leaving it to `commit.gpgSign`.
+fn needs_git_commit(repo: &gix::Repository, workdir: &Path, signs: Option<bool>) -> Option<String> {
+    let config = repo.config_snapshot();
+    if signs.unwrap_or_else(|| config.boolean("commit.gpgSign").unwrap_or(false)) {
+        return Some("commits are signed".to_string());
+    }
+
+    let hooks_dir = match config.string("core.hooksPath") {
+        Some(path) => workdir.join(gix::path::from_bstr(path.as_ref())),
+        None => repo.common_dir().join("hooks"),
+    };
+    COMMIT_HOOKS
+        .iter()
+        .find(|hook| hooks_dir.join(hook).is_file())
+        .map(|hook| format!("the repository has a {} hook", hook))
+}
+
+/// Whether `rela_path`, relative to the worktree, or a directory it's in is
+/// ignored by the rules in `excludes`.
+pub(crate) fn is_ignored(
+    excludes: &mut gix::AttributeStack<'_>,
+    rela_path: &Path,
+    is_dir: bool,
+) -> Result<bool> {
+    for (depth, path) in rela_path.ancestors().enumerate() {
+        if path.as_os_str().is_empty() {
+            break;
+        }
+        let mode = if is_dir || depth > 0 {
+            Mode::DIR
+        } else {
+            Mode::FILE
+        };
+        let platform = excludes
+            .at_path(path, Some(mode))
+            .map_err(|e| gix_error("Failed to read ignore rules", e))?;
+        if platform.is_excluded() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// `path` as reported by the overlay, e.g. `./src/main.rs`, relative to the
+/// worktree. None for the root, the worktree's `.git` and whiteout markers.
+fn worktree_relative(path: &Path) -> Option<PathBuf> {
+    let rela_path: PathBuf = path
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect();
+    let first = rela_path.components().next()?;
+    if first.as_os_str() == ".git"
+        || rela_path.components().any(|component| {
+            Whiteout::is_whiteout_marker(component.as_os_str())
+                || Whiteout::is_opaque_marker(component.as_os_str())
+        })
+    {
+        return None;
+    }
+    Some(rela_path)
+}
+
+/// Whether two indexes stage the same content at the same paths.
+fn same_entries(a: &gix::index::File, b: &gix::index::File) -> bool {
+    a.entries().len() == b.entries().len()
+        && a.entries().iter().zip(b.entries()).all(|(x, y)| {
+            x.path(a) == y.path(b) && x.id == y.id && x.mode == y.mode && x.stage() == y.stage()
+        })
+}
+
+/// Update `index` to match the committed `changes`.
+fn apply_to_index(index: &mut gix::index::File, changes: &[PathChange]) {
+    // An upsert replaces whatever was at its path, whether a file or a
+    // directory, as well as a file where one of its directories now is
+    index.remove_entries(|_, entry_path, _| {
+        changes.iter().any(|change| {
+            let path = change.path();
+            entry_path == path
+                || is_inside(entry_path, path)
+                || matches!(change, PathChange::Upsert { .. }) && is_inside(path, entry_path)
+        })
+    });
+
+    for change in changes {
+        if let PathChange::Upsert {
+            path,
+            kind,
+            id,
+            stat,
+        } = change
+        {
+            let mode = Mode::from(gix::objs::tree::EntryMode::from(*kind));
+            index.dangerously_push_entry(*stat, *id, Flags::empty(), mode, path.as_bstr());
+        }
+    }
+    index.sort_entries();
+}
+
+/// Whether `path` is inside the directory `dir`.
+fn is_inside(path: &BStr, dir: &BStr) -> bool {
+    path.len() > dir.len() && path.starts_with(dir) && path[dir.len()] == b'/'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worktree_relative() {
+        assert_eq!(
+            worktree_relative(Path::new("./src/main.rs")),
+            Some(PathBuf::from("src/main.rs"))
+        );
+        assert_eq!(worktree_relative(Path::new(".")), None);
+        assert_eq!(worktree_relative(Path::new("./.git/index")), None);
+        assert_eq!(worktree_relative(Path::new("./src/.wh.old.rs")), None);
+    }
+
+    #[test]
+    fn test_is_inside() {
+        assert!(is_inside("src/main.rs".into(), "src".into()));
+        assert!(!is_inside("src".into(), "src".into()));
+        assert!(!is_inside("srcs/main.rs".into(), "src".into()));
+    }
+}
@@ -1,6 +1,17 @@
+mod attribution;
+mod checkpoint;
+mod commit;
+mod signing;
+
+pub use attribution::{CommitAttribution, CommitSession};
+pub use checkpoint::Checkpoint;
+pub(crate) use commit::is_ignored;
+pub use signing::{CommitKind, CommitSigning};
+
 use crate::cleanup;
 use crate::config::get_worktree_dir;
 use crate::error::{Result, TreebeardError};
+use crate::overlay::whiteout::Whiteout;
 use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 use std::thread;
@@ -9,6 +20,9 @@ use std::time::Duration;
 const MAX_ATTEMPTS: u32 = 3;
 const INITIAL_BACKOFF_MS: u64 = 100;
 
+/// Pathspec excluding `.wh.*` whiteout and opaque markers at any depth.
+const WHITEOUT_PATHSPEC: &str = ":(exclude,glob)**/.wh.*";
+
 fn is_transient_git_error(error: &TreebeardError) -> bool {
     match error {
         TreebeardError::Config(msg) | TreebeardError::Git(msg) => {
@@ -84,6 +98,10 @@ pub struct GitRepo {
     pub repo_name: String,
     pub workdir: PathBuf,
     pub git_dir: PathBuf,
+    /// How commits made through this repo are signed
+    pub signing: CommitSigning,
+    /// Identities and trailers for commits made through this repo
+    pub attribution: CommitAttribution,
 }
 
 impl GitRepo {
@@ -107,9 +125,23 @@ impl GitRepo {
             repo_name,
             workdir,
             git_dir,
+            signing: CommitSigning::default(),
+            attribution: CommitAttribution::default(),
         })
     }
 
+    /// Sign commits made through this repo according to `signing`.
+    pub fn with_signing(mut self, signing: CommitSigning) -> Self {
+        self.signing = signing;
+        self
+    }
+
+    /// Attribute commits made through this repo according to `attribution`.
+    pub fn with_attribution(mut self, attribution: CommitAttribution) -> Self {
+        self.attribution = attribution;
+        self
+    }
+
     pub fn workdir(&self) -> &Path {
         &self.workdir
     }
@@ -130,19 +162,99 @@ impl GitRepo {
     }
 
     pub fn create_branch(&self, branch_name: &str) -> Result<()> {
+        self.create_branch_at(branch_name, "HEAD")
+    }
+
+    /// Create `branch_name` pointing at `start_point`, any commit-ish git
+    /// understands.
+    pub fn create_branch_at(&self, branch_name: &str, start_point: &str) -> Result<()> {
         if self.branch_exists(branch_name) {
             return Err(TreebeardError::BranchAlreadyExists(branch_name.to_string()));
         }
 
         run_git(
             &self.workdir,
-            &["branch", branch_name],
+            &["branch", branch_name, start_point],
             "Failed to create branch",
         )?;
 
         Ok(())
     }
 
+    fn open_gix(&self) -> Result<gix::Repository> {
+        gix::open(&self.workdir).map_err(|e| {
+            TreebeardError::Git(format!(
+                "Failed to open repository {}: {}",
+                self.workdir.display(),
+                e
+            ))
+        })
+    }
+
+    /// Resolve a rev-spec such as `v1.2`, `origin/main` or `HEAD~3` to the
+    /// full id of the commit it names.
+    pub fn resolve_commit(&self, rev: &str) -> Result<String> {
+        let repo = self.open_gix()?;
+        let not_a_commit = |e: &dyn std::fmt::Display| {
+            TreebeardError::Git(format!("'{}' isn't a commit: {}", rev, e))
+        };
+        let commit = repo
+            .rev_parse_single(rev)
+            .map_err(|e| TreebeardError::Git(format!("Can't resolve '{}': {}", rev, e)))?
+            .object()
+            .map_err(|e| not_a_commit(&e))?
+            .peel_to_commit()
+            .map_err(|e| not_a_commit(&e))?;
+        Ok(commit.id.to_string())
+    }
+
+    /// Find the already fetched head of pull request `number`.
+    ///
+    /// Looks for `refs/pull/<number>/head` and then for remote-tracking refs
+    /// named `pr/<number>` or `pull/<number>` under any remote, preferring
+    /// `origin`. Nothing is fetched.
+    pub fn find_pull_request_ref(&self, number: u64) -> Result<String> {
+        let repo = self.open_gix()?;
+        let direct = format!("refs/pull/{}/head", number);
+        let suffixes = [
+            format!("/pr/{}", number),
+            format!("/pull/{}", number),
+            format!("/pull/{}/head", number),
+        ];
+
+        let mut candidates: Vec<String> = Vec::new();
+        let references = repo
+            .references()
+            .map_err(|e| TreebeardError::Git(format!("Failed to read references: {}", e)))?;
+        let all = references
+            .all()
+            .map_err(|e| TreebeardError::Git(format!("Failed to read references: {}", e)))?;
+        for reference in all.flatten() {
+            let name = reference.name().as_bstr().to_string();
+            let is_remote_pr = name.starts_with("refs/remotes/")
+                && suffixes
+                    .iter()
+                    .any(|suffix| name.ends_with(suffix.as_str()));
+            if name == direct || is_remote_pr {
+                candidates.push(name);
+            }
+        }
+
+        candidates.sort_by_key(|name| {
+            (
+                name != &direct,
+                !name.starts_with("refs/remotes/origin/"),
+                name.clone(),
+            )
+        });
+        candidates.into_iter().next().ok_or_else(|| {
+            TreebeardError::Git(format!(
+                "Pull request #{} hasn't been fetched. Fetch it with: git fetch origin pull/{}/head:refs/remotes/origin/pr/{}",
+                number, number, number
+            ))
+        })
+    }
+
     pub fn worktree_exists(&self, branch_name: &str) -> bool {
         let worktrees_dir = self.git_dir.join("worktrees").join(branch_name);
         worktrees_dir.exists()
@@ -208,9 +320,55 @@ impl GitRepo {
         Ok(gitdir_path.parent().unwrap().to_path_buf())
     }
 
+    /// Stage every change in the worktree except overlay whiteout markers,
+    /// which only exist to hide lower-layer files from the mount.
+    ///
+    /// If `git add` fails, it's retried once kernel whiteouts are unstaged.
+    /// They're looked for on the filesystem rather than in git's error
+    /// message, which may be translated.
     fn stage_all(&self) -> Result<()> {
+        match self.add_all() {
+            Err(e) => match self.unstage_kernel_whiteouts() {
+                Ok(unstaged) if unstaged > 0 => self.add_all(),
+                _ => Err(e),
+            },
+            result => result,
+        }
+    }
+
+    /// Remove tracked files that kernel overlayfs has replaced with whiteouts
+    /// from the index, returning how many there were. `git add` refuses to
+    /// stage the 0/0 character devices, and once untracked they're skipped
+    /// like any other special file.
+    fn unstage_kernel_whiteouts(&self) -> Result<usize> {
+        let output = run_git(
+            &self.workdir,
+            &["diff", "--name-only", "-z"],
+            "Failed to list changed files",
+        )?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let whiteouts: Vec<&str> = stdout
+            .split('\0')
+            .filter(|p| !p.is_empty() && Whiteout::is_kernel_whiteout(&self.workdir.join(p)))
+            .collect();
+        if whiteouts.is_empty() {
+            return Ok(0);
+        }
+
+        tracing::debug!(
+            "Unstaging {} deleted file(s) left as whiteouts",
+            whiteouts.len()
+        );
+        let count = whiteouts.len();
+        let mut args = vec!["rm", "--cached", "--quiet", "--"];
+        args.extend(whiteouts);
+        run_git(&self.workdir, &args, "Failed to unstage deleted files")?;
+        Ok(count)
+    }
+
+    fn add_all(&self) -> Result<()> {
         let stage_output = std::process::Command::new("git")
-            .args(["add", "-A"])
+            .args(["add", "-A", "--", ".", WHITEOUT_PATHSPEC])
             .current_dir(&self.workdir)
             .output()
             .map_err(|e| {
@@ -255,13 +413,7 @@ impl GitRepo {
                     return Ok(());
                 }
 
-                run_git(
-                    &self.workdir,
-                    &["commit", "-m", message],
-                    "Failed to commit",
-                )?;
-
-                Ok(())
+                self.git_commit(CommitKind::AutoSave, message, "Failed to commit")
             },
             "stage_and_commit",
         )
@@ -307,54 +459,55 @@ impl GitRepo {
         tracing::debug!("commit_staged() in directory: {:?}", self.workdir);
 
         with_retry(
-            || {
-                run_git(
-                    &self.workdir,
-                    &["commit", "-m", message],
-                    "Failed to commit",
-                )?;
-                Ok(())
-            },
+            || self.git_commit(CommitKind::AutoSave, message, "Failed to commit"),
             "commit_staged",
         )
     }
 
-    pub fn squash_commits(&self, branch_name: &str, base_message: &str) -> Result<()> {
-        let original_head = self.get_head()?;
-
-        let output = std::process::Command::new("git")
-            .args(["reset", "--soft", &format!("refs/heads/{}~1", branch_name)])
-            .current_dir(&self.workdir)
-            .output()
-            .map_err(|e| {
-                TreebeardError::Config(format!(
-                    "Failed to execute 'git reset --soft' in {}: {}",
-                    self.workdir.display(),
-                    e
-                ))
-            })?;
+    /// Arguments for `git` committing `message` as a commit of `kind`, signed
+    /// and attributed as configured.
+    fn git_commit_args(&self, kind: CommitKind, message: &str) -> Vec<String> {
+        let mut args = self.signing.git_config_args();
+        args.extend(self.attribution.git_config_args());
+        args.push("commit".to_string());
+        args.extend(self.signing.git_commit_flag(kind).map(String::from));
+        args.extend(["-m".to_string(), self.attribution.message(message)]);
+        args
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let error_msg = if stderr.trim().is_empty() {
-                String::from_utf8_lossy(&output.stdout).to_string()
-            } else {
-                stderr.to_string()
-            };
+    /// Run `git commit -m message`, signing and attributing it as configured.
+    fn git_commit(&self, kind: CommitKind, message: &str, error_prefix: &str) -> Result<()> {
+        let args = self.git_commit_args(kind, message);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        run_git(&self.workdir, &args, error_prefix).map_err(signing::classify_commit_error)?;
+        Ok(())
+    }
 
-            if error_msg.contains("ambiguous argument") || error_msg.contains("unknown revision") {
-                tracing::debug!("Branch has only one commit, skipping squash (nothing to squash)");
-                return Ok(());
-            }
+    /// Replace every commit since `base_commit` with a single commit.
+    ///
+    /// The commit is made in-process when it can be, and otherwise with
+    /// `git reset --soft` and `git commit`, which is undone if it fails.
+    pub fn squash_commits(&self, base_commit: &str, base_message: &str) -> Result<()> {
+        let original_head = self.get_head()?;
+        if original_head == base_commit {
+            tracing::debug!("No commits since the base, skipping squash (nothing to squash)");
+            return Ok(());
+        }
 
-            return Err(TreebeardError::Git(format!(
-                "Failed to reset: {}",
-                error_msg.trim()
-            )));
+        match self.try_squash_commits(base_commit, &original_head, base_message) {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(e) => tracing::debug!("In-process squash failed, squashing with git: {}", e),
         }
 
+        run_git(
+            &self.workdir,
+            &["reset", "--soft", base_commit],
+            "Failed to reset",
+        )?;
+
         let commit_output = std::process::Command::new("git")
-            .args(["commit", "-m", base_message])
+            .args(self.git_commit_args(CommitKind::Squash, base_message))
             .current_dir(&self.workdir)
             .output()
             .map_err(|e| {
@@ -378,10 +531,10 @@ impl GitRepo {
                 stderr.to_string()
             };
 
-            let original_error = TreebeardError::Git(format!(
+            let original_error = signing::classify_commit_error(TreebeardError::Git(format!(
                 "Failed to create squash commit: {}",
                 error_msg.trim()
-            ));
+            )));
 
             match rollback_result {
                 Ok(ref rollback) if rollback.status.success() => {}
@@ -548,6 +701,30 @@ impl GitRepo {
         Ok(stdout.lines().filter(|line| !line.is_empty()).count())
     }
 
+    /// Files tracked at HEAD that `commit` doesn't have, relative to the
+    /// workdir.
+    pub fn tracked_paths_missing_from(&self, commit: &str) -> Result<Vec<PathBuf>> {
+        let output = run_git(
+            &self.workdir,
+            &[
+                "diff",
+                "--name-only",
+                "-z",
+                "--no-renames",
+                "--diff-filter=A",
+                commit,
+                "HEAD",
+            ],
+            "Failed to list added files",
+        )?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .split('\0')
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .collect())
+    }
+
     pub fn get_commit_count_since(&self, branch_name: &str, base_commit: &str) -> Result<usize> {
         let range = format!("{}..{}", base_commit, branch_name);
         let output = run_git(
@@ -625,12 +802,49 @@ pub fn offer_stash_if_needed(repo: &GitRepo) -> Result<Option<String>> {
     Ok(Some(message))
 }
 
-/// Sets up the git environment: discovers repo, creates branch and worktree
-pub fn setup_git_environment(branch_name: &str) -> Result<GitEnvironmentSetup> {
+/// Where a new branch starts, when not at the main repo's HEAD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BranchStart {
+    /// Any rev-spec, e.g. `origin/main` or `v1.2`
+    Rev(String),
+    /// The fetched head of a pull request, see `GitRepo::find_pull_request_ref`
+    PullRequest(u64),
+}
+
+impl BranchStart {
+    /// The rev-spec this start point refers to in `repo`.
+    fn rev(&self, repo: &GitRepo) -> Result<String> {
+        match self {
+            BranchStart::Rev(rev) => Ok(rev.clone()),
+            BranchStart::PullRequest(number) => repo.find_pull_request_ref(*number),
+        }
+    }
+}
+
+/// Sets up the git environment: discovers repo, creates branch and worktree.
+///
+/// New branches start at `start`, or the main repo's HEAD if it's `None`.
+/// An existing branch is used as it is.
+pub fn setup_git_environment(
+    branch_name: &str,
+    start: Option<&BranchStart>,
+) -> Result<GitEnvironmentSetup> {
     let repo = GitRepo::discover()?;
 
     println!("Repository: {}", repo.workdir().display());
 
+    // Resolve the start point before touching the working tree, so that a
+    // bad --from fails without stashing anything
+    let branch_exists = repo.branch_exists(branch_name);
+    let resolved_start = match start {
+        Some(start) if !branch_exists => {
+            let rev = start.rev(&repo)?;
+            let commit = repo.resolve_commit(&rev)?;
+            Some((rev, commit))
+        }
+        _ => None,
+    };
+
     // Offer to stash uncommitted changes before creating worktree
     let auto_stash_message = offer_stash_if_needed(&repo)?;
     if let Some(ref stash_message) = auto_stash_message {
@@ -639,11 +853,22 @@ pub fn setup_git_environment(branch_name: &str) -> Result<GitEnvironmentSetup> {
 
     println!("Creating branch: {}", branch_name);
 
-    if repo.branch_exists(branch_name) {
+    if branch_exists {
         eprintln!(
             "Warning: Branch '{}' already exists, continuing with existing branch",
             branch_name
         );
+        if start.is_some() {
+            eprintln!("Warning: Ignoring --from/--from-pr for the existing branch");
+        }
+    } else if let Some((ref rev, ref commit)) = resolved_start {
+        repo.create_branch_at(branch_name, commit)?;
+        println!(
+            "Created branch: {} from {} ({})",
+            branch_name,
+            rev,
+            &commit[..commit.len().min(12)]
+        );
     } else {
         repo.create_branch(branch_name)?;
         println!("Created branch: {}", branch_name);
@@ -671,8 +896,10 @@ pub fn setup_git_environment(branch_name: &str) -> Result<GitEnvironmentSetup> {
 
     let main_repo_path = repo.workdir().to_path_buf();
 
-    let worktree_repo = GitRepo::from_path(&worktree_path)?;
-    let base_commit = worktree_repo.get_head()?;
+    let base_commit = match resolved_start {
+        Some((_, commit)) => commit,
+        None => GitRepo::from_path(&worktree_path)?.get_head()?,
+    };
 
     Ok(GitEnvironmentSetup {
         repo,
@@ -0,0 +1,159 @@
+//! Signing policy for the commits a session makes.
+//!
+//! Auto-commits and the squash commit are made by `git commit`, which signs
+//! them when `commit.gpgSign` says so. `[commit] signing` overrides that per
+//! kind of commit, so repos that require signed commits can leave the
+//! work-in-progress auto-commits unsigned and sign only the squash commit,
+//! optionally with a key of their own.
+
+use crate::config::{SigningFormat, SigningPolicy};
+use crate::error::TreebeardError;
+
+/// What git prints when it couldn't sign a commit, lowercased.
+const SIGNING_FAILURES: &[&str] = &[
+    "failed to sign",
+    "gpg failed",
+    "ssh-keygen",
+    "signingkey",
+    "signing key",
+];
+
+/// Which kind of commit is being made, for the signing policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitKind {
+    /// A work-in-progress auto-commit
+    AutoSave,
+    /// The commit that replaces a session's auto-commits when squashing
+    Squash,
+}
+
+/// How a session's commits are signed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitSigning {
+    pub policy: SigningPolicy,
+    /// Key to sign with instead of git's `user.signingKey`
+    pub key: Option<String>,
+    /// Format to sign in instead of git's `gpg.format`
+    pub format: Option<SigningFormat>,
+}
+
+impl CommitSigning {
+    /// Whether commits of `kind` are signed, or None to leave it to git's
+    /// `commit.gpgSign`.
+    pub fn signs(&self, kind: CommitKind) -> Option<bool> {
+        match (self.policy, kind) {
+            (SigningPolicy::Auto, _) => None,
+            (SigningPolicy::None, _) | (SigningPolicy::FinalOnly, CommitKind::AutoSave) => {
+                Some(false)
+            }
+            (SigningPolicy::FinalOnly, CommitKind::Squash) => Some(true),
+        }
+    }
+
+    /// `-c` options for `git` that select the key and format to sign with.
+    pub fn git_config_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(format) = self.format {
+            args.extend(["-c".to_string(), format!("gpg.format={}", format)]);
+        }
+        if let Some(key) = &self.key {
+            args.extend(["-c".to_string(), format!("user.signingKey={}", key)]);
+        }
+        args
+    }
+
+    /// The `git commit` flag that applies the policy to a commit of `kind`.
+    pub fn git_commit_flag(&self, kind: CommitKind) -> Option<&'static str> {
+        match self.signs(kind) {
+            Some(true) => Some("--gpg-sign"),
+            Some(false) => Some("--no-gpg-sign"),
+            None => None,
+        }
+    }
+}
+
+/// Turn a failed `git commit` into a signing error if signing is what
+/// failed.
+pub(crate) fn classify_commit_error(error: TreebeardError) -> TreebeardError {
+    match error {
+        TreebeardError::Git(msg) if is_signing_failure(&msg) => TreebeardError::Signing(msg),
+        error => error,
+    }
+}
+
+fn is_signing_failure(msg: &str) -> bool {
+    let lower = msg.to_lowercase();
+    SIGNING_FAILURES.iter().any(|marker| lower.contains(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_policy_per_commit_kind() {
+        let signing = |policy| CommitSigning {
+            policy,
+            ..Default::default()
+        };
+        assert_eq!(
+            signing(SigningPolicy::Auto).signs(CommitKind::AutoSave),
+            None
+        );
+        assert_eq!(
+            signing(SigningPolicy::None).signs(CommitKind::Squash),
+            Some(false)
+        );
+        assert_eq!(
+            signing(SigningPolicy::FinalOnly).signs(CommitKind::AutoSave),
+            Some(false)
+        );
+        assert_eq!(
+            signing(SigningPolicy::FinalOnly).signs(CommitKind::Squash),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_git_args() {
+        let signing = CommitSigning::default();
+        assert!(signing.git_config_args().is_empty());
+        assert_eq!(signing.git_commit_flag(CommitKind::Squash), None);
+
+        let signing = CommitSigning {
+            policy: SigningPolicy::FinalOnly,
+            key: Some("~/.ssh/session.pub".to_string()),
+            format: Some(SigningFormat::Ssh),
+        };
+        assert_eq!(
+            signing.git_config_args(),
+            [
+                "-c",
+                "gpg.format=ssh",
+                "-c",
+                "user.signingKey=~/.ssh/session.pub"
+            ]
+        );
+        assert_eq!(
+            signing.git_commit_flag(CommitKind::Squash),
+            Some("--gpg-sign")
+        );
+        assert_eq!(
+            signing.git_commit_flag(CommitKind::AutoSave),
+            Some("--no-gpg-sign")
+        );
+    }
+
+    #[test]
+    fn test_classify_commit_error() {
+        let error = classify_commit_error(TreebeardError::Git(
+            "Failed to commit: error: gpg failed to sign the data".to_string(),
+        ));
+        assert!(matches!(error, TreebeardError::Signing(_)));
+
+        let error = classify_commit_error(TreebeardError::Git(
+            "Failed to commit: nothing to commit".to_string(),
+        ));
+        assert!(matches!(error, TreebeardError::Git(_)));
+    }
+}
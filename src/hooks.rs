@@ -139,17 +139,16 @@ pub async fn run_hooks(hooks: &[String], context: &HookContext, working_dir: &Pa
         let expanded = expand_template(hook, context);
         tracing::info!("Running hook: {}", expanded);
 
-        let status = Command::new("sh")
-            .arg("-c")
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
             .arg(&expanded)
             .current_dir(working_dir)
             .envs(context.env_vars())
-            .stdin(Stdio::null())
-            .status()
-            .await
-            .map_err(|e| {
-                TreebeardError::Hook(format!("Failed to execute hook '{}': {}", expanded, e))
-            })?;
+            .stdin(Stdio::null());
+        crate::overlay::join_mount_namespace(&mut cmd, working_dir)?;
+        let status = cmd.status().await.map_err(|e| {
+            TreebeardError::Hook(format!("Failed to execute hook '{}': {}", expanded, e))
+        })?;
 
         if !status.success() {
             let exit_code = status
@@ -190,20 +189,19 @@ pub async fn run_commit_message_hook(
     let expanded = expand_template(hook, context);
     tracing::info!("Running commit message hook: {}", expanded);
 
-    let output = Command::new("sh")
-        .arg("-c")
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
         .arg(&expanded)
         .current_dir(working_dir)
         .envs(context.env_vars())
-        .stdin(Stdio::null())
-        .output()
-        .await
-        .map_err(|e| {
-            TreebeardError::Hook(format!(
-                "Failed to execute commit message hook '{}': {}",
-                expanded, e
-            ))
-        })?;
+        .stdin(Stdio::null());
+    crate::overlay::join_mount_namespace(&mut cmd, working_dir)?;
+    let output = cmd.output().await.map_err(|e| {
+        TreebeardError::Hook(format!(
+            "Failed to execute commit message hook '{}': {}",
+            expanded, e
+        ))
+    })?;
 
     if !output.status.success() {
         let exit_code = output
@@ -1,3 +1,5 @@
+#[cfg(target_os = "linux")]
+pub mod cgroup;
 pub mod cleanup;
 pub mod config;
 pub mod error;
@@ -7,6 +9,7 @@ pub mod overlay;
 pub mod sandbox;
 pub mod session;
 pub mod shell;
+pub mod supervisor;
 pub mod sync;
 pub mod watcher;
 
@@ -19,10 +22,13 @@ pub use config::load_config;
 pub use config::save_config;
 pub use config::Config;
 pub use config::HooksConfig;
+pub use config::InodeCacheConfig;
 pub use config::NetworkMode;
 pub use config::OnExitBehavior;
+pub use config::OverlayBackendKind;
 pub use config::SandboxConfig;
 pub use config::SandboxNetworkConfig;
+pub use config::SyncPolicy;
 
 pub use error::{Result, TreebeardError};
 
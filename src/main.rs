@@ -1,7 +1,8 @@
 use clap::Parser;
-use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 
+#[cfg(target_os = "linux")]
+mod cgroup;
 mod cleanup;
 mod cli;
 mod commands;
@@ -13,6 +14,7 @@ mod overlay;
 mod sandbox;
 mod session;
 mod shell;
+mod supervisor;
 mod sync;
 mod watcher;
 
@@ -20,7 +22,8 @@ use cli::{Args, Commands};
 use config::load_config;
 use git::setup_git_environment;
 use overlay::setup_overlay_and_watcher;
-use session::{add_active_session, run_shell_and_cleanup};
+use session::{add_active_session, new_session_id, run_shell_and_cleanup};
+use watcher::AutoCommitFailures;
 
 #[tokio::main]
 async fn main() {
@@ -65,8 +68,91 @@ async fn run() -> error::Result<i32> {
         Commands::Branch {
             branch_name,
             no_shell,
+            detach,
+            from,
+            from_pr,
+            sync,
             command,
-        } => Ok(create_branch(&branch_name, no_shell, command).await?),
+        } => {
+            let start = from
+                .map(git::BranchStart::Rev)
+                .or(from_pr.map(git::BranchStart::PullRequest));
+            if detach {
+                create_detached_branch(&branch_name, start.as_ref(), &sync, command)?;
+                Ok(0)
+            } else {
+                Ok(create_branch(&branch_name, start.as_ref(), no_shell, &sync, command).await?)
+            }
+        }
+        Commands::Attach {
+            branch_name,
+            command,
+        } => Ok(commands::attach_to_session(&branch_name, command).await?),
+        Commands::Resume {
+            branch_name,
+            sync,
+            command,
+        } => Ok(commands::resume_session(&branch_name, &sync, command).await?),
+        Commands::Diff {
+            branch_name,
+            stat,
+            name_status,
+            json,
+        } => {
+            commands::show_diff(
+                &branch_name,
+                commands::DiffFormat::from_flags(stat, name_status, json),
+            )?;
+            Ok(0)
+        }
+        Commands::Sync {
+            branch_name,
+            paths,
+            passthrough,
+            sync,
+        } => {
+            let after = if passthrough {
+                commands::AfterSync::Passthrough
+            } else {
+                commands::AfterSync::Keep
+            };
+            commands::sync_session(&branch_name, &paths, after, &sync)?;
+            Ok(0)
+        }
+        Commands::Logs {
+            branch_name,
+            follow,
+        } => {
+            commands::show_logs(&branch_name, follow).await?;
+            Ok(0)
+        }
+        Commands::Stop { branch_name } => {
+            commands::stop_session(&branch_name)?;
+            Ok(0)
+        }
+        Commands::Stats { branch_name, json } => {
+            commands::show_stats(&branch_name, json)?;
+            Ok(0)
+        }
+        Commands::Reload { branch_name, force } => {
+            commands::reload_session(&branch_name, force)?;
+            Ok(0)
+        }
+        Commands::Log { branch_name } => {
+            commands::show_log(&branch_name)?;
+            Ok(0)
+        }
+        Commands::Rollback {
+            branch_name,
+            checkpoint,
+        } => {
+            commands::rollback_session(&branch_name, &checkpoint)?;
+            Ok(0)
+        }
+        Commands::Finish { branch_name, sync } => {
+            commands::finish_session(&branch_name, &sync).await?;
+            Ok(0)
+        }
         Commands::Config { action } => {
             commands::handle_config_command(action)?;
             Ok(0)
@@ -93,23 +179,96 @@ async fn run() -> error::Result<i32> {
             yes,
             force,
             stale,
+            sync,
         } => {
-            commands::cleanup_branch(branch_names, all, delete_branch, yes, force, stale)?;
+            commands::cleanup_branch(branch_names, all, delete_branch, yes, force, stale, &sync)?;
             Ok(0)
         }
+        Commands::Supervise => {
+            supervisor::server::run_supervisor().await?;
+            Ok(0)
+        }
+    }
+}
+
+/// Create the branch and worktree here, then hand the session to the
+/// supervisor, which mounts the overlay and runs `command` in the background.
+fn create_detached_branch(
+    branch_name: &str,
+    start: Option<&git::BranchStart>,
+    sync: &cli::SyncArgs,
+    command: Vec<String>,
+) -> error::Result<()> {
+    cli::validate_branch_name(branch_name)?;
+
+    if command.is_empty() {
+        return Err(error::TreebeardError::Config(
+            "--detach requires a command, e.g. treebeard branch <name> --detach -- <cmd>"
+                .to_string(),
+        ));
     }
+
+    let mut config = load_config()?;
+    sync.apply(&mut config.sync);
+
+    let git_env = setup_git_environment(branch_name, start)?;
+
+    supervisor::client::ensure_running()?;
+    let response = supervisor::client::send_request(&supervisor::protocol::Request::Start {
+        request: Box::new(supervisor::protocol::StartRequest {
+            repo_path: git_env.main_repo_path.clone(),
+            branch_name: branch_name.to_string(),
+            worktree_path: git_env.worktree_path.clone(),
+            base_commit: git_env.base_commit.clone(),
+            command,
+            config,
+        }),
+    })?;
+    let session = match response {
+        supervisor::protocol::Response::Session { session } => session,
+        other => return Err(supervisor::client::unexpected(other)),
+    };
+
+    println!(
+        "Started detached session '{}' (pid {})",
+        branch_name, session.pid
+    );
+    println!("Mount: {}", session.mount_path.display());
+    println!();
+    println!("  treebeard logs {} --follow   # watch output", branch_name);
+    println!(
+        "  treebeard attach {}          # open a shell in the mount",
+        branch_name
+    );
+    println!(
+        "  treebeard stop {}            # terminate the command",
+        branch_name
+    );
+    println!(
+        "  treebeard finish {}          # sync and clean up after exit",
+        branch_name
+    );
+
+    if git_env.auto_stash_message.is_some() {
+        println!("Note: Your stashed changes are available via 'git stash list'");
+    }
+
+    Ok(())
 }
 
 async fn create_branch(
     branch_name: &str,
+    start: Option<&git::BranchStart>,
     no_shell: bool,
+    sync: &cli::SyncArgs,
     command: Vec<String>,
 ) -> error::Result<i32> {
     cli::validate_branch_name(branch_name)?;
 
-    let config = load_config()?;
+    let mut config = load_config()?;
+    sync.apply(&mut config.sync);
 
-    let git_env = setup_git_environment(branch_name)?;
+    let git_env = setup_git_environment(branch_name, start)?;
 
     if no_shell {
         println!(
@@ -119,9 +278,15 @@ async fn create_branch(
         return Ok(0);
     }
 
-    let failure_count = Arc::new(AtomicUsize::new(0));
+    let session = git::CommitSession {
+        id: new_session_id(),
+        branch: branch_name.to_string(),
+        command: command.clone(),
+        base_commit: git_env.base_commit.clone(),
+    };
+    let failure_count = Arc::new(AutoCommitFailures::default());
     let overlay = setup_overlay_and_watcher(
-        branch_name,
+        &session,
         &git_env.worktree_path,
         &git_env.main_repo_path,
         &config,
@@ -130,9 +295,9 @@ async fn create_branch(
 
     if let Err(e) = add_active_session(
         &git_env.main_repo_path,
-        branch_name,
         &git_env.worktree_path,
         &overlay.mount_path,
+        &session,
     ) {
         tracing::warn!("Failed to save session state: {}", e);
     }
@@ -172,8 +337,8 @@ async fn create_branch(
         println!("Note: Your stashed changes are available via 'git stash list'");
     }
 
-    // Warn user about sandbox restrictions (macOS only)
-    #[cfg(target_os = "macos")]
+    // Warn user about sandbox restrictions (macOS and Linux)
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
     {
         if config.sandbox.enabled && !config.sandbox.deny_read.is_empty() {
             println!("Sandbox enabled. Read access is blocked to:");
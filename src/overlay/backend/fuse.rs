@@ -0,0 +1,64 @@
+use super::{MountRequest, MountedOverlay, OverlayBackend};
+use crate::error::{Result, TreebeardError};
+use crate::overlay::mount::{cleanup_stale_fuse_mounts, mount_fuse, unmount_fuse};
+use crate::overlay::whiteout::Whiteout;
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// The default backend: the userspace overlay served over FUSE.
+pub struct FuseBackend;
+
+impl OverlayBackend for FuseBackend {
+    fn name(&self) -> &'static str {
+        "fuse"
+    }
+
+    fn mount_hint(&self) -> &'static str {
+        if cfg!(target_os = "macos") {
+            "Make sure macFUSE is installed: brew install --cask macfuse"
+        } else {
+            "Make sure FUSE is installed and /dev/fuse is accessible"
+        }
+    }
+
+    /// The overlay checks the upper layer on each lookup, and is told to
+    /// drop what it caches through its stats socket.
+    fn allows_upper_changes(&self) -> bool {
+        true
+    }
+
+    fn white_out(&self, parent_dir: &Path, name: &OsStr) -> Result<()> {
+        Whiteout::create(parent_dir, name).map_err(|errno| {
+            TreebeardError::Overlay(format!(
+                "Failed to white out {}: {}",
+                parent_dir.join(name).display(),
+                std::io::Error::from_raw_os_error(errno)
+            ))
+        })
+    }
+
+    fn mount(&self, request: &MountRequest) -> Result<MountedOverlay> {
+        mount_fuse(
+            request.mount_point,
+            request.upper_layer,
+            request.lower_layer,
+            request.ttl_secs,
+            request.passthrough_patterns.clone(),
+            request.journal_path,
+            request.inode_cache,
+        )
+    }
+
+    /// FUSE is the fallback for any mount no other backend claims.
+    fn owns_mount(&self, _mount_path: &Path) -> bool {
+        true
+    }
+
+    fn unmount(&self, mount_path: &Path) -> Result<bool> {
+        unmount_fuse(mount_path)
+    }
+
+    fn cleanup_stale_mounts(&self) {
+        cleanup_stale_fuse_mounts();
+    }
+}
@@ -0,0 +1,300 @@
+//! Pluggable implementations of a session's copy-on-write mount.
+//!
+//! The default [`FuseBackend`] serves the userspace overlay over FUSE. On
+//! Linux, [`OverlayfsBackend`] mounts kernel overlayfs instead, which avoids a
+//! round trip through userspace for every read and write.
+//!
+//! Both backends fill in the same `MutationTracker` and mutation channel, so
+//! the watcher and sync don't need to know which one is in use. Unmounting
+//! and stale mount cleanup are dispatched on what's actually mounted rather
+//! than the current config, so changing `overlay_backend` never strands a
+//! mount made with the previous setting.
+
+mod fuse;
+#[cfg(target_os = "linux")]
+mod overlayfs;
+
+pub use fuse::FuseBackend;
+#[cfg(target_os = "linux")]
+pub use overlayfs::{MountNamespace, OverlayfsBackend};
+
+use crate::config::{InodeCacheConfig, OverlayBackendKind};
+use crate::error::{Result, TreebeardError};
+use crate::overlay::types::MutationTracker;
+use crate::overlay::whiteout::Whiteout;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// Everything a backend needs to mount a session's overlay.
+pub struct MountRequest<'a> {
+    /// Directory where the merged view is mounted
+    pub mount_point: &'a Path,
+    /// Directory for the upper (writable) layer: the session's worktree
+    pub upper_layer: &'a Path,
+    /// Directory for the lower (read-only) layer: the main repo
+    pub lower_layer: &'a Path,
+    /// Cache TTL in seconds for attributes and entries, where supported
+    pub ttl_secs: u64,
+    /// Patterns for paths that bypass the overlay, where supported
+    pub passthrough_patterns: Vec<String>,
+    /// Optional on-disk mutation journal (see `MutationJournal`)
+    pub journal_path: Option<&'a Path>,
+    /// Sizing of the inode table, where the backend keeps one
+    pub inode_cache: &'a InodeCacheConfig,
+}
+
+/// A mounted overlay:
+/// - MutationTracker for checking mutations on cleanup
+/// - Receiver for mutation events to trigger commits. It closes once the
+///   overlay is unmounted.
+pub type MountedOverlay = (MutationTracker, UnboundedReceiver<PathBuf>);
+
+/// A way of mounting, unmounting and cleaning up session overlays.
+pub trait OverlayBackend {
+    /// Short name used in messages and config, e.g. "fuse".
+    fn name(&self) -> &'static str;
+
+    /// Suggestion printed when mounting fails.
+    fn mount_hint(&self) -> &'static str;
+
+    /// Whether the upper layer may be changed from outside a live mount, for
+    /// `sync --passthrough` and `rollback`.
+    fn allows_upper_changes(&self) -> bool;
+
+    /// Hide `name` from the lower layer by creating a whiteout for it in
+    /// `parent_dir`, an upper-layer directory, in the form this backend
+    /// reads. Only valid while the overlay isn't mounted.
+    fn white_out(&self, parent_dir: &Path, name: &OsStr) -> Result<()>;
+
+    /// Mount the overlay described by `request`.
+    fn mount(&self, request: &MountRequest) -> Result<MountedOverlay>;
+
+    /// Whether the mount at `mount_path` was made by this backend.
+    fn owns_mount(&self, mount_path: &Path) -> bool;
+
+    /// Unmount the overlay at `mount_path`, leaving the directory in place.
+    ///
+    /// Returns Ok(true) if unmount succeeded, Ok(false) if it may already be
+    /// unmounted, or an error if the path isn't a treebeard mount.
+    fn unmount(&self, mount_path: &Path) -> Result<bool>;
+
+    /// Unmount mounts left behind by sessions that crashed or were killed.
+    fn cleanup_stale_mounts(&self);
+}
+
+/// Returns the backend selected by `kind`, or an error if it isn't
+/// available on this platform.
+pub fn backend(kind: OverlayBackendKind) -> Result<Box<dyn OverlayBackend>> {
+    match kind {
+        OverlayBackendKind::Fuse => Ok(Box::new(FuseBackend)),
+        #[cfg(target_os = "linux")]
+        OverlayBackendKind::Overlayfs => Ok(Box::new(OverlayfsBackend)),
+        #[cfg(not(target_os = "linux"))]
+        OverlayBackendKind::Overlayfs => Err(TreebeardError::Config(
+            "The overlayfs backend is only available on Linux; set overlay_backend = \"fuse\""
+                .to_string(),
+        )),
+    }
+}
+
+/// All backends available on this platform.
+fn available_backends() -> Vec<Box<dyn OverlayBackend>> {
+    vec![
+        #[cfg(target_os = "linux")]
+        Box::new(OverlayfsBackend),
+        Box::new(FuseBackend),
+    ]
+}
+
+/// Returns the backend that made the mount at `mount_path`. FUSE is assumed
+/// when no other backend claims it.
+pub fn backend_for_mount(mount_path: &Path) -> Box<dyn OverlayBackend> {
+    available_backends()
+        .into_iter()
+        .find(|b| b.owns_mount(mount_path))
+        .unwrap_or_else(|| Box::new(FuseBackend))
+}
+
+/// Returns an error naming `operation` if the mount at `mount_path` is up
+/// and its backend doesn't allow changing the upper layer underneath it.
+pub fn check_upper_changes_allowed(mount_path: &Path, operation: &str) -> Result<()> {
+    let backend = backend_for_mount(mount_path);
+    if backend.allows_upper_changes() {
+        return Ok(());
+    }
+    Err(TreebeardError::Config(format!(
+        "{} isn't supported while a session using the {} backend is running, because changing \
+         its worktree underneath a live mount is undefined. Stop the session first",
+        operation,
+        backend.name()
+    )))
+}
+
+/// Hides each of `paths` that exists in `lower_layer` but not in
+/// `upper_layer`, using `backend`'s whiteouts.
+///
+/// A missing directory is whited out as a whole rather than file by file.
+/// Paths already hidden by a whiteout, a file or an opaque directory in the
+/// upper layer are left alone. Returns how many whiteouts were created.
+pub fn hide_lower_paths(
+    backend: &dyn OverlayBackend,
+    upper_layer: &Path,
+    lower_layer: &Path,
+    paths: &[PathBuf],
+) -> Result<usize> {
+    let mut created = 0;
+    for path in paths {
+        let mut relative = PathBuf::new();
+        for component in path.components() {
+            let parent_dir = upper_layer.join(&relative);
+            if Whiteout::is_opaque(&parent_dir) {
+                break;
+            }
+            relative.push(component);
+
+            let upper_path = upper_layer.join(&relative);
+            match upper_path.symlink_metadata() {
+                Ok(metadata) if metadata.is_dir() => continue,
+                Ok(_) => break,
+                Err(_) => {}
+            }
+            if Whiteout::is_whiteout(&upper_path)
+                || lower_layer.join(&relative).symlink_metadata().is_err()
+            {
+                break;
+            }
+
+            backend.white_out(&parent_dir, component.as_os_str())?;
+            created += 1;
+            break;
+        }
+    }
+    Ok(created)
+}
+
+/// Unmount a session overlay with whichever backend mounted it.
+pub fn unmount(mount_path: &Path) -> Result<bool> {
+    backend_for_mount(mount_path).unmount(mount_path)
+}
+
+/// Clean up stale mounts from crashed sessions, for every backend.
+///
+/// This is called by the `treebeard cleanup --stale` command and logs what it does,
+/// but doesn't fail if cleanup encounters errors. Users can skip this with the
+/// TREEBEARD_NO_CLEANUP=1 env var.
+pub fn cleanup_stale_mounts() {
+    // Allow users to skip automatic cleanup
+    if std::env::var("TREEBEARD_NO_CLEANUP").is_ok() {
+        tracing::debug!("Skipping stale mount cleanup (TREEBEARD_NO_CLEANUP=1)");
+        return;
+    }
+
+    for backend in available_backends() {
+        tracing::debug!("Checking for stale {} mounts...", backend.name());
+        backend.cleanup_stale_mounts();
+    }
+}
+
+/// Makes `cmd` run inside the session mount at `working_dir` if its backend
+/// keeps the mount in a private namespace. FUSE mounts are visible to every
+/// process, so this does nothing for them.
+pub fn join_mount_namespace(cmd: &mut tokio::process::Command, working_dir: &Path) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    if let Some(namespace) = MountNamespace::for_mount(working_dir, working_dir)? {
+        // SAFETY: MountNamespace::enter only makes raw syscalls on data
+        // prepared in the parent.
+        unsafe {
+            cmd.pre_exec(move || namespace.enter());
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = (cmd, working_dir);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_selection() {
+        assert_eq!(backend(OverlayBackendKind::Fuse).unwrap().name(), "fuse");
+
+        let overlayfs = backend(OverlayBackendKind::Overlayfs);
+        if cfg!(target_os = "linux") {
+            assert_eq!(overlayfs.unwrap().name(), "overlayfs");
+        } else {
+            assert!(overlayfs.is_err());
+        }
+    }
+
+    #[test]
+    fn test_hide_lower_paths() {
+        use crate::overlay::test_helpers::setup;
+        use crate::overlay::OverlayFs;
+        use fuser::FUSE_ROOT_ID;
+        use std::ffi::OsString;
+        use std::fs;
+
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::create_dir_all(lower_layer.join("newdir/sub")).unwrap();
+        for file in [
+            "added.txt",
+            "kept.txt",
+            "gone.txt",
+            "newdir/a.txt",
+            "newdir/sub/b.txt",
+        ] {
+            fs::write(lower_layer.join(file), "lower").unwrap();
+        }
+        fs::write(upper_layer.join("kept.txt"), "upper").unwrap();
+        Whiteout::create(&upper_layer, OsStr::new("gone.txt")).unwrap();
+
+        let paths: Vec<PathBuf> = [
+            "added.txt",
+            "kept.txt",
+            "gone.txt",
+            "missing.txt",
+            "newdir/a.txt",
+            "newdir/sub/b.txt",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let hidden = hide_lower_paths(&FuseBackend, &upper_layer, &lower_layer, &paths).unwrap();
+
+        // A new directory is hidden by one whiteout, and existing entries,
+        // whiteouts and paths missing from the lower layer are left alone
+        assert_eq!(hidden, 2);
+        assert!(upper_layer.join(".wh.added.txt").exists());
+        assert!(upper_layer.join(".wh.newdir").exists());
+        assert!(!upper_layer.join(".wh.kept.txt").exists());
+        assert!(!upper_layer.join(".wh.missing.txt").exists());
+        assert_eq!(
+            hide_lower_paths(&FuseBackend, &upper_layer, &lower_layer, &paths).unwrap(),
+            0
+        );
+
+        let fs = OverlayFs::new(upper_layer, lower_layer, None, 1, vec![]).unwrap();
+        let lookup = |name: &str| {
+            fs.lookup_overlay(
+                FUSE_ROOT_ID,
+                OsString::from(name),
+                PathBuf::from(format!("./{}", name)),
+            )
+            .unwrap()
+        };
+        assert!(lookup("added.txt").is_none());
+        assert!(lookup("newdir").is_none());
+        assert!(lookup("kept.txt").is_some());
+    }
+
+    #[test]
+    fn test_unclaimed_mount_falls_back_to_fuse() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(backend_for_mount(&dir.path().join("main")).name(), "fuse");
+    }
+}
@@ -0,0 +1,878 @@
+//! Kernel overlayfs backend (Linux only).
+//!
+//! Mounts the worktree over the main repo with the kernel's own overlayfs,
+//! without root. A small holder process is forked into a new user and mount
+//! namespace, mounts the overlay there (unprivileged overlayfs needs Linux
+//! 5.11+ and the `userxattr` option) and then just sits there keeping the
+//! namespace alive. Processes that should see the mount — the session shell,
+//! `treebeard attach` and hooks — join that namespace with [`MountNamespace`]
+//! before they exec. Every other process sees an empty mount directory.
+//!
+//! The holder's pid is kept in a `.<branch>.overlayfs` record next to the
+//! mount directory, which is how other treebeard processes find it to join
+//! or unmount the overlay. The holder exits with the process that mounted it,
+//! much like FUSE's AutoUnmount.
+//!
+//! The kernel doesn't report writes the way FUSE callbacks do, so mutations
+//! are tracked by watching the upper layer with inotify, and by scanning
+//! directories as they appear so files created in them before the watch was
+//! added aren't missed.
+
+use super::{MountRequest, MountedOverlay, OverlayBackend};
+use crate::config::get_mount_dir;
+use crate::error::{Result, TreebeardError};
+use crate::overlay::base::BaseStore;
+use crate::overlay::journal::MutationJournal;
+use crate::overlay::mount::{perform_fuse_cleanup, validate_mount_path};
+use crate::overlay::types::{MutationTracker, MutationType};
+use crate::overlay::whiteout::Whiteout;
+use crate::sandbox::linux::UserNamespaceMaps;
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Unprivileged overlayfs mounts arrived in Linux 5.11.
+const MIN_KERNEL_VERSION: (u32, u32) = (5, 11);
+
+/// How often the mutation watcher checks whether the overlay was unmounted.
+const UNMOUNT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const RECORD_SUFFIX: &str = ".overlayfs";
+const WORK_DIR_SUFFIX: &str = ".overlayfs-work";
+
+/// Mounts session overlays with kernel overlayfs in a user namespace.
+pub struct OverlayfsBackend;
+
+impl OverlayBackend for OverlayfsBackend {
+    fn name(&self) -> &'static str {
+        "overlayfs"
+    }
+
+    fn mount_hint(&self) -> &'static str {
+        "The overlayfs backend needs Linux 5.11+ with unprivileged user namespaces enabled; \
+         set overlay_backend = \"fuse\" to use FUSE instead"
+    }
+
+    /// The overlayfs docs leave changes to the upper layer of a live mount
+    /// undefined.
+    fn allows_upper_changes(&self) -> bool {
+        false
+    }
+
+    fn white_out(&self, parent_dir: &Path, name: &OsStr) -> Result<()> {
+        Whiteout::create_kernel(parent_dir, name).map_err(|errno| {
+            TreebeardError::Overlay(format!(
+                "Failed to white out {}: {}",
+                parent_dir.join(name).display(),
+                io::Error::from_raw_os_error(errno)
+            ))
+        })
+    }
+
+    fn mount(&self, request: &MountRequest) -> Result<MountedOverlay> {
+        check_kernel_version()?;
+
+        if !request.passthrough_patterns.is_empty() {
+            tracing::warn!(
+                "Passthrough paths aren't supported by the overlayfs backend; they will be copied up like any other file"
+            );
+        }
+
+        fs::create_dir_all(request.mount_point).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to create mount directory {}: {}",
+                request.mount_point.display(),
+                e
+            ))
+        })?;
+        let work_dir = work_dir_for(request.upper_layer);
+        fs::create_dir_all(&work_dir).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to create overlayfs work directory {}: {}",
+                work_dir.display(),
+                e
+            ))
+        })?;
+
+        let canonical = |path: &Path| {
+            path.canonicalize().map_err(|e| {
+                TreebeardError::Config(format!("Failed to resolve {}: {}", path.display(), e))
+            })
+        };
+        let upper_layer = canonical(request.upper_layer)?;
+        let lower_layer = canonical(request.lower_layer)?;
+        let target = canonical(request.mount_point)?;
+
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let mutations: MutationTracker = Arc::new(RwLock::new(HashMap::new()));
+        let mut tracker = UpperLayerTracker {
+            upper_layer: upper_layer.clone(),
+            lower_layer: lower_layer.clone(),
+            mounted_at: SystemTime::now(),
+            mutations: Arc::clone(&mutations),
+            journal: None,
+            base_store: None,
+            tx,
+        };
+        if let Some(journal_path) = request.journal_path {
+            tracker = tracker.with_journal(journal_path)?;
+        }
+
+        // Watch before mounting so no write through the overlay goes unseen
+        let watcher = tracker.watch()?;
+
+        let holder = HolderSetup::new(&lower_layer, &upper_layer, &work_dir, &target)?;
+        tracing::info!("Mounting overlayfs at {}", target.display());
+        let pid = holder.spawn().map_err(|e| {
+            TreebeardError::Overlay(format!(
+                "overlayfs mount at {} failed: {}",
+                target.display(),
+                e
+            ))
+        })?;
+
+        let record = HolderRecord { pid, work_dir };
+        if let Err(e) = record.write(request.mount_point) {
+            stop_holder(pid);
+            return Err(e);
+        }
+
+        // Dropping the watcher closes the mutation channel, which tells the
+        // auto-commit watcher the session is over
+        let mount_point = request.mount_point.to_path_buf();
+        std::thread::spawn(move || {
+            while HolderRecord::read(&mount_point).is_some_and(|r| r.pid == pid) {
+                std::thread::sleep(UNMOUNT_POLL_INTERVAL);
+            }
+            drop(watcher);
+            tracing::info!("overlayfs unmounted");
+        });
+
+        Ok((mutations, rx))
+    }
+
+    fn owns_mount(&self, mount_path: &Path) -> bool {
+        record_path(mount_path).exists()
+    }
+
+    /// Stops the holder process, which takes the mount down with its
+    /// namespace once any processes that joined it have exited.
+    fn unmount(&self, mount_path: &Path) -> Result<bool> {
+        validate_mount_path(mount_path)?;
+
+        let Some(record) = HolderRecord::read(mount_path) else {
+            return Ok(false);
+        };
+        if holds_mount(record.pid, mount_path) {
+            stop_holder(record.pid);
+        }
+
+        if let Err(e) = fs::remove_file(record_path(mount_path)) {
+            tracing::warn!(
+                "Failed to remove overlayfs record for {}: {}",
+                mount_path.display(),
+                e
+            );
+        }
+        remove_work_dir(&record.work_dir);
+
+        Ok(true)
+    }
+
+    fn cleanup_stale_mounts(&self) {
+        let Ok(mount_dir) = get_mount_dir() else {
+            return;
+        };
+
+        // Stay on this filesystem so live FUSE mounts aren't walked
+        let stale_mounts: Vec<PathBuf> = walkdir::WalkDir::new(&mount_dir)
+            .same_file_system(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| mount_path_for_record(e.path()))
+            .collect();
+
+        if stale_mounts.is_empty() {
+            tracing::debug!("No stale overlayfs mounts found");
+            return;
+        }
+
+        tracing::info!("Found {} stale overlayfs mount(s)", stale_mounts.len());
+
+        for mount_path in &stale_mounts {
+            tracing::info!(
+                "Attempting to unmount stale mount: {}",
+                mount_path.display()
+            );
+
+            let result = perform_fuse_cleanup(mount_path);
+            if result.unmount_succeeded {
+                tracing::info!("Successfully unmounted: {}", mount_path.display());
+            } else {
+                tracing::warn!("Failed to unmount stale mount: {}", mount_path.display());
+            }
+        }
+    }
+}
+
+/// Handles on the namespaces that hold an overlayfs mount, for joining them
+/// between `fork` and `exec`.
+#[derive(Debug)]
+pub struct MountNamespace {
+    user_ns: OwnedFd,
+    mount_ns: OwnedFd,
+    working_dir: CString,
+}
+
+impl MountNamespace {
+    /// Opens the namespaces of the overlayfs mount at `mount_path`. Returns
+    /// `None` if the mount wasn't made by the overlayfs backend.
+    ///
+    /// `working_dir` is where the process starts once it has joined, since
+    /// entering a mount namespace resets the working directory to its root.
+    pub fn for_mount(mount_path: &Path, working_dir: &Path) -> Result<Option<Self>> {
+        let Some(record) = HolderRecord::read(mount_path) else {
+            return Ok(None);
+        };
+        if !holds_mount(record.pid, mount_path) {
+            return Err(TreebeardError::Overlay(format!(
+                "The overlayfs mount at {} is gone. Run: treebeard cleanup --stale",
+                mount_path.display()
+            )));
+        }
+
+        let open = |ns: &str| {
+            let path = format!("/proc/{}/ns/{}", record.pid, ns);
+            File::open(&path)
+                .map(OwnedFd::from)
+                .map_err(|e| TreebeardError::Overlay(format!("Failed to open {}: {}", path, e)))
+        };
+        let working_dir = CString::new(working_dir.as_os_str().as_bytes()).map_err(|_| {
+            TreebeardError::Config(format!(
+                "Working directory contains a NUL byte: {}",
+                working_dir.display()
+            ))
+        })?;
+
+        Ok(Some(Self {
+            user_ns: open("user")?,
+            mount_ns: open("mnt")?,
+            working_dir,
+        }))
+    }
+
+    /// Joins the namespaces and changes to the working directory.
+    ///
+    /// Intended to be called from `pre_exec`: it only makes raw syscalls on
+    /// data prepared by [`MountNamespace::for_mount`] and is
+    /// async-signal-safe.
+    pub fn enter(&self) -> io::Result<()> {
+        // SAFETY: setns and chdir take an open fd and a NUL-terminated path.
+        unsafe {
+            if libc::setns(self.user_ns.as_raw_fd(), libc::CLONE_NEWUSER) != 0
+                || libc::setns(self.mount_ns.as_raw_fd(), libc::CLONE_NEWNS) != 0
+                || libc::chdir(self.working_dir.as_ptr()) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What the holder record next to a mount directory stores.
+#[derive(Debug, PartialEq)]
+struct HolderRecord {
+    pid: libc::pid_t,
+    work_dir: PathBuf,
+}
+
+impl HolderRecord {
+    fn read(mount_path: &Path) -> Option<Self> {
+        let contents = fs::read(record_path(mount_path)).ok()?;
+        Self::parse(&contents)
+    }
+
+    fn parse(contents: &[u8]) -> Option<Self> {
+        let mut lines = contents.splitn(2, |b| *b == b'\n');
+        let pid = std::str::from_utf8(lines.next()?).ok()?.parse().ok()?;
+        let work_dir = lines.next()?.strip_suffix(b"\n")?;
+        if work_dir.is_empty() {
+            return None;
+        }
+        Some(Self {
+            pid,
+            work_dir: PathBuf::from(std::ffi::OsStr::from_bytes(work_dir)),
+        })
+    }
+
+    fn write(&self, mount_path: &Path) -> Result<()> {
+        let mut contents = format!("{}\n", self.pid).into_bytes();
+        contents.extend_from_slice(self.work_dir.as_os_str().as_bytes());
+        contents.push(b'\n');
+
+        let path = record_path(mount_path);
+        fs::write(&path, contents).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to write overlayfs record {}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+}
+
+/// `<parent>/.<name>.overlayfs` for a mount at `<parent>/<name>`.
+fn record_path(mount_path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(mount_path.file_name().unwrap_or_default());
+    name.push(RECORD_SUFFIX);
+    mount_path.with_file_name(name)
+}
+
+/// The mount directory a holder record belongs to, if `record` is one.
+fn mount_path_for_record(record: &Path) -> Option<PathBuf> {
+    let name = record.file_name()?.as_bytes();
+    let mount_name = name
+        .strip_prefix(b".")?
+        .strip_suffix(RECORD_SUFFIX.as_bytes())?;
+    if mount_name.is_empty() {
+        return None;
+    }
+    Some(record.with_file_name(std::ffi::OsStr::from_bytes(mount_name)))
+}
+
+/// overlayfs needs an empty scratch directory on the upper layer's
+/// filesystem, so it's kept next to the worktree.
+fn work_dir_for(upper_layer: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(upper_layer.file_name().unwrap_or_default());
+    name.push(WORK_DIR_SUFFIX);
+    upper_layer.with_file_name(name)
+}
+
+fn remove_work_dir(work_dir: &Path) {
+    // overlayfs leaves its scratch directory without any permissions
+    let _ = fs::set_permissions(work_dir.join("work"), fs::Permissions::from_mode(0o700));
+    if let Err(e) = fs::remove_dir_all(work_dir) {
+        if e.kind() != io::ErrorKind::NotFound {
+            tracing::debug!(
+                "Failed to remove overlayfs work directory {}: {}",
+                work_dir.display(),
+                e
+            );
+        }
+    }
+}
+
+fn check_kernel_version() -> Result<()> {
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+    match parse_kernel_version(release.trim()) {
+        Some(version) if version >= MIN_KERNEL_VERSION => Ok(()),
+        _ => Err(TreebeardError::Overlay(format!(
+            "The overlayfs backend needs Linux {}.{} or later (running {})",
+            MIN_KERNEL_VERSION.0,
+            MIN_KERNEL_VERSION.1,
+            release.trim()
+        ))),
+    }
+}
+
+/// Parses the major and minor version out of a kernel release such as
+/// "6.8.0-45-generic".
+fn parse_kernel_version(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// Builds the overlayfs mount options. Commas, colons and backslashes in
+/// layer paths are escaped with a backslash, as overlayfs expects.
+fn mount_options(lower_layer: &Path, upper_layer: &Path, work_dir: &Path) -> Vec<u8> {
+    let mut options = Vec::new();
+    for (key, path) in [
+        ("lowerdir", lower_layer),
+        ("upperdir", upper_layer),
+        ("workdir", work_dir),
+    ] {
+        options.extend_from_slice(key.as_bytes());
+        options.push(b'=');
+        for &b in path.as_os_str().as_bytes() {
+            if matches!(b, b',' | b':' | b'\\') {
+                options.push(b'\\');
+            }
+            options.push(b);
+        }
+        options.push(b',');
+    }
+    // Keep overlayfs metadata in user.* xattrs, which an unprivileged
+    // mounter may write
+    options.extend_from_slice(b"userxattr");
+    options
+}
+
+/// Whether `pid` still holds an overlay mounted at `mount_path`. Checking its
+/// mount table, rather than just that it's alive, guards against pid reuse.
+fn holds_mount(pid: libc::pid_t, mount_path: &Path) -> bool {
+    let mount_path = mount_path
+        .canonicalize()
+        .unwrap_or_else(|_| mount_path.to_path_buf());
+    fs::read_to_string(format!("/proc/{}/mountinfo", pid))
+        .is_ok_and(|info| mountinfo_has_overlay(&info, &mount_path))
+}
+
+fn mountinfo_has_overlay(mountinfo: &str, mount_path: &Path) -> bool {
+    let target = escape_mountinfo_path(mount_path);
+    mountinfo.lines().any(|line| {
+        let Some((mount, filesystem)) = line.split_once(" - ") else {
+            return false;
+        };
+        mount.split(' ').nth(4) == Some(target.as_str())
+            && filesystem.split(' ').next() == Some("overlay")
+    })
+}
+
+/// Escapes a path the way the kernel does in `/proc/<pid>/mountinfo`.
+fn escape_mountinfo_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' => escaped.push_str("\\040"),
+            '\t' => escaped.push_str("\\011"),
+            '\n' => escaped.push_str("\\012"),
+            '\\' => escaped.push_str("\\134"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn stop_holder(pid: libc::pid_t) {
+    // SAFETY: kill and waitpid take only integer arguments. waitpid reaps the
+    // holder if this process forked it and fails with ECHILD otherwise.
+    unsafe {
+        libc::kill(pid, libc::SIGKILL);
+        libc::waitpid(pid, std::ptr::null_mut(), 0);
+    }
+}
+
+/// Everything the holder needs after `fork`, prepared in the parent so the
+/// child never allocates.
+struct HolderSetup {
+    maps: UserNamespaceMaps,
+    root: CString,
+    overlay: CString,
+    target: CString,
+    options: CString,
+}
+
+impl HolderSetup {
+    fn new(lower_layer: &Path, upper_layer: &Path, work_dir: &Path, target: &Path) -> Result<Self> {
+        let c_string = |bytes: Vec<u8>| {
+            CString::new(bytes).map_err(|_| {
+                TreebeardError::Config("overlayfs layer paths can't contain NUL bytes".to_string())
+            })
+        };
+
+        Ok(Self {
+            maps: UserNamespaceMaps::for_current_user(),
+            root: c_string(b"/".to_vec())?,
+            overlay: c_string(b"overlay".to_vec())?,
+            target: c_string(target.as_os_str().as_bytes().to_vec())?,
+            options: c_string(mount_options(lower_layer, upper_layer, work_dir))?,
+        })
+    }
+
+    /// Forks the holder process and waits until it has mounted the overlay.
+    fn spawn(&self) -> io::Result<libc::pid_t> {
+        let mut fds = [0; 2];
+        // SAFETY: pipe2 fills in two new fds, which are owned from here on.
+        let (status_rx, status_tx) = unsafe {
+            if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+        };
+        // SAFETY: getpid takes no arguments.
+        let parent = unsafe { libc::getpid() };
+
+        // SAFETY: the child only makes async-signal-safe syscalls on data
+        // prepared before the fork, and never returns.
+        match unsafe { libc::fork() } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => {
+                let errno = match self.mount_in_new_namespace(parent) {
+                    Ok(()) => 0,
+                    Err(e) => e.raw_os_error().unwrap_or(libc::EIO),
+                };
+                // SAFETY: writes a local integer to the status pipe.
+                unsafe {
+                    libc::write(
+                        status_tx.as_raw_fd(),
+                        (&errno as *const libc::c_int).cast(),
+                        std::mem::size_of::<libc::c_int>(),
+                    );
+                }
+                if errno != 0 {
+                    // SAFETY: _exit ends the forked child without unwinding.
+                    unsafe { libc::_exit(1) };
+                }
+                hold(parent)
+            }
+            pid => {
+                drop(status_tx);
+                let mut status = [0u8; std::mem::size_of::<libc::c_int>()];
+                let result = File::from(status_rx).read_exact(&mut status);
+                match (result, libc::c_int::from_ne_bytes(status)) {
+                    (Ok(()), 0) => Ok(pid),
+                    (Ok(()), errno) => {
+                        stop_holder(pid);
+                        Err(io::Error::from_raw_os_error(errno))
+                    }
+                    (Err(_), _) => {
+                        stop_holder(pid);
+                        Err(io::Error::other("holder process exited before mounting"))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs in the forked child: detaches from treebeard's process group,
+    /// then mounts the overlay in a new user and mount namespace.
+    fn mount_in_new_namespace(&self, parent: libc::pid_t) -> io::Result<()> {
+        // SAFETY: setsid and getppid take no arguments.
+        unsafe {
+            // Keep Ctrl+C in treebeard's terminal from reaching the holder
+            libc::setsid();
+            if libc::getppid() != parent {
+                return Err(io::Error::from_raw_os_error(libc::ESRCH));
+            }
+        }
+
+        self.maps.unshare(libc::CLONE_NEWNS)?;
+
+        // SAFETY: all strings are NUL-terminated and outlive the calls.
+        unsafe {
+            if libc::mount(
+                std::ptr::null(),
+                self.root.as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            ) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::mount(
+                self.overlay.as_ptr(),
+                self.target.as_ptr(),
+                self.overlay.as_ptr(),
+                0,
+                self.options.as_ptr().cast(),
+            ) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The holder's main loop: keep the namespace alive until treebeard exits.
+///
+/// The parent is polled rather than watched with `PR_SET_PDEATHSIG`, which
+/// fires when the forking *thread* exits and would take the mount down with
+/// whichever runtime thread happened to mount it.
+fn hold(parent: libc::pid_t) -> ! {
+    // SAFETY: close_range, sleep, getppid and _exit take only integers.
+    unsafe {
+        // Don't keep treebeard's pipes and sockets open
+        libc::syscall(libc::SYS_close_range, 0, libc::c_uint::MAX, 0);
+        while libc::getppid() == parent {
+            libc::sleep(1);
+        }
+        libc::_exit(0)
+    }
+}
+
+/// Turns inotify events from the upper layer into mutations.
+struct UpperLayerTracker {
+    upper_layer: PathBuf,
+    lower_layer: PathBuf,
+    /// Files created before this are part of the worktree checkout, and
+    /// writes to them aren't mutations, just as with FUSE
+    mounted_at: SystemTime,
+    mutations: MutationTracker,
+    journal: Option<MutationJournal>,
+    base_store: Option<BaseStore>,
+    tx: UnboundedSender<PathBuf>,
+}
+
+impl UpperLayerTracker {
+    /// Persists mutations to the journal at `journal_path`, after loading
+    /// any it already holds. See `OverlayFs::with_journal`.
+    fn with_journal(mut self, journal_path: &Path) -> Result<Self> {
+        let recovered = MutationJournal::load(journal_path)?;
+        if !recovered.is_empty() {
+            tracing::info!(
+                "Recovered {} mutation(s) from journal {}",
+                recovered.len(),
+                journal_path.display()
+            );
+        }
+        self.mutations.write().extend(recovered);
+        self.journal = Some(MutationJournal::open(journal_path)?);
+        self.base_store = Some(BaseStore::open(&BaseStore::dir_for_journal(journal_path))?);
+        Ok(self)
+    }
+
+    /// Starts watching the upper layer. Events stop, and the mutation channel
+    /// closes, when the returned watcher is dropped.
+    fn watch(self) -> Result<RecommendedWatcher> {
+        let upper_layer = self.upper_layer.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => self.handle_event(event),
+                Err(e) => tracing::warn!("overlayfs mutation watcher error: {}", e),
+            })
+            .map_err(|e| {
+                TreebeardError::Overlay(format!("Failed to start mutation watcher: {}", e))
+            })?;
+        watcher
+            .watch(&upper_layer, RecursiveMode::Recursive)
+            .map_err(|e| {
+                TreebeardError::Overlay(format!("Failed to watch {}: {}", upper_layer.display(), e))
+            })?;
+        Ok(watcher)
+    }
+
+    fn handle_event(&self, event: notify::Event) {
+        let scan_dirs = match event.kind {
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => false,
+            EventKind::Access(_) => return,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)) => true,
+            _ => false,
+        };
+        for path in &event.paths {
+            self.observe(path, scan_dirs);
+        }
+    }
+
+    /// Records the current state of `path`, and when `scan_dirs` is set,
+    /// of everything under it if it's a directory.
+    fn observe(&self, path: &Path, scan_dirs: bool) {
+        let Ok(relative) = path.strip_prefix(&self.upper_layer) else {
+            return;
+        };
+        if relative.as_os_str().is_empty() || relative.starts_with(".git") {
+            return;
+        }
+
+        if path.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            if !scan_dirs {
+                return;
+            }
+            // notify only adds a watch for a new directory once it sees it,
+            // so anything created in it before then is found by scanning
+            for entry in walkdir::WalkDir::new(path)
+                .min_depth(1)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| !e.file_type().is_dir())
+            {
+                if let Ok(relative) = entry.path().strip_prefix(&self.upper_layer) {
+                    self.classify(entry.path(), relative);
+                }
+            }
+            return;
+        }
+
+        self.classify(path, relative);
+    }
+
+    fn classify(&self, path: &Path, relative: &Path) {
+        let lower_path = self.lower_layer.join(relative);
+        let lower = lower_path.symlink_metadata();
+        let in_lower = lower.is_ok();
+        let relative = Path::new(".").join(relative);
+
+        match path.symlink_metadata() {
+            Ok(_) if Whiteout::is_kernel_whiteout(path) => {
+                self.record_mutation(&relative, MutationType::Deleted)
+            }
+            Ok(metadata) => {
+                let previous = self.mutations.read().get(&relative).cloned();
+                let created_since_mount = metadata
+                    .created()
+                    .map_or(true, |created| created >= self.mounted_at);
+                match previous {
+                    Some(MutationType::Created | MutationType::CopiedUp) => {}
+                    _ if !created_since_mount => {}
+                    _ if in_lower => {
+                        // The lower file is untouched by the copy-up, so it's
+                        // still the base
+                        if let (Some(base_store), Ok(lower)) = (&self.base_store, &lower) {
+                            if let Err(e) = base_store.record(&relative, lower, &lower_path) {
+                                tracing::warn!("{}", e);
+                            }
+                        }
+                        self.record_mutation(&relative, MutationType::CopiedUp);
+                    }
+                    _ => self.record_mutation(&relative, MutationType::Created),
+                }
+            }
+            // overlayfs leaves a whiteout in its place if the lower layer has it
+            Err(_) if in_lower => self.record_mutation(&relative, MutationType::Deleted),
+            Err(_) => self.forget_mutation(&relative),
+        }
+
+        if let Err(e) = self.tx.send(relative.clone()) {
+            tracing::debug!("Failed to signal mutation for {:?}: {}", relative, e);
+        }
+    }
+
+    fn record_mutation(&self, relative_path: &Path, mutation: MutationType) {
+        if let Some(ref journal) = self.journal {
+            if let Err(e) = journal.append(relative_path, &mutation) {
+                tracing::warn!("{}", e);
+            }
+        }
+        self.mutations
+            .write()
+            .insert(relative_path.to_path_buf(), mutation);
+    }
+
+    fn forget_mutation(&self, relative_path: &Path) {
+        if self.mutations.write().remove(relative_path).is_none() {
+            return;
+        }
+        if let Some(ref journal) = self.journal {
+            if let Err(e) = journal.forget(relative_path) {
+                tracing::warn!("{}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kernel_version() {
+        assert_eq!(parse_kernel_version("6.8.0-45-generic"), Some((6, 8)));
+        assert_eq!(parse_kernel_version("5.11.0"), Some((5, 11)));
+        assert_eq!(parse_kernel_version("4.19.112+"), Some((4, 19)));
+        assert_eq!(parse_kernel_version("garbage"), None);
+        assert!(parse_kernel_version("5.10.0").unwrap() < MIN_KERNEL_VERSION);
+    }
+
+    #[test]
+    fn test_mount_options_escape_separators() {
+        let options = mount_options(
+            Path::new("/repos/a,b"),
+            Path::new("/work/x:y"),
+            Path::new("/work/.x.overlayfs-work"),
+        );
+        assert_eq!(
+            String::from_utf8(options).unwrap(),
+            "lowerdir=/repos/a\\,b,upperdir=/work/x\\:y,workdir=/work/.x.overlayfs-work,userxattr"
+        );
+    }
+
+    #[test]
+    fn test_record_paths() {
+        let mount_path = Path::new("/mounts/repo/feature/login");
+        let record = record_path(mount_path);
+        assert_eq!(record, Path::new("/mounts/repo/feature/.login.overlayfs"));
+        assert_eq!(mount_path_for_record(&record).as_deref(), Some(mount_path));
+        assert_eq!(mount_path_for_record(Path::new("/mounts/repo/main")), None);
+        assert_eq!(
+            mount_path_for_record(Path::new("/mounts/repo/.overlayfs")),
+            None
+        );
+
+        assert_eq!(
+            work_dir_for(Path::new("/worktrees/repo/login")),
+            Path::new("/worktrees/repo/.login.overlayfs-work")
+        );
+    }
+
+    #[test]
+    fn test_holder_record_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_path = dir.path().join("main");
+        let record = HolderRecord {
+            pid: 4242,
+            work_dir: dir.path().join("work dir"),
+        };
+
+        record.write(&mount_path).unwrap();
+        assert_eq!(HolderRecord::read(&mount_path), Some(record));
+
+        assert_eq!(HolderRecord::parse(b"not a pid\n/work\n"), None);
+        assert_eq!(HolderRecord::parse(b"12\n"), None);
+    }
+
+    #[test]
+    fn test_upper_changes_refused_while_mounted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_path = dir.path().join("main");
+        assert!(crate::overlay::check_upper_changes_allowed(&mount_path, "rollback").is_ok());
+
+        HolderRecord {
+            pid: 4242,
+            work_dir: dir.path().join("work"),
+        }
+        .write(&mount_path)
+        .unwrap();
+        let err = crate::overlay::check_upper_changes_allowed(&mount_path, "rollback")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("rollback") && err.contains("overlayfs"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_mountinfo_has_overlay() {
+        let mountinfo = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+93 22 0:48 / /home/me/mounts/repo/main rw,relatime - overlay overlay rw,lowerdir=/repo
+94 22 0:49 / /home/me/mounts/repo/my\\040branch rw - overlay overlay rw
+95 22 0:50 / /home/me/mounts/repo/fuse rw,nosuid - fuse treebeard rw
+";
+        assert!(mountinfo_has_overlay(
+            mountinfo,
+            Path::new("/home/me/mounts/repo/main")
+        ));
+        assert!(mountinfo_has_overlay(
+            mountinfo,
+            Path::new("/home/me/mounts/repo/my branch")
+        ));
+        assert!(!mountinfo_has_overlay(
+            mountinfo,
+            Path::new("/home/me/mounts/repo/fuse")
+        ));
+        assert!(!mountinfo_has_overlay(
+            mountinfo,
+            Path::new("/home/me/mounts/repo")
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, TreebeardError};
+
+/// Files up to this size keep a copy of their base content so conflicts can
+/// be shown as diffs and merged. Larger files only record their size and
+/// modification time, so copying them up never reads them.
+pub const MAX_BASE_SNAPSHOT_SIZE: u64 = 100 * 1024;
+
+/// Returns the git blob hash of the file at `path`.
+///
+/// Blob hashes match `git hash-object`, which makes the recorded bases easy
+/// to inspect by hand.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let oid = gix::objs::compute_stream_hash(
+        gix::hash::Kind::Sha1,
+        gix::objs::Kind::Blob,
+        &mut file,
+        len,
+        &mut gix::features::progress::Discard,
+        &AtomicBool::new(false),
+    )?;
+    Ok(oid.to_string())
+}
+
+/// Size and modification time of a lower file, standing in for its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct BaseStat {
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+}
+
+impl BaseStat {
+    fn from_metadata(metadata: &fs::Metadata) -> Self {
+        Self {
+            size: metadata.len(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+        }
+    }
+}
+
+/// What a session's copy of a file started from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BaseRecord {
+    /// The git blob hash of the content
+    Hash(String),
+    /// The lower file's stat, for files too large to hash on copy-up
+    Stat(BaseStat),
+}
+
+/// A single base record, stored as one JSON object per line in `index.jsonl`.
+/// Exactly one of `hash` and `stat` is set.
+#[derive(Debug, Serialize, Deserialize)]
+struct BaseEntry {
+    path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stat: Option<BaseStat>,
+}
+
+/// Records the content of lower-layer files as they were when first copied
+/// up into a session.
+///
+/// At sync time the record is compared with the main repository's current
+/// file. If they differ, the file was changed outside the session (for
+/// example by a parallel session that finished first) and syncing would
+/// overwrite that change. Small files are recorded by hash and keep their
+/// content under `objects/<hash>` so the conflict can be shown as a
+/// three-way diff. Large files are recorded by the lower file's stat, as git
+/// does for its index, so a copy-up stays as cheap as the reflink it makes.
+pub struct BaseStore {
+    dir: PathBuf,
+    index: Mutex<File>,
+}
+
+impl BaseStore {
+    /// Returns the base store directory that belongs to the journal at
+    /// `journal_path`.
+    pub fn dir_for_journal(journal_path: &Path) -> PathBuf {
+        journal_path.with_extension("base")
+    }
+
+    /// Opens (or creates) the base store in `dir`.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir.join("objects")).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to create base store {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+
+        let index_path = dir.join("index.jsonl");
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&index_path)
+            .map_err(|e| {
+                TreebeardError::Config(format!(
+                    "Failed to open base index {}: {}",
+                    index_path.display(),
+                    e
+                ))
+            })?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Records the base of `relative_path`: `copy`, a fresh copy of the
+    /// lower file, whose metadata `lower` was read before the copy was made.
+    ///
+    /// Small files are hashed from the copy rather than the lower file so the
+    /// record matches exactly what the session started from, even if the
+    /// lower file changes while the copy is made. A large file changed during
+    /// the copy no longer matches `lower`, and shows up as a conflict.
+    pub fn record(&self, relative_path: &Path, lower: &fs::Metadata, copy: &Path) -> Result<()> {
+        let (hash, stat) = if lower.len() > MAX_BASE_SNAPSHOT_SIZE {
+            (None, Some(BaseStat::from_metadata(lower)))
+        } else {
+            (Some(self.snapshot(relative_path, copy)?), None)
+        };
+
+        let entry = BaseEntry {
+            path: relative_path.to_path_buf(),
+            hash,
+            stat,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        self.index.lock().write_all(&line).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to append to base index in {}: {}",
+                self.dir.display(),
+                e
+            ))
+        })
+    }
+
+    /// Hashes `copy` and keeps its content under `objects/<hash>`.
+    fn snapshot(&self, relative_path: &Path, copy: &Path) -> Result<String> {
+        let hash = hash_file(copy).map_err(|e| {
+            TreebeardError::Config(format!("Failed to hash {}: {}", copy.display(), e))
+        })?;
+
+        let object = self.dir.join("objects").join(&hash);
+        if !object.exists() {
+            fs::copy(copy, &object).map_err(|e| {
+                TreebeardError::Config(format!(
+                    "Failed to save base of {}: {}",
+                    relative_path.display(),
+                    e
+                ))
+            })?;
+        }
+        Ok(hash)
+    }
+
+    /// Deletes the base store in `dir`. A missing store is not an error.
+    pub fn remove(dir: &Path) -> Result<()> {
+        match fs::remove_dir_all(dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(TreebeardError::Config(format!(
+                "Failed to remove base store {}: {}",
+                dir.display(),
+                e
+            ))),
+        }
+    }
+}
+
+/// Read-only view of a [`BaseStore`], loaded at sync time.
+#[derive(Debug, Default)]
+pub struct BaseSnapshots {
+    dir: PathBuf,
+    records: HashMap<PathBuf, BaseRecord>,
+}
+
+impl BaseSnapshots {
+    /// Loads the base records in `dir`. Returns an empty set if the store
+    /// does not exist. As with the mutation journal, later records for a
+    /// path win and unparseable lines are skipped.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut records = HashMap::new();
+
+        let index_path = dir.join("index.jsonl");
+        let file = match File::open(&index_path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    dir: dir.to_path_buf(),
+                    records,
+                })
+            }
+            Err(e) => {
+                return Err(TreebeardError::Config(format!(
+                    "Failed to read base index {}: {}",
+                    index_path.display(),
+                    e
+                )))
+            }
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(TreebeardError::Io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<BaseEntry>(&line) {
+                Ok(BaseEntry {
+                    path,
+                    hash: Some(hash),
+                    ..
+                }) => {
+                    records.insert(path, BaseRecord::Hash(hash));
+                }
+                Ok(BaseEntry {
+                    path,
+                    stat: Some(stat),
+                    ..
+                }) => {
+                    records.insert(path, BaseRecord::Stat(stat));
+                }
+                Ok(entry) => {
+                    tracing::warn!("Skipping base index entry without a record: {:?}", entry);
+                }
+                Err(e) => {
+                    tracing::warn!("Skipping corrupt base index entry: {}", e);
+                }
+            }
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            records,
+        })
+    }
+
+    /// Returns the recorded base hash of `relative_path`, if it was copied up
+    /// and small enough to be hashed.
+    pub fn hash(&self, relative_path: &Path) -> Option<&str> {
+        match self.records.get(relative_path)? {
+            BaseRecord::Hash(hash) => Some(hash),
+            BaseRecord::Stat(_) => None,
+        }
+    }
+
+    /// Returns whether `current` still matches the recorded base of
+    /// `relative_path`, or `None` if it was never copied up. A missing or
+    /// unreadable file doesn't match.
+    pub fn matches_base(&self, relative_path: &Path, current: &Path) -> Option<bool> {
+        Some(match self.records.get(relative_path)? {
+            BaseRecord::Hash(hash) => hash_file(current).is_ok_and(|h| &h == hash),
+            BaseRecord::Stat(stat) => {
+                fs::metadata(current).is_ok_and(|m| BaseStat::from_metadata(&m) == *stat)
+            }
+        })
+    }
+
+    /// Returns the base content of `relative_path`, if a snapshot was kept.
+    pub fn content(&self, relative_path: &Path) -> Option<Vec<u8>> {
+        let hash = self.hash(relative_path)?;
+        fs::read(self.dir.join("objects").join(hash)).ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_file_matches_git_blob_hash() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("file");
+        fs::write(&path, "hello\n").unwrap();
+
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "ce013625030ba8dba906f756967f9e9ca394464a"
+        );
+    }
+
+    #[test]
+    fn test_base_store_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = BaseStore::dir_for_journal(&temp.path().join("branch.jsonl"));
+        let small = temp.path().join("small");
+        let large = temp.path().join("large");
+        fs::write(&small, "KEY=1\n").unwrap();
+        fs::write(&large, vec![b'x'; MAX_BASE_SNAPSHOT_SIZE as usize + 1]).unwrap();
+
+        let store = BaseStore::open(&dir).unwrap();
+        let record = |path: &str, copy: &Path| {
+            let lower = fs::metadata(copy).unwrap();
+            store.record(Path::new(path), &lower, copy).unwrap();
+        };
+        record(".env", &small);
+        record("cache.bin", &large);
+
+        let bases = BaseSnapshots::load(&dir).unwrap();
+        assert_eq!(
+            bases.hash(Path::new(".env")),
+            Some(hash_file(&small).unwrap().as_str())
+        );
+        assert_eq!(bases.content(Path::new(".env")), Some(b"KEY=1\n".to_vec()));
+        assert!(bases.hash(Path::new("cache.bin")).is_none());
+        assert!(bases.content(Path::new("cache.bin")).is_none());
+        assert!(bases.hash(Path::new("other")).is_none());
+
+        // Large files are compared by stat, without reading them
+        assert_eq!(
+            bases.matches_base(Path::new("cache.bin"), &large),
+            Some(true)
+        );
+        assert_eq!(bases.matches_base(Path::new(".env"), &small), Some(true));
+        assert_eq!(bases.matches_base(Path::new("other"), &small), None);
+        fs::write(&large, vec![b'y'; MAX_BASE_SNAPSHOT_SIZE as usize + 2]).unwrap();
+        fs::write(&small, "KEY=2\n").unwrap();
+        assert_eq!(
+            bases.matches_base(Path::new("cache.bin"), &large),
+            Some(false)
+        );
+        assert_eq!(bases.matches_base(Path::new(".env"), &small), Some(false));
+
+        BaseStore::remove(&dir).unwrap();
+        assert!(BaseSnapshots::load(&dir).unwrap().is_empty());
+        assert!(BaseStore::remove(&dir).is_ok());
+    }
+}
@@ -0,0 +1,601 @@
+//! Multi-threaded dispatch of FUSE requests to the overlay.
+//!
+//! fuser reads requests from the kernel on a single session thread and hands
+//! each one to the `Filesystem` implementation. `TreebeardFs` moves the request
+//! and its reply onto a pool of worker threads, so parallel builds reading
+//! through the mount aren't serialized behind one another's disk I/O and
+//! copy-ups. The kernel still orders requests that depend on each other, such
+//! as `release` after the reads on that handle, by waiting for their replies.
+
+use std::ffi::OsStr;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Instant, SystemTime};
+
+use fuser::{
+    Filesystem, KernelConfig, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyLock, ReplyLseek, ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr, Request,
+    TimeOrNow,
+};
+use parking_lot::Mutex;
+
+use crate::overlay::locks::{LockRange, LockWaiter};
+use crate::overlay::stats::StatsSource;
+use crate::overlay::OverlayFs;
+
+/// Upper bound on the default number of worker threads. Requests mostly wait
+/// on disk I/O, but more threads than this only add contention on the inode
+/// table.
+const MAX_DEFAULT_WORKER_THREADS: usize = 16;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed set of threads running jobs from a shared queue.
+struct WorkerPool {
+    sender: Option<mpsc::Sender<Job>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    fn new(size: usize) -> std::io::Result<Self> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let threads = (0..size)
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("treebeard-fuse-{}", i))
+                    .spawn(move || loop {
+                        // The lock is only held while waiting for the next job
+                        let job = receiver.lock().recv();
+                        let Ok(job) = job else {
+                            break;
+                        };
+                        // A panicking handler drops its reply, which answers the
+                        // request with EIO. Keep the worker for the next one.
+                        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                            tracing::error!("FUSE request handler panicked");
+                        }
+                    })
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(WorkerPool {
+            sender: Some(sender),
+            threads,
+        })
+    }
+
+    fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        if let Some(ref sender) = self.sender {
+            if sender.send(Box::new(job)).is_err() {
+                tracing::error!("FUSE worker threads have exited, dropping request");
+            }
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the queue lets each worker finish its current job and exit
+        self.sender.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The FUSE filesystem for treebeard's overlay.
+///
+/// Requests are handled by an [`OverlayFs`] shared by a pool of worker
+/// threads, which start when the kernel initializes the session.
+pub struct TreebeardFs {
+    overlay: Arc<OverlayFs>,
+    worker_threads: usize,
+    workers: Option<WorkerPool>,
+    /// Waits for blocking lock requests, started by the first one
+    lock_waiter: Option<LockWaiter>,
+    /// Requests received from the kernel, reported by `treebeard stats`
+    requests: Arc<AtomicU64>,
+}
+
+impl TreebeardFs {
+    /// Creates the filesystem without a journal, for tests that mount it
+    /// directly. `mount_fuse` builds the [`OverlayFs`] itself.
+    #[allow(dead_code)]
+    pub fn new(
+        upper_layer: PathBuf,
+        lower_layer: PathBuf,
+        mutation_tx: Option<tokio::sync::mpsc::UnboundedSender<PathBuf>>,
+        ttl_secs: u64,
+        passthrough_patterns: Vec<String>,
+    ) -> crate::error::Result<Self> {
+        let overlay = OverlayFs::new(
+            upper_layer,
+            lower_layer,
+            mutation_tx,
+            ttl_secs,
+            passthrough_patterns,
+        )?;
+        Ok(overlay.into())
+    }
+
+    /// Sets the number of threads handling requests. Defaults to the number
+    /// of CPUs, up to 16. With one thread, requests are handled in order.
+    #[allow(dead_code)]
+    pub fn with_worker_threads(mut self, worker_threads: usize) -> Self {
+        self.worker_threads = worker_threads.max(1);
+        self
+    }
+
+    /// The counters `treebeard stats` reports for this filesystem.
+    pub(crate) fn stats_source(&self) -> StatsSource {
+        StatsSource {
+            overlay: Arc::clone(&self.overlay),
+            requests: Arc::clone(&self.requests),
+            mounted_at: Instant::now(),
+        }
+    }
+
+    /// Runs `handler` on a worker thread, or inline if the pool isn't running.
+    fn dispatch(&self, handler: impl FnOnce(&OverlayFs) + Send + 'static) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let overlay = Arc::clone(&self.overlay);
+        match self.workers {
+            Some(ref workers) => workers.execute(move || handler(&overlay)),
+            None => handler(&overlay),
+        }
+    }
+}
+
+impl From<OverlayFs> for TreebeardFs {
+    fn from(overlay: OverlayFs) -> Self {
+        let worker_threads = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(MAX_DEFAULT_WORKER_THREADS);
+        TreebeardFs {
+            overlay: Arc::new(overlay),
+            worker_threads,
+            workers: None,
+            lock_waiter: None,
+            requests: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl Filesystem for TreebeardFs {
+    fn init(
+        &mut self,
+        _req: &Request,
+        #[allow(unused_variables)] config: &mut KernelConfig,
+    ) -> std::result::Result<(), libc::c_int> {
+        // Have the kernel send fcntl and flock locks to `setlk` rather than
+        // keeping them local to the mount. See `locks` for why not on macOS.
+        #[cfg(target_os = "linux")]
+        if let Err(unsupported) = config
+            .add_capabilities(fuser::consts::FUSE_POSIX_LOCKS | fuser::consts::FUSE_FLOCK_LOCKS)
+        {
+            tracing::warn!(
+                "Kernel can't forward file locks (capabilities {:#x}), keeping them local to the mount",
+                unsupported
+            );
+        }
+
+        match WorkerPool::new(self.worker_threads) {
+            Ok(workers) => self.workers = Some(workers),
+            Err(e) => tracing::warn!(
+                "Failed to start FUSE worker threads, handling requests on one thread: {}",
+                e
+            ),
+        }
+        tracing::info!(
+            "Treebeard FUSE filesystem initialized with {} worker thread(s)",
+            self.workers.as_ref().map_or(1, |w| w.threads.len())
+        );
+        Ok(())
+    }
+
+    fn destroy(&mut self) {
+        // Wait for requests already handed to the workers
+        self.workers.take();
+        // Drops the lock requests still waiting
+        self.lock_waiter.take();
+        let stats = self.overlay.inode_manager.cache_stats();
+        tracing::info!(
+            "Inode cache: {} hits, {} misses, {} evictions, {} pinned",
+            stats.hits,
+            stats.misses,
+            stats.evictions,
+            stats.pinned
+        );
+        tracing::info!("Treebeard FUSE filesystem destroyed");
+    }
+
+    fn forget(&mut self, _req: &Request, ino: u64, nlookup: u64) {
+        self.dispatch(move |fs| fs.forget(ino, nlookup));
+    }
+
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.lookup(parent, &name, reply));
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        self.dispatch(move |fs| fs.getattr(ino, reply));
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        self.dispatch(move |fs| fs.setattr(ino, size, reply));
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        self.dispatch(move |fs| fs.open(ino, flags, reply));
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        self.dispatch(move |fs| fs.read(ino, fh, offset, size, reply));
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let data = data.to_vec();
+        self.dispatch(move |fs| fs.write(ino, fh, offset, &data, reply));
+    }
+
+    fn flush(&mut self, _req: &Request, ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        self.dispatch(move |fs| fs.flush(ino, fh, reply));
+    }
+
+    fn fsync(&mut self, _req: &Request, _ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        self.dispatch(move |fs| fs.fsync(fh, datasync, reply));
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.dispatch(move |fs| fs.release(ino, fh, reply));
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.create(parent, &name, mode, reply));
+    }
+
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.unlink(parent, &name, reply));
+    }
+
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.rmdir(parent, &name, reply));
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        let name = name.to_os_string();
+        let newname = newname.to_os_string();
+        self.dispatch(move |fs| fs.rename(parent, &name, newparent, &newname, flags, reply));
+    }
+
+    fn getlk(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        fh: u64,
+        _lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        _pid: u32,
+        reply: ReplyLock,
+    ) {
+        let range = LockRange { start, end, typ };
+        self.dispatch(move |fs| fs.getlk(fh, range, reply));
+    }
+
+    fn setlk(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        fh: u64,
+        _lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        sleep: bool,
+        reply: ReplyEmpty,
+    ) {
+        let range = LockRange { start, end, typ };
+        if !sleep {
+            self.dispatch(move |fs| fs.setlk(fh, range, pid, reply));
+            return;
+        }
+
+        // Waiting for a lock can take arbitrarily long, so it's left to the
+        // lock waiter rather than tying up a worker
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if self.lock_waiter.is_none() {
+            match LockWaiter::spawn(Arc::clone(&self.overlay)) {
+                Ok(waiter) => self.lock_waiter = Some(waiter),
+                Err(e) => {
+                    tracing::error!("Failed to spawn thread to wait for locks: {}", e);
+                    reply.error(libc::ENOLCK);
+                    return;
+                }
+            }
+        }
+        if let Some(ref waiter) = self.lock_waiter {
+            waiter.wait(fh, range, pid, move |result| match result {
+                Ok(()) => reply.ok(),
+                Err(errno) => reply.error(errno),
+            });
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, reply: ReplyDirectory) {
+        self.dispatch(move |fs| fs.readdir(ino, offset, reply));
+    }
+
+    fn fsyncdir(&mut self, _req: &Request, ino: u64, _fh: u64, datasync: bool, reply: ReplyEmpty) {
+        self.dispatch(move |fs| fs.fsyncdir(ino, datasync, reply));
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.mkdir(parent, &name, reply));
+    }
+
+    fn mknod(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        _umask: u32,
+        rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.mknod(parent, &name, mode, rdev, reply));
+    }
+
+    fn symlink(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        link: &Path,
+        reply: ReplyEntry,
+    ) {
+        let name = name.to_os_string();
+        let link = link.to_path_buf();
+        self.dispatch(move |fs| fs.symlink(parent, &name, &link, reply));
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        self.dispatch(move |fs| fs.readlink(ino, reply));
+    }
+
+    fn link(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        let newname = newname.to_os_string();
+        self.dispatch(move |fs| fs.link(ino, newparent, &newname, reply));
+    }
+
+    fn setxattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        _flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        let name = name.to_os_string();
+        let value = value.to_vec();
+        self.dispatch(move |fs| fs.setxattr(ino, &name, &value, reply));
+    }
+
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.getxattr(ino, &name, size, reply));
+    }
+
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        self.dispatch(move |fs| fs.listxattr(ino, size, reply));
+    }
+
+    fn removexattr(&mut self, _req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        let name = name.to_os_string();
+        self.dispatch(move |fs| fs.removexattr(ino, &name, reply));
+    }
+
+    fn statfs(&mut self, _req: &Request, _ino: u64, reply: ReplyStatfs) {
+        self.dispatch(move |fs| fs.statfs(reply));
+    }
+
+    fn access(&mut self, _req: &Request, ino: u64, mask: i32, reply: ReplyEmpty) {
+        self.dispatch(move |fs| fs.access(ino, mask, reply));
+    }
+
+    fn fallocate(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        self.dispatch(move |fs| fs.fallocate(ino, fh, offset, length, mode, reply));
+    }
+
+    fn lseek(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: ReplyLseek,
+    ) {
+        self.dispatch(move |fs| fs.lseek(fh, offset, whence, reply));
+    }
+
+    fn copy_file_range(
+        &mut self,
+        _req: &Request,
+        _ino_in: u64,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        flags: u32,
+        reply: ReplyWrite,
+    ) {
+        self.dispatch(move |fs| {
+            fs.copy_file_range(
+                fh_in, offset_in, ino_out, fh_out, offset_out, len, flags, reply,
+            )
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_worker_pool_runs_jobs_concurrently() {
+        let pool = WorkerPool::new(4).unwrap();
+        let (started_tx, started_rx) = mpsc::channel();
+        let release = Arc::new(std::sync::Barrier::new(5));
+
+        // Each job blocks until all four are running, which only happens if
+        // they run on separate threads
+        for _ in 0..4 {
+            let started_tx = started_tx.clone();
+            let release = Arc::clone(&release);
+            pool.execute(move || {
+                started_tx.send(()).unwrap();
+                release.wait();
+            });
+        }
+        for _ in 0..4 {
+            started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        release.wait();
+    }
+
+    #[test]
+    fn test_worker_pool_drains_queue_on_drop() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let pool = WorkerPool::new(2).unwrap();
+        for _ in 0..100 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        drop(pool);
+
+        assert_eq!(done.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn test_worker_pool_survives_panicking_job() {
+        let pool = WorkerPool::new(1).unwrap();
+        pool.execute(|| panic!("handler bug"));
+
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || tx.send(()).unwrap());
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+}
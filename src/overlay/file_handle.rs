@@ -1,13 +1,98 @@
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use std::cell::RefCell;
 use std::fs::File;
 use std::sync::Arc;
 
+use crate::overlay::locks::HeldLocks;
+use crate::overlay::types::LayerType;
+
 thread_local! {
     pub(crate) static READ_BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::with_capacity(128 * 1024));
 }
 
 #[derive(Debug)]
 pub(crate) struct FileHandle {
-    pub file: Arc<Mutex<File>>,
+    /// The inode this handle was opened on
+    pub ino: u64,
+    /// The open file and the layer it's in. A lower-layer file is swapped for
+    /// its upper copy when the inode is copied up while the handle is open.
+    /// Read and written with positional I/O, so it needs no lock of its own.
+    open: RwLock<(Arc<File>, LayerType)>,
+    /// Locks taken through this handle, re-acquired on the upper copy after a copy-up
+    pub locks: Mutex<HeldLocks>,
+    /// A read-write reopening of a lower-layer file that the handle's locks
+    /// are taken on, since write locks need a writable descriptor. Dropped
+    /// when the handle moves to the upper copy.
+    lock_file: Mutex<Option<Arc<File>>>,
+}
+
+impl FileHandle {
+    pub fn new(ino: u64, file: File, layer: LayerType) -> Self {
+        FileHandle {
+            ino,
+            open: RwLock::new((Arc::new(file), layer)),
+            locks: Mutex::new(HeldLocks::default()),
+            lock_file: Mutex::new(None),
+        }
+    }
+
+    /// The currently open file. Cloning the `Arc` lets I/O run without
+    /// holding up a concurrent copy-up.
+    pub fn file(&self) -> Arc<File> {
+        Arc::clone(&self.open.read().0)
+    }
+
+    pub fn layer(&self) -> LayerType {
+        self.open.read().1
+    }
+
+    /// Swaps in `file`, which is in `layer`. The previous file is closed
+    /// once in-flight I/O on it finishes.
+    pub fn replace(&self, file: File, layer: LayerType) {
+        *self.open.write() = (Arc::new(file), layer);
+        self.lock_file.lock().take();
+    }
+
+    /// The file this handle's locks are taken on.
+    ///
+    /// Upper-layer files are opened read-write already. A lower-layer file is
+    /// opened read-only, so it's reopened read-write through `/proc` the first
+    /// time it's locked. Nothing is written through the reopened file. If the
+    /// lower file isn't writable, the read-only file is used and only read
+    /// locks can be taken.
+    pub fn lock_file(&self) -> Arc<File> {
+        let (file, layer) = self.open.read().clone();
+        if layer == LayerType::Upper {
+            return file;
+        }
+
+        let mut lock_file = self.lock_file.lock();
+        if let Some(ref reopened) = *lock_file {
+            return Arc::clone(reopened);
+        }
+        let reopened = match reopen_read_write(&file) {
+            Ok(reopened) => Arc::new(reopened),
+            Err(e) => {
+                tracing::debug!("Can't reopen lower file read-write for locking: {}", e);
+                file
+            }
+        };
+        *lock_file = Some(Arc::clone(&reopened));
+        reopened
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reopen_read_write(file: &File) -> std::io::Result<File> {
+    use std::os::fd::AsRawFd;
+
+    File::options()
+        .read(true)
+        .write(true)
+        .open(format!("/proc/self/fd/{}", file.as_raw_fd()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reopen_read_write(_file: &File) -> std::io::Result<File> {
+    Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
 }
@@ -0,0 +1,587 @@
+//! The less common file operations: `statfs`, `access`, `fsync`, `fsyncdir`,
+//! `fallocate`, `lseek`, `copy_file_range` and `mknod`.
+//!
+//! Operations that only look at a file use whichever layer it's in. Anything
+//! that changes a file's contents copies it up first, the same as `write`, and
+//! new nodes are created where `create` would put them: in the upper layer, or
+//! the lower one for passthrough paths.
+
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::Path;
+
+use fuser::{FileAttr, FileType};
+
+use crate::overlay::convert::{io_error_to_libc, metadata_to_fileattr};
+use crate::overlay::inode_manager::InodeManager;
+use crate::overlay::types::{LayerType, MutationType};
+use crate::overlay::OverlayFs;
+
+/// Buffer size for copying between files the kernel can't copy between itself
+const COPY_CHUNK_SIZE: usize = 128 * 1024;
+
+/// Filesystem usage as reported by `statfs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FsStats {
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub bsize: u32,
+    pub namelen: u32,
+    pub frsize: u32,
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains null byte"))
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// The widths of the statvfs fields vary by platform
+#[allow(clippy::unnecessary_cast)]
+fn statvfs(path: &Path) -> io::Result<FsStats> {
+    let path = c_path(path)?;
+    let mut stat = MaybeUninit::<libc::statvfs>::zeroed();
+    // SAFETY: The path is null-terminated and `stat` has room for the result.
+    check(unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) })?;
+    // SAFETY: statvfs() succeeded, so it filled in `stat`.
+    let stat = unsafe { stat.assume_init() };
+    Ok(FsStats {
+        blocks: stat.f_blocks as u64,
+        bfree: stat.f_bfree as u64,
+        bavail: stat.f_bavail as u64,
+        files: stat.f_files as u64,
+        ffree: stat.f_ffree as u64,
+        bsize: stat.f_bsize as u32,
+        namelen: stat.f_namemax as u32,
+        frsize: stat.f_frsize as u32,
+    })
+}
+
+fn sync(file: &File, datasync: bool) -> io::Result<()> {
+    if datasync {
+        file.sync_data()
+    } else {
+        file.sync_all()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn allocate(file: &File, offset: i64, length: i64, mode: i32) -> io::Result<()> {
+    // SAFETY: The descriptor stays open for the duration of the call.
+    check(unsafe { libc::fallocate(file.as_raw_fd(), mode, offset, length) })
+}
+
+/// macOS has no `fallocate()`; macFUSE sends it for `F_PREALLOCATE`, which
+/// reserves space without changing the file's size. Reserve enough past the
+/// end of the real file for it to reach `offset + length`.
+#[cfg(target_os = "macos")]
+fn allocate(file: &File, offset: i64, length: i64, mode: i32) -> io::Result<()> {
+    if mode != 0 {
+        return Err(io::Error::from_raw_os_error(libc::EOPNOTSUPP));
+    }
+    if offset < 0 || length <= 0 {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+    let end = offset
+        .checked_add(length)
+        .ok_or_else(|| io::Error::from_raw_os_error(libc::EFBIG))?;
+    let size = file.metadata()?.len() as i64;
+    if end <= size {
+        return Ok(());
+    }
+
+    let mut store = libc::fstore_t {
+        fst_flags: libc::F_ALLOCATEALL,
+        fst_posmode: libc::F_PEOFPOSMODE,
+        fst_offset: 0,
+        fst_length: end - size,
+        fst_bytesalloc: 0,
+    };
+    // SAFETY: The descriptor stays open for the duration of the call, and
+    // `store` outlives it.
+    let ret = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) };
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn allocate(_file: &File, _offset: i64, _length: i64, _mode: i32) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::EOPNOTSUPP))
+}
+
+fn seek(file: &File, offset: i64, whence: i32) -> io::Result<i64> {
+    // SAFETY: The descriptor stays open for the duration of the call.
+    let pos = unsafe { libc::lseek(file.as_raw_fd(), offset, whence) };
+    if pos < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(pos)
+    }
+}
+
+/// Copies up to `len` bytes between the files, returning how many were copied.
+/// Stays in the kernel where it can, which lets filesystems share extents.
+#[cfg(target_os = "linux")]
+fn copy_range(
+    src: &File,
+    src_offset: u64,
+    dest: &File,
+    dest_offset: u64,
+    len: usize,
+) -> io::Result<usize> {
+    let mut off_in = src_offset as i64;
+    let mut off_out = dest_offset as i64;
+    // SAFETY: Both descriptors stay open for the duration of the call, and
+    // the offsets are only used to pass the positions in.
+    let n = unsafe {
+        libc::copy_file_range(
+            src.as_raw_fd(),
+            &mut off_in,
+            dest.as_raw_fd(),
+            &mut off_out,
+            len,
+            0,
+        )
+    };
+    if n >= 0 {
+        return Ok(n as usize);
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        // The layers are on different filesystems, or theirs can't do it
+        Some(libc::EXDEV | libc::ENOSYS | libc::EOPNOTSUPP) => {
+            copy_range_by_reading(src, src_offset, dest, dest_offset, len)
+        }
+        _ => Err(e),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_range(
+    src: &File,
+    src_offset: u64,
+    dest: &File,
+    dest_offset: u64,
+    len: usize,
+) -> io::Result<usize> {
+    copy_range_by_reading(src, src_offset, dest, dest_offset, len)
+}
+
+fn copy_range_by_reading(
+    src: &File,
+    src_offset: u64,
+    dest: &File,
+    dest_offset: u64,
+    len: usize,
+) -> io::Result<usize> {
+    let mut buf = vec![0u8; len.min(COPY_CHUNK_SIZE)];
+    let mut copied = 0;
+    while copied < len {
+        let want = (len - copied).min(buf.len());
+        let n = src.read_at(&mut buf[..want], src_offset + copied as u64)?;
+        if n == 0 {
+            break;
+        }
+        dest.write_all_at(&buf[..n], dest_offset + copied as u64)?;
+        copied += n;
+    }
+    Ok(copied)
+}
+
+/// Creates the node `mode` describes at `path`: a FIFO, socket, device or
+/// empty regular file.
+#[allow(clippy::unnecessary_cast)]
+fn make_node(path: &Path, mode: u32, rdev: u32) -> io::Result<()> {
+    let perm = mode & 0o7777;
+    match mode & libc::S_IFMT as u32 {
+        kind if kind == libc::S_IFREG as u32 => OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(perm)
+            .open(path)
+            .map(drop),
+        kind if kind == libc::S_IFIFO as u32 => {
+            let path = c_path(path)?;
+            // SAFETY: The path is null-terminated.
+            check(unsafe { libc::mkfifo(path.as_ptr(), perm as libc::mode_t) })
+        }
+        // mknod() needs root for sockets on macOS. Binding one creates the
+        // node, which stays behind when the listener is closed.
+        #[cfg(target_os = "macos")]
+        kind if kind == libc::S_IFSOCK as u32 => {
+            use std::os::unix::fs::PermissionsExt;
+            drop(std::os::unix::net::UnixListener::bind(path)?);
+            fs::set_permissions(path, fs::Permissions::from_mode(perm))
+        }
+        _ => {
+            let path = c_path(path)?;
+            // SAFETY: The path is null-terminated.
+            check(unsafe { libc::mknod(path.as_ptr(), mode as libc::mode_t, rdev as libc::dev_t) })
+        }
+    }
+}
+
+impl OverlayFs {
+    /// Copies `ino` up before its contents change, unless it's already in the
+    /// upper layer or is a passthrough file, the same as `write`.
+    fn copy_up_before_write(&self, ino: u64) -> Result<(), i32> {
+        let (rel_path, layer, _has_open) =
+            self.inode_manager.get_inode_info(ino).ok_or(libc::ENOENT)?;
+        if layer == LayerType::Lower && !self.path_resolver.is_passthrough(&rel_path) {
+            self.copy_up(ino)?;
+        }
+        Ok(())
+    }
+
+    /// Usage of the upper layer's filesystem, which is where new files and
+    /// copied-up changes take up space.
+    pub(crate) fn do_statfs(&self) -> Result<FsStats, i32> {
+        statvfs(&self.path_resolver.layer_base_path(LayerType::Upper))
+            .map_err(|e| io_error_to_libc(&e))
+    }
+
+    /// Checks `mask` against the file backing `ino`. A lower-layer file is
+    /// checked as it is, since writing to it copies it up with the same mode.
+    pub(crate) fn do_access(&self, ino: u64, mask: i32) -> Result<(), i32> {
+        let (rel_path, layer, _has_open) =
+            self.inode_manager.get_inode_info(ino).ok_or(libc::ENOENT)?;
+        let (path, _layer) = self
+            .path_resolver
+            .resolve_path(&rel_path, layer)
+            .ok_or(libc::ENOENT)?;
+        if mask == libc::F_OK {
+            return Ok(());
+        }
+
+        let path = c_path(&path).map_err(|e| io_error_to_libc(&e))?;
+        // SAFETY: The path is null-terminated.
+        check(unsafe { libc::access(path.as_ptr(), mask) }).map_err(|e| io_error_to_libc(&e))
+    }
+
+    pub(crate) fn do_fsync(&self, fh: u64, datasync: bool) -> Result<(), i32> {
+        let file = self.handle(fh)?.file();
+        sync(&file, datasync).map_err(|e| io_error_to_libc(&e))
+    }
+
+    /// Syncs the entries of directory `ino`. They're created in the upper
+    /// layer, or the lower one for passthrough paths, so each layer's copy
+    /// of the directory that exists is synced.
+    pub(crate) fn do_fsyncdir(&self, ino: u64, datasync: bool) -> Result<(), i32> {
+        let rel_path = self.inode_manager.get_path(ino).ok_or(libc::ENOENT)?;
+        for dir in [
+            self.path_resolver.upper_path(&rel_path),
+            self.path_resolver.lower_path(&rel_path),
+        ] {
+            let file = match File::open(&dir) {
+                Ok(f) => f,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(io_error_to_libc(&e)),
+            };
+            sync(&file, datasync).map_err(|e| io_error_to_libc(&e))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn do_fallocate(
+        &self,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+    ) -> Result<(), i32> {
+        self.copy_up_before_write(ino)?;
+
+        // Copy-up moves open handles to the upper copy, so get the file after it
+        let file = self.handle(fh)?.file();
+        allocate(&file, offset, length, mode).map_err(|e| io_error_to_libc(&e))?;
+
+        if let Ok(metadata) = file.metadata() {
+            self.inode_manager
+                .update_attrs(ino, metadata_to_fileattr(&metadata, ino));
+        }
+        Ok(())
+    }
+
+    /// Repositions within handle `fh`, which the kernel only asks for with
+    /// `SEEK_DATA` and `SEEK_HOLE`. Reads and writes use positional I/O, so
+    /// moving the file's offset doesn't affect them.
+    pub(crate) fn do_lseek(&self, fh: u64, offset: i64, whence: i32) -> Result<i64, i32> {
+        let file = self.handle(fh)?.file();
+        seek(&file, offset, whence).map_err(|e| io_error_to_libc(&e))
+    }
+
+    /// Copies `len` bytes from handle `fh_in` to `fh_out`, which is open on
+    /// `ino_out`, returning how many were copied.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn do_copy_file_range(
+        &self,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        flags: u32,
+    ) -> Result<u32, i32> {
+        if flags != 0 || offset_in < 0 || offset_out < 0 {
+            return Err(libc::EINVAL);
+        }
+        self.copy_up_before_write(ino_out)?;
+
+        // Copy-up may have moved either handle to the upper copy
+        let src = self.handle(fh_in)?.file();
+        let dest = self.handle(fh_out)?.file();
+        let len = len.min(u32::MAX as u64) as usize;
+        let n = copy_range(&src, offset_in as u64, &dest, offset_out as u64, len)
+            .map_err(|e| io_error_to_libc(&e))?;
+
+        if let Some(attrs) = self.inode_manager.get_attrs(ino_out) {
+            let new_size = std::cmp::max(attrs.size, offset_out as u64 + n as u64);
+            self.inode_manager.update_size(ino_out, new_size);
+        }
+        Ok(n as u32)
+    }
+
+    /// Creates a FIFO, socket, device or regular file named `name` in `parent`.
+    pub(crate) fn do_mknod(
+        &self,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        rdev: u32,
+    ) -> Result<FileAttr, i32> {
+        let parent_path = self.inode_manager.get_path(parent).ok_or(libc::ENOENT)?;
+        if self.inode_manager.lookup_child(parent, name).is_some() {
+            return Err(libc::EEXIST);
+        }
+
+        let relative_path = parent_path.join(name);
+        let is_passthrough = self.path_resolver.is_passthrough(&relative_path);
+        let layer = if is_passthrough {
+            LayerType::Lower
+        } else {
+            LayerType::Upper
+        };
+        let node_path = self
+            .path_resolver
+            .layer_base_path(layer)
+            .join(&relative_path);
+
+        if let Err(e) = make_node(&node_path, mode, rdev) {
+            tracing::error!("mknod error: {}", e);
+            return Err(io_error_to_libc(&e));
+        }
+        let metadata = fs::symlink_metadata(&node_path).map_err(|e| io_error_to_libc(&e))?;
+
+        let new_ino = self.inode_manager.alloc_inode();
+        let file_attrs = metadata_to_fileattr(&metadata, new_ino);
+        let inode = InodeManager::create_inode_data(
+            new_ino,
+            parent,
+            name.to_os_string(),
+            layer,
+            relative_path.clone(),
+            file_attrs,
+        );
+        self.inode_manager.insert(inode);
+
+        if !is_passthrough {
+            self.remove_whiteout(&parent_path, name);
+        }
+        // Git can't record FIFOs, sockets or devices, so only a regular file
+        // is a change worth syncing
+        if !is_passthrough && file_attrs.kind == FileType::RegularFile {
+            self.record_mutation(&relative_path, MutationType::Created);
+            self.signal_mutation(&relative_path);
+        }
+        Ok(file_attrs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay::test_helpers::{lookup, open_handle, setup};
+    use fuser::FUSE_ROOT_ID;
+
+    // FUSE passes modes as `u32`, while their libc type varies by platform
+    #[allow(clippy::unnecessary_cast)]
+    const S_IFIFO: u32 = libc::S_IFIFO as u32;
+    #[allow(clippy::unnecessary_cast)]
+    const S_IFSOCK: u32 = libc::S_IFSOCK as u32;
+
+    #[test]
+    fn test_statfs_reports_upper_layer_filesystem() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        let fs = OverlayFs::new(upper_layer, lower_layer, None, 1, vec![]).unwrap();
+
+        let stats = fs.do_statfs().unwrap();
+        assert!(stats.blocks > 0);
+        assert!(stats.bsize > 0);
+        assert!(stats.namelen > 0);
+    }
+
+    #[test]
+    fn test_access_checks_backing_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("script.sh"), "#!/bin/sh").unwrap();
+        fs::set_permissions(
+            lower_layer.join("script.sh"),
+            fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+        let fs = OverlayFs::new(upper_layer, lower_layer.clone(), None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, "script.sh").inode;
+
+        fs.do_access(ino, libc::F_OK).unwrap();
+        fs.do_access(ino, libc::R_OK).unwrap();
+        assert_eq!(fs.do_access(ino, libc::X_OK), Err(libc::EACCES));
+
+        fs::set_permissions(
+            lower_layer.join("script.sh"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs.do_access(ino, libc::X_OK).unwrap();
+
+        fs::remove_file(lower_layer.join("script.sh")).unwrap();
+        assert_eq!(fs.do_access(ino, libc::F_OK), Err(libc::ENOENT));
+    }
+
+    #[test]
+    fn test_fsync_and_fsyncdir() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(upper_layer.join("app.db"), "data").unwrap();
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, "app.db").inode;
+        let fh = open_handle(&fs, ino, &upper_layer.join("app.db"), LayerType::Upper);
+
+        fs.do_fsync(fh, false).unwrap();
+        fs.do_fsync(fh, true).unwrap();
+        assert_eq!(fs.do_fsync(fh + 1, false), Err(libc::EBADF));
+        fs.do_fsyncdir(FUSE_ROOT_ID, false).unwrap();
+    }
+
+    #[test]
+    fn test_fallocate_copies_up_lower_file() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("data.bin"), "data").unwrap();
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, "data.bin").inode;
+        let fh = open_handle(&fs, ino, &lower_layer.join("data.bin"), LayerType::Lower);
+
+        fs.do_fallocate(ino, fh, 0, 4096, 0).unwrap();
+
+        assert_eq!(fs.inode_manager.get_layer(ino), Some(LayerType::Upper));
+        assert_eq!(fs::read(lower_layer.join("data.bin")).unwrap(), b"data");
+        // Only Linux's fallocate() extends the file; macOS just reserves space
+        let expected_size = if cfg!(target_os = "linux") { 4096 } else { 4 };
+        assert_eq!(
+            fs::metadata(upper_layer.join("data.bin")).unwrap().len(),
+            expected_size
+        );
+        assert_eq!(fs.inode_manager.get_attrs(ino).unwrap().size, expected_size);
+    }
+
+    #[test]
+    fn test_lseek_finds_data_and_hole() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("data.bin"), vec![1u8; 8192]).unwrap();
+        let fs = OverlayFs::new(upper_layer, lower_layer.clone(), None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, "data.bin").inode;
+        let fh = open_handle(&fs, ino, &lower_layer.join("data.bin"), LayerType::Lower);
+
+        assert_eq!(fs.do_lseek(fh, 100, libc::SEEK_DATA), Ok(100));
+        // Without holes, the only one is at the end of the file
+        assert_eq!(fs.do_lseek(fh, 0, libc::SEEK_HOLE), Ok(8192));
+        assert_eq!(fs.do_lseek(fh, 9000, libc::SEEK_DATA), Err(libc::ENXIO));
+    }
+
+    #[test]
+    fn test_copy_file_range_copies_up_destination() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("src.txt"), "hello world").unwrap();
+        fs::write(lower_layer.join("dest.txt"), "goodbye").unwrap();
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        let src_ino = lookup(&fs, "src.txt").inode;
+        let dest_ino = lookup(&fs, "dest.txt").inode;
+        let src = open_handle(&fs, src_ino, &lower_layer.join("src.txt"), LayerType::Lower);
+        let dest = open_handle(
+            &fs,
+            dest_ino,
+            &lower_layer.join("dest.txt"),
+            LayerType::Lower,
+        );
+
+        assert_eq!(
+            fs.do_copy_file_range(src, 6, dest_ino, dest, 4, 100, 0),
+            Ok(5)
+        );
+
+        assert_eq!(
+            fs::read(upper_layer.join("dest.txt")).unwrap(),
+            b"goodworld"
+        );
+        assert_eq!(fs::read(lower_layer.join("dest.txt")).unwrap(), b"goodbye");
+        // The source is only read, so it stays in the lower layer
+        assert!(!upper_layer.join("src.txt").exists());
+        assert_eq!(fs.inode_manager.get_attrs(dest_ino).unwrap().size, 9);
+        assert_eq!(
+            fs.do_copy_file_range(src, 0, dest_ino, dest, 0, 1, 1),
+            Err(libc::EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_mknod_creates_fifo_and_socket_in_upper_layer() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+
+        let fifo = fs
+            .do_mknod(FUSE_ROOT_ID, OsStr::new("events"), S_IFIFO | 0o600, 0)
+            .unwrap();
+        assert_eq!(fifo.kind, FileType::NamedPipe);
+        assert_eq!(fifo.perm, 0o600);
+        assert_eq!(
+            fs.inode_manager
+                .lookup_child(FUSE_ROOT_ID, OsStr::new("events")),
+            Some(fifo.ino)
+        );
+
+        let socket = fs
+            .do_mknod(FUSE_ROOT_ID, OsStr::new("dev.sock"), S_IFSOCK | 0o755, 0)
+            .unwrap();
+        assert_eq!(socket.kind, FileType::Socket);
+
+        assert!(upper_layer.join("events").exists());
+        assert!(!lower_layer.join("events").exists());
+        // Special files aren't changes git can sync
+        assert!(fs.mutations.read().is_empty());
+
+        assert_eq!(
+            fs.do_mknod(FUSE_ROOT_ID, OsStr::new("events"), S_IFIFO | 0o600, 0),
+            Err(libc::EEXIST)
+        );
+    }
+}
@@ -4,16 +4,17 @@ use std::ffi::{CString, OsStr, OsString};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 
 use crate::overlay::convert::{metadata_to_fileattr, std_filetype_to_fuser};
 use crate::overlay::inode_manager::InodeManager;
 use crate::overlay::types::{InodeData, LayerType, MutationType};
 use crate::overlay::whiteout::Whiteout;
-use crate::overlay::TreebeardFs;
+use crate::overlay::OverlayFs;
 
-impl TreebeardFs {
+impl OverlayFs {
     #[cfg(target_os = "macos")]
-    pub(crate) fn clone_file_optimized(src: &Path, dest: &Path) -> io::Result<()> {
+    fn clonefile(src: &Path, dest: &Path) -> io::Result<()> {
         use std::os::unix::ffi::OsStrExt;
 
         let src_cstr = CString::new(src.as_os_str().as_bytes())
@@ -22,27 +23,188 @@ impl TreebeardFs {
             .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains null byte"))?;
 
         // SAFETY: Both paths are properly null-terminated CStrings that outlive the call.
-        unsafe {
-            if libc::clonefile(src_cstr.as_ptr(), dest_cstr.as_ptr(), 0) == 0 {
-                return Ok(());
-            }
+        if unsafe { libc::clonefile(src_cstr.as_ptr(), dest_cstr.as_ptr(), 0) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub(crate) fn clone_file_optimized(src: &Path, dest: &Path) -> io::Result<()> {
+        if Self::clonefile(src, dest).is_ok() {
+            return Ok(());
         }
         fs::copy(src, dest)?;
         Ok(())
     }
 
-    #[cfg(not(target_os = "macos"))]
+    /// Makes `dest` share `src`'s data blocks (btrfs, XFS, bcachefs).
+    #[cfg(target_os = "linux")]
+    fn ficlone(src: &fs::File, dest: &fs::File) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: Both descriptors stay open for the duration of the call.
+        if unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Copies `len` bytes with `copy_file_range`, which stays in the kernel
+    /// and can share extents or copy server-side on network filesystems.
+    ///
+    /// Returns `false` if the filesystems don't support it and nothing was
+    /// copied, so the caller can fall back to a plain copy.
+    #[cfg(target_os = "linux")]
+    fn copy_file_range_all(src: &fs::File, dest: &fs::File, len: u64) -> io::Result<bool> {
+        use std::os::fd::AsRawFd;
+
+        let mut copied = 0u64;
+        while copied < len {
+            let chunk = (len - copied).min(1 << 30) as usize;
+            // SAFETY: Both descriptors stay open for the duration of the call.
+            // Null offsets use and advance each file's own position.
+            let n = unsafe {
+                libc::copy_file_range(
+                    src.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    dest.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    chunk,
+                    0,
+                )
+            };
+            match n {
+                // The source shrank while being copied
+                0 => break,
+                n if n > 0 => copied += n as u64,
+                _ => {
+                    let e = io::Error::last_os_error();
+                    let unsupported = matches!(
+                        e.raw_os_error(),
+                        Some(
+                            libc::EXDEV
+                                | libc::ENOSYS
+                                | libc::EOPNOTSUPP
+                                | libc::EINVAL
+                                | libc::EPERM
+                        )
+                    );
+                    if copied == 0 && unsupported {
+                        return Ok(false);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Copies `src` to `dest` as cheaply as the filesystems allow: a reflink
+    /// where the upper and lower layers share a filesystem that supports
+    /// them, then `copy_file_range`, then a plain read/write copy.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn clone_file_optimized(src: &Path, dest: &Path) -> io::Result<()> {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        let mut src_file = fs::File::open(src)?;
+        let metadata = src_file.metadata()?;
+        let mut dest_file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(metadata.permissions().mode())
+            .open(dest)?;
+
+        if Self::ficlone(&src_file, &dest_file).is_err()
+            && !Self::copy_file_range_all(&src_file, &dest_file, metadata.len())?
+        {
+            io::copy(&mut src_file, &mut dest_file)?;
+        }
+
+        // The mode passed to open() was masked by the umask
+        dest_file.set_permissions(metadata.permissions())?;
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
     pub(crate) fn clone_file_optimized(src: &Path, dest: &Path) -> io::Result<()> {
         fs::copy(src, dest)?;
         Ok(())
     }
 
+    /// Checks whether files in `dir` can be cloned as reflinks, by cloning
+    /// a small probe file that is removed again.
+    ///
+    /// When the upper and lower layers share such a filesystem, copy-up
+    /// shares data with the main repo instead of copying it.
+    pub fn reflink_supported(dir: &Path) -> io::Result<bool> {
+        let src = dir.join(format!(".treebeard-reflink-probe-{}", std::process::id()));
+        let dest = dir.join(format!(
+            ".treebeard-reflink-probe-{}.clone",
+            std::process::id()
+        ));
+        fs::write(&src, b"treebeard")?;
+
+        #[cfg(target_os = "macos")]
+        let cloned = Self::clonefile(&src, &dest).is_ok();
+        #[cfg(target_os = "linux")]
+        let cloned = fs::File::create(&dest)
+            .and_then(|dest_file| Self::ficlone(&fs::File::open(&src)?, &dest_file))
+            .is_ok();
+        #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+        let cloned = false;
+
+        let _ = fs::remove_file(&src);
+        let _ = fs::remove_file(&dest);
+        Ok(cloned)
+    }
+
+    /// Returns the layer an inode should be read from, correcting a stale
+    /// `Upper` to `Lower` when the upper copy was removed from outside the
+    /// overlay (`treebeard sync --passthrough` does this after syncing).
+    ///
+    /// Without this, the next write would skip copy-up and fail on the
+    /// read-only lower file.
+    pub(crate) fn refresh_released_layer(
+        &self,
+        ino: u64,
+        rel_path: &Path,
+        layer: LayerType,
+    ) -> LayerType {
+        if layer != LayerType::Upper || rel_path.as_os_str().is_empty() {
+            return layer;
+        }
+
+        let upper_path = self.path_resolver.upper_path(rel_path);
+        if upper_path.symlink_metadata().is_ok()
+            || self.path_resolver.is_whiteout(&upper_path)
+            || self
+                .path_resolver
+                .lower_path(rel_path)
+                .symlink_metadata()
+                .is_err()
+        {
+            return layer;
+        }
+
+        self.inode_manager
+            .update_layer_if_needed(ino, LayerType::Lower);
+        LayerType::Lower
+    }
+
     /// Internal copy-up implementation that assumes the caller is already holding
     /// the copy-up lock for this inode. Does not acquire or release the lock.
     ///
     /// This is used by `open()` which holds the lock through the entire operation
     /// to prevent race conditions between checking the layer and opening the file.
-    pub(crate) fn copy_up_internal(&mut self, ino: u64) -> Result<(), i32> {
+    pub(crate) fn copy_up_internal(&self, ino: u64) -> Result<(), i32> {
+        if let Some((rel_path, layer, _)) = self.inode_manager.get_inode_info(ino) {
+            self.refresh_released_layer(ino, &rel_path, layer);
+        }
+
         // Check if already in upper layer
         if self.inode_manager.is_in_upper_layer(ino) {
             return Ok(());
@@ -57,7 +219,7 @@ impl TreebeardFs {
         let src_path = self.path_resolver.lower_path(&src_info.0);
         let dest_path = self.path_resolver.upper_path(&src_info.0);
 
-        let _src_metadata = match fs::symlink_metadata(&src_path) {
+        let src_metadata = match fs::symlink_metadata(&src_path) {
             Ok(m) => m,
             Err(e) if e.kind() == io::ErrorKind::NotFound => {
                 tracing::debug!(
@@ -145,17 +307,21 @@ impl TreebeardFs {
         // Clone the path from Arc for update - this is an infrequent operation
         self.inode_manager
             .update_after_copy_up(ino, (*src_info.0).clone(), file_attrs);
+        self.counters.copy_ups.fetch_add(1, Ordering::Relaxed);
 
         if src_info.2.kind == fuser::FileType::RegularFile {
-            self.mutations
-                .write()
-                .insert((*src_info.0).clone(), MutationType::CopiedUp);
+            self.counters
+                .bytes_copied
+                .fetch_add(new_attrs.len(), Ordering::Relaxed);
+            self.reopen_handles_after_copy_up(ino, &dest_path);
+            self.record_base(&src_info.0, &src_metadata, &dest_path);
+            self.record_mutation(&src_info.0, MutationType::CopiedUp);
         }
 
         Ok(())
     }
 
-    pub(crate) fn copy_up(&mut self, ino: u64) -> Result<u64, i32> {
+    pub(crate) fn copy_up(&self, ino: u64) -> Result<u64, i32> {
         let lock = self.inode_manager.get_copy_up_lock(ino);
         let _guard = lock.lock();
 
@@ -173,11 +339,7 @@ impl TreebeardFs {
     /// corresponding directory to mark that the original file should be hidden.
     ///
     /// See [`Whiteout`] for more details on whiteout handling.
-    pub(crate) fn create_whiteout(
-        &mut self,
-        parent: u64,
-        name: &std::ffi::OsStr,
-    ) -> Result<(), i32> {
+    pub(crate) fn create_whiteout(&self, parent: u64, name: &std::ffi::OsStr) -> Result<(), i32> {
         let parent_path = {
             let inodes = self.inode_manager.inodes.read();
             let parent_inode = inodes.peek(parent);
@@ -192,10 +354,90 @@ impl TreebeardFs {
             fs::create_dir_all(&parent_path).map_err(|_| libc::EIO)?;
         }
 
-        Whiteout::create(&parent_path, name)
+        Whiteout::create(&parent_path, name)?;
+        self.counters.whiteouts.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Remove the whiteout for `name` in the upper layer's copy of
+    /// `parent_path`, for a new entry taking over the name. Returns whether
+    /// there was one.
+    pub(crate) fn remove_whiteout(&self, parent_path: &Path, name: &OsStr) -> bool {
+        let marker = self
+            .path_resolver
+            .upper_path(parent_path)
+            .join(Whiteout::marker_name(name));
+        match fs::remove_file(&marker) {
+            Ok(()) => true,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    tracing::warn!("Failed to remove {}: {}", marker.display(), e);
+                }
+                false
+            }
+        }
+    }
+
+    /// Prepare directory `name` in `parent` for `rmdir`.
+    ///
+    /// Fails with `ENOTEMPTY` if the directory has entries in the overlay
+    /// view, counting lower-layer entries that aren't whited out unless the
+    /// directory is opaque. Otherwise removes the whiteout and opaque markers
+    /// from its upper-layer copy so the copy itself can be removed.
+    pub(crate) fn clear_directory_for_removal(&self, parent: u64, name: &OsStr) -> Result<(), i32> {
+        let ino = self
+            .inode_manager
+            .lookup_child(parent, name)
+            .ok_or(libc::ENOENT)?;
+        let rel_path = self.inode_manager.get_path(ino).ok_or(libc::ENOENT)?;
+        if self.path_resolver.is_passthrough(&rel_path) {
+            return Ok(());
+        }
+
+        let upper_dir = self.path_resolver.upper_path(&rel_path);
+        let mut markers = Vec::new();
+        let mut whited_out = HashSet::new();
+        if let Ok(read_dir) = fs::read_dir(&upper_dir) {
+            for entry in read_dir.flatten() {
+                let entry_name = entry.file_name();
+                if Whiteout::is_opaque_marker(&entry_name) {
+                    markers.push(entry.path());
+                } else if let Some(target) = Whiteout::extract_target(&entry_name) {
+                    whited_out.insert(target);
+                    markers.push(entry.path());
+                } else {
+                    return Err(libc::ENOTEMPTY);
+                }
+            }
+        }
+
+        if !Whiteout::is_opaque(&upper_dir) {
+            if let Ok(read_dir) = fs::read_dir(self.path_resolver.lower_path(&rel_path)) {
+                if read_dir
+                    .flatten()
+                    .any(|entry| !whited_out.contains(&entry.file_name()))
+                {
+                    return Err(libc::ENOTEMPTY);
+                }
+            }
+        }
+
+        for marker in markers {
+            fs::remove_file(&marker).map_err(|e| e.raw_os_error().unwrap_or(libc::EIO))?;
+        }
+        // Whiting out a lower-layer directory's children created its upper
+        // copy, which has to go for the whiteout of the directory to apply
+        if self.inode_manager.get_layer(ino) == Some(LayerType::Lower) {
+            if let Err(e) = fs::remove_dir(&upper_dir) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(e.raw_os_error().unwrap_or(libc::EIO));
+                }
+            }
+        }
+        Ok(())
     }
 
-    pub(crate) fn do_delete(&mut self, ino: u64) {
+    pub(crate) fn do_delete(&self, ino: u64) {
         let path = {
             let inodes = self.inode_manager.inodes.read();
             let inode = inodes.peek(ino);
@@ -214,7 +456,7 @@ impl TreebeardFs {
         }
     }
 
-    pub(crate) fn do_gc(&mut self, ino: u64) {
+    pub(crate) fn do_gc(&self, ino: u64) {
         let (layer, path) = {
             let inodes = self.inode_manager.inodes.read();
             let inode = inodes.peek(ino);
@@ -286,7 +528,7 @@ impl TreebeardFs {
     /// This implements standard overlay filesystem lookup:
     /// 1. Check for whiteout in upper layer (file was deleted)
     /// 2. Check upper layer first (modifications take precedence)
-    /// 3. Fall back to lower layer
+    /// 3. Fall back to lower layer, unless the parent directory is opaque
     ///
     /// # Returns
     /// - `Ok(Some((inode, attrs)))` - File found, inode data and attributes returned
@@ -317,6 +559,9 @@ impl TreebeardFs {
             return Ok(None);
         }
 
+        // Nothing in the lower layer shows through an opaque directory
+        let parent_is_opaque = child_upper.parent().is_some_and(Whiteout::is_opaque);
+
         // Try each layer in order: upper first (overlay semantics)
         let layers_to_check = [
             (&child_upper, LayerType::Upper, "upper"),
@@ -324,6 +569,10 @@ impl TreebeardFs {
         ];
 
         for (child_path, layer, layer_name) in layers_to_check {
+            if layer == LayerType::Lower && parent_is_opaque {
+                tracing::debug!("lookup_overlay: parent of {:?} is opaque", child_name);
+                break;
+            }
             if child_path.exists() {
                 tracing::debug!(
                     "lookup_overlay: file exists in {} layer: {}",
@@ -415,7 +664,8 @@ impl TreebeardFs {
     ///
     /// This helper is used by readdir() to scan both the lower and upper layers.
     /// It collects existing children from the inode table, then processes directory
-    /// entries, creating new inodes as needed.
+    /// entries, creating new inodes as needed. The lower layer is skipped when
+    /// the directory is opaque.
     ///
     /// # Arguments
     /// * `layer_dir` - The directory path to scan
@@ -453,6 +703,13 @@ impl TreebeardFs {
             return;
         }
 
+        if layer == LayerType::Lower
+            && Whiteout::is_opaque(&self.path_resolver.upper_path(dir_path))
+        {
+            tracing::debug!("readdir: {:?} is opaque, skipping lower layer", dir_path);
+            return;
+        }
+
         let Ok(read_dir) = fs::read_dir(layer_dir) else {
             return;
         };
@@ -478,6 +735,9 @@ impl TreebeardFs {
 
             // Handle whiteouts only in upper layer
             if layer == LayerType::Upper {
+                if Whiteout::is_opaque_marker(&name) {
+                    continue;
+                }
                 if let Some(target) = Whiteout::extract_target(&name) {
                     entries.remove(&target);
                     whiteouts.insert(target);
@@ -541,7 +801,7 @@ impl TreebeardFs {
     }
 
     pub(crate) fn do_remove<F>(
-        &mut self,
+        &self,
         parent: u64,
         name: &OsStr,
         reply: fuser::ReplyEmpty,
@@ -585,6 +845,19 @@ impl TreebeardFs {
                     } else {
                         self.do_delete(ino);
                     }
+
+                    // The lower layer's copy would show through once the upper one is gone
+                    if self
+                        .path_resolver
+                        .lower_path(&path)
+                        .symlink_metadata()
+                        .is_ok()
+                    {
+                        if let Err(e) = self.create_whiteout(parent, name) {
+                            reply.error(e);
+                            return;
+                        }
+                    }
                 }
                 LayerType::Lower => {
                     if let Err(e) = self.create_whiteout(parent, name) {
@@ -614,13 +887,47 @@ mod tests {
         let test_content = b"Hello, Treebeard!";
         fs::write(&src_path, test_content).unwrap();
 
-        TreebeardFs::clone_file_optimized(&src_path, &dest_path).unwrap();
+        OverlayFs::clone_file_optimized(&src_path, &dest_path).unwrap();
 
         assert!(dest_path.exists(), "Destination file should exist");
         let dest_content = fs::read(&dest_path).unwrap();
         assert_eq!(dest_content, test_content, "Content should match");
     }
 
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_clone_file_optimized_keeps_mode_and_large_content() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let src_path = temp_dir.path().join("artifact.bin");
+        let dest_path = temp_dir.path().join("copy.bin");
+
+        let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        fs::write(&src_path, &content).unwrap();
+        fs::set_permissions(&src_path, fs::Permissions::from_mode(0o755)).unwrap();
+        // An existing destination is overwritten, as with fs::copy
+        fs::write(&dest_path, "stale content that is longer than nothing").unwrap();
+
+        OverlayFs::clone_file_optimized(&src_path, &dest_path).unwrap();
+
+        assert_eq!(fs::read(&dest_path).unwrap(), content);
+        assert_eq!(
+            fs::metadata(&dest_path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+    }
+
+    #[test]
+    fn test_reflink_supported_removes_probe() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        // Whether it's supported depends on the filesystem under the temp dir
+        OverlayFs::reflink_supported(temp_dir.path()).unwrap();
+
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
     #[test]
     fn test_copy_up_locks_count() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -630,7 +937,7 @@ mod tests {
         fs::create_dir_all(&lower_layer).unwrap();
 
         // Create filesystem with 1 second TTL (the default from config)
-        let fs = TreebeardFs::new(upper_layer, lower_layer, None, 1, vec![]).unwrap();
+        let fs = OverlayFs::new(upper_layer, lower_layer, None, 1, vec![]).unwrap();
 
         // Initially, copy_up_locks should be empty
         assert_eq!(
@@ -639,4 +946,183 @@ mod tests {
             "copy_up_locks should be empty initially"
         );
     }
+
+    #[test]
+    fn test_copy_up_records_base() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upper_layer = temp_dir.path().join("upper");
+        let lower_layer = temp_dir.path().join("lower");
+        let journal_path = temp_dir.path().join("state/branch.jsonl");
+
+        fs::create_dir_all(&upper_layer).unwrap();
+        fs::create_dir_all(&lower_layer).unwrap();
+        fs::write(lower_layer.join(".env"), "KEY=1\n").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![])
+            .unwrap()
+            .with_journal(&journal_path)
+            .unwrap();
+
+        let (inode, _) = fs
+            .lookup_overlay(
+                fuser::FUSE_ROOT_ID,
+                OsString::from(".env"),
+                PathBuf::from(".env"),
+            )
+            .unwrap()
+            .unwrap();
+        let ino = inode.inode;
+        fs.inode_manager.insert(inode);
+        fs.copy_up(ino).unwrap();
+
+        assert!(upper_layer.join(".env").exists());
+        let bases = crate::overlay::BaseSnapshots::load(
+            &crate::overlay::BaseStore::dir_for_journal(&journal_path),
+        )
+        .unwrap();
+        assert_eq!(bases.content(Path::new(".env")), Some(b"KEY=1\n".to_vec()));
+    }
+
+    #[test]
+    fn test_copy_up_after_upper_copy_released() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upper_layer = temp_dir.path().join("upper");
+        let lower_layer = temp_dir.path().join("lower");
+
+        fs::create_dir_all(&upper_layer).unwrap();
+        fs::create_dir_all(&lower_layer).unwrap();
+        fs::write(lower_layer.join(".env"), "KEY=1\n").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+
+        let (inode, _) = fs
+            .lookup_overlay(
+                fuser::FUSE_ROOT_ID,
+                OsString::from(".env"),
+                PathBuf::from(".env"),
+            )
+            .unwrap()
+            .unwrap();
+        let ino = inode.inode;
+        fs.inode_manager.insert(inode);
+        fs.copy_up(ino).unwrap();
+        assert!(fs.inode_manager.is_in_upper_layer(ino));
+
+        // Synced back and handed to the lower layer from outside the overlay
+        fs::write(lower_layer.join(".env"), "KEY=2\n").unwrap();
+        fs::remove_file(upper_layer.join(".env")).unwrap();
+
+        fs.copy_up(ino).unwrap();
+        assert!(fs.inode_manager.is_in_upper_layer(ino));
+        assert_eq!(
+            fs::read_to_string(upper_layer.join(".env")).unwrap(),
+            "KEY=2\n"
+        );
+    }
+
+    fn lookup_and_insert(fs: &OverlayFs, parent: u64, rel_path: &str) -> Option<InodeData> {
+        let rel_path = PathBuf::from(rel_path);
+        let name = rel_path.file_name().unwrap().to_os_string();
+        let (inode, _) = fs.lookup_overlay(parent, name, rel_path).unwrap()?;
+        fs.inode_manager.insert(inode.clone());
+        Some(inode)
+    }
+
+    #[test]
+    fn test_opaque_directory_hides_lower_layer() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upper_layer = temp_dir.path().join("upper");
+        let lower_layer = temp_dir.path().join("lower");
+
+        fs::create_dir_all(lower_layer.join("build")).unwrap();
+        fs::write(lower_layer.join("build/old.o"), "stale").unwrap();
+        fs::create_dir_all(upper_layer.join("build")).unwrap();
+        fs::write(upper_layer.join("build/new.o"), "fresh").unwrap();
+        Whiteout::make_opaque(&upper_layer.join("build")).unwrap();
+
+        let fs = OverlayFs::new(upper_layer, lower_layer, None, 1, vec![]).unwrap();
+        let build = lookup_and_insert(&fs, fuser::FUSE_ROOT_ID, "./build").unwrap();
+
+        assert!(lookup_and_insert(&fs, build.inode, "./build/old.o").is_none());
+        assert!(lookup_and_insert(&fs, build.inode, "./build/new.o").is_some());
+        assert!(fs
+            .path_resolver
+            .resolve_path(Path::new("./build/old.o"), LayerType::Lower)
+            .is_none());
+
+        let mut entries = HashMap::new();
+        let mut whiteouts = HashSet::new();
+        let mut new_inodes = Vec::new();
+        let mut layer_updates = Vec::new();
+        for layer in [LayerType::Lower, LayerType::Upper] {
+            fs.scan_directory_layer(
+                &fs.path_resolver.layer_base_path(layer).join("./build"),
+                layer,
+                build.inode,
+                Path::new("./build"),
+                &mut entries,
+                &mut whiteouts,
+                &mut new_inodes,
+                &mut layer_updates,
+            );
+        }
+        let names: Vec<_> = entries.keys().cloned().collect();
+        assert_eq!(names, vec![OsString::from("new.o")]);
+        assert!(whiteouts.is_empty());
+    }
+
+    #[test]
+    fn test_clear_directory_for_removal() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upper_layer = temp_dir.path().join("upper");
+        let lower_layer = temp_dir.path().join("lower");
+
+        fs::create_dir_all(lower_layer.join("build")).unwrap();
+        fs::write(lower_layer.join("build/a.o"), "a").unwrap();
+        fs::write(lower_layer.join("build/b.o"), "b").unwrap();
+        fs::create_dir_all(&upper_layer).unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        let build = lookup_and_insert(&fs, fuser::FUSE_ROOT_ID, "./build").unwrap();
+        let name = OsStr::new("build");
+
+        // Lower-layer entries that aren't whited out keep it from being removed
+        fs.create_whiteout(build.inode, OsStr::new("a.o")).unwrap();
+        assert_eq!(
+            fs.clear_directory_for_removal(fuser::FUSE_ROOT_ID, name),
+            Err(libc::ENOTEMPTY)
+        );
+
+        fs.create_whiteout(build.inode, OsStr::new("b.o")).unwrap();
+        fs.clear_directory_for_removal(fuser::FUSE_ROOT_ID, name)
+            .unwrap();
+        assert!(!upper_layer.join("build").exists());
+        assert!(lower_layer.join("build/a.o").exists());
+    }
+
+    #[test]
+    fn test_clear_opaque_directory_ignores_lower_layer() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upper_layer = temp_dir.path().join("upper");
+        let lower_layer = temp_dir.path().join("lower");
+
+        fs::create_dir_all(lower_layer.join("build")).unwrap();
+        fs::write(lower_layer.join("build/old.o"), "stale").unwrap();
+        fs::create_dir_all(upper_layer.join("build")).unwrap();
+        Whiteout::make_opaque(&upper_layer.join("build")).unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![]).unwrap();
+        lookup_and_insert(&fs, fuser::FUSE_ROOT_ID, "./build").unwrap();
+
+        fs.clear_directory_for_removal(fuser::FUSE_ROOT_ID, OsStr::new("build"))
+            .unwrap();
+        // The marker is gone, leaving the upper copy empty for rmdir to remove
+        assert_eq!(fs::read_dir(upper_layer.join("build")).unwrap().count(), 0);
+
+        fs::write(upper_layer.join("build/new.o"), "fresh").unwrap();
+        assert_eq!(
+            fs.clear_directory_for_removal(fuser::FUSE_ROOT_ID, OsStr::new("build")),
+            Err(libc::ENOTEMPTY)
+        );
+    }
 }
@@ -5,7 +5,8 @@ use std::ffi::{OsStr, OsString};
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use crate::overlay::types::{InodeData, InodeTable, LayerType};
+use crate::config::InodeCacheConfig;
+use crate::overlay::types::{InodeCacheStats, InodeData, InodeTable, LayerType};
 
 /// Manages inode allocation, the inode table, and related operations.
 ///
@@ -42,6 +43,16 @@ impl InodeManager {
         }
     }
 
+    /// Apply the inode cache's capacity and pinning settings.
+    pub fn configure_cache(&self, config: &InodeCacheConfig) {
+        self.inodes.write().configure(config);
+    }
+
+    /// Hit, miss and eviction counts for the inode cache.
+    pub fn cache_stats(&self) -> InodeCacheStats {
+        self.inodes.read().stats()
+    }
+
     /// Allocate a new unique inode number.
     pub fn alloc_inode(&self) -> u64 {
         let mut next = self.next_ino.lock();
@@ -58,6 +69,22 @@ impl InodeManager {
         self.inodes.write().insert(inode);
     }
 
+    /// Insert an inode found by a lookup, unless a concurrent lookup of the
+    /// same name cached one first. Returns the attributes of the cached inode,
+    /// so both lookups reply with the same inode number.
+    pub fn insert_if_absent(&self, inode: InodeData) -> FileAttr {
+        let mut inodes = self.inodes.write();
+        if let Some(existing) = inodes
+            .lookup_child(inode.parent, &inode.name)
+            .and_then(|ino| inodes.peek(ino))
+        {
+            return existing.attrs;
+        }
+        let attrs = inode.attrs;
+        inodes.insert(inode);
+        attrs
+    }
+
     /// Look up an inode by number, returning a clone of its path, layer, and open handle status.
     ///
     /// This is the common lookup pattern used throughout FUSE callbacks.
@@ -85,6 +112,27 @@ impl InodeManager {
         inodes.lookup_child(parent, name)
     }
 
+    /// Look up a child inode for a kernel lookup request, counting the
+    /// result as an inode cache hit or miss.
+    pub fn lookup_cached(&self, parent: u64, name: &OsStr) -> Option<u64> {
+        let inodes = self.inodes.read();
+        inodes.lookup_cached(parent, name)
+    }
+
+    /// Record that the kernel was handed this inode in an entry reply. It
+    /// won't be evicted until the kernel forgets it.
+    pub fn add_lookup(&self, ino: u64) {
+        if !self.inodes.write().add_lookup(ino) {
+            tracing::warn!("Inode {} was evicted before its lookup was recorded", ino);
+        }
+    }
+
+    /// Release `nlookup` kernel references to an inode. Returns how many the
+    /// kernel still holds.
+    pub fn forget(&self, ino: u64, nlookup: u64) -> u64 {
+        self.inodes.write().forget(ino, nlookup)
+    }
+
     /// Add a child to a parent directory.
     pub fn add_child(&self, parent: u64, name: OsString, ino: u64) {
         self.inodes.write().add_child(parent, name, ino);
@@ -105,6 +153,15 @@ impl InodeManager {
         }
     }
 
+    /// Point an inode at `layer` with the attributes of its file there.
+    pub fn update_layer_and_attrs(&self, ino: u64, layer: LayerType, attrs: FileAttr) {
+        let mut inodes = self.inodes.write();
+        if let Some(inode) = inodes.get_mut(ino) {
+            inode.layer = layer;
+            inode.attrs = attrs;
+        }
+    }
+
     /// Update the attributes of an inode.
     pub fn update_attrs(&self, ino: u64, attrs: FileAttr) {
         self.inodes.write().update_attrs(ino, attrs);
@@ -190,6 +247,46 @@ impl InodeManager {
             inode.name = new_name;
             inode.layer = layer;
         }
+        inodes.rebase_descendants(ino);
+    }
+
+    /// Update both inodes after a `RENAME_EXCHANGE` swapped their names.
+    /// Each side is `(inode, parent, name)` as it was before the exchange.
+    pub fn update_after_exchange(
+        &self,
+        a: (u64, u64, &OsStr),
+        b: (u64, u64, &OsStr),
+        layer: LayerType,
+    ) {
+        let mut inodes = self.inodes.write();
+        for ((ino, _, _), (_, parent, name)) in [(a, b), (b, a)] {
+            let Some(parent_path) = inodes.peek(parent).map(|p| Arc::clone(&p.path)) else {
+                continue;
+            };
+            inodes.add_child(parent, name.to_os_string(), ino);
+            if let Some(inode) = inodes.get_mut(ino) {
+                inode.path = Arc::new(parent_path.join(name));
+                inode.parent = parent;
+                inode.name = name.to_os_string();
+                inode.layer = layer;
+            }
+            inodes.rebase_descendants(ino);
+        }
+    }
+
+    /// Drop an inode whose name was taken over by a rename. An inode that
+    /// is still open stays in the table, unlinked, so its handles keep
+    /// working until release.
+    pub fn detach_replaced(&self, ino: u64) {
+        let mut inodes = self.inodes.write();
+        match inodes.get_mut(ino) {
+            Some(inode) if inode.open_file_handles > 0 => {
+                inode.hardlinks = 0;
+                inode.attrs.nlink = 0;
+            }
+            Some(_) => inodes.remove(ino),
+            None => {}
+        }
     }
 
     /// Mark an inode as deleted (has open handles, will be garbage collected later).
@@ -268,6 +365,7 @@ impl InodeManager {
             } else {
                 1
             },
+            lookups: 0,
         }
     }
 
@@ -367,6 +465,34 @@ mod tests {
         assert!(!has_open);
     }
 
+    #[test]
+    fn test_insert_if_absent_keeps_first_lookup() {
+        let manager = InodeManager::new();
+
+        let first = InodeManager::create_inode_data(
+            100,
+            1,
+            OsString::from("test.txt"),
+            LayerType::Lower,
+            PathBuf::from("./test.txt"),
+            create_test_attrs(100),
+        );
+        let second = InodeManager::create_inode_data(
+            101,
+            1,
+            OsString::from("test.txt"),
+            LayerType::Lower,
+            PathBuf::from("./test.txt"),
+            create_test_attrs(101),
+        );
+
+        assert_eq!(manager.insert_if_absent(first).ino, 100);
+        // A concurrent lookup of the same name reuses the cached inode
+        assert_eq!(manager.insert_if_absent(second).ino, 100);
+        assert_eq!(manager.lookup_child(1, OsStr::new("test.txt")), Some(100));
+        assert!(manager.get_inode_info(101).is_none());
+    }
+
     #[test]
     fn test_open_handles() {
         let manager = InodeManager::new();
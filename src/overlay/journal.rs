@@ -0,0 +1,331 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, TreebeardError};
+use crate::overlay::base::BaseStore;
+use crate::overlay::types::MutationType;
+
+/// A single journaled mutation, stored as one JSON object per line.
+///
+/// A `null` mutation means the path was synced mid-session and handed back
+/// to the lower layer, so it no longer counts as changed.
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    path: PathBuf,
+    mutation: Option<MutationType>,
+}
+
+/// Append-only on-disk record of overlay mutations.
+///
+/// The in-memory `MutationTracker` is lost if treebeard crashes. Every
+/// mutation is also appended here so that a later `cleanup` or a re-run of
+/// `treebeard branch` can rebuild the map and still offer the sync flow.
+/// Entries are replayed in order, so later events for a path win.
+pub struct MutationJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl MutationJournal {
+    /// Opens (or creates) the journal at `path` for appending.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                TreebeardError::Config(format!(
+                    "Failed to create journal directory {}: {}",
+                    parent.display(),
+                    e
+                ))
+            })?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                TreebeardError::Config(format!(
+                    "Failed to open mutation journal {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends a mutation event. Each entry is written with a single `write`
+    /// call so a crash never leaves a partially written line behind.
+    pub fn append(&self, relative_path: &Path, mutation: &MutationType) -> Result<()> {
+        self.write_entry(JournalEntry {
+            path: relative_path.to_path_buf(),
+            mutation: Some(mutation.clone()),
+        })
+    }
+
+    /// Records that `relative_path` no longer differs from the lower layer.
+    /// A later `append` for the same path makes it count as changed again.
+    pub fn forget(&self, relative_path: &Path) -> Result<()> {
+        self.write_entry(JournalEntry {
+            path: relative_path.to_path_buf(),
+            mutation: None,
+        })
+    }
+
+    fn write_entry(&self, entry: JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        self.file.lock().write_all(&line).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to append to mutation journal {}: {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Rebuilds the mutation map from the journal at `path`.
+    ///
+    /// Returns an empty map if the journal does not exist. Lines that cannot
+    /// be parsed (e.g. a torn write at the end of the file) are skipped.
+    pub fn load(path: &Path) -> Result<HashMap<PathBuf, MutationType>> {
+        Ok(Self::replay(path)?
+            .into_iter()
+            .filter_map(|(path, mutation)| mutation.map(|m| (path, m)))
+            .collect())
+    }
+
+    /// Returns the paths whose last journal entry is a `forget`.
+    ///
+    /// A running session's in-memory tracker still lists these; callers that
+    /// start from the tracker use this to drop them.
+    pub fn load_forgotten(path: &Path) -> Result<HashSet<PathBuf>> {
+        Ok(Self::replay(path)?
+            .into_iter()
+            .filter(|(_, mutation)| mutation.is_none())
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    fn replay(path: &Path) -> Result<HashMap<PathBuf, Option<MutationType>>> {
+        let mut mutations = HashMap::new();
+
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(mutations),
+            Err(e) => {
+                return Err(TreebeardError::Config(format!(
+                    "Failed to read mutation journal {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(TreebeardError::Io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<JournalEntry>(&line) {
+                Ok(entry) => {
+                    mutations.insert(entry.path, entry.mutation);
+                }
+                Err(e) => {
+                    tracing::warn!("Skipping corrupt mutation journal entry: {}", e);
+                }
+            }
+        }
+
+        Ok(mutations)
+    }
+
+    /// Deletes the journal at `path` along with its base store. A missing
+    /// journal is not an error.
+    pub fn remove(path: &Path) -> Result<()> {
+        BaseStore::remove(&BaseStore::dir_for_journal(path))?;
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(TreebeardError::Config(format!(
+                "Failed to remove mutation journal {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("nested/branch.jsonl");
+
+        let journal = MutationJournal::open(&path).unwrap();
+        journal
+            .append(Path::new(".env"), &MutationType::CopiedUp)
+            .unwrap();
+        journal
+            .append(Path::new("build/out.o"), &MutationType::Created)
+            .unwrap();
+        journal
+            .append(Path::new("old.log"), &MutationType::Deleted)
+            .unwrap();
+
+        let mutations = MutationJournal::load(&path).unwrap();
+        assert_eq!(mutations.len(), 3);
+        assert_eq!(
+            mutations.get(Path::new(".env")),
+            Some(&MutationType::CopiedUp)
+        );
+        assert_eq!(
+            mutations.get(Path::new("build/out.o")),
+            Some(&MutationType::Created)
+        );
+        assert_eq!(
+            mutations.get(Path::new("old.log")),
+            Some(&MutationType::Deleted)
+        );
+    }
+
+    #[test]
+    fn test_journal_later_entries_win() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("branch.jsonl");
+
+        let journal = MutationJournal::open(&path).unwrap();
+        journal
+            .append(Path::new("a.txt"), &MutationType::Created)
+            .unwrap();
+        journal
+            .append(Path::new("a.txt"), &MutationType::Deleted)
+            .unwrap();
+
+        let mutations = MutationJournal::load(&path).unwrap();
+        assert_eq!(
+            mutations.get(Path::new("a.txt")),
+            Some(&MutationType::Deleted)
+        );
+    }
+
+    #[test]
+    fn test_journal_forget() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("branch.jsonl");
+
+        let journal = MutationJournal::open(&path).unwrap();
+        journal
+            .append(Path::new(".env"), &MutationType::CopiedUp)
+            .unwrap();
+        journal
+            .append(Path::new("gen.rs"), &MutationType::Created)
+            .unwrap();
+        journal.forget(Path::new(".env")).unwrap();
+        journal.forget(Path::new("gen.rs")).unwrap();
+        journal
+            .append(Path::new("gen.rs"), &MutationType::CopiedUp)
+            .unwrap();
+
+        let mutations = MutationJournal::load(&path).unwrap();
+        assert_eq!(mutations.len(), 1);
+        assert_eq!(
+            mutations.get(Path::new("gen.rs")),
+            Some(&MutationType::CopiedUp)
+        );
+
+        let forgotten = MutationJournal::load_forgotten(&path).unwrap();
+        assert_eq!(forgotten, HashSet::from([PathBuf::from(".env")]));
+    }
+
+    #[test]
+    fn test_journal_appends_across_reopen() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("branch.jsonl");
+
+        MutationJournal::open(&path)
+            .unwrap()
+            .append(Path::new("first"), &MutationType::Created)
+            .unwrap();
+        MutationJournal::open(&path)
+            .unwrap()
+            .append(Path::new("second"), &MutationType::CopiedUp)
+            .unwrap();
+
+        assert_eq!(MutationJournal::load(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_journal_skips_torn_trailing_line() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("branch.jsonl");
+        fs::write(
+            &path,
+            "{\"path\":\"ok.txt\",\"mutation\":\"created\"}\n{\"path\":\"tor",
+        )
+        .unwrap();
+
+        let mutations = MutationJournal::load(&path).unwrap();
+        assert_eq!(mutations.len(), 1);
+        assert!(mutations.contains_key(Path::new("ok.txt")));
+    }
+
+    #[test]
+    fn test_journal_load_and_remove_missing() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("missing.jsonl");
+
+        assert!(MutationJournal::load(&path).unwrap().is_empty());
+        assert!(MutationJournal::remove(&path).is_ok());
+    }
+
+    #[test]
+    fn test_treebeard_fs_with_journal_recovers_and_records() {
+        let upper = tempfile::tempdir().unwrap();
+        let lower = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let path = state.path().join("branch.jsonl");
+
+        MutationJournal::open(&path)
+            .unwrap()
+            .append(Path::new("recovered.txt"), &MutationType::CopiedUp)
+            .unwrap();
+
+        let fs = crate::overlay::OverlayFs::new(
+            upper.path().to_path_buf(),
+            lower.path().to_path_buf(),
+            None,
+            1,
+            vec![],
+        )
+        .unwrap()
+        .with_journal(&path)
+        .unwrap();
+
+        assert_eq!(
+            fs.mutations.read().get(Path::new("recovered.txt")),
+            Some(&MutationType::CopiedUp)
+        );
+
+        fs.record_mutation(Path::new("new.txt"), MutationType::Created);
+
+        let reloaded = MutationJournal::load(&path).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(
+            reloaded.get(Path::new("new.txt")),
+            Some(&MutationType::Created)
+        );
+    }
+}
@@ -0,0 +1,632 @@
+//! Advisory file locking.
+//!
+//! FUSE forwards `fcntl` and `flock` locks as byte ranges on a file handle
+//! (`flock` always covers the whole file). On Linux they're taken as open file
+//! description locks on the handle's real file, so they conflict with each
+//! other and with `fcntl` locks that processes outside the mount take on the
+//! same file. They don't conflict with `flock()` locks taken on the file
+//! outside the mount, which the kernel keeps apart from `fcntl` locks. Locks
+//! belong to the handle: a process holding two handles on one file can
+//! conflict with itself, and closing one doesn't release the other's locks,
+//! the same as locks taken with `F_OFD_SETLK`.
+//!
+//! A lower-layer handle's locks live on the lower file, taken through a
+//! read-write reopening of it (see [`FileHandle::lock_file`]) so that write
+//! locks work on files opened read-only, as `flock` does. When the file is
+//! copied up, each open handle is moved to the upper copy and its locks
+//! re-acquired there before the copy becomes visible, so they keep
+//! conflicting with locks taken through handles opened afterwards.
+//!
+//! Blocking requests don't wait in `F_OFD_SETLKW`. A [`LockWaiter`] retries
+//! them until they're granted or the process that made them exits.
+//!
+//! macOS has no open file description locks, so no locks are forwarded and
+//! macFUSE keeps them local to the mount.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::overlay::file_handle::FileHandle;
+use crate::overlay::types::LayerType;
+use crate::overlay::OverlayFs;
+
+// FUSE passes lock types as `i32`, while their libc type varies by platform
+#[allow(clippy::unnecessary_cast)]
+const F_UNLCK: i32 = libc::F_UNLCK as i32;
+#[allow(clippy::unnecessary_cast)]
+const F_WRLCK: i32 = libc::F_WRLCK as i32;
+
+/// The end FUSE uses for a range that extends to the end of the file.
+const OFFSET_MAX: u64 = i64::MAX as u64;
+
+/// How long a blocked lock request waits before it's first tried again. The
+/// wait doubles with each try, up to [`MAX_LOCK_RETRY_DELAY`].
+const MIN_LOCK_RETRY_DELAY: Duration = Duration::from_millis(1);
+const MAX_LOCK_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// A locked byte range. `end` is inclusive, as in FUSE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LockRange {
+    pub start: u64,
+    pub end: u64,
+    pub typ: i32,
+}
+
+impl LockRange {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+}
+
+/// The ranges locked through one file handle, so they can be re-acquired on
+/// another file.
+#[derive(Debug, Default)]
+pub(crate) struct HeldLocks {
+    ranges: Vec<LockRange>,
+    /// The process that last locked through the handle, reported by getlk
+    pid: u32,
+}
+
+impl HeldLocks {
+    /// Records a lock or unlock of `start..=end`, splitting any held ranges
+    /// it partly covers as `fcntl` does.
+    pub fn apply(&mut self, range: LockRange) {
+        let mut kept = Vec::with_capacity(self.ranges.len() + 2);
+        for held in self.ranges.drain(..) {
+            if !held.overlaps(range.start, range.end) {
+                kept.push(held);
+                continue;
+            }
+            if held.start < range.start {
+                kept.push(LockRange {
+                    end: range.start - 1,
+                    ..held
+                });
+            }
+            if held.end > range.end {
+                kept.push(LockRange {
+                    start: range.end + 1,
+                    ..held
+                });
+            }
+        }
+        if range.typ != F_UNLCK {
+            kept.push(range);
+        }
+        self.ranges = kept;
+    }
+
+    /// Whether a lock of `typ` on `start..=end` conflicts with a held range.
+    fn conflicts(&self, start: u64, end: u64, typ: i32) -> bool {
+        self.ranges
+            .iter()
+            .any(|held| held.overlaps(start, end) && (held.typ == F_WRLCK || typ == F_WRLCK))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn to_flock(range: LockRange) -> libc::flock {
+    // SAFETY: flock is a plain C struct for which all zeroes is valid.
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = range.typ as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    lock.l_start = range.start as libc::off_t;
+    // A length of 0 extends to the end of the file
+    lock.l_len = if range.end >= OFFSET_MAX {
+        0
+    } else {
+        (range.end - range.start + 1) as libc::off_t
+    };
+    lock
+}
+
+/// Takes or releases an open file description lock on `file`, failing with
+/// `EAGAIN` if a conflicting lock is held.
+#[cfg(target_os = "linux")]
+fn set_lock(file: &File, range: LockRange) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let lock = to_flock(range);
+    // SAFETY: The descriptor stays open for the duration of the call and
+    // `lock` is a valid flock struct.
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_OFD_SETLK, &lock) } == 0 {
+        return Ok(());
+    }
+    Err(io::Error::last_os_error())
+}
+
+/// Returns the first lock that would conflict with `range`, if any.
+#[cfg(target_os = "linux")]
+fn get_lock(file: &File, range: LockRange) -> io::Result<Option<LockRange>> {
+    use std::os::fd::AsRawFd;
+
+    let mut lock = to_flock(range);
+    // SAFETY: The descriptor stays open for the duration of the call and
+    // `lock` is a valid flock struct that fcntl fills in.
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_OFD_GETLK, &mut lock) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if i32::from(lock.l_type) == F_UNLCK {
+        return Ok(None);
+    }
+    let start = lock.l_start as u64;
+    Ok(Some(LockRange {
+        start,
+        end: if lock.l_len == 0 {
+            OFFSET_MAX
+        } else {
+            start + lock.l_len as u64 - 1
+        },
+        typ: i32::from(lock.l_type),
+    }))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_lock(_file: &File, _range: LockRange) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_lock(_file: &File, _range: LockRange) -> io::Result<Option<LockRange>> {
+    Err(io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+/// Whether process `pid` is still running. The kernel reports 0 when the
+/// locking process isn't visible to the overlay, which is taken as running.
+fn process_exists(pid: u32) -> bool {
+    if pid == 0 {
+        return true;
+    }
+    // SAFETY: Signal 0 only checks that the process exists.
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+type LockReply = Box<dyn FnOnce(Result<(), i32>) + Send>;
+
+/// A blocking lock request waiting for conflicting locks to go away.
+struct BlockedLock {
+    fh: u64,
+    range: LockRange,
+    pid: u32,
+    retry_at: Instant,
+    delay: Duration,
+    reply: LockReply,
+}
+
+/// Waits for blocking lock requests on a single thread.
+///
+/// fuser answers `FUSE_INTERRUPT` itself without passing it on, so a request
+/// blocked in `F_OFD_SETLKW` can't be cancelled, and would hold its thread
+/// and later take the lock for a process that's gone. Instead each request is
+/// retried without blocking, backing off from [`MIN_LOCK_RETRY_DELAY`] to
+/// [`MAX_LOCK_RETRY_DELAY`], and dropped once its process has exited.
+pub(crate) struct LockWaiter {
+    sender: mpsc::Sender<BlockedLock>,
+}
+
+impl LockWaiter {
+    pub fn spawn(overlay: Arc<OverlayFs>) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("treebeard-fuse-lock".to_string())
+            .spawn(move || wait_for_locks(&overlay, receiver))?;
+        Ok(LockWaiter { sender })
+    }
+
+    /// Queues a blocking request for `range` on handle `fh`. `reply` is
+    /// called with the result once the lock is granted or the request fails.
+    pub fn wait(
+        &self,
+        fh: u64,
+        range: LockRange,
+        pid: u32,
+        reply: impl FnOnce(Result<(), i32>) + Send + 'static,
+    ) {
+        let blocked = BlockedLock {
+            fh,
+            range,
+            pid,
+            retry_at: Instant::now(),
+            delay: MIN_LOCK_RETRY_DELAY,
+            reply: Box::new(reply),
+        };
+        if self.sender.send(blocked).is_err() {
+            tracing::error!("Lock waiter thread has exited, dropping lock request");
+        }
+    }
+}
+
+/// Retries blocked requests as they come due until the [`LockWaiter`] is
+/// dropped. Requests still waiting then are dropped too.
+fn wait_for_locks(overlay: &OverlayFs, receiver: mpsc::Receiver<BlockedLock>) {
+    let mut blocked: Vec<BlockedLock> = Vec::new();
+    loop {
+        let received = match blocked.iter().map(|b| b.retry_at).min() {
+            Some(retry_at) => {
+                receiver.recv_timeout(retry_at.saturating_duration_since(Instant::now()))
+            }
+            None => receiver
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(request) => blocked.push(request),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+
+        let now = Instant::now();
+        for mut request in std::mem::take(&mut blocked) {
+            if request.retry_at > now {
+                blocked.push(request);
+                continue;
+            }
+            match overlay.try_blocked_lock(request.fh, request.range, request.pid) {
+                Some(result) => (request.reply)(result),
+                None => {
+                    request.retry_at = now + request.delay;
+                    request.delay = (request.delay * 2).min(MAX_LOCK_RETRY_DELAY);
+                    blocked.push(request);
+                }
+            }
+        }
+    }
+}
+
+fn lock_error(e: &io::Error) -> i32 {
+    match e.raw_os_error() {
+        // fcntl reports a conflict as either
+        Some(libc::EACCES) | Some(libc::EAGAIN) => libc::EAGAIN,
+        Some(errno) => errno,
+        None => libc::EIO,
+    }
+}
+
+impl OverlayFs {
+    pub(crate) fn handle(&self, fh: u64) -> Result<Arc<FileHandle>, i32> {
+        self.file_handles
+            .get(&fh)
+            .map(|h| Arc::clone(h.value()))
+            .ok_or(libc::EBADF)
+    }
+
+    /// Tests for a lock conflicting with `range` on handle `fh`, returning
+    /// the conflicting lock, or `range` with type `F_UNLCK` if there is none.
+    pub(crate) fn do_getlk(&self, fh: u64, range: LockRange) -> Result<(LockRange, u32), i32> {
+        let handle = self.handle(fh)?;
+        let Some(conflict) = get_lock(&handle.lock_file(), range).map_err(|e| lock_error(&e))?
+        else {
+            return Ok((
+                LockRange {
+                    typ: F_UNLCK,
+                    ..range
+                },
+                0,
+            ));
+        };
+
+        // Open file description locks have no owning process, so report the
+        // one that took it if it was taken through the overlay
+        let others: Vec<Arc<FileHandle>> = self
+            .file_handles
+            .iter()
+            .filter(|h| h.ino == handle.ino && !Arc::ptr_eq(h.value(), &handle))
+            .map(|h| Arc::clone(h.value()))
+            .collect();
+        let pid = others
+            .iter()
+            .find_map(|other| {
+                let locks = other.locks.lock();
+                locks
+                    .conflicts(range.start, range.end, range.typ)
+                    .then_some(locks.pid)
+            })
+            .unwrap_or(0);
+
+        Ok((conflict, pid))
+    }
+
+    /// Takes or releases a lock on handle `fh`, failing with `EAGAIN` if a
+    /// conflicting lock is held.
+    pub(crate) fn do_setlk(&self, fh: u64, range: LockRange, pid: u32) -> Result<(), i32> {
+        let handle = self.handle(fh)?;
+        loop {
+            let file = handle.lock_file();
+            set_lock(&file, range).map_err(|e| lock_error(&e))?;
+
+            let mut locks = handle.locks.lock();
+            if Arc::ptr_eq(&file, &handle.lock_file()) {
+                locks.apply(range);
+                if range.typ != F_UNLCK {
+                    locks.pid = pid;
+                }
+                return Ok(());
+            }
+            // The handle was moved to the upper copy while this lock was being
+            // taken on the lower file. Try again on the file it uses now; the
+            // lower lock goes away with the last reference to the old file.
+            tracing::debug!("setlk: handle {} was copied up, retrying lock", fh);
+        }
+    }
+
+    /// Makes one attempt at a blocking lock request from process `pid`.
+    /// Returns `None` while a conflicting lock is held, or the result to
+    /// reply with once the request is settled.
+    ///
+    /// A request from a process that has exited is dropped, and a lock
+    /// granted just as its process exited is released again.
+    fn try_blocked_lock(&self, fh: u64, range: LockRange, pid: u32) -> Option<Result<(), i32>> {
+        if !process_exists(pid) {
+            return Some(Err(libc::EINTR));
+        }
+        match self.do_setlk(fh, range, pid) {
+            Err(libc::EAGAIN) => None,
+            Ok(()) if !process_exists(pid) => {
+                let _ = self.do_setlk(
+                    fh,
+                    LockRange {
+                        typ: F_UNLCK,
+                        ..range
+                    },
+                    pid,
+                );
+                Some(Err(libc::EINTR))
+            }
+            result => Some(result),
+        }
+    }
+
+    /// Moves handles open on `ino`'s lower-layer file to its upper copy at
+    /// `upper_path`, re-acquiring their locks there.
+    ///
+    /// Called during copy-up with the inode's copy-up lock held, so no handle
+    /// can be opened on the upper copy, and lock it, until this finishes.
+    pub(crate) fn reopen_handles_after_copy_up(&self, ino: u64, upper_path: &Path) {
+        let handles: Vec<Arc<FileHandle>> = self
+            .file_handles
+            .iter()
+            .filter(|h| h.ino == ino && h.layer() == LayerType::Lower)
+            .map(|h| Arc::clone(h.value()))
+            .collect();
+
+        for handle in handles {
+            let locks = handle.locks.lock();
+            // Upper-layer files are always opened read-write, see `open`
+            let file = match File::options().read(true).write(true).open(upper_path) {
+                Ok(f) => f,
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to reopen {} after copy-up: {}",
+                        upper_path.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            for range in &locks.ranges {
+                // Nothing else can lock the fresh copy yet, so this can't conflict
+                if let Err(e) = set_lock(&file, *range) {
+                    tracing::warn!(
+                        "Failed to move lock on {} to its upper copy: {}",
+                        upper_path.display(),
+                        e
+                    );
+                }
+            }
+            handle.replace(file, LayerType::Upper);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay::test_helpers::{lookup, open_handle, setup};
+    use std::fs;
+    use std::os::unix::fs::FileExt;
+
+    #[allow(clippy::unnecessary_cast)]
+    const F_RDLCK: i32 = libc::F_RDLCK as i32;
+
+    fn range(start: u64, end: u64, typ: i32) -> LockRange {
+        LockRange { start, end, typ }
+    }
+
+    #[test]
+    fn test_held_locks_split_on_partial_unlock() {
+        let mut locks = HeldLocks::default();
+        locks.apply(range(0, 99, F_WRLCK));
+        locks.apply(range(40, 59, F_UNLCK));
+
+        assert_eq!(
+            locks.ranges,
+            vec![range(0, 39, F_WRLCK), range(60, 99, F_WRLCK)]
+        );
+        assert!(!locks.conflicts(40, 59, F_WRLCK));
+        assert!(locks.conflicts(50, 70, F_RDLCK));
+    }
+
+    #[test]
+    fn test_held_locks_downgrade_and_unlock_all() {
+        let mut locks = HeldLocks::default();
+        locks.apply(range(10, OFFSET_MAX, F_WRLCK));
+        locks.apply(range(10, 19, F_RDLCK));
+
+        assert_eq!(
+            locks.ranges,
+            vec![range(20, OFFSET_MAX, F_WRLCK), range(10, 19, F_RDLCK)]
+        );
+        assert!(!locks.conflicts(0, 15, F_RDLCK));
+        assert!(locks.conflicts(0, 15, F_WRLCK));
+
+        locks.apply(range(0, OFFSET_MAX, F_UNLCK));
+        assert!(locks.ranges.is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_locks_conflict_between_handles() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(upper_layer.join("app.db"), "data").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, "app.db").inode;
+        let path = upper_layer.join("app.db");
+        let a = open_handle(&fs, ino, &path, LayerType::Upper);
+        let b = open_handle(&fs, ino, &path, LayerType::Upper);
+
+        fs.do_setlk(a, range(0, 9, F_WRLCK), 100).unwrap();
+        assert_eq!(fs.do_setlk(b, range(5, 5, F_RDLCK), 200), Err(libc::EAGAIN));
+        // Locks on other ranges don't conflict
+        fs.do_setlk(b, range(10, OFFSET_MAX, F_WRLCK), 200).unwrap();
+
+        let (conflict, pid) = fs.do_getlk(b, range(0, 0, F_RDLCK)).unwrap();
+        assert_eq!(
+            (conflict.start, conflict.end, conflict.typ),
+            (0, 9, F_WRLCK)
+        );
+        assert_eq!(pid, 100);
+
+        fs.do_setlk(a, range(0, OFFSET_MAX, F_UNLCK), 100).unwrap();
+        let (free, _) = fs.do_getlk(b, range(0, 9, F_WRLCK)).unwrap();
+        assert_eq!(free.typ, F_UNLCK);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_locks_move_to_upper_copy_on_copy_up() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("app.db"), "data").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, "app.db").inode;
+
+        // A reader locks the lower file, then a writer's open copies it up
+        let reader = open_handle(&fs, ino, &lower_layer.join("app.db"), LayerType::Lower);
+        fs.do_setlk(reader, range(0, OFFSET_MAX, F_RDLCK), 100)
+            .unwrap();
+        fs.copy_up(ino).unwrap();
+        let writer = open_handle(&fs, ino, &upper_layer.join("app.db"), LayerType::Upper);
+
+        // The reader's lock now lives on the copy the writer has open
+        assert_eq!(
+            fs.do_setlk(writer, range(0, 0, F_WRLCK), 200),
+            Err(libc::EAGAIN)
+        );
+        fs.do_setlk(reader, range(0, OFFSET_MAX, F_UNLCK), 100)
+            .unwrap();
+        fs.do_setlk(writer, range(0, 0, F_WRLCK), 200).unwrap();
+
+        // And the reader sees writes made through the copy
+        let reader_handle = fs.handle(reader).unwrap();
+        assert_eq!(reader_handle.layer(), LayerType::Upper);
+        fs.handle(writer)
+            .unwrap()
+            .file()
+            .write_at(b"DATA", 0)
+            .unwrap();
+        let mut buf = [0u8; 4];
+        reader_handle.file().read_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"DATA");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_write_lock_on_read_only_lower_handle() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("Cargo.lock"), "data").unwrap();
+
+        let fs = OverlayFs::new(upper_layer, lower_layer.clone(), None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, "Cargo.lock").inode;
+        let path = lower_layer.join("Cargo.lock");
+        let a = open_handle(&fs, ino, &path, LayerType::Lower);
+        let b = open_handle(&fs, ino, &path, LayerType::Lower);
+
+        // flock(LOCK_EX) on a read-only descriptor, then an upgrade of a read lock
+        fs.do_setlk(a, range(0, OFFSET_MAX, F_WRLCK), 100).unwrap();
+        assert_eq!(
+            fs.do_setlk(b, range(0, OFFSET_MAX, F_RDLCK), 200),
+            Err(libc::EAGAIN)
+        );
+        fs.do_setlk(a, range(0, OFFSET_MAX, F_RDLCK), 100).unwrap();
+        fs.do_setlk(b, range(0, OFFSET_MAX, F_RDLCK), 200).unwrap();
+        fs.do_setlk(b, range(0, OFFSET_MAX, F_UNLCK), 200).unwrap();
+        fs.do_setlk(a, range(0, OFFSET_MAX, F_WRLCK), 100).unwrap();
+
+        // The handle's own lock doesn't show up as a conflict
+        let (free, _) = fs.do_getlk(a, range(0, 0, F_WRLCK)).unwrap();
+        assert_eq!(free.typ, F_UNLCK);
+
+        // Locking didn't copy the file up or write to it
+        assert_eq!(fs.handle(a).unwrap().layer(), LayerType::Lower);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_lock_waiter_grants_lock_once_released() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(upper_layer.join("app.db"), "data").unwrap();
+
+        let fs =
+            Arc::new(OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![]).unwrap());
+        let ino = lookup(&fs, "app.db").inode;
+        let path = upper_layer.join("app.db");
+        let a = open_handle(&fs, ino, &path, LayerType::Upper);
+        let b = open_handle(&fs, ino, &path, LayerType::Upper);
+        fs.do_setlk(a, range(0, OFFSET_MAX, F_WRLCK), 100).unwrap();
+
+        let waiter = LockWaiter::spawn(Arc::clone(&fs)).unwrap();
+        let (tx, rx) = mpsc::channel();
+        waiter.wait(
+            b,
+            range(0, OFFSET_MAX, F_WRLCK),
+            std::process::id(),
+            move |result| {
+                tx.send(result).unwrap();
+            },
+        );
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+
+        fs.do_setlk(a, range(0, OFFSET_MAX, F_UNLCK), 100).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), Ok(()));
+        assert_eq!(fs.do_setlk(a, range(0, 0, F_RDLCK), 100), Err(libc::EAGAIN));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_blocked_lock_dropped_when_process_exits() {
+        let (_temp_dir, upper_layer, lower_layer) = setup();
+        fs::write(upper_layer.join("app.db"), "data").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, "app.db").inode;
+        let path = upper_layer.join("app.db");
+        let a = open_handle(&fs, ino, &path, LayerType::Upper);
+        let b = open_handle(&fs, ino, &path, LayerType::Upper);
+
+        // A waiter that was killed while the lock was held
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let gone = child.id();
+        child.wait().unwrap();
+
+        fs.do_setlk(a, range(0, OFFSET_MAX, F_WRLCK), 100).unwrap();
+        assert_eq!(
+            fs.try_blocked_lock(b, range(0, OFFSET_MAX, F_WRLCK), std::process::id()),
+            None
+        );
+        fs.do_setlk(a, range(0, OFFSET_MAX, F_UNLCK), 100).unwrap();
+
+        // Once the lock is free it isn't taken for the exited process
+        assert_eq!(
+            fs.try_blocked_lock(b, range(0, OFFSET_MAX, F_WRLCK), gone),
+            Some(Err(libc::EINTR))
+        );
+        fs.do_setlk(a, range(0, 0, F_WRLCK), 100).unwrap();
+    }
+}
@@ -1,64 +1,89 @@
+pub mod backend;
+pub mod base;
 mod convert;
+mod dispatch;
 mod file_handle;
+mod file_ops;
 mod helpers;
 mod inode_manager;
+pub mod journal;
+mod locks;
 pub mod mount;
 mod path_resolver;
+mod refresh;
+pub mod reload;
+mod rename;
 pub mod setup;
+pub mod stats;
+#[cfg(test)]
+mod test_helpers;
 pub mod types;
 pub mod whiteout;
 
-pub use mount::{cleanup_stale_mounts, perform_fuse_cleanup};
+pub use backend::{check_upper_changes_allowed, cleanup_stale_mounts, join_mount_namespace};
+pub use base::{BaseSnapshots, BaseStore};
+pub use dispatch::TreebeardFs;
+pub use journal::MutationJournal;
+pub use mount::perform_fuse_cleanup;
 pub use setup::setup_overlay_and_watcher;
 pub use types::{MutationTracker, MutationType};
 
+use crate::config::InodeCacheConfig;
 use convert::{io_error_to_libc, metadata_to_fileattr};
 use file_handle::{FileHandle, READ_BUFFER};
 use inode_manager::InodeManager;
+use locks::LockRange;
 use path_resolver::PathResolver;
+use stats::OverlayCounters;
 use types::{InodeData, LayerType};
+use whiteout::Whiteout;
 
 use dashmap::DashMap;
 use fuser::{
-    FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyCreate, ReplyData,
-    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, ReplyXattr, Request, TimeOrNow,
-    FUSE_ROOT_ID,
+    FileAttr, FileType, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
+    ReplyLock, ReplyLseek, ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr, FUSE_ROOT_ID,
 };
 use parking_lot::Mutex;
 use std::collections::{HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::fs::{self, File, OpenOptions};
 use std::os::unix::ffi::OsStrExt;
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 
-/// The main FUSE filesystem implementation for treebeard.
+/// The overlay filesystem state and request handlers for treebeard.
 ///
-/// TreebeardFs acts as a thin coordinator that delegates to specialized components:
+/// OverlayFs acts as a thin coordinator that delegates to specialized components:
 /// - `InodeManager`: Handles inode allocation, tracking, and lifecycle
 /// - `PathResolver`: Handles path resolution and passthrough detection
 ///
 /// This separation of concerns keeps the FUSE implementation focused on
-/// implementing the Filesystem trait while delegating state management
-/// to dedicated types.
+/// handling requests while delegating state management to dedicated types.
+///
+/// All handlers take `&self` so [`TreebeardFs`] can run them on several
+/// worker threads at once.
 ///
 /// The `file_handles` map uses `DashMap` for lock-free concurrent access,
 /// reducing lock contention in FUSE hot paths. This allows multiple concurrent
 /// file operations without blocking each other on handle lookups.
-pub struct TreebeardFs {
+pub struct OverlayFs {
     /// Manages inode allocation, tracking, and lifecycle
     pub(crate) inode_manager: InodeManager,
     /// Handles path resolution and passthrough detection
     pub(crate) path_resolver: PathResolver,
     /// File handles for open files.
     /// Uses DashMap for lock-free concurrent access in FUSE hot paths.
-    file_handles: Arc<DashMap<u64, FileHandle>>,
+    file_handles: Arc<DashMap<u64, Arc<FileHandle>>>,
     /// Next file handle to allocate
     next_fh: Arc<Mutex<u64>>,
     /// Tracks all file mutations in the overlay
     pub(crate) mutations: MutationTracker,
+    /// On-disk copy of `mutations` that survives crashes, if enabled
+    journal: Option<MutationJournal>,
+    /// Content of lower-layer files at copy-up, kept alongside the journal
+    base_store: Option<BaseStore>,
     /// Channel for signaling file mutations to the commit task.
     /// Unbounded because mutation events should never block FUSE operations.
     mutation_tx: Option<tokio::sync::mpsc::UnboundedSender<PathBuf>>,
@@ -66,9 +91,11 @@ pub struct TreebeardFs {
     /// Higher values reduce kernel-userspace round trips but may delay visibility
     /// of external filesystem changes.
     ttl: Duration,
+    /// Copy-up and whiteout counts reported by `treebeard stats`
+    pub(crate) counters: OverlayCounters,
 }
 
-impl TreebeardFs {
+impl OverlayFs {
     pub fn new(
         upper_layer: PathBuf,
         lower_layer: PathBuf,
@@ -79,20 +106,91 @@ impl TreebeardFs {
         let path_resolver = PathResolver::new(upper_layer, lower_layer, passthrough_patterns)?;
         let inode_manager = InodeManager::new();
 
-        let mut fs = TreebeardFs {
+        let fs = OverlayFs {
             inode_manager,
             path_resolver,
             file_handles: Arc::new(DashMap::new()),
             next_fh: Arc::new(Mutex::new(1)),
             mutations: Arc::new(parking_lot::RwLock::new(HashMap::new())),
+            journal: None,
+            base_store: None,
             mutation_tx,
             ttl: Duration::from_secs(ttl_secs),
+            counters: OverlayCounters::default(),
         };
 
         fs.initialize_root()?;
         Ok(fs)
     }
 
+    /// Persists mutations to the journal at `journal_path`.
+    ///
+    /// Any mutations already recorded there by an earlier session for the same
+    /// branch are loaded into the tracker first, so they are offered for sync
+    /// again at cleanup. Copy-ups are also recorded in the base store next to
+    /// the journal so sync can detect files changed in the main repo since.
+    pub fn with_journal(mut self, journal_path: &std::path::Path) -> crate::error::Result<Self> {
+        let recovered = MutationJournal::load(journal_path)?;
+        if !recovered.is_empty() {
+            tracing::info!(
+                "Recovered {} mutation(s) from journal {}",
+                recovered.len(),
+                journal_path.display()
+            );
+        }
+        self.mutations.write().extend(recovered);
+        self.journal = Some(MutationJournal::open(journal_path)?);
+        self.base_store = Some(BaseStore::open(&BaseStore::dir_for_journal(journal_path))?);
+        Ok(self)
+    }
+
+    /// Sizes the inode cache and sets whether inodes the kernel references
+    /// are pinned in it. Defaults to `InodeCacheConfig::default()`.
+    pub fn with_inode_cache(self, config: &InodeCacheConfig) -> Self {
+        self.inode_manager.configure_cache(config);
+        self
+    }
+
+    /// Records the base content of a freshly copied-up file, if enabled.
+    /// Failures are logged and don't affect FUSE operations.
+    pub(crate) fn record_base(
+        &self,
+        relative_path: &std::path::Path,
+        lower: &std::fs::Metadata,
+        copy: &std::path::Path,
+    ) {
+        if let Some(ref base_store) = self.base_store {
+            if let Err(e) = base_store.record(relative_path, lower, copy) {
+                tracing::warn!("{}", e);
+            }
+        }
+    }
+
+    /// Records a mutation in the tracker and, if enabled, the on-disk journal.
+    /// Journal failures are logged and don't affect FUSE operations.
+    pub(crate) fn record_mutation(&self, relative_path: &std::path::Path, mutation: MutationType) {
+        if let Some(ref journal) = self.journal {
+            if let Err(e) = journal.append(relative_path, &mutation) {
+                tracing::warn!("{}", e);
+            }
+        }
+        self.mutations
+            .write()
+            .insert(relative_path.to_path_buf(), mutation);
+    }
+
+    /// Drops a path from the tracker and, if enabled, the on-disk journal.
+    pub(crate) fn forget_mutation(&self, relative_path: &std::path::Path) {
+        if self.mutations.write().remove(relative_path).is_none() {
+            return;
+        }
+        if let Some(ref journal) = self.journal {
+            if let Err(e) = journal.forget(relative_path) {
+                tracing::warn!("{}", e);
+            }
+        }
+    }
+
     #[cfg(test)]
     pub fn copy_up_locks_count(&self) -> usize {
         self.inode_manager.copy_up_locks_count()
@@ -108,7 +206,7 @@ impl TreebeardFs {
         }
     }
 
-    fn initialize_root(&mut self) -> crate::error::Result<()> {
+    fn initialize_root(&self) -> crate::error::Result<()> {
         let upper_layer = &self.path_resolver.upper_layer;
         let root_attrs = if upper_layer.exists() {
             fs::metadata(upper_layer).map_err(|e| {
@@ -149,6 +247,13 @@ impl TreebeardFs {
         Ok(())
     }
 
+    /// Replies with a directory entry. The kernel holds a lookup reference
+    /// on the inode from then on, until it sends `forget`.
+    fn reply_entry(&self, reply: ReplyEntry, attrs: &FileAttr) {
+        self.inode_manager.add_lookup(attrs.ino);
+        reply.entry(&self.ttl, attrs, 0);
+    }
+
     fn alloc_fh(&self) -> u64 {
         let mut next = self.next_fh.lock();
         let fh = *next;
@@ -159,25 +264,14 @@ impl TreebeardFs {
     }
 }
 
-impl Filesystem for TreebeardFs {
-    fn init(
-        &mut self,
-        _req: &Request,
-        _config: &mut KernelConfig,
-    ) -> std::result::Result<(), libc::c_int> {
-        tracing::info!("Treebeard FUSE filesystem initialized");
-        Ok(())
-    }
-
-    fn destroy(&mut self) {
-        tracing::info!("Treebeard FUSE filesystem destroyed");
-    }
-
-    fn forget(&mut self, _req: &Request, ino: u64, _nlookup: u64) {
-        // The kernel is releasing nlookup references to this inode.
-        // We track open file handles separately, so we only need to check
-        // if the inode should be garbage collected when it's in the deleted set.
-        let should_gc = self.inode_manager.should_gc(ino);
+// FUSE request handlers, run on worker threads by `TreebeardFs`
+impl OverlayFs {
+    pub(crate) fn forget(&self, ino: u64, nlookup: u64) {
+        // The kernel is releasing nlookup references to this inode. Once it
+        // holds none, the inode may be evicted, or garbage collected if it
+        // was deleted while open and has no open file handles left.
+        let remaining = self.inode_manager.forget(ino, nlookup);
+        let should_gc = remaining == 0 && self.inode_manager.should_gc(ino);
 
         if should_gc && self.inode_manager.is_deleted(ino) {
             self.do_gc(ino);
@@ -185,16 +279,16 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+    pub(crate) fn lookup(&self, parent: u64, name: &OsStr, reply: ReplyEntry) {
         tracing::debug!("lookup(parent={}, name={:?})", parent, name);
 
         // First check if we already have this inode cached
-        let cached_ino = self.inode_manager.lookup_child(parent, name);
+        let cached_ino = self.inode_manager.lookup_cached(parent, name);
 
         if let Some(ino) = cached_ino {
             tracing::debug!("lookup: found cached inode {} for {:?}", ino, name);
             match self.lookup_check_cached(ino, name) {
-                Ok(attrs) => reply.entry(&self.ttl, &attrs, 0),
+                Ok(attrs) => self.reply_entry(reply, &attrs),
                 Err(errno) => reply.error(errno),
             }
             return;
@@ -224,9 +318,9 @@ impl Filesystem for TreebeardFs {
             self.lookup_passthrough(parent, child_name.clone(), relative_path.clone())
         {
             match result {
-                Ok((inode, file_attrs)) => {
-                    self.inode_manager.insert(inode);
-                    reply.entry(&self.ttl, &file_attrs, 0);
+                Ok((inode, _)) => {
+                    let file_attrs = self.inode_manager.insert_if_absent(inode);
+                    self.reply_entry(reply, &file_attrs);
                 }
                 Err(errno) => reply.error(errno),
             }
@@ -235,9 +329,9 @@ impl Filesystem for TreebeardFs {
 
         // Use standard overlay lookup (upper shadows lower)
         match self.lookup_overlay(parent, child_name, relative_path.clone()) {
-            Ok(Some((inode, file_attrs))) => {
-                self.inode_manager.insert(inode);
-                reply.entry(&self.ttl, &file_attrs, 0);
+            Ok(Some((inode, _))) => {
+                let file_attrs = self.inode_manager.insert_if_absent(inode);
+                self.reply_entry(reply, &file_attrs);
             }
             Ok(None) => {
                 tracing::debug!("lookup: file {:?} not found in either layer", name);
@@ -247,7 +341,7 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+    pub(crate) fn getattr(&self, ino: u64, reply: ReplyAttr) {
         tracing::trace!("getattr(ino={})", ino);
 
         if let Some(attrs) = self.inode_manager.get_attrs(ino) {
@@ -259,24 +353,7 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn setattr(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        _mode: Option<u32>,
-        _uid: Option<u32>,
-        _gid: Option<u32>,
-        size: Option<u64>,
-        _atime: Option<TimeOrNow>,
-        _mtime: Option<TimeOrNow>,
-        _ctime: Option<SystemTime>,
-        _fh: Option<u64>,
-        _crtime: Option<SystemTime>,
-        _chgtime: Option<SystemTime>,
-        _bkuptime: Option<SystemTime>,
-        _flags: Option<u32>,
-        reply: ReplyAttr,
-    ) {
+    pub(crate) fn setattr(&self, ino: u64, size: Option<u64>, reply: ReplyAttr) {
         let Some((rel_path, _layer, _has_open)) = self.inode_manager.get_inode_info(ino) else {
             reply.error(libc::ENOENT);
             return;
@@ -328,7 +405,7 @@ impl Filesystem for TreebeardFs {
     /// When multiple threads open the same file concurrently, they may see different
     /// layer states. However, the copy-up operation is atomic per-file and the lock
     /// ensures consistency during the open sequence.
-    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+    pub(crate) fn open(&self, ino: u64, flags: i32, reply: ReplyOpen) {
         tracing::debug!("open(ino={}, flags={:#x})", ino, flags);
 
         // Acquire the copy-up lock for this inode before checking the layer.
@@ -341,6 +418,7 @@ impl Filesystem for TreebeardFs {
             reply.error(libc::ENOENT);
             return;
         };
+        let layer = self.refresh_released_layer(ino, &rel_path, layer);
 
         tracing::debug!(
             "open: inode {} -> path={:?}, layer={:?}",
@@ -445,11 +523,9 @@ impl Filesystem for TreebeardFs {
         };
 
         let fh = self.alloc_fh();
-        let handle = FileHandle {
-            file: Arc::new(Mutex::new(file)),
-        };
+        let handle = FileHandle::new(ino, file, current_layer);
 
-        self.file_handles.insert(fh, handle);
+        self.file_handles.insert(fh, Arc::new(handle));
 
         // Update the inode's layer if it changed (e.g., file was found in different layer)
         self.inode_manager.increment_open_handles(ino);
@@ -459,17 +535,7 @@ impl Filesystem for TreebeardFs {
         reply.opened(fh, 0);
     }
 
-    fn read(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        size: u32,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        reply: ReplyData,
-    ) {
+    pub(crate) fn read(&self, ino: u64, fh: u64, offset: i64, size: u32, reply: ReplyData) {
         tracing::debug!(
             "read(ino={}, fh={}, offset={}, size={})",
             ino,
@@ -480,14 +546,13 @@ impl Filesystem for TreebeardFs {
         // Clone the file handle Arc to release the DashMap reference before I/O.
         // This prevents slow disk I/O from blocking other file open/close operations.
         let file_arc = match self.file_handles.get(&fh) {
-            Some(h) => Arc::clone(&h.file),
+            Some(h) => h.file(),
             None => {
                 tracing::warn!("read: file handle {} not found", fh);
                 reply.error(libc::EBADF);
                 return;
             }
         };
-        let mut file = file_arc.lock();
 
         READ_BUFFER.with(|buffer| {
             let mut buf = buffer.borrow_mut();
@@ -499,16 +564,9 @@ impl Filesystem for TreebeardFs {
             }
             buf.resize(requested_size, 0u8);
 
-            match std::io::Seek::seek(&mut *file, std::io::SeekFrom::Start(offset as u64)) {
-                Ok(_) => {}
-                Err(e) => {
-                    tracing::error!("read: seek failed - {}", e);
-                    reply.error(io_error_to_libc(&e));
-                    return;
-                }
-            }
-
-            match std::io::Read::read(&mut *file, &mut buf[..]) {
+            // Positional reads don't move a shared file offset, so reads on
+            // the same handle can run on several worker threads at once.
+            match file_arc.read_at(&mut buf[..], offset as u64) {
                 Ok(n) => {
                     tracing::debug!("read: successfully read {} bytes", n);
                     reply.data(&buf[..n]);
@@ -521,18 +579,7 @@ impl Filesystem for TreebeardFs {
         });
     }
 
-    fn write(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        data: &[u8],
-        _write_flags: u32,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        reply: ReplyWrite,
-    ) {
+    pub(crate) fn write(&self, ino: u64, fh: u64, offset: i64, data: &[u8], reply: ReplyWrite) {
         tracing::debug!(
             "write(ino={}, fh={}, offset={}, len={})",
             ino,
@@ -566,24 +613,14 @@ impl Filesystem for TreebeardFs {
         // Clone the file handle Arc to release the DashMap reference before I/O.
         // This prevents slow disk I/O from blocking other file open/close operations.
         let file_arc = match self.file_handles.get(&fh) {
-            Some(h) => Arc::clone(&h.file),
+            Some(h) => h.file(),
             None => {
                 reply.error(libc::EBADF);
                 return;
             }
         };
 
-        let mut file = file_arc.lock();
-        match std::io::Seek::seek(&mut *file, std::io::SeekFrom::Start(offset as u64)) {
-            Ok(_) => {}
-            Err(e) => {
-                tracing::error!("write: seek failed - {}", e);
-                reply.error(io_error_to_libc(&e));
-                return;
-            }
-        }
-
-        match std::io::Write::write(&mut *file, data) {
+        match file_arc.write_at(data, offset as u64) {
             Ok(n) => {
                 tracing::debug!("write: successfully wrote {} bytes", n);
 
@@ -605,7 +642,7 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn flush(&mut self, _req: &Request, ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+    pub(crate) fn flush(&self, ino: u64, fh: u64, reply: ReplyEmpty) {
         // Verify the file handle exists (lock-free check with DashMap)
         if !self.file_handles.contains_key(&fh) {
             reply.error(libc::EBADF);
@@ -641,16 +678,14 @@ impl Filesystem for TreebeardFs {
         reply.ok();
     }
 
-    fn release(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        fh: u64,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        _flush: bool,
-        reply: fuser::ReplyEmpty,
-    ) {
+    pub(crate) fn fsync(&self, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        match self.do_fsync(fh, datasync) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    pub(crate) fn release(&self, ino: u64, fh: u64, reply: ReplyEmpty) {
         self.file_handles.remove(&fh);
 
         // Check deleted status first, before acquiring inodes lock, to avoid
@@ -666,16 +701,7 @@ impl Filesystem for TreebeardFs {
         reply.ok();
     }
 
-    fn create(
-        &mut self,
-        _req: &Request,
-        parent: u64,
-        name: &OsStr,
-        mode: u32,
-        _umask: u32,
-        _flags: i32,
-        reply: ReplyCreate,
-    ) {
+    pub(crate) fn create(&self, parent: u64, name: &OsStr, mode: u32, reply: ReplyCreate) {
         tracing::debug!(
             "create(parent={}, name={:?}, mode={:#o})",
             parent,
@@ -758,9 +784,8 @@ impl Filesystem for TreebeardFs {
                 self.inode_manager.insert(inode);
 
                 if !is_passthrough {
-                    self.mutations
-                        .write()
-                        .insert(relative_path.clone(), MutationType::Created);
+                    self.remove_whiteout(&parent_path, name);
+                    self.record_mutation(&relative_path, MutationType::Created);
 
                     // Signal that a new file was created
                     self.signal_mutation(&relative_path);
@@ -770,12 +795,11 @@ impl Filesystem for TreebeardFs {
                 match File::options().read(true).write(true).open(&child_path) {
                     Ok(file) => {
                         let fh = self.alloc_fh();
-                        let handle = FileHandle {
-                            file: Arc::new(Mutex::new(file)),
-                        };
-                        self.file_handles.insert(fh, handle);
+                        let handle = FileHandle::new(new_ino, file, layer);
+                        self.file_handles.insert(fh, Arc::new(handle));
 
                         self.inode_manager.increment_open_handles(new_ino);
+                        self.inode_manager.add_lookup(new_ino);
 
                         reply.created(&self.ttl, &file_attrs, 0, fh, flags as u32);
                     }
@@ -786,7 +810,7 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+    pub(crate) fn unlink(&self, parent: u64, name: &OsStr, reply: ReplyEmpty) {
         let lookup_result = self.inode_manager.lookup_child(parent, name);
         let ino = match lookup_result {
             Some(ino) => ino,
@@ -803,133 +827,58 @@ impl Filesystem for TreebeardFs {
 
         let is_passthrough = self.path_resolver.is_passthrough(&rel_path);
 
-        if layer == LayerType::Lower && !is_passthrough {
-            self.mutations
-                .write()
-                .insert((*rel_path).clone(), MutationType::Deleted);
+        // Deleting a copied-up file deletes the lower layer's copy as well
+        let in_lower = layer == LayerType::Lower
+            || self
+                .path_resolver
+                .lower_path(&rel_path)
+                .symlink_metadata()
+                .is_ok();
+        if in_lower && !is_passthrough {
+            self.record_mutation(&rel_path, MutationType::Deleted);
         }
 
         self.do_remove(parent, name, reply, std::fs::remove_file);
     }
 
-    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+    pub(crate) fn rmdir(&self, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        if let Err(e) = self.clear_directory_for_removal(parent, name) {
+            reply.error(e);
+            return;
+        }
         self.do_remove(parent, name, reply, std::fs::remove_dir);
     }
 
-    fn rename(
-        &mut self,
-        _req: &Request,
+    pub(crate) fn rename(
+        &self,
         parent: u64,
         name: &OsStr,
         newparent: u64,
         newname: &OsStr,
-        _flags: u32,
+        flags: u32,
         reply: ReplyEmpty,
     ) {
-        let lookup_result = self.inode_manager.lookup_child(parent, name);
-        let ino = match lookup_result {
-            Some(ino) => ino,
-            None => {
-                reply.error(libc::ENOENT);
-                return;
-            }
-        };
-
-        if self
-            .inode_manager
-            .lookup_child(newparent, newname)
-            .is_some()
-        {
-            reply.error(libc::EEXIST);
-            return;
+        match self.do_rename(parent, name, newparent, newname, flags) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
         }
+    }
 
-        let Some((rel_path, _layer, _has_open)) = self.inode_manager.get_inode_info(ino) else {
-            reply.error(libc::ENOENT);
-            return;
-        };
-
-        let Some((new_parent_rel_path, _, _new_parent_has_open)) =
-            self.inode_manager.get_inode_info(newparent)
-        else {
-            reply.error(libc::ENOENT);
-            return;
-        };
-
-        let new_rel_path = new_parent_rel_path.join(newname);
-        let src_is_passthrough = self.path_resolver.is_passthrough(&rel_path);
-        let dest_is_passthrough = self.path_resolver.is_passthrough(&new_rel_path);
-
-        // If either is passthrough, we operate on lower layer and skip COW
-        if !src_is_passthrough && !dest_is_passthrough {
-            if let Err(e) = self.copy_up(ino) {
-                reply.error(e);
-                return;
-            }
-        }
-
-        // Get source and dest paths in one block to avoid borrow issues
-        let (src_path, dest_path, new_parent_path, old_relative_path, actual_layer) = {
-            let inodes = self.inode_manager.inodes.read();
-            let inode = inodes.peek(ino);
-            let newparent_inode = inodes.peek(newparent);
-
-            match (inode, newparent_inode) {
-                (Some(i), Some(np)) => {
-                    let layer = if src_is_passthrough || dest_is_passthrough {
-                        LayerType::Lower
-                    } else {
-                        LayerType::Upper
-                    };
-                    let base = self.path_resolver.layer_base_path(layer);
-                    let src = base.join(&*i.path);
-                    let dest = base.join(&*np.path).join(newname);
-                    let parent_path = Arc::clone(&np.path);
-                    let old_rel = Arc::clone(&i.path);
-                    (src, dest, parent_path, old_rel, layer)
-                }
-                _ => {
-                    reply.error(libc::ENOENT);
-                    return;
-                }
-            }
-        };
-
-        if let Err(e) = fs::rename(&src_path, &dest_path) {
-            tracing::error!("rename error: {}", e);
-            reply.error(io_error_to_libc(&e));
-            return;
+    pub(crate) fn getlk(&self, fh: u64, range: LockRange, reply: ReplyLock) {
+        match self.do_getlk(fh, range) {
+            Ok((lock, pid)) => reply.locked(lock.start, lock.end, lock.typ, pid),
+            Err(errno) => reply.error(errno),
         }
+    }
 
-        let new_relative_path = new_parent_path.join(newname);
-
-        self.inode_manager.update_after_rename(
-            ino,
-            parent,
-            name,
-            newparent,
-            newname.to_os_string(),
-            new_relative_path.clone(),
-            actual_layer,
-        );
-
-        if !src_is_passthrough && !dest_is_passthrough {
-            // Signal both old and new paths for rename
-            self.signal_mutation(&old_relative_path);
-            self.signal_mutation(&new_relative_path);
+    pub(crate) fn setlk(&self, fh: u64, range: LockRange, pid: u32, reply: ReplyEmpty) {
+        match self.do_setlk(fh, range, pid) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
         }
-
-        reply.ok();
     }
 
-    fn readdir(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        _fh: u64,
-        offset: i64,
-        mut reply: ReplyDirectory,
-    ) {
+    pub(crate) fn readdir(&self, ino: u64, offset: i64, mut reply: ReplyDirectory) {
         tracing::debug!("readdir(ino={}, offset={})", ino, offset);
         let Some((dir_path, _layer, _has_open)) = self.inode_manager.get_inode_info(ino) else {
             tracing::warn!("readdir: inode {} not found", ino);
@@ -1033,15 +982,14 @@ impl Filesystem for TreebeardFs {
         reply.ok();
     }
 
-    fn mkdir(
-        &mut self,
-        _req: &Request,
-        parent: u64,
-        name: &OsStr,
-        _mode: u32,
-        _umask: u32,
-        reply: ReplyEntry,
-    ) {
+    pub(crate) fn fsyncdir(&self, ino: u64, datasync: bool, reply: ReplyEmpty) {
+        match self.do_fsyncdir(ino, datasync) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    pub(crate) fn mkdir(&self, parent: u64, name: &OsStr, reply: ReplyEntry) {
         let Some((parent_path, _layer, _has_open)) = self.inode_manager.get_inode_info(parent)
         else {
             reply.error(libc::ENOENT);
@@ -1070,6 +1018,20 @@ impl Filesystem for TreebeardFs {
             return;
         }
 
+        if !is_passthrough {
+            // Whatever the lower layer has at this path was deleted, or is
+            // hidden by an opaque parent, so the new directory starts empty
+            if self.path_resolver.lower_path(&rel_path).exists() {
+                if let Err(e) = Whiteout::make_opaque(&child_path) {
+                    tracing::error!("mkdir: failed to make {:?} opaque: {}", rel_path, e);
+                    let _ = fs::remove_dir(&child_path);
+                    reply.error(e);
+                    return;
+                }
+            }
+            self.remove_whiteout(&parent_path, name);
+        }
+
         match fs::metadata(&child_path) {
             Ok(attrs) => {
                 let new_ino = self.inode_manager.alloc_inode();
@@ -1092,15 +1054,21 @@ impl Filesystem for TreebeardFs {
                     self.signal_mutation(&relative_path);
                 }
 
-                reply.entry(&self.ttl, &file_attrs, 0);
+                self.reply_entry(reply, &file_attrs);
             }
             Err(e) => reply.error(io_error_to_libc(&e)),
         }
     }
 
-    fn symlink(
-        &mut self,
-        _req: &Request,
+    pub(crate) fn mknod(&self, parent: u64, name: &OsStr, mode: u32, rdev: u32, reply: ReplyEntry) {
+        match self.do_mknod(parent, name, mode, rdev) {
+            Ok(attrs) => self.reply_entry(reply, &attrs),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    pub(crate) fn symlink(
+        &self,
         parent: u64,
         name: &OsStr,
         link: &std::path::Path,
@@ -1141,13 +1109,13 @@ impl Filesystem for TreebeardFs {
                 );
 
                 self.inode_manager.insert(inode);
-                reply.entry(&self.ttl, &file_attrs, 0);
+                self.reply_entry(reply, &file_attrs);
             }
             Err(e) => reply.error(io_error_to_libc(&e)),
         }
     }
 
-    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+    pub(crate) fn readlink(&self, ino: u64, reply: ReplyData) {
         let Some((rel_path, layer, _has_open)) = self.inode_manager.get_inode_info(ino) else {
             reply.error(libc::ENOENT);
             return;
@@ -1169,14 +1137,7 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn link(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        newparent: u64,
-        newname: &OsStr,
-        reply: ReplyEntry,
-    ) {
+    pub(crate) fn link(&self, ino: u64, newparent: u64, newname: &OsStr, reply: ReplyEntry) {
         // Check if target doesn't already exist
         if self
             .inode_manager
@@ -1233,22 +1194,13 @@ impl Filesystem for TreebeardFs {
         self.inode_manager.increment_hardlinks(ino);
 
         if let Some(attrs) = self.inode_manager.get_attrs(ino) {
-            reply.entry(&self.ttl, &attrs, 0);
+            self.reply_entry(reply, &attrs);
         } else {
             reply.error(libc::ENOENT);
         }
     }
 
-    fn setxattr(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        name: &OsStr,
-        value: &[u8],
-        _flags: i32,
-        _position: u32,
-        reply: ReplyEmpty,
-    ) {
+    pub(crate) fn setxattr(&self, ino: u64, name: &OsStr, value: &[u8], reply: ReplyEmpty) {
         // Copy-up if file is in lower layer
         if let Err(e) = self.copy_up(ino) {
             reply.error(e);
@@ -1268,7 +1220,7 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+    pub(crate) fn getxattr(&self, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
         let Some((rel_path, layer, _has_open)) = self.inode_manager.get_inode_info(ino) else {
             reply.error(libc::ENOENT);
             return;
@@ -1297,7 +1249,7 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+    pub(crate) fn listxattr(&self, ino: u64, size: u32, reply: ReplyXattr) {
         let Some((rel_path, layer, _has_open)) = self.inode_manager.get_inode_info(ino) else {
             reply.error(libc::ENOENT);
             return;
@@ -1325,7 +1277,7 @@ impl Filesystem for TreebeardFs {
         }
     }
 
-    fn removexattr(&mut self, _req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+    pub(crate) fn removexattr(&self, ino: u64, name: &OsStr, reply: ReplyEmpty) {
         // Copy-up if file is in lower layer
         if let Err(e) = self.copy_up(ino) {
             reply.error(e);
@@ -1344,4 +1296,67 @@ impl Filesystem for TreebeardFs {
             Err(e) => reply.error(e.raw_os_error().unwrap_or(libc::EIO)),
         }
     }
+
+    pub(crate) fn statfs(&self, reply: ReplyStatfs) {
+        match self.do_statfs() {
+            Ok(stats) => reply.statfs(
+                stats.blocks,
+                stats.bfree,
+                stats.bavail,
+                stats.files,
+                stats.ffree,
+                stats.bsize,
+                stats.namelen,
+                stats.frsize,
+            ),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    pub(crate) fn access(&self, ino: u64, mask: i32, reply: ReplyEmpty) {
+        match self.do_access(ino, mask) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    pub(crate) fn fallocate(
+        &self,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        match self.do_fallocate(ino, fh, offset, length, mode) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    pub(crate) fn lseek(&self, fh: u64, offset: i64, whence: i32, reply: ReplyLseek) {
+        match self.do_lseek(fh, offset, whence) {
+            Ok(offset) => reply.offset(offset),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn copy_file_range(
+        &self,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        flags: u32,
+        reply: ReplyWrite,
+    ) {
+        match self.do_copy_file_range(fh_in, offset_in, ino_out, fh_out, offset_out, len, flags) {
+            Ok(written) => reply.written(written),
+            Err(errno) => reply.error(errno),
+        }
+    }
 }
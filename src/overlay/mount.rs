@@ -2,10 +2,12 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use crate::config::get_mount_dir;
+use crate::config::{get_mount_dir, InodeCacheConfig};
 use crate::error::{Result, TreebeardError};
+use crate::overlay::backend;
+use crate::overlay::stats::StatsServer;
 use crate::overlay::types::MutationTracker;
-use crate::overlay::TreebeardFs;
+use crate::overlay::{OverlayFs, TreebeardFs};
 
 /// Mount the FUSE filesystem in a background thread.
 /// Returns:
@@ -17,12 +19,16 @@ use crate::overlay::TreebeardFs;
 /// * `upper_layer` - Directory for the upper (writable) layer
 /// * `lower_layer` - Directory for the lower (read-only) layer  
 /// * `ttl_secs` - Cache TTL in seconds for FUSE attributes and entries
+/// * `journal_path` - Optional on-disk mutation journal (see `MutationJournal`)
+/// * `inode_cache` - Capacity and pinning of the inode table
 pub fn mount_fuse(
     mount_point: &Path,
     upper_layer: &Path,
     lower_layer: &Path,
     ttl_secs: u64,
     passthrough_patterns: Vec<String>,
+    journal_path: Option<&Path>,
+    inode_cache: &InodeCacheConfig,
 ) -> crate::error::Result<(
     MutationTracker,
     tokio::sync::mpsc::UnboundedReceiver<PathBuf>,
@@ -37,15 +43,21 @@ pub fn mount_fuse(
 
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
-    let fs = TreebeardFs::new(
+    let mut overlay = OverlayFs::new(
         upper_layer.to_path_buf(),
         lower_layer.to_path_buf(),
         Some(tx),
         ttl_secs,
         passthrough_patterns,
-    )?;
+    )?
+    .with_inode_cache(inode_cache);
+    if let Some(journal_path) = journal_path {
+        overlay = overlay.with_journal(journal_path)?;
+    }
 
-    let mutations = Arc::clone(&fs.mutations);
+    let mutations = Arc::clone(&overlay.mutations);
+    let fs = TreebeardFs::from(overlay);
+    let stats_source = fs.stats_source();
     let mount_point_clone = mount_point.to_path_buf();
 
     // Channel to communicate mount status from the spawned thread back to the main thread.
@@ -64,7 +76,18 @@ pub fn mount_fuse(
             mount_point_clone.display()
         );
 
-        match fuser::mount2(fs, &mount_point_clone, &mount_options) {
+        let result =
+            fuser::Session::new(fs, &mount_point_clone, &mount_options).and_then(|mut session| {
+                // Serves `treebeard stats` and `treebeard reload` for as long
+                // as the mount is up
+                let _stats_server =
+                    StatsServer::start(&mount_point_clone, stats_source, Some(session.notifier()))
+                        .map_err(|e| tracing::warn!("Failed to start overlay stats socket: {}", e))
+                        .ok();
+                session.run()
+            });
+
+        match result {
             Ok(_) => {
                 // Mount succeeded and then was unmounted (normal shutdown).
                 // By the time we get here, the mount was working, so we signal success
@@ -211,17 +234,19 @@ pub struct FuseCleanupResult {
     pub directory_removed: bool,
 }
 
-/// Unmount a FUSE filesystem and remove its mount directory.
+/// Unmount a session overlay and remove its mount directory.
 ///
-/// This is the canonical way to clean up a FUSE mount. It handles:
-/// - Unmounting the FUSE filesystem with platform-specific commands
+/// This is the canonical way to clean up a session mount, whichever backend
+/// made it. It handles:
+/// - Unmounting with the backend's own mechanism (platform-specific commands
+///   for FUSE)
 /// - Removing the mount directory after successful unmount
 /// - Validation that the path is within treebeard's mount directory
 ///
 /// Returns a `FuseCleanupResult` indicating what operations succeeded.
 /// Errors are logged but don't cause the function to fail - cleanup is best-effort.
 pub fn perform_fuse_cleanup(mount_path: &Path) -> FuseCleanupResult {
-    let unmount_succeeded = match unmount_fuse(mount_path) {
+    let unmount_succeeded = match backend::unmount(mount_path) {
         Ok(true) => true,
         Ok(false) => {
             tracing::warn!(
@@ -266,16 +291,9 @@ pub fn perform_fuse_cleanup(mount_path: &Path) -> FuseCleanupResult {
 /// limit of 64 simultaneous mounts, so stale mounts can prevent new mounts from
 /// being created.
 ///
-/// This is called by the `treebeard cleanup --stale` command and logs what it does,
-/// but doesn't fail if cleanup encounters errors. Users can skip this with the
-/// TREEBEARD_NO_CLEANUP=1 env var.
-pub fn cleanup_stale_mounts() {
-    // Allow users to skip automatic cleanup
-    if std::env::var("TREEBEARD_NO_CLEANUP").is_ok() {
-        tracing::debug!("Skipping stale mount cleanup (TREEBEARD_NO_CLEANUP=1)");
-        return;
-    }
-
+/// Called through `overlay::cleanup_stale_mounts`, which handles the
+/// TREEBEARD_NO_CLEANUP opt-out.
+pub fn cleanup_stale_fuse_mounts() {
     if cfg!(not(target_os = "macos")) {
         return;
     }
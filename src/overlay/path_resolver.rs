@@ -1,10 +1,25 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use crate::overlay::types::LayerType;
 use crate::overlay::whiteout::Whiteout;
 
+/// How often paths were checked against the passthrough patterns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PassthroughStats {
+    /// Paths checked, from the cache or by matching the patterns
+    pub checks: u64,
+    /// Checks that found a passthrough path
+    pub hits: u64,
+    /// Checks that weren't cached and ran the glob patterns
+    pub pattern_evaluations: u64,
+    /// Paths whose result is cached
+    pub cached_paths: usize,
+}
+
 /// Handles path resolution, passthrough detection, and layer path computation.
 ///
 /// This type encapsulates the single responsibility of managing paths within
@@ -15,10 +30,14 @@ pub struct PathResolver {
     pub(crate) upper_layer: PathBuf,
     /// The lower layer directory (the original read-only source)
     pub(crate) lower_layer: PathBuf,
-    /// Glob patterns for paths that bypass the upper layer entirely
-    passthrough_patterns: Vec<glob::Pattern>,
+    /// Glob patterns for paths that bypass the upper layer entirely.
+    /// Replaced when `treebeard reload` changes them in a running mount.
+    passthrough_patterns: parking_lot::RwLock<Vec<glob::Pattern>>,
     /// Cache of passthrough status for paths already checked
     passthrough_cache: Arc<parking_lot::RwLock<HashMap<Arc<str>, bool>>>,
+    passthrough_checks: AtomicU64,
+    passthrough_hits: AtomicU64,
+    pattern_evaluations: AtomicU64,
 }
 
 impl PathResolver {
@@ -28,23 +47,16 @@ impl PathResolver {
         lower_layer: PathBuf,
         passthrough_patterns: Vec<String>,
     ) -> crate::error::Result<Self> {
-        let compiled_patterns = passthrough_patterns
-            .into_iter()
-            .map(|p| {
-                glob::Pattern::new(&p).map_err(|e| {
-                    crate::error::TreebeardError::Config(format!(
-                        "Invalid passthrough glob pattern '{}': {}",
-                        p, e
-                    ))
-                })
-            })
-            .collect::<crate::error::Result<Vec<glob::Pattern>>>()?;
-
         Ok(PathResolver {
             upper_layer,
             lower_layer,
-            passthrough_patterns: compiled_patterns,
+            passthrough_patterns: parking_lot::RwLock::new(compile_passthrough_patterns(
+                &passthrough_patterns,
+            )?),
             passthrough_cache: Arc::new(parking_lot::RwLock::new(HashMap::new())),
+            passthrough_checks: AtomicU64::new(0),
+            passthrough_hits: AtomicU64::new(0),
+            pattern_evaluations: AtomicU64::new(0),
         })
     }
 
@@ -68,38 +80,54 @@ impl PathResolver {
     /// Results are cached to avoid repeated pattern matching for the same paths.
     pub fn is_passthrough(&self, relative_path: &Path) -> bool {
         let path_to_match = relative_path.strip_prefix(".").unwrap_or(relative_path);
-        let path_str = path_to_match.to_string_lossy();
-        let path_key: Arc<str> = Arc::from(path_str.as_ref());
+        let path_key: Arc<str> = Arc::from(path_to_match.to_string_lossy().as_ref());
 
+        self.passthrough_checks.fetch_add(1, Ordering::Relaxed);
         {
             let cache = self.passthrough_cache.read();
             if let Some(&cached) = cache.get(&path_key) {
+                if cached {
+                    self.passthrough_hits.fetch_add(1, Ordering::Relaxed);
+                }
                 return cached;
             }
         }
 
-        let result = self.passthrough_patterns.iter().any(|p| {
-            if p.matches_path(path_to_match) {
-                return true;
-            }
+        self.pattern_evaluations.fetch_add(1, Ordering::Relaxed);
 
-            let pattern_str = p.as_str();
-            if let Some(prefix) = pattern_str.strip_suffix("/**") {
-                if path_str == prefix {
-                    return true;
-                }
-                if prefix.starts_with(&format!("{}/", path_str)) {
-                    return true;
-                }
-            }
+        // Holding the patterns across the cache insert keeps a result computed
+        // from replaced patterns out of the freshly cleared cache
+        let patterns = self.passthrough_patterns.read();
+        let result = matches_passthrough(&patterns, relative_path);
+        self.passthrough_cache.write().insert(path_key, result);
+        drop(patterns);
+
+        if result {
+            self.passthrough_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
 
-            false
-        });
+    /// The current passthrough patterns.
+    pub fn passthrough_patterns(&self) -> Vec<glob::Pattern> {
+        self.passthrough_patterns.read().clone()
+    }
 
-        let cache_key = Arc::clone(&path_key);
-        self.passthrough_cache.write().insert(cache_key, result);
+    /// Replace the passthrough patterns and forget every cached result.
+    pub fn set_passthrough_patterns(&self, patterns: Vec<glob::Pattern>) {
+        let mut current = self.passthrough_patterns.write();
+        *current = patterns;
+        self.passthrough_cache.write().clear();
+    }
 
-        result
+    /// Passthrough check counts since the mount started.
+    pub fn passthrough_stats(&self) -> PassthroughStats {
+        PassthroughStats {
+            checks: self.passthrough_checks.load(Ordering::Relaxed),
+            hits: self.passthrough_hits.load(Ordering::Relaxed),
+            pattern_evaluations: self.pattern_evaluations.load(Ordering::Relaxed),
+            cached_paths: self.passthrough_cache.read().len(),
+        }
     }
 
     /// Resolve the actual filesystem path for a relative path.
@@ -161,8 +189,9 @@ impl PathResolver {
             }
         }
 
-        // Fall back to lower layer
-        if lower_path.exists() {
+        // Fall back to lower layer, unless an opaque directory hides it
+        let parent_is_opaque = upper_path.parent().is_some_and(Whiteout::is_opaque);
+        if lower_path.exists() && !parent_is_opaque {
             tracing::trace!("resolve_path: found in lower layer");
             // Canonicalize to resolve symlinks and prevent TOCTOU via symlink replacement
             match lower_path.canonicalize() {
@@ -213,6 +242,48 @@ impl PathResolver {
     }
 }
 
+/// Compile passthrough glob patterns, failing on the first invalid one.
+pub(crate) fn compile_passthrough_patterns(
+    patterns: &[String],
+) -> crate::error::Result<Vec<glob::Pattern>> {
+    patterns
+        .iter()
+        .map(|p| {
+            glob::Pattern::new(p).map_err(|e| {
+                crate::error::TreebeardError::Config(format!(
+                    "Invalid passthrough glob pattern '{}': {}",
+                    p, e
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Whether `relative_path` is passthrough under `patterns`, without the
+/// cache. See [`PathResolver::is_passthrough`].
+pub(crate) fn matches_passthrough(patterns: &[glob::Pattern], relative_path: &Path) -> bool {
+    let path_to_match = relative_path.strip_prefix(".").unwrap_or(relative_path);
+    let path_str = path_to_match.to_string_lossy();
+
+    patterns.iter().any(|p| {
+        if p.matches_path(path_to_match) {
+            return true;
+        }
+
+        let pattern_str = p.as_str();
+        if let Some(prefix) = pattern_str.strip_suffix("/**") {
+            if path_str == prefix {
+                return true;
+            }
+            if prefix.starts_with(&format!("{}/", path_str)) {
+                return true;
+            }
+        }
+
+        false
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -256,6 +327,32 @@ mod tests {
         assert!(!resolver.is_passthrough(Path::new(".git/config")));
     }
 
+    #[test]
+    fn test_passthrough_stats() {
+        let temp_dir = tempdir().unwrap();
+        let resolver = PathResolver::new(
+            temp_dir.path().join("upper"),
+            temp_dir.path().join("lower"),
+            vec!["node_modules/**".to_string()],
+        )
+        .unwrap();
+
+        assert!(resolver.is_passthrough(Path::new("./node_modules/a.js")));
+        assert!(resolver.is_passthrough(Path::new("./node_modules/a.js")));
+        assert!(!resolver.is_passthrough(Path::new("./src/main.rs")));
+
+        // Repeat checks are answered from the cache
+        assert_eq!(
+            resolver.passthrough_stats(),
+            PassthroughStats {
+                checks: 3,
+                hits: 2,
+                pattern_evaluations: 2,
+                cached_paths: 2,
+            }
+        );
+    }
+
     #[test]
     fn test_is_passthrough_caching() {
         let temp_dir = tempdir().unwrap();
@@ -0,0 +1,241 @@
+//! Bringing a mounted overlay up to date with changes made to its worktree
+//! from outside the mount.
+//!
+//! `treebeard rollback` resets the worktree, which is the overlay's upper
+//! layer, with git, and `treebeard sync --passthrough` removes the session's
+//! copies of synced files from it. The mount still caches what it last saw of
+//! each path, in [`InodeManager`](crate::overlay::inode_manager::InodeManager)
+//! and in the kernel, and after a rollback still has whiteouts for files the
+//! session deleted. A refresh fixes up the whiteouts so that the upper
+//! layer's copy of each changed path is what the mount shows, then points the
+//! cached inodes at the layer each path now resolves to and asks the kernel
+//! to drop its entries for them.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::overlay::convert::metadata_to_fileattr;
+use crate::overlay::types::LayerType;
+use crate::overlay::whiteout::Whiteout;
+use crate::overlay::OverlayFs;
+
+/// A cached inode whose path changed: its number, parent, name, layer and
+/// path.
+pub(crate) type StaleInode = (u64, u64, std::ffi::OsString, LayerType, Arc<PathBuf>);
+
+impl OverlayFs {
+    /// Show the worktree's current state of `paths`, relative to the mount.
+    ///
+    /// With `whiteout_missing`, the paths are ones git tracks, so one missing
+    /// from the worktree was deleted, and the main repo's copy is whited out.
+    /// Otherwise it reads through to the main repo's copy. Returns how many
+    /// cached inodes were refreshed.
+    pub(crate) fn refresh_paths(
+        &self,
+        paths: &[PathBuf],
+        whiteout_missing: bool,
+        notifier: Option<&fuser::Notifier>,
+    ) -> Result<usize> {
+        let mut affected: HashSet<PathBuf> = HashSet::new();
+        for path in paths {
+            let relative = Path::new(".").join(path);
+            self.fix_whiteouts(&relative, whiteout_missing);
+            affected.extend(relative.ancestors().map(Path::to_path_buf));
+        }
+
+        let stale: Vec<StaleInode> = self
+            .inode_manager
+            .inodes
+            .read()
+            .iter()
+            .filter(|inode| inode.inode != fuser::FUSE_ROOT_ID)
+            .filter(|inode| affected.contains(inode.path.as_path()))
+            .map(|inode| {
+                (
+                    inode.inode,
+                    inode.parent,
+                    inode.name.clone(),
+                    inode.layer,
+                    Arc::clone(&inode.path),
+                )
+            })
+            .collect();
+
+        self.refresh_inodes(&stale, notifier);
+        if let Some(notifier) = notifier {
+            // The root's listing changes when a top-level path comes or goes
+            if let Err(e) = notifier.inval_inode(fuser::FUSE_ROOT_ID, 0, 0) {
+                tracing::debug!("Failed to invalidate the root inode: {}", e);
+            }
+        }
+
+        tracing::info!(
+            "Refreshed {} path(s) changed in the worktree: {} inode(s) affected",
+            paths.len(),
+            stale.len()
+        );
+        Ok(stale.len())
+    }
+
+    /// Remove whiteouts hiding `relative_path` if the upper layer has it, or
+    /// white out the lower layer's copy if it doesn't and `whiteout_missing`
+    /// is set.
+    fn fix_whiteouts(&self, relative_path: &Path, whiteout_missing: bool) {
+        let resolver = &self.path_resolver;
+        if resolver.is_passthrough(relative_path) {
+            return;
+        }
+
+        let upper_path = resolver.upper_path(relative_path);
+        if upper_path.symlink_metadata().is_ok() {
+            for ancestor in upper_path.ancestors() {
+                if ancestor == resolver.upper_layer {
+                    break;
+                }
+                let (Some(parent), Some(name)) = (ancestor.parent(), ancestor.file_name()) else {
+                    break;
+                };
+                let marker = parent.join(Whiteout::marker_name(name));
+                if let Err(e) = fs::remove_file(&marker) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        tracing::warn!("Failed to remove whiteout {}: {}", marker.display(), e);
+                    }
+                }
+            }
+        } else if whiteout_missing
+            && resolver
+                .lower_path(relative_path)
+                .symlink_metadata()
+                .is_ok()
+        {
+            let (Some(parent), Some(name)) = (upper_path.parent(), upper_path.file_name()) else {
+                return;
+            };
+            let created = fs::create_dir_all(parent)
+                .map_err(|e| e.raw_os_error().unwrap_or(libc::EIO))
+                .and_then(|()| Whiteout::create(parent, name));
+            if let Err(errno) = created {
+                tracing::warn!(
+                    "Failed to white out {}: errno {}",
+                    relative_path.display(),
+                    errno
+                );
+            }
+        }
+    }
+
+    /// Point each of `stale` at the layer its path now resolves to, or drop
+    /// it if the path is gone, and invalidate the kernel's entries for it
+    /// through `notifier`.
+    pub(crate) fn refresh_inodes(&self, stale: &[StaleInode], notifier: Option<&fuser::Notifier>) {
+        for (ino, parent, name, layer, path) in stale {
+            let resolved = self
+                .path_resolver
+                .resolve_path(path, *layer)
+                .and_then(|(real_path, layer)| Some((layer, fs::metadata(real_path).ok()?)));
+            match resolved {
+                Some((layer, metadata)) => {
+                    let attrs = metadata_to_fileattr(&metadata, *ino);
+                    self.inode_manager
+                        .update_layer_and_attrs(*ino, layer, attrs);
+                }
+                // Gone from the layer it now resolves to; the next lookup
+                // reports that
+                None => self.inode_manager.remove_child(*parent, name),
+            }
+
+            if let Some(notifier) = notifier {
+                if let Err(e) = notifier.inval_entry(*parent, name) {
+                    tracing::debug!("Failed to invalidate entry {:?}: {}", name, e);
+                }
+                if let Err(e) = notifier.inval_inode(*ino, 0, 0) {
+                    tracing::debug!("Failed to invalidate inode {}: {}", ino, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(overlay: &OverlayFs, parent: u64, name: &str, path: &str) -> Option<u64> {
+        let (inode, _) = overlay
+            .lookup_overlay(parent, name.into(), PathBuf::from(path))
+            .unwrap()?;
+        let ino = inode.inode;
+        overlay.inode_manager.insert(inode);
+        Some(ino)
+    }
+
+    #[test]
+    fn test_refresh_fixes_whiteouts_and_cached_inodes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upper_layer = temp_dir.path().join("upper");
+        let lower_layer = temp_dir.path().join("lower");
+        fs::create_dir_all(upper_layer.join("src")).unwrap();
+        fs::create_dir_all(lower_layer.join("src")).unwrap();
+        fs::write(lower_layer.join("src/old.rs"), "lower").unwrap();
+        fs::write(lower_layer.join("src/gone.rs"), "lower").unwrap();
+        fs::write(upper_layer.join("src/gone.rs"), "upper").unwrap();
+        // The session deleted old.rs
+        Whiteout::create(&upper_layer.join("src"), "old.rs".as_ref()).unwrap();
+
+        let overlay = OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![]).unwrap();
+        let src = lookup(&overlay, fuser::FUSE_ROOT_ID, "src", "./src").unwrap();
+        assert!(lookup(&overlay, src, "gone.rs", "./src/gone.rs").is_some());
+        assert!(lookup(&overlay, src, "old.rs", "./src/old.rs").is_none());
+
+        // A rollback restores old.rs and deletes gone.rs in the worktree
+        fs::write(upper_layer.join("src/old.rs"), "restored").unwrap();
+        fs::remove_file(upper_layer.join("src/gone.rs")).unwrap();
+
+        let refreshed = overlay
+            .refresh_paths(
+                &[PathBuf::from("src/old.rs"), PathBuf::from("src/gone.rs")],
+                true,
+                None,
+            )
+            .unwrap();
+        assert_eq!(refreshed, 2);
+
+        assert!(!upper_layer.join("src/.wh.old.rs").exists());
+        assert!(upper_layer.join("src/.wh.gone.rs").exists());
+        assert_eq!(
+            overlay.inode_manager.lookup_child(src, "gone.rs".as_ref()),
+            None
+        );
+        let old = lookup(&overlay, src, "old.rs", "./src/old.rs").unwrap();
+        assert_eq!(overlay.inode_manager.get_layer(old), Some(LayerType::Upper));
+        assert!(lookup(&overlay, src, "gone.rs", "./src/gone.rs").is_none());
+    }
+
+    #[test]
+    fn test_refresh_reads_released_paths_through() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upper_layer = temp_dir.path().join("upper");
+        let lower_layer = temp_dir.path().join("lower");
+        fs::create_dir_all(&upper_layer).unwrap();
+        fs::create_dir_all(&lower_layer).unwrap();
+        fs::write(lower_layer.join(".env"), "synced").unwrap();
+        fs::write(upper_layer.join(".env"), "synced").unwrap();
+
+        let overlay = OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![]).unwrap();
+        let env = lookup(&overlay, fuser::FUSE_ROOT_ID, ".env", "./.env").unwrap();
+        assert_eq!(overlay.inode_manager.get_layer(env), Some(LayerType::Upper));
+
+        // `sync --passthrough` removes the session's copy
+        fs::remove_file(upper_layer.join(".env")).unwrap();
+        let refreshed = overlay
+            .refresh_paths(&[PathBuf::from(".env")], false, None)
+            .unwrap();
+        assert_eq!(refreshed, 1);
+
+        assert!(!upper_layer.join(".wh..env").exists());
+        assert_eq!(overlay.inode_manager.get_layer(env), Some(LayerType::Lower));
+    }
+}
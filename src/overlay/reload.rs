@@ -0,0 +1,214 @@
+//! Changing the passthrough patterns of a mounted overlay.
+//!
+//! `treebeard reload` sends the patterns from the current config to the
+//! mount's stats socket. Passthrough status is cached per path, both in
+//! [`PathResolver`](crate::overlay::path_resolver::PathResolver) and, through
+//! the inodes it handed out, in the kernel. A reload clears the first, points
+//! each inode whose status changed at the layer it now resolves to, and asks
+//! the kernel to drop its entries for them so the next access looks them up
+//! again.
+//!
+//! A path that becomes passthrough reads the main repo's copy from then on,
+//! so a copy in the worktree is hidden, though not deleted. Such copies are
+//! reported, and the reload is refused unless forced.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::overlay::path_resolver::{compile_passthrough_patterns, matches_passthrough};
+use crate::overlay::refresh::StaleInode;
+use crate::overlay::whiteout::Whiteout;
+use crate::overlay::OverlayFs;
+
+/// Outcome of reloading a mount's passthrough patterns.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PassthroughReload {
+    /// Whether the new patterns are in effect
+    pub applied: bool,
+    /// Worktree files the new patterns hide, relative to the mount
+    pub shadowed: Vec<PathBuf>,
+    /// Inodes whose passthrough status changed
+    pub changed: usize,
+}
+
+impl OverlayFs {
+    /// Replace the passthrough patterns of this overlay.
+    ///
+    /// Nothing changes if the new patterns would hide files in the upper
+    /// layer, unless `force` is set. Kernel entries for the affected inodes
+    /// are invalidated through `notifier` when the overlay is mounted.
+    pub(crate) fn reload_passthrough(
+        &self,
+        patterns: &[String],
+        force: bool,
+        notifier: Option<&fuser::Notifier>,
+    ) -> Result<PassthroughReload> {
+        let new_patterns = compile_passthrough_patterns(patterns)?;
+        let old_patterns = self.path_resolver.passthrough_patterns();
+        let becomes_passthrough = |path: &Path| {
+            matches_passthrough(&new_patterns, path) && !matches_passthrough(&old_patterns, path)
+        };
+
+        let shadowed = self.upper_files_matching(&becomes_passthrough);
+        if !shadowed.is_empty() && !force {
+            return Ok(PassthroughReload {
+                applied: false,
+                shadowed,
+                changed: 0,
+            });
+        }
+
+        let changed: Vec<StaleInode> = self
+            .inode_manager
+            .inodes
+            .read()
+            .iter()
+            .filter(|inode| inode.inode != fuser::FUSE_ROOT_ID)
+            .filter(|inode| {
+                matches_passthrough(&new_patterns, &inode.path)
+                    != matches_passthrough(&old_patterns, &inode.path)
+            })
+            .map(|inode| {
+                (
+                    inode.inode,
+                    inode.parent,
+                    inode.name.clone(),
+                    inode.layer,
+                    Arc::clone(&inode.path),
+                )
+            })
+            .collect();
+
+        self.path_resolver.set_passthrough_patterns(new_patterns);
+        self.refresh_inodes(&changed, notifier);
+
+        tracing::info!(
+            "Reloaded passthrough patterns: {} inode(s) changed, {} worktree file(s) hidden",
+            changed.len(),
+            shadowed.len()
+        );
+
+        Ok(PassthroughReload {
+            applied: true,
+            shadowed,
+            changed: changed.len(),
+        })
+    }
+
+    /// Files in the upper layer whose path satisfies `filter`, relative to
+    /// the mount. Whiteout markers and the worktree's `.git` aren't included.
+    fn upper_files_matching(&self, filter: &dyn Fn(&Path) -> bool) -> Vec<PathBuf> {
+        let upper_layer = &self.path_resolver.upper_layer;
+        walkdir::WalkDir::new(upper_layer)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| !(entry.depth() == 1 && entry.file_name() == ".git"))
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| !entry.file_type().is_dir())
+            .filter(|entry| {
+                !Whiteout::is_whiteout_marker(entry.file_name())
+                    && !Whiteout::is_opaque_marker(entry.file_name())
+            })
+            .filter_map(|entry| {
+                let relative = entry.path().strip_prefix(upper_layer).ok()?;
+                filter(relative).then(|| relative.to_path_buf())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay::types::LayerType;
+    use std::fs;
+
+    fn overlay_with_layers() -> (tempfile::TempDir, OverlayFs) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upper_layer = temp_dir.path().join("upper");
+        let lower_layer = temp_dir.path().join("lower");
+        fs::create_dir_all(upper_layer.join("target")).unwrap();
+        fs::create_dir_all(lower_layer.join("target")).unwrap();
+        fs::create_dir_all(lower_layer.join(".venv")).unwrap();
+        fs::write(upper_layer.join("target/build.log"), "upper").unwrap();
+        fs::write(lower_layer.join("target/build.log"), "lower").unwrap();
+        fs::write(lower_layer.join(".venv/pyvenv.cfg"), "lower").unwrap();
+
+        let overlay = OverlayFs::new(upper_layer, lower_layer, None, 1, vec![]).unwrap();
+        (temp_dir, overlay)
+    }
+
+    fn lookup(overlay: &OverlayFs, parent: u64, name: &str, path: &str) -> u64 {
+        let (inode, _) = overlay
+            .lookup_overlay(parent, name.into(), PathBuf::from(path))
+            .unwrap()
+            .unwrap();
+        let ino = inode.inode;
+        overlay.inode_manager.insert(inode);
+        ino
+    }
+
+    #[test]
+    fn test_reload_refuses_to_hide_upper_copies_unless_forced() {
+        let (_temp_dir, overlay) = overlay_with_layers();
+        let target = lookup(&overlay, fuser::FUSE_ROOT_ID, "target", "./target");
+        let log = lookup(&overlay, target, "build.log", "./target/build.log");
+        assert!(!overlay
+            .path_resolver
+            .is_passthrough(Path::new("./target/build.log")));
+
+        let patterns = vec!["target/**".to_string()];
+        let refused = overlay.reload_passthrough(&patterns, false, None).unwrap();
+        assert!(!refused.applied);
+        assert_eq!(refused.shadowed, vec![PathBuf::from("target/build.log")]);
+        assert!(!overlay
+            .path_resolver
+            .is_passthrough(Path::new("./target/build.log")));
+
+        let forced = overlay.reload_passthrough(&patterns, true, None).unwrap();
+        assert!(forced.applied);
+        assert_eq!(forced.changed, 2);
+        assert!(overlay
+            .path_resolver
+            .is_passthrough(Path::new("./target/build.log")));
+        // The cached inode now reads the main repo's copy
+        assert_eq!(overlay.inode_manager.get_layer(log), Some(LayerType::Lower));
+        assert_eq!(
+            overlay.inode_manager.get_attrs(log).unwrap().size,
+            "lower".len() as u64
+        );
+    }
+
+    #[test]
+    fn test_reload_without_upper_copies_applies() {
+        let (_temp_dir, overlay) = overlay_with_layers();
+
+        let reload = overlay
+            .reload_passthrough(&[".venv/**".to_string()], false, None)
+            .unwrap();
+        assert!(reload.applied);
+        assert!(reload.shadowed.is_empty());
+        assert!(overlay
+            .path_resolver
+            .is_passthrough(Path::new("./.venv/pyvenv.cfg")));
+
+        // Removing the pattern again hides nothing
+        let reload = overlay.reload_passthrough(&[], false, None).unwrap();
+        assert!(reload.applied);
+        assert!(!overlay
+            .path_resolver
+            .is_passthrough(Path::new("./.venv/pyvenv.cfg")));
+    }
+
+    #[test]
+    fn test_reload_rejects_invalid_pattern() {
+        let (_temp_dir, overlay) = overlay_with_layers();
+        assert!(overlay
+            .reload_passthrough(&["[".to_string()], true, None)
+            .is_err());
+    }
+}
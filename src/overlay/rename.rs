@@ -0,0 +1,577 @@
+//! `rename` for the overlay, with the replace-on-rename semantics POSIX
+//! requires and the `renameat2()` flags.
+//!
+//! Renaming over an existing name replaces it atomically in the upper layer,
+//! whichever layer the old file came from. A source that exists in the lower
+//! layer is copied up first and its old name whited out, and a whiteout at the
+//! destination is removed once the file is in place. A directory moved over
+//! a name the lower layer has is made opaque, as `mkdir` would make it.
+//!
+//! Directories that exist in the lower layer can't be moved, since only their
+//! upper half would go with them. Like overlayfs, those renames fail with
+//! `EXDEV`, and tools such as `mv` fall back to copy and delete.
+
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::overlay::convert::io_error_to_libc;
+use crate::overlay::types::{LayerType, MutationType};
+use crate::overlay::whiteout::Whiteout;
+use crate::overlay::OverlayFs;
+
+#[cfg(target_os = "linux")]
+const RENAME_NOREPLACE: u32 = libc::RENAME_NOREPLACE;
+#[cfg(target_os = "linux")]
+const RENAME_EXCHANGE: u32 = libc::RENAME_EXCHANGE;
+
+// renamex_np(2) names for the same flags
+#[cfg(target_os = "macos")]
+const RENAME_NOREPLACE: u32 = libc::RENAME_EXCL;
+#[cfg(target_os = "macos")]
+const RENAME_EXCHANGE: u32 = libc::RENAME_SWAP;
+
+/// Atomically swaps two paths on the same filesystem.
+#[cfg(target_os = "macos")]
+fn exchange_paths(a: &Path, b: &Path) -> io::Result<()> {
+    let (a, b) = (path_to_cstring(a)?, path_to_cstring(b)?);
+    // SAFETY: Both paths are properly null-terminated CStrings that outlive the call.
+    if unsafe { libc::renamex_np(a.as_ptr(), b.as_ptr(), libc::RENAME_SWAP) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Atomically swaps two paths on the same filesystem.
+#[cfg(not(target_os = "macos"))]
+fn exchange_paths(a: &Path, b: &Path) -> io::Result<()> {
+    let (a, b) = (path_to_cstring(a)?, path_to_cstring(b)?);
+    // SAFETY: Both paths are properly null-terminated CStrings that outlive the call.
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn path_to_cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains null byte"))
+}
+
+fn exists(path: &Path) -> bool {
+    path.symlink_metadata().is_ok()
+}
+
+impl OverlayFs {
+    /// Returns the inode currently visible at `name` in `parent`, looking it
+    /// up from the layers if it isn't cached. `None` if nothing is there.
+    fn lookup_existing_child(
+        &self,
+        parent: u64,
+        name: &OsStr,
+        relative_path: &Path,
+    ) -> Result<Option<u64>, i32> {
+        if let Some(ino) = self.inode_manager.lookup_child(parent, name) {
+            return match self.lookup_check_cached(ino, name) {
+                Ok(_) => Ok(Some(ino)),
+                Err(libc::ENOENT) => Ok(None),
+                Err(e) => Err(e),
+            };
+        }
+
+        let inode =
+            match self.lookup_passthrough(parent, name.to_os_string(), relative_path.to_path_buf())
+            {
+                Some(Ok((inode, _))) => Some(inode),
+                Some(Err(libc::ENOENT)) => None,
+                Some(Err(e)) => return Err(e),
+                None => self
+                    .lookup_overlay(parent, name.to_os_string(), relative_path.to_path_buf())?
+                    .map(|(inode, _)| inode),
+            };
+
+        Ok(inode.map(|inode| self.inode_manager.insert_if_absent(inode).ino))
+    }
+
+    fn is_directory(&self, ino: u64) -> bool {
+        self.inode_manager
+            .get_attrs(ino)
+            .is_some_and(|attrs| attrs.kind == fuser::FileType::Directory)
+    }
+
+    fn is_regular_file(&self, ino: u64) -> bool {
+        self.inode_manager
+            .get_attrs(ino)
+            .is_some_and(|attrs| attrs.kind == fuser::FileType::RegularFile)
+    }
+
+    /// A directory with a lower-layer half, which a rename can't move.
+    fn is_merged_directory(&self, ino: u64, relative_path: &Path) -> bool {
+        self.is_directory(ino)
+            && fs::symlink_metadata(self.path_resolver.lower_path(relative_path))
+                .is_ok_and(|m| m.is_dir())
+    }
+
+    /// Moves tracked mutations from each `old` path, and everything below
+    /// it, to the matching `new` path. All moves are read before any is
+    /// applied so swapped paths don't overwrite each other.
+    fn move_mutations(&self, moves: &[(&Path, &Path)]) {
+        let moved: Vec<(PathBuf, PathBuf, MutationType)> = {
+            let mutations = self.mutations.read();
+            moves
+                .iter()
+                .flat_map(|(old, new)| {
+                    mutations.iter().filter_map(move |(path, mutation)| {
+                        let rest = path.strip_prefix(old).ok()?;
+                        let moved_to = if rest.as_os_str().is_empty() {
+                            new.to_path_buf()
+                        } else {
+                            new.join(rest)
+                        };
+                        Some((path.clone(), moved_to, mutation.clone()))
+                    })
+                })
+                .collect()
+        };
+
+        for (old, _, _) in &moved {
+            self.forget_mutation(old);
+        }
+        for (_, new, mutation) in moved {
+            self.record_mutation(&new, mutation);
+        }
+    }
+
+    /// Records a regular file that now sits at `relative_path` in the upper
+    /// layer: a copy-up if it shadows a lower file, otherwise a creation.
+    fn record_renamed_file(&self, relative_path: &Path) {
+        let lower_path = self.path_resolver.lower_path(relative_path);
+        if let Ok(lower) = fs::symlink_metadata(&lower_path) {
+            // A file that replaced a lower file without copying it up
+            // takes the lower content as its base
+            if !self.mutations.read().contains_key(relative_path) {
+                self.record_base(relative_path, &lower, &lower_path);
+            }
+            self.record_mutation(relative_path, MutationType::CopiedUp);
+        } else {
+            self.record_mutation(relative_path, MutationType::Created);
+        }
+    }
+
+    /// Renames `name` in `parent` to `newname` in `newparent`.
+    ///
+    /// An existing destination is replaced unless `flags` has
+    /// `RENAME_NOREPLACE`; `RENAME_EXCHANGE` swaps two existing names.
+    /// Returns the errno to reply with on failure.
+    pub(crate) fn do_rename(
+        &self,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+    ) -> Result<(), i32> {
+        let noreplace = flags & RENAME_NOREPLACE != 0;
+        let exchange = flags & RENAME_EXCHANGE != 0;
+        if flags & !(RENAME_NOREPLACE | RENAME_EXCHANGE) != 0 || (noreplace && exchange) {
+            return Err(libc::EINVAL);
+        }
+
+        let ino = self
+            .inode_manager
+            .lookup_child(parent, name)
+            .ok_or(libc::ENOENT)?;
+        let (rel_path, _, _) = self.inode_manager.get_inode_info(ino).ok_or(libc::ENOENT)?;
+        let new_parent_path = self.inode_manager.get_path(newparent).ok_or(libc::ENOENT)?;
+        let new_rel_path = new_parent_path.join(newname);
+
+        let dest = self.lookup_existing_child(newparent, newname, &new_rel_path)?;
+        match dest {
+            // Two names for the same file: POSIX says do nothing
+            Some(dest_ino) if dest_ino == ino => return Ok(()),
+            Some(_) if noreplace => return Err(libc::EEXIST),
+            None if exchange => return Err(libc::ENOENT),
+            _ => {}
+        }
+
+        if let (Some(dest_ino), false) = (dest, exchange) {
+            match (self.is_directory(ino), self.is_directory(dest_ino)) {
+                (true, false) => return Err(libc::ENOTDIR),
+                (false, true) => return Err(libc::EISDIR),
+                _ => {}
+            }
+        }
+
+        let src_is_passthrough = self.path_resolver.is_passthrough(&rel_path);
+        let dest_is_passthrough = self.path_resolver.is_passthrough(&new_rel_path);
+        if src_is_passthrough != dest_is_passthrough {
+            // One side bypasses the upper layer, so they are different layers
+            return Err(libc::EXDEV);
+        }
+
+        // Passthrough paths live in the lower layer only and skip COW
+        let layer = if src_is_passthrough {
+            LayerType::Lower
+        } else {
+            LayerType::Upper
+        };
+
+        if layer == LayerType::Upper {
+            if self.is_merged_directory(ino, &rel_path)
+                || dest.is_some_and(|d| self.is_merged_directory(d, &new_rel_path))
+            {
+                return Err(libc::EXDEV);
+            }
+
+            self.copy_up(ino)?;
+            if let (Some(dest_ino), true) = (dest, exchange) {
+                self.copy_up(dest_ino)?;
+            }
+
+            let upper_parent = self.path_resolver.upper_path(&new_parent_path);
+            fs::create_dir_all(&upper_parent).map_err(|e| io_error_to_libc(&e))?;
+        }
+
+        let base = self.path_resolver.layer_base_path(layer);
+        let src_path = base.join(&*rel_path);
+        let dest_path = base.join(&new_rel_path);
+
+        let result = if exchange {
+            exchange_paths(&src_path, &dest_path)
+        } else {
+            fs::rename(&src_path, &dest_path)
+        };
+        if let Err(e) = result {
+            tracing::error!("rename error: {}", e);
+            return Err(io_error_to_libc(&e));
+        }
+
+        let src_is_file = self.is_regular_file(ino);
+        match dest {
+            Some(dest_ino) if exchange => {
+                let dest_is_file = self.is_regular_file(dest_ino);
+                self.inode_manager.update_after_exchange(
+                    (ino, parent, name),
+                    (dest_ino, newparent, newname),
+                    layer,
+                );
+
+                if layer == LayerType::Upper {
+                    self.move_mutations(&[(&rel_path, &new_rel_path), (&new_rel_path, &rel_path)]);
+                    if src_is_file {
+                        self.record_renamed_file(&new_rel_path);
+                    }
+                    if dest_is_file {
+                        self.record_renamed_file(&rel_path);
+                    }
+                }
+            }
+            _ => {
+                if let Some(dest_ino) = dest {
+                    // Before the rename takes over the name in the table
+                    self.inode_manager.detach_replaced(dest_ino);
+                }
+                self.inode_manager.update_after_rename(
+                    ino,
+                    parent,
+                    name,
+                    newparent,
+                    newname.to_os_string(),
+                    new_rel_path.clone(),
+                    layer,
+                );
+
+                if layer == LayerType::Upper {
+                    // The destination may have been deleted earlier in the
+                    // session; the new file replaces the whiteout
+                    self.remove_whiteout(&new_parent_path, newname);
+
+                    // As with mkdir, a directory moved over a name the lower
+                    // layer has must not merge in the lower directory's entries
+                    if self.is_directory(ino)
+                        && exists(&self.path_resolver.lower_path(&new_rel_path))
+                    {
+                        if let Err(e) = Whiteout::make_opaque(&dest_path) {
+                            tracing::error!(
+                                "rename: failed to make {:?} opaque: {}",
+                                new_rel_path,
+                                e
+                            );
+                            return Err(e);
+                        }
+                    }
+
+                    let lower_src_exists = exists(&self.path_resolver.lower_path(&rel_path));
+                    if src_is_file {
+                        if lower_src_exists {
+                            self.record_mutation(&rel_path, MutationType::Deleted);
+                        } else {
+                            self.forget_mutation(&rel_path);
+                        }
+                        self.record_renamed_file(&new_rel_path);
+                    } else {
+                        self.move_mutations(&[(&rel_path, &new_rel_path)]);
+                    }
+
+                    // The copy moved away, so hide the lower file it came from
+                    if lower_src_exists {
+                        self.create_whiteout(parent, name)?;
+                    }
+                }
+            }
+        }
+
+        if layer == LayerType::Upper {
+            // Signal both old and new paths for rename
+            self.signal_mutation(&rel_path);
+            self.signal_mutation(&new_rel_path);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay::test_helpers::setup;
+    use fuser::FUSE_ROOT_ID;
+    use std::ffi::OsString;
+
+    /// Looks up `name` in `parent` as the kernel would before a rename.
+    fn lookup_in(fs: &OverlayFs, parent: u64, name: &str, path: &str) -> u64 {
+        let (inode, _) = fs
+            .lookup_overlay(parent, OsString::from(name), PathBuf::from(path))
+            .unwrap()
+            .unwrap();
+        let ino = inode.inode;
+        fs.inode_manager.insert(inode);
+        ino
+    }
+
+    fn lookup(fs: &OverlayFs, name: &str) -> u64 {
+        lookup_in(fs, FUSE_ROOT_ID, name, &format!("./{}", name))
+    }
+
+    fn rename(fs: &OverlayFs, from: &str, to: &str, flags: u32) -> Result<(), i32> {
+        fs.do_rename(
+            FUSE_ROOT_ID,
+            OsStr::new(from),
+            FUSE_ROOT_ID,
+            OsStr::new(to),
+            flags,
+        )
+    }
+
+    #[test]
+    fn test_rename_replaces_lower_file() {
+        let (_temp, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("config.json"), "old").unwrap();
+        fs::write(upper_layer.join(".config.json.tmp"), "new").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        let ino = lookup(&fs, ".config.json.tmp");
+        let old_ino = lookup(&fs, "config.json");
+
+        rename(&fs, ".config.json.tmp", "config.json", 0).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(upper_layer.join("config.json")).unwrap(),
+            "new"
+        );
+        assert!(!upper_layer.join(".config.json.tmp").exists());
+        assert!(!upper_layer.join(".wh..config.json.tmp").exists());
+        assert_eq!(
+            fs::read_to_string(lower_layer.join("config.json")).unwrap(),
+            "old"
+        );
+
+        assert_eq!(
+            fs.inode_manager
+                .lookup_child(FUSE_ROOT_ID, OsStr::new("config.json")),
+            Some(ino)
+        );
+        assert!(fs.inode_manager.get_path(old_ino).is_none());
+        assert!(fs
+            .inode_manager
+            .lookup_child(FUSE_ROOT_ID, OsStr::new(".config.json.tmp"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_rename_lower_file_whites_out_old_name() {
+        let (_temp, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("a.txt"), "from lower").unwrap();
+        fs::write(upper_layer.join("b.txt"), "replaced").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        lookup(&fs, "a.txt");
+
+        rename(&fs, "a.txt", "b.txt", 0).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(upper_layer.join("b.txt")).unwrap(),
+            "from lower"
+        );
+        assert!(upper_layer.join(".wh.a.txt").exists());
+        assert!(fs
+            .lookup_overlay(
+                FUSE_ROOT_ID,
+                OsString::from("a.txt"),
+                PathBuf::from("./a.txt")
+            )
+            .unwrap()
+            .is_none());
+
+        let mutations = fs.mutations.read();
+        assert_eq!(
+            mutations.get(Path::new("./a.txt")),
+            Some(&MutationType::Deleted)
+        );
+        assert_eq!(
+            mutations.get(Path::new("./b.txt")),
+            Some(&MutationType::Created)
+        );
+    }
+
+    #[test]
+    fn test_rename_over_whiteout() {
+        let (_temp, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("a.txt"), "deleted").unwrap();
+        fs::write(upper_layer.join(".wh.a.txt"), "").unwrap();
+        fs::write(upper_layer.join("b.txt"), "restored").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        lookup(&fs, "b.txt");
+
+        rename(&fs, "b.txt", "a.txt", RENAME_NOREPLACE).unwrap();
+
+        assert!(!upper_layer.join(".wh.a.txt").exists());
+        assert_eq!(
+            fs::read_to_string(upper_layer.join("a.txt")).unwrap(),
+            "restored"
+        );
+    }
+
+    #[test]
+    fn test_rename_noreplace_and_exchange() {
+        let (_temp, upper_layer, lower_layer) = setup();
+        fs::write(lower_layer.join("a.txt"), "A").unwrap();
+        fs::write(upper_layer.join("b.txt"), "B").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        let a = lookup(&fs, "a.txt");
+        let b = lookup(&fs, "b.txt");
+
+        assert_eq!(
+            rename(&fs, "b.txt", "a.txt", RENAME_NOREPLACE),
+            Err(libc::EEXIST)
+        );
+        assert_eq!(
+            rename(&fs, "b.txt", "missing", RENAME_EXCHANGE),
+            Err(libc::ENOENT)
+        );
+        assert_eq!(
+            rename(&fs, "b.txt", "a.txt", RENAME_NOREPLACE | RENAME_EXCHANGE),
+            Err(libc::EINVAL)
+        );
+        assert_eq!(fs::read_to_string(upper_layer.join("b.txt")).unwrap(), "B");
+
+        rename(&fs, "b.txt", "a.txt", RENAME_EXCHANGE).unwrap();
+
+        assert_eq!(fs::read_to_string(upper_layer.join("a.txt")).unwrap(), "B");
+        assert_eq!(fs::read_to_string(upper_layer.join("b.txt")).unwrap(), "A");
+        assert!(!upper_layer.join(".wh.a.txt").exists());
+        assert!(!upper_layer.join(".wh.b.txt").exists());
+        assert_eq!(
+            fs.inode_manager
+                .lookup_child(FUSE_ROOT_ID, OsStr::new("a.txt")),
+            Some(b)
+        );
+        assert_eq!(
+            fs.inode_manager
+                .lookup_child(FUSE_ROOT_ID, OsStr::new("b.txt")),
+            Some(a)
+        );
+        assert_eq!(
+            fs.inode_manager.get_path(a).as_deref(),
+            Some(&PathBuf::from("./b.txt"))
+        );
+    }
+
+    #[test]
+    fn test_rename_directories() {
+        let (_temp, upper_layer, lower_layer) = setup();
+        fs::create_dir(lower_layer.join("src")).unwrap();
+        fs::create_dir_all(upper_layer.join("build/out")).unwrap();
+        fs::write(upper_layer.join("build/out/app"), "bin").unwrap();
+
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]).unwrap();
+        lookup(&fs, "src");
+        let build = lookup(&fs, "build");
+        let out = lookup_in(&fs, build, "out", "./build/out");
+
+        assert_eq!(rename(&fs, "src", "source", 0), Err(libc::EXDEV));
+
+        rename(&fs, "build", "dist", 0).unwrap();
+
+        assert!(upper_layer.join("dist/out/app").exists());
+        assert_eq!(
+            fs.inode_manager.get_path(out).as_deref(),
+            Some(&PathBuf::from("./dist/out"))
+        );
+    }
+
+    #[test]
+    fn test_rename_directory_over_lower_directory() {
+        use std::collections::{HashMap, HashSet};
+
+        let (_temp, upper_layer, lower_layer) = setup();
+        fs::create_dir(lower_layer.join("build")).unwrap();
+        fs::write(lower_layer.join("build/old.o"), "stale").unwrap();
+        fs::create_dir(upper_layer.join("build.tmp")).unwrap();
+        fs::write(upper_layer.join("build.tmp/new.o"), "fresh").unwrap();
+        Whiteout::create(&upper_layer, OsStr::new("build")).unwrap();
+
+        // The tail of `rm -rf build && mv build.tmp build`
+        let fs = OverlayFs::new(upper_layer.clone(), lower_layer, None, 1, vec![]).unwrap();
+        lookup(&fs, "build.tmp");
+        rename(&fs, "build.tmp", "build", 0).unwrap();
+
+        assert!(Whiteout::is_opaque(&upper_layer.join("build")));
+        assert!(!Whiteout::is_whiteout(&upper_layer.join("build")));
+
+        let build = lookup(&fs, "build");
+        let mut entries = HashMap::new();
+        let mut whiteouts = HashSet::new();
+        let mut new_inodes = Vec::new();
+        let mut layer_updates = Vec::new();
+        for layer in [LayerType::Lower, LayerType::Upper] {
+            fs.scan_directory_layer(
+                &fs.path_resolver.layer_base_path(layer).join("./build"),
+                layer,
+                build,
+                Path::new("./build"),
+                &mut entries,
+                &mut whiteouts,
+                &mut new_inodes,
+                &mut layer_updates,
+            );
+        }
+        let names: Vec<_> = entries.keys().cloned().collect();
+        assert_eq!(names, vec![OsString::from("new.o")]);
+    }
+}
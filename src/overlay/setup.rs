@@ -1,10 +1,10 @@
 use crate::config::{get_mount_dir, Config};
 use crate::error::Result;
-use crate::git::GitRepo;
-use crate::overlay::mount::mount_fuse;
-use crate::watcher;
+use crate::git::{CommitSession, GitRepo};
+use crate::overlay::backend::{self, MountRequest, OverlayBackend};
+use crate::session::get_mutation_journal_path;
+use crate::watcher::{self, AutoCommitFailures};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use tokio::task;
 
@@ -18,14 +18,37 @@ pub struct OverlaySetup {
     pub watcher_handle: task::JoinHandle<()>,
 }
 
-/// Sets up the FUSE overlay filesystem and spawns the file watcher
+/// Whites out files the main repo tracks at its HEAD but the worktree's HEAD
+/// doesn't have, such as files added to the main branch since a `--from`
+/// start point. Otherwise they'd show through from the lower layer as if the
+/// session had created them.
+fn hide_lower_only_tracked_paths(
+    backend: &dyn OverlayBackend,
+    worktree_path: &Path,
+    main_repo_path: &Path,
+) -> Result<()> {
+    let worktree_head = GitRepo::from_path(worktree_path)?.get_head()?;
+    let paths = GitRepo::from_path(main_repo_path)?.tracked_paths_missing_from(&worktree_head)?;
+    let hidden = backend::hide_lower_paths(backend, worktree_path, main_repo_path, &paths)?;
+    if hidden > 0 {
+        tracing::debug!(
+            "Whited out {} path(s) tracked in the main repo but not in the worktree",
+            hidden
+        );
+    }
+    Ok(())
+}
+
+/// Mounts the overlay filesystem with the configured backend and spawns the
+/// file watcher, which commits on behalf of `session`
 pub fn setup_overlay_and_watcher(
-    branch_name: &str,
+    session: &CommitSession,
     worktree_path: &Path,
     main_repo_path: &Path,
     config: &Config,
-    failure_count: Arc<AtomicUsize>,
+    failure_count: Arc<AutoCommitFailures>,
 ) -> Result<OverlaySetup> {
+    let branch_name = session.branch.as_str();
     let mount_base_dir = get_mount_dir()?;
     let repo_name = worktree_path
         .parent()
@@ -34,30 +57,52 @@ pub fn setup_overlay_and_watcher(
         .unwrap_or_else(|| "unknown".to_string());
     let mount_path = mount_base_dir.join(&repo_name).join(branch_name);
 
-    println!("Mounting overlay filesystem at: {}", mount_path.display());
+    let journal_path = get_mutation_journal_path(main_repo_path, branch_name)?;
+
+    let backend = backend::backend(config.get_overlay_backend())?;
 
-    let (mutations, mutation_rx) = match mount_fuse(
-        &mount_path,
-        worktree_path,
-        main_repo_path,
-        config.get_fuse_ttl_secs(),
-        config.paths.get_passthrough(),
-    ) {
+    println!(
+        "Mounting {} overlay filesystem at: {}",
+        backend.name(),
+        mount_path.display()
+    );
+
+    hide_lower_only_tracked_paths(backend.as_ref(), worktree_path, main_repo_path)?;
+
+    let (mutations, mutation_rx) = match backend.mount(&MountRequest {
+        mount_point: &mount_path,
+        upper_layer: worktree_path,
+        lower_layer: main_repo_path,
+        ttl_secs: config.get_fuse_ttl_secs(),
+        passthrough_patterns: config.paths.get_passthrough(),
+        journal_path: Some(&journal_path),
+        inode_cache: &config.inode_cache,
+    }) {
         Ok(f) => f,
         Err(e) => {
-            eprintln!("Failed to mount FUSE filesystem: {}", e);
-            eprintln!("Make sure macFUSE is installed: brew install --cask macfuse");
+            eprintln!("Failed to mount {} overlay: {}", backend.name(), e);
+            eprintln!("{}", backend.mount_hint());
             return Err(e);
         }
     };
 
+    let recovered = mutations.read().len();
+    if recovered > 0 {
+        println!(
+            "Recovered {} file change(s) from a previous session; they will be offered for sync at cleanup",
+            recovered
+        );
+    }
+
     tracing::debug!(
         "Creating GitRepo for worktree at: {}",
         worktree_path.display()
     );
 
     let worktree_repo = match GitRepo::from_path(worktree_path) {
-        Ok(r) => r,
+        Ok(r) => r
+            .with_signing(config.commit.get_signing())
+            .with_attribution(config.commit.get_attribution(session)),
         Err(e) => {
             tracing::error!("Failed to create GitRepo for worktree: {}", e);
             return Err(e);
@@ -114,7 +159,7 @@ pub fn setup_overlay_and_watcher(
         })
     };
 
-    println!("FUSE filesystem mounted successfully");
+    println!("Overlay filesystem mounted successfully");
 
     Ok(OverlaySetup {
         mutations,
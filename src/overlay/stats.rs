@@ -0,0 +1,408 @@
+//! Runtime statistics for a mounted FUSE overlay.
+//!
+//! `TreebeardFs`, `OverlayFs`, `InodeManager` and `PathResolver` keep relaxed
+//! atomic counters as they handle requests. While the mount is up, the
+//! process serving it listens on a Unix socket next to the mount point. Each
+//! connection carries one JSON [`StatsRequest`] line and gets one JSON
+//! [`StatsResponse`] line back. `treebeard stats` reads a snapshot of the
+//! counters this way. The same socket is how `treebeard reload` changes the
+//! overlay's passthrough patterns, and how `treebeard rollback` and `treebeard
+//! sync --passthrough` have it pick up the files they changed in the worktree.
+
+use crate::error::{Result, TreebeardError};
+use crate::overlay::path_resolver::PassthroughStats;
+use crate::overlay::reload::PassthroughReload;
+use crate::overlay::types::InodeCacheStats;
+use crate::overlay::OverlayFs;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long clients wait for the mount's process to answer. Reloads and
+/// refreshes walk the inode table, so this is generous.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A snapshot of an overlay's counters since it was mounted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlayStats {
+    pub uptime_secs: u64,
+    /// FUSE requests handled, of any kind
+    pub requests: u64,
+    /// Kernel lookups, answered from the inode cache or the layers
+    pub lookups: u64,
+    pub inode_cache: InodeCacheStats,
+    /// Files and directories copied from the main repo to the worktree
+    pub copy_ups: u64,
+    /// Bytes of file content copied up
+    pub bytes_copied: u64,
+    /// Whiteout markers created for deleted main repo files
+    pub whiteouts: u64,
+    pub passthrough: PassthroughStats,
+}
+
+/// Counters kept by `OverlayFs` itself.
+#[derive(Debug, Default)]
+pub(crate) struct OverlayCounters {
+    pub copy_ups: AtomicU64,
+    pub bytes_copied: AtomicU64,
+    pub whiteouts: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StatsRequest {
+    /// Return the overlay's counters.
+    Stats,
+    /// Replace the passthrough patterns. Refused if they would hide files in
+    /// the worktree, unless `force` is set.
+    ReloadPassthrough { patterns: Vec<String>, force: bool },
+    /// Show the worktree's current state of these paths, relative to the
+    /// mount, after they were changed from outside it. With
+    /// `whiteout_missing`, a path missing from the worktree was deleted and
+    /// the main repo's copy is hidden; otherwise it reads through to it.
+    RefreshPaths {
+        paths: Vec<PathBuf>,
+        whiteout_missing: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StatsResponse {
+    Stats { stats: OverlayStats },
+    PassthroughReloaded { reload: PassthroughReload },
+    PathsRefreshed { inodes: usize },
+    Error { message: String },
+}
+
+/// Everything the stats socket answers from, shared with the mounted
+/// filesystem.
+#[derive(Clone)]
+pub(crate) struct StatsSource {
+    pub overlay: Arc<OverlayFs>,
+    pub requests: Arc<AtomicU64>,
+    pub mounted_at: Instant,
+}
+
+impl StatsSource {
+    pub fn snapshot(&self) -> OverlayStats {
+        let inode_cache = self.overlay.inode_manager.cache_stats();
+        let counters = &self.overlay.counters;
+        OverlayStats {
+            uptime_secs: self.mounted_at.elapsed().as_secs(),
+            requests: self.requests.load(Ordering::Relaxed),
+            lookups: inode_cache.hits + inode_cache.misses,
+            inode_cache,
+            copy_ups: counters.copy_ups.load(Ordering::Relaxed),
+            bytes_copied: counters.bytes_copied.load(Ordering::Relaxed),
+            whiteouts: counters.whiteouts.load(Ordering::Relaxed),
+            passthrough: self.overlay.path_resolver.passthrough_stats(),
+        }
+    }
+
+    fn handle(&self, request: StatsRequest, notifier: Option<&fuser::Notifier>) -> StatsResponse {
+        match request {
+            StatsRequest::Stats => StatsResponse::Stats {
+                stats: self.snapshot(),
+            },
+            StatsRequest::ReloadPassthrough { patterns, force } => {
+                match self.overlay.reload_passthrough(&patterns, force, notifier) {
+                    Ok(reload) => StatsResponse::PassthroughReloaded { reload },
+                    Err(e) => StatsResponse::Error {
+                        message: e.to_string(),
+                    },
+                }
+            }
+            StatsRequest::RefreshPaths {
+                paths,
+                whiteout_missing,
+            } => {
+                match self
+                    .overlay
+                    .refresh_paths(&paths, whiteout_missing, notifier)
+                {
+                    Ok(inodes) => StatsResponse::PathsRefreshed { inodes },
+                    Err(e) => StatsResponse::Error {
+                        message: e.to_string(),
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Path of the stats socket for the mount at `mount_path`: a hidden file
+/// next to it, so it's never visible inside the mount.
+pub fn stats_socket_path(mount_path: &Path) -> PathBuf {
+    let name = mount_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    mount_path
+        .parent()
+        .unwrap_or(mount_path)
+        .join(format!(".{}.stats.sock", name))
+}
+
+/// Serves a mount's stats socket until dropped.
+pub(crate) struct StatsServer {
+    socket_path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StatsServer {
+    /// Start serving requests for the mount at `mount_path`. `notifier`
+    /// invalidates kernel caches after changes, if the session is running.
+    pub fn start(
+        mount_path: &Path,
+        source: StatsSource,
+        notifier: Option<fuser::Notifier>,
+    ) -> std::io::Result<Self> {
+        let socket_path = stats_socket_path(mount_path);
+        // Only the process serving this mount binds here, so an existing
+        // socket was left behind by one that crashed
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path)?;
+        std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600))?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            std::thread::Builder::new()
+                .name("treebeard-stats".to_string())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        if stop.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        match stream {
+                            Ok(stream) => serve(stream, &source, notifier.as_ref()),
+                            Err(e) => tracing::debug!("Stats socket accept failed: {}", e),
+                        }
+                    }
+                })?
+        };
+
+        Ok(StatsServer {
+            socket_path,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for StatsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the thread from accept() so it sees the stop flag
+        let _ = UnixStream::connect(&self.socket_path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if let Err(e) = std::fs::remove_file(&self.socket_path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to remove stats socket: {}", e);
+            }
+        }
+    }
+}
+
+fn serve(stream: UnixStream, source: &StatsSource, notifier: Option<&fuser::Notifier>) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let mut line = String::new();
+    if let Err(e) = BufReader::new(&stream).read_line(&mut line) {
+        tracing::debug!("Failed to read stats socket request: {}", e);
+        return;
+    }
+    if line.is_empty() {
+        return;
+    }
+
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => source.handle(request, notifier),
+        Err(e) => StatsResponse::Error {
+            message: format!("Invalid stats socket request: {}", e),
+        },
+    };
+    let mut reply = match serde_json::to_vec(&response) {
+        Ok(reply) => reply,
+        Err(e) => {
+            tracing::warn!("Failed to serialize stats socket response: {}", e);
+            return;
+        }
+    };
+    reply.push(b'\n');
+    if let Err(e) = (&stream).write_all(&reply) {
+        tracing::debug!("Failed to send stats socket response: {}", e);
+    }
+}
+
+/// Send `request` to the process serving the mount at `mount_path`.
+fn send_request(mount_path: &Path, request: &StatsRequest) -> Result<StatsResponse> {
+    let socket_path = stats_socket_path(mount_path);
+    let mut stream = UnixStream::connect(&socket_path).map_err(|e| {
+        TreebeardError::Overlay(format!(
+            "Can't reach the overlay at {}: {}. This needs a running session using the FUSE backend.",
+            mount_path.display(),
+            e
+        ))
+    })?;
+    stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(TreebeardError::Io)?;
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line).map_err(|e| {
+        TreebeardError::Overlay(format!("Failed to send request to the overlay: {}", e))
+    })?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(|e| TreebeardError::Overlay(format!("Failed to read overlay reply: {}", e)))?;
+    if reply.is_empty() {
+        return Err(TreebeardError::Overlay(
+            "The overlay closed the connection without replying".to_string(),
+        ));
+    }
+
+    match serde_json::from_str::<StatsResponse>(&reply)? {
+        StatsResponse::Error { message } => Err(TreebeardError::Overlay(message)),
+        response => Ok(response),
+    }
+}
+
+/// Ask the process serving the mount at `mount_path` for its stats.
+pub fn read_stats(mount_path: &Path) -> Result<OverlayStats> {
+    match send_request(mount_path, &StatsRequest::Stats)? {
+        StatsResponse::Stats { stats } => Ok(stats),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// Replace the passthrough patterns of the mount at `mount_path`.
+pub fn reload_passthrough(
+    mount_path: &Path,
+    patterns: Vec<String>,
+    force: bool,
+) -> Result<PassthroughReload> {
+    match send_request(
+        mount_path,
+        &StatsRequest::ReloadPassthrough { patterns, force },
+    )? {
+        StatsResponse::PassthroughReloaded { reload } => Ok(reload),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// Have the mount at `mount_path` show the worktree's current state of
+/// `paths`, whiting out those missing from it if `whiteout_missing` is set.
+/// Returns how many cached inodes were refreshed.
+pub fn refresh_paths(
+    mount_path: &Path,
+    paths: Vec<PathBuf>,
+    whiteout_missing: bool,
+) -> Result<usize> {
+    let request = StatsRequest::RefreshPaths {
+        paths,
+        whiteout_missing,
+    };
+    match send_request(mount_path, &request)? {
+        StatsResponse::PathsRefreshed { inodes } => Ok(inodes),
+        other => Err(unexpected(other)),
+    }
+}
+
+fn unexpected(response: StatsResponse) -> TreebeardError {
+    TreebeardError::Overlay(format!("Unexpected reply from the overlay: {:?}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_socket_path_is_hidden_sibling() {
+        assert_eq!(
+            stats_socket_path(Path::new("/mounts/repo/feature")),
+            PathBuf::from("/mounts/repo/.feature.stats.sock")
+        );
+    }
+
+    /// An overlay whose `a.txt` was copied up from the main repo.
+    fn copied_up_source(temp_dir: &Path) -> StatsSource {
+        let upper_layer = temp_dir.join("upper");
+        let lower_layer = temp_dir.join("lower");
+        std::fs::create_dir_all(&upper_layer).unwrap();
+        std::fs::create_dir_all(&lower_layer).unwrap();
+        std::fs::write(lower_layer.join("a.txt"), "from lower").unwrap();
+
+        let overlay = OverlayFs::new(upper_layer, lower_layer, None, 1, vec![]).unwrap();
+        let source = StatsSource {
+            overlay: Arc::new(overlay),
+            requests: Arc::new(AtomicU64::new(7)),
+            mounted_at: Instant::now(),
+        };
+        let (inode, _) = source
+            .overlay
+            .lookup_overlay(
+                fuser::FUSE_ROOT_ID,
+                "a.txt".into(),
+                PathBuf::from("./a.txt"),
+            )
+            .unwrap()
+            .unwrap();
+        let ino = inode.inode;
+        source.overlay.inode_manager.insert(inode);
+        source.overlay.copy_up(ino).unwrap();
+        source
+    }
+
+    #[test]
+    fn test_stats_server_serves_snapshots() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = copied_up_source(temp_dir.path());
+
+        let mount_path = temp_dir.path().join("feature");
+        let server = StatsServer::start(&mount_path, source, None).unwrap();
+
+        let stats = read_stats(&mount_path).unwrap();
+        assert_eq!(stats.requests, 7);
+        assert_eq!(stats.copy_ups, 1);
+        assert_eq!(stats.bytes_copied, "from lower".len() as u64);
+        // The root and a.txt
+        assert_eq!(stats.inode_cache.pinned + stats.inode_cache.cached, 2);
+
+        drop(server);
+        assert!(!stats_socket_path(&mount_path).exists());
+        assert!(read_stats(&mount_path).is_err());
+    }
+
+    #[test]
+    fn test_stats_server_reloads_passthrough() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = copied_up_source(temp_dir.path());
+
+        let mount_path = temp_dir.path().join("feature");
+        let _server = StatsServer::start(&mount_path, source, None).unwrap();
+
+        // a.txt now has a worktree copy that passthrough would hide
+        let refused = reload_passthrough(&mount_path, vec!["*.txt".to_string()], false).unwrap();
+        assert!(!refused.applied);
+        assert_eq!(refused.shadowed, vec![PathBuf::from("a.txt")]);
+
+        let invalid = reload_passthrough(&mount_path, vec!["[".to_string()], false);
+        assert!(invalid
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid passthrough"));
+    }
+}
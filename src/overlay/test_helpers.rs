@@ -0,0 +1,51 @@
+//! Fixtures shared by the overlay's unit tests.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use fuser::FUSE_ROOT_ID;
+
+use crate::overlay::file_handle::FileHandle;
+use crate::overlay::types::{InodeData, LayerType};
+use crate::overlay::OverlayFs;
+
+/// Creates empty upper and lower layers in a temporary directory, which is
+/// removed when the returned guard is dropped.
+pub(crate) fn setup() -> (tempfile::TempDir, PathBuf, PathBuf) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let upper_layer = temp_dir.path().join("upper");
+    let lower_layer = temp_dir.path().join("lower");
+    fs::create_dir_all(&upper_layer).unwrap();
+    fs::create_dir_all(&lower_layer).unwrap();
+    (temp_dir, upper_layer, lower_layer)
+}
+
+/// Looks up `name` in the root directory and caches its inode, as the kernel
+/// would before operating on it.
+pub(crate) fn lookup(fs: &OverlayFs, name: &str) -> InodeData {
+    let (inode, _) = fs
+        .lookup_overlay(
+            FUSE_ROOT_ID,
+            OsString::from(name),
+            PathBuf::from(format!("./{}", name)),
+        )
+        .unwrap()
+        .unwrap();
+    fs.inode_manager.insert(inode.clone());
+    inode
+}
+
+/// Opens `path` as a handle of `ino` in `layer`, writable in the upper layer.
+pub(crate) fn open_handle(fs: &OverlayFs, ino: u64, path: &Path, layer: LayerType) -> u64 {
+    let file = File::options()
+        .read(true)
+        .write(layer == LayerType::Upper)
+        .open(path)
+        .unwrap();
+    let fh = fs.alloc_fh();
+    fs.file_handles
+        .insert(fh, Arc::new(FileHandle::new(ino, file, layer)));
+    fh
+}
@@ -1,18 +1,20 @@
+use crate::config::InodeCacheConfig;
 use fuser::{FileAttr, FUSE_ROOT_ID};
 use fxhash::hash64;
 use lru::LruCache;
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::num::NonZeroUsize;
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-const DEFAULT_INODE_CACHE_CAPACITY: usize = 10000;
-
 /// Represents a mutation type for tracking
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MutationType {
     CopiedUp,
     Created,
@@ -40,20 +42,80 @@ pub(crate) struct InodeData {
     pub attrs: FileAttr,
     pub open_file_handles: u64,
     pub hardlinks: u32,
+    /// References the kernel holds from entry replies, released by `forget`
+    pub lookups: u64,
+}
+
+/// Hit, miss and eviction counts for the inode cache, and its current size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InodeCacheStats {
+    /// Kernel lookups answered from the cache
+    pub hits: u64,
+    /// Kernel lookups that had to go to the layers
+    pub misses: u64,
+    /// Inodes dropped to stay within the cache's capacity
+    pub evictions: u64,
+    /// Inodes that may be evicted
+    pub cached: usize,
+    /// Inodes that are never evicted: the root, and those the kernel still
+    /// references when pinning is enabled
+    pub pinned: usize,
 }
 
+/// The overlay's inodes, bounded in memory.
+///
+/// Inodes the kernel may still send requests for are kept in `pinned`, and
+/// everything else in an LRU of configurable capacity. The kernel holds a
+/// lookup reference on every inode it was given in an entry reply until it
+/// sends `forget`, so evicting such an inode would make its requests fail.
 #[derive(Debug)]
 pub(crate) struct InodeTable {
-    inodes: LruCache<u64, InodeData>,
+    pinned: HashMap<u64, InodeData>,
+    cached: LruCache<u64, InodeData>,
     children: HashMap<u64, HashMap<u64, u64>>,
+    /// Whether inodes with lookup references are pinned. When off, only the
+    /// root is, and the LRU bounds everything else.
+    pin_referenced: bool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
 }
 
 impl InodeTable {
     pub fn new() -> Self {
+        let config = InodeCacheConfig::default();
         InodeTable {
-            inodes: LruCache::new(NonZeroUsize::new(DEFAULT_INODE_CACHE_CAPACITY).unwrap()),
+            pinned: HashMap::new(),
+            cached: LruCache::new(cache_capacity(&config)),
             children: HashMap::new(),
+            pin_referenced: config.get_pin_referenced(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Applies `config`, moving inodes between the pinned set and the LRU
+    /// as needed.
+    pub fn configure(&mut self, config: &InodeCacheConfig) {
+        self.pin_referenced = config.get_pin_referenced();
+        let inos: Vec<u64> = self
+            .pinned
+            .keys()
+            .chain(self.cached.iter().map(|(ino, _)| ino))
+            .copied()
+            .collect();
+        for ino in inos {
+            self.repin(ino);
+        }
+
+        let capacity = cache_capacity(config);
+        while self.cached.len() > capacity.get() {
+            if let Some((_, inode)) = self.cached.pop_lru() {
+                self.evicted(inode);
+            }
         }
+        self.cached.resize(capacity);
     }
 
     fn children_map(&self, parent: u64) -> Option<&HashMap<u64, u64>> {
@@ -64,21 +126,109 @@ impl InodeTable {
         self.children.entry(parent).or_default()
     }
 
-    pub fn insert(&mut self, inode: InodeData) {
+    pub fn insert(&mut self, mut inode: InodeData) {
         if inode.inode != FUSE_ROOT_ID {
             let name_hash = hash64(inode.name.as_bytes());
             self.children_map_mut(inode.parent)
                 .insert(name_hash, inode.inode);
         }
-        self.inodes.put(inode.inode, inode);
+        // The kernel's references are to the inode number, so they carry over
+        if let Some(existing) = self.take(inode.inode) {
+            inode.lookups = existing.lookups;
+        }
+        self.place(inode);
+    }
+
+    fn should_pin(&self, inode: &InodeData) -> bool {
+        inode.inode == FUSE_ROOT_ID || (self.pin_referenced && inode.lookups > 0)
+    }
+
+    /// Puts an inode that isn't in the table into the pinned set or the LRU,
+    /// evicting the least recently used inode if the LRU is full.
+    fn place(&mut self, inode: InodeData) {
+        if self.should_pin(&inode) {
+            self.pinned.insert(inode.inode, inode);
+            return;
+        }
+        let ino = inode.inode;
+        if let Some((evicted_ino, evicted)) = self.cached.push(ino, inode) {
+            if evicted_ino != ino {
+                self.evicted(evicted);
+            }
+        }
+    }
+
+    /// Moves an inode to the pinned set or the LRU after its lookup count
+    /// changed.
+    fn repin(&mut self, ino: u64) {
+        let Some(pin) = self.peek(ino).map(|inode| self.should_pin(inode)) else {
+            return;
+        };
+        if pin != self.pinned.contains_key(&ino) {
+            if let Some(inode) = self.take(ino) {
+                self.place(inode);
+            }
+        }
+    }
+
+    /// Drops the directory entries of an inode evicted from the LRU. An
+    /// evicted directory's children map goes too: the kernel no longer
+    /// references it, so its next lookup allocates a new inode.
+    fn evicted(&mut self, inode: InodeData) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        let name_hash = hash64(inode.name.as_bytes());
+        if let Some(map) = self.children.get_mut(&inode.parent) {
+            if map.get(&name_hash) == Some(&inode.inode) {
+                map.remove(&name_hash);
+            }
+        }
+        self.children.remove(&inode.inode);
+    }
+
+    fn take(&mut self, ino: u64) -> Option<InodeData> {
+        self.pinned.remove(&ino).or_else(|| self.cached.pop(&ino))
     }
 
     pub fn peek(&self, ino: u64) -> Option<&InodeData> {
-        self.inodes.peek(&ino)
+        self.pinned.get(&ino).or_else(|| self.cached.peek(&ino))
     }
 
+    /// Like `peek`, but marks the inode as recently used.
     pub fn get_mut(&mut self, ino: u64) -> Option<&mut InodeData> {
-        self.inodes.get_mut(&ino)
+        if self.pinned.contains_key(&ino) {
+            return self.pinned.get_mut(&ino);
+        }
+        self.cached.get_mut(&ino)
+    }
+
+    fn peek_mut(&mut self, ino: u64) -> Option<&mut InodeData> {
+        if self.pinned.contains_key(&ino) {
+            return self.pinned.get_mut(&ino);
+        }
+        self.cached.peek_mut(&ino)
+    }
+
+    /// Takes a kernel lookup reference on an inode, pinning it if enabled.
+    /// Returns false if the inode isn't in the table.
+    pub fn add_lookup(&mut self, ino: u64) -> bool {
+        let Some(inode) = self.get_mut(ino) else {
+            return false;
+        };
+        inode.lookups += 1;
+        self.repin(ino);
+        true
+    }
+
+    /// Releases `nlookup` kernel references to an inode, returning how many
+    /// it still holds. Once there are none, the inode can be evicted.
+    pub fn forget(&mut self, ino: u64, nlookup: u64) -> u64 {
+        let Some(inode) = self.peek_mut(ino) else {
+            return 0;
+        };
+        inode.lookups = inode.lookups.saturating_sub(nlookup);
+        let remaining = inode.lookups;
+        self.repin(ino);
+        remaining
     }
 
     pub fn update_attrs(&mut self, ino: u64, attrs: FileAttr) {
@@ -109,6 +259,19 @@ impl InodeTable {
             .and_then(|ino| self.peek(ino).map(|_| ino))
     }
 
+    /// Like `lookup_child`, counting the result as a cache hit or miss. For
+    /// the kernel's lookup requests.
+    pub fn lookup_cached(&self, parent: u64, name: &OsStr) -> Option<u64> {
+        let ino = self.lookup_child(parent, name);
+        let counter = if ino.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        ino
+    }
+
     pub fn add_child(&mut self, parent: u64, name: OsString, ino: u64) {
         let name_hash = hash64(name.as_bytes());
         self.children_map_mut(parent).insert(name_hash, ino);
@@ -122,7 +285,7 @@ impl InodeTable {
     }
 
     pub fn remove(&mut self, ino: u64) {
-        if let Some(inode) = self.inodes.pop(&ino) {
+        if let Some(inode) = self.take(ino) {
             let name_hash = hash64(inode.name.as_bytes());
             if let Some(map) = self.children.get_mut(&inode.parent) {
                 map.remove(&name_hash);
@@ -130,17 +293,63 @@ impl InodeTable {
         }
     }
 
+    /// Rewrites the paths of the cached descendants of a directory that was
+    /// moved, so they resolve under its new path.
+    pub fn rebase_descendants(&mut self, ino: u64) {
+        let mut dirs = vec![ino];
+        while let Some(dir) = dirs.pop() {
+            let Some(dir_path) = self.peek(dir).map(|i| Arc::clone(&i.path)) else {
+                continue;
+            };
+            let children: Vec<u64> = self
+                .children_map(dir)
+                .map(|map| map.values().copied().collect())
+                .unwrap_or_default();
+
+            for child in children {
+                if let Some(inode) = self.peek_mut(child) {
+                    inode.path = Arc::new(dir_path.join(&inode.name));
+                    if inode.attrs.kind == fuser::FileType::Directory {
+                        dirs.push(child);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every inode in the table, pinned or cached, without touching the LRU
+    /// order.
+    pub fn iter(&self) -> impl Iterator<Item = &InodeData> {
+        self.pinned
+            .values()
+            .chain(self.cached.iter().map(|(_, inode)| inode))
+    }
+
+    pub fn stats(&self) -> InodeCacheStats {
+        InodeCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            cached: self.cached.len(),
+            pinned: self.pinned.len(),
+        }
+    }
+
     #[cfg(test)]
     pub fn len(&self) -> usize {
-        self.inodes.len()
+        self.pinned.len() + self.cached.len()
     }
 
     #[cfg(test)]
     pub fn cap(&self) -> NonZeroUsize {
-        self.inodes.cap()
+        self.cached.cap()
     }
 }
 
+fn cache_capacity(config: &InodeCacheConfig) -> NonZeroUsize {
+    NonZeroUsize::new(config.get_capacity()).unwrap_or(NonZeroUsize::MIN)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,8 +362,9 @@ mod tests {
         let capacity = table.cap().get();
 
         assert_eq!(
-            capacity, DEFAULT_INODE_CACHE_CAPACITY,
-            "InodeTable should have capacity of DEFAULT_INODE_CACHE_CAPACITY"
+            capacity,
+            InodeCacheConfig::default().get_capacity(),
+            "InodeTable should have the default inode cache capacity"
         );
     }
 
@@ -187,6 +397,7 @@ mod tests {
             },
             open_file_handles: 0,
             hardlinks: 1,
+            lookups: 0,
         };
 
         table.insert(inode.clone());
@@ -203,7 +414,8 @@ mod tests {
 
         let capacity = table.cap().get();
 
-        for i in 1..=capacity as u64 + 1 {
+        // Start after the root, which is never evicted
+        for i in 2..=capacity as u64 + 2 {
             let inode = InodeData {
                 inode: i,
                 parent: 1,
@@ -229,6 +441,7 @@ mod tests {
                 },
                 open_file_handles: 0,
                 hardlinks: 1,
+                lookups: 0,
             };
             table.insert(inode);
         }
@@ -237,17 +450,199 @@ mod tests {
         assert_eq!(table.len(), capacity);
 
         // The first entry should have been evicted (least recently used)
-        let first_entry = table.peek(1);
+        let first_entry = table.peek(2);
         assert!(
             first_entry.is_none(),
             "First entry should be evicted after exceeding capacity"
         );
 
         // The most recent entry should still be present
-        let last_entry = table.peek(capacity as u64 + 1);
+        let last_entry = table.peek(capacity as u64 + 2);
         assert!(
             last_entry.is_some(),
             "Most recent entry should still be in cache"
         );
     }
+
+    fn test_inode(ino: u64, parent: u64, name: &str, kind: FileType) -> InodeData {
+        InodeData {
+            inode: ino,
+            parent,
+            name: OsString::from(name),
+            layer: LayerType::Lower,
+            path: Arc::new(PathBuf::from(name)),
+            attrs: FileAttr {
+                ino,
+                size: 0,
+                blocks: 0,
+                atime: SystemTime::UNIX_EPOCH,
+                mtime: SystemTime::UNIX_EPOCH,
+                ctime: SystemTime::UNIX_EPOCH,
+                crtime: SystemTime::UNIX_EPOCH,
+                kind,
+                perm: 0o644,
+                nlink: 1,
+                uid: 0,
+                gid: 0,
+                rdev: 0,
+                blksize: 512,
+                flags: 0,
+            },
+            open_file_handles: 0,
+            hardlinks: 1,
+            lookups: 0,
+        }
+    }
+
+    fn table_with_capacity(capacity: usize, pin_referenced: bool) -> InodeTable {
+        let mut table = InodeTable::new();
+        table.configure(&InodeCacheConfig {
+            capacity: Some(capacity),
+            pin_referenced: Some(pin_referenced),
+        });
+        table
+    }
+
+    #[test]
+    fn test_inode_table_never_evicts_referenced_inodes() {
+        let mut table = table_with_capacity(2, true);
+        table.insert(test_inode(FUSE_ROOT_ID, 0, "", FileType::Directory));
+
+        table.insert(test_inode(2, FUSE_ROOT_ID, "held", FileType::RegularFile));
+        assert!(table.add_lookup(2));
+        for ino in 3..10 {
+            table.insert(test_inode(ino, FUSE_ROOT_ID, "f", FileType::RegularFile));
+        }
+
+        assert!(table.peek(FUSE_ROOT_ID).is_some());
+        assert!(table.peek(2).is_some());
+        assert_eq!(
+            table.lookup_child(FUSE_ROOT_ID, OsStr::new("held")),
+            Some(2)
+        );
+        assert_eq!(table.stats().pinned, 2);
+        assert_eq!(table.stats().cached, 2);
+        assert_eq!(table.stats().evictions, 5);
+
+        // Once the kernel forgets it, the inode is evictable again
+        assert_eq!(table.forget(2, 1), 0);
+        for ino in 10..12 {
+            table.insert(test_inode(ino, FUSE_ROOT_ID, "g", FileType::RegularFile));
+        }
+        assert!(table.peek(2).is_none());
+        assert_eq!(table.lookup_child(FUSE_ROOT_ID, OsStr::new("held")), None);
+    }
+
+    #[test]
+    fn test_inode_table_without_pinning_evicts_referenced_inodes() {
+        let mut table = table_with_capacity(2, false);
+        table.insert(test_inode(FUSE_ROOT_ID, 0, "", FileType::Directory));
+
+        table.insert(test_inode(2, FUSE_ROOT_ID, "held", FileType::RegularFile));
+        table.add_lookup(2);
+        for ino in 3..5 {
+            table.insert(test_inode(ino, FUSE_ROOT_ID, "f", FileType::RegularFile));
+        }
+
+        assert!(table.peek(2).is_none());
+        // The root is pinned regardless
+        assert!(table.peek(FUSE_ROOT_ID).is_some());
+        assert_eq!(table.stats().pinned, 1);
+    }
+
+    #[test]
+    fn test_inode_table_counts_hits_and_misses() {
+        let mut table = InodeTable::new();
+        table.insert(test_inode(2, FUSE_ROOT_ID, "a", FileType::RegularFile));
+
+        assert_eq!(table.lookup_cached(FUSE_ROOT_ID, OsStr::new("a")), Some(2));
+        assert_eq!(table.lookup_cached(FUSE_ROOT_ID, OsStr::new("b")), None);
+        // Internal lookups aren't counted
+        table.lookup_child(FUSE_ROOT_ID, OsStr::new("a"));
+
+        let stats = table.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_inode_table_shrinking_evicts_least_recently_used() {
+        let mut table = InodeTable::new();
+        for ino in 2..12 {
+            table.insert(test_inode(ino, FUSE_ROOT_ID, "f", FileType::RegularFile));
+        }
+        table.configure(&InodeCacheConfig {
+            capacity: Some(4),
+            pin_referenced: None,
+        });
+
+        assert_eq!(table.len(), 4);
+        assert!(table.peek(7).is_none());
+        assert!(table.peek(8).is_some());
+        assert_eq!(table.stats().evictions, 6);
+    }
+
+    /// Walks a 200k-file tree the way `find` or a build tool would: the
+    /// kernel looks up each directory and file, and forgets files once it's
+    /// done with them. One file per directory stays open, so the kernel keeps
+    /// its reference, and its directory's, throughout.
+    #[test]
+    fn test_inode_table_walk_of_200k_files_stays_bounded() {
+        const DIRS: u64 = 200;
+        const FILES_PER_DIR: u64 = 1000;
+        const CAPACITY: usize = 5000;
+
+        let mut table = table_with_capacity(CAPACITY, true);
+        table.insert(test_inode(FUSE_ROOT_ID, 0, "", FileType::Directory));
+
+        let mut next_ino = 2;
+        let mut held = Vec::new();
+        for d in 0..DIRS {
+            let dir_ino = next_ino;
+            next_ino += 1;
+            let dir_name = format!("dir{}", d);
+            assert_eq!(
+                table.lookup_cached(FUSE_ROOT_ID, OsStr::new(&dir_name)),
+                None
+            );
+            table.insert(test_inode(
+                dir_ino,
+                FUSE_ROOT_ID,
+                &dir_name,
+                FileType::Directory,
+            ));
+            table.add_lookup(dir_ino);
+
+            for f in 0..FILES_PER_DIR {
+                let ino = next_ino;
+                next_ino += 1;
+                let name = format!("file{}", f);
+                table.insert(test_inode(ino, dir_ino, &name, FileType::RegularFile));
+                assert!(table.add_lookup(ino), "inode {} evicted before use", ino);
+                if f == 0 {
+                    held.push((dir_ino, name, ino));
+                } else {
+                    table.forget(ino, 1);
+                }
+            }
+        }
+
+        for (dir_ino, name, ino) in &held {
+            assert!(table.peek(*dir_ino).is_some(), "held directory evicted");
+            assert!(table.peek(*ino).is_some(), "held inode {} evicted", ino);
+            assert_eq!(table.lookup_child(*dir_ino, OsStr::new(name)), Some(*ino));
+        }
+
+        let stats = table.stats();
+        let pinned = 1 + 2 * DIRS as usize;
+        assert_eq!(stats.pinned, pinned);
+        assert_eq!(stats.cached, CAPACITY);
+        assert_eq!(
+            stats.evictions,
+            DIRS * (FILES_PER_DIR - 1) - CAPACITY as u64
+        );
+        assert_eq!(stats.misses, DIRS);
+        // Evicted files don't linger in their directories' children maps
+        let children: usize = table.children.values().map(|m| m.len()).sum();
+        assert_eq!(children, CAPACITY + pinned - 1);
+    }
 }
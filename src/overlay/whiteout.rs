@@ -8,6 +8,11 @@
 //! We use AUFS-style whiteouts: a whiteout for a file named `foo` is
 //! represented by an empty file named `.wh.foo` in the same directory
 //! in the upper layer.
+//!
+//! A directory can also be made opaque with an empty `.wh..wh..opq` file
+//! inside its upper-layer copy. Nothing in the lower layer's copy of an
+//! opaque directory shows through, so a directory that is deleted and created
+//! again starts out empty instead of merging in the old one's contents.
 
 use std::ffi::{OsStr, OsString};
 use std::fs::File;
@@ -19,6 +24,9 @@ use std::os::unix::ffi::OsStrExt;
 /// The prefix used for AUFS-style whiteout marker files.
 pub const WHITEOUT_PREFIX: &str = ".wh.";
 
+/// The AUFS-style marker file that makes the directory containing it opaque.
+pub const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
 /// Whiteout handling utilities for overlay filesystems.
 ///
 /// This type provides methods for creating, checking, and parsing whiteout
@@ -68,6 +76,86 @@ impl Whiteout {
         }
     }
 
+    /// Check if a path is a kernel overlayfs whiteout.
+    ///
+    /// Kernel overlayfs doesn't use `.wh.` markers: it replaces a deleted
+    /// entry in the upper layer with a character device numbered 0/0. These
+    /// turn up in worktrees used with the overlayfs backend.
+    ///
+    /// # Arguments
+    /// * `path` - The absolute path of the entry in the upper layer
+    ///
+    /// # Returns
+    /// `true` if the entry is a 0/0 character device, `false` otherwise
+    pub fn is_kernel_whiteout(path: &Path) -> bool {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        path.symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_char_device() && m.rdev() == 0)
+    }
+
+    /// Create a kernel overlayfs whiteout for the given filename in the
+    /// specified directory.
+    ///
+    /// Linux 5.8+ lets unprivileged users create the 0/0 character device
+    /// that overlayfs reads as a whiteout.
+    ///
+    /// # Arguments
+    /// * `parent_dir` - The upper-layer directory where the whiteout should be created
+    /// * `name` - The name of the file to white out
+    ///
+    /// # Returns
+    /// * `Ok(())` on success
+    /// * `Err(errno)` on failure (e.g., the entry already exists)
+    #[cfg(target_os = "linux")]
+    pub fn create_kernel(parent_dir: &Path, name: &OsStr) -> Result<(), i32> {
+        let path = std::ffi::CString::new(parent_dir.join(name).as_os_str().as_bytes())
+            .map_err(|_| libc::EINVAL)?;
+
+        // SAFETY: `path` is a valid NUL-terminated string
+        if unsafe { libc::mknod(path.as_ptr(), libc::S_IFCHR | 0o600, 0) } != 0 {
+            return Err(std::io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or(libc::EIO));
+        }
+
+        Ok(())
+    }
+
+    /// Make a directory in the upper layer opaque, hiding the contents of
+    /// the lower layer's directory at the same path.
+    ///
+    /// # Arguments
+    /// * `dir` - The upper-layer directory to make opaque
+    ///
+    /// # Returns
+    /// * `Ok(())` on success
+    /// * `Err(errno)` on failure (e.g., I/O error)
+    pub fn make_opaque(dir: &Path) -> Result<(), i32> {
+        File::create(dir.join(OPAQUE_MARKER)).map_err(|e| e.raw_os_error().unwrap_or(libc::EIO))?;
+
+        Ok(())
+    }
+
+    /// Check if an upper-layer directory is opaque.
+    ///
+    /// # Arguments
+    /// * `dir` - The absolute path of the directory in the upper layer
+    ///
+    /// # Returns
+    /// `true` if the directory contains the opaque marker, `false` otherwise
+    pub fn is_opaque(dir: &Path) -> bool {
+        dir.join(OPAQUE_MARKER).exists()
+    }
+
+    /// Check if a filename is the opaque directory marker.
+    ///
+    /// The marker also starts with `.wh.`, so directory scans must check for
+    /// it before treating a name as a whiteout.
+    pub fn is_opaque_marker(name: &OsStr) -> bool {
+        name == OPAQUE_MARKER
+    }
+
     /// Generate the whiteout marker filename for a given filename.
     ///
     /// For a file named `foo`, this returns `.wh.foo`.
@@ -194,6 +282,38 @@ mod tests {
         assert!(Whiteout::is_whiteout(&file_path));
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_create_kernel_whiteout() {
+        let temp_dir = tempdir().unwrap();
+        let parent = temp_dir.path();
+        let name = OsStr::new("test.txt");
+
+        Whiteout::create_kernel(parent, name).unwrap();
+        assert!(Whiteout::is_kernel_whiteout(&parent.join(name)));
+        assert!(!Whiteout::is_whiteout(&parent.join(name)));
+
+        // mknod doesn't replace an existing entry
+        assert_eq!(Whiteout::create_kernel(parent, name), Err(libc::EEXIST));
+    }
+
+    #[test]
+    fn test_make_opaque_and_is_opaque() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path().join("build");
+        fs::create_dir(&dir).unwrap();
+
+        assert!(!Whiteout::is_opaque(&dir));
+        Whiteout::make_opaque(&dir).unwrap();
+        assert!(Whiteout::is_opaque(&dir));
+        assert!(dir.join(".wh..wh..opq").exists());
+
+        // The marker is a whiteout-prefixed name, but not a whiteout of any entry
+        assert!(Whiteout::is_opaque_marker(OsStr::new(OPAQUE_MARKER)));
+        assert!(Whiteout::is_whiteout_marker(OsStr::new(OPAQUE_MARKER)));
+        assert!(!Whiteout::is_opaque_marker(OsStr::new(".wh.build")));
+    }
+
     #[test]
     fn test_is_whiteout_with_existing_marker() {
         let temp_dir = tempdir().unwrap();
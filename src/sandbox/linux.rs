@@ -0,0 +1,687 @@
+//! Linux sandbox backend.
+//!
+//! Enforces a `SandboxConfig` on Linux using kernel primitives instead of
+//! `sandbox-exec`:
+//!
+//! - **Landlock** restricts filesystem reads and writes. Landlock only
+//!   supports allow rules, so `deny_read` is implemented by granting read
+//!   access to every sibling along the path to each denied entry.
+//! - **seccomp** blocks creation of `AF_INET`/`AF_INET6` sockets for
+//!   `NetworkMode::Deny`, leaving Unix domain sockets usable.
+//! - **Network namespaces** (inside an unprivileged user namespace) give
+//!   `NetworkMode::Localhost` a private loopback-only network.
+//!
+//! All rules are prepared in the parent by [`LinuxSandbox::prepare`]. The child
+//! only calls [`LinuxSandbox::apply`] between `fork` and `exec`, which performs
+//! raw syscalls and never allocates.
+
+use crate::config::{expand_tilde, NetworkMode, SandboxConfig};
+use crate::error::{Result, TreebeardError};
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_uint = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+/// Available from Landlock ABI v2.
+const ACCESS_FS_REFER: u64 = 1 << 13;
+/// Available from Landlock ABI v3.
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+const ACCESS_FS_ABI_V1: u64 = ACCESS_FS_EXECUTE
+    | ACCESS_FS_WRITE_FILE
+    | ACCESS_FS_READ_FILE
+    | ACCESS_FS_READ_DIR
+    | ACCESS_FS_REMOVE_DIR
+    | ACCESS_FS_REMOVE_FILE
+    | ACCESS_FS_MAKE_CHAR
+    | ACCESS_FS_MAKE_DIR
+    | ACCESS_FS_MAKE_REG
+    | ACCESS_FS_MAKE_SOCK
+    | ACCESS_FS_MAKE_FIFO
+    | ACCESS_FS_MAKE_BLOCK
+    | ACCESS_FS_MAKE_SYM;
+
+/// Rights needed to read files, list directories and execute binaries.
+const ACCESS_READ: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+
+/// Rights that Landlock accepts on rules attached to non-directory files.
+const ACCESS_FILE: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+/// Directories that are always writable inside the sandbox, mirroring the
+/// temp and device allowances of the macOS profile.
+const ALWAYS_WRITABLE: &[&str] = &["/tmp", "/var/tmp", "/dev"];
+
+#[repr(C)]
+struct LandlockRulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct LandlockPathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// How network access is restricted for the sandboxed process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NetworkIsolation {
+    None,
+    /// Block `socket(AF_INET | AF_INET6, ...)` with a seccomp filter.
+    BlockInet,
+    /// Move into a fresh network namespace; bring up loopback if requested.
+    Namespace {
+        loopback: bool,
+    },
+}
+
+/// Pre-computed `/proc/self/*` writes that map the current user into a new
+/// user namespace. Prepared in the parent so the child does not allocate.
+#[derive(Debug)]
+pub(crate) struct UserNamespaceMaps {
+    setgroups_path: CString,
+    uid_map_path: CString,
+    gid_map_path: CString,
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+}
+
+impl UserNamespaceMaps {
+    pub(crate) fn for_current_user() -> Self {
+        let uid = nix::unistd::getuid().as_raw();
+        let gid = nix::unistd::getgid().as_raw();
+        Self {
+            setgroups_path: CString::new("/proc/self/setgroups").unwrap(),
+            uid_map_path: CString::new("/proc/self/uid_map").unwrap(),
+            gid_map_path: CString::new("/proc/self/gid_map").unwrap(),
+            uid_map: format!("{} {} 1\n", uid, uid).into_bytes(),
+            gid_map: format!("{} {} 1\n", gid, gid).into_bytes(),
+        }
+    }
+
+    /// Moves the calling process into a new user namespace, plus any other
+    /// namespaces in `flags`, and maps the current user into it.
+    ///
+    /// Async-signal-safe, so it can run between `fork` and `exec`.
+    pub(crate) fn unshare(&self, flags: libc::c_int) -> io::Result<()> {
+        // SAFETY: unshare only affects the calling (single-threaded, forked) process.
+        if unsafe { libc::unshare(libc::CLONE_NEWUSER | flags) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        write_proc_file(&self.setgroups_path, b"deny")?;
+        write_proc_file(&self.uid_map_path, &self.uid_map)?;
+        write_proc_file(&self.gid_map_path, &self.gid_map)?;
+        Ok(())
+    }
+}
+
+/// A prepared Linux sandbox, ready to be applied in a forked child.
+#[derive(Debug)]
+pub struct LinuxSandbox {
+    ruleset: Option<OwnedFd>,
+    network: NetworkIsolation,
+    userns: Option<UserNamespaceMaps>,
+}
+
+impl LinuxSandbox {
+    /// Builds the Landlock ruleset and network policy for `config`.
+    ///
+    /// The mount path is always readable and writable. If the kernel does not
+    /// support Landlock, filesystem rules are skipped with a warning and only
+    /// the network policy is enforced.
+    ///
+    /// # Arguments
+    /// * `config` - The sandbox configuration
+    /// * `mount_path` - The FUSE mount path (always allowed for writes)
+    pub fn prepare(config: &SandboxConfig, mount_path: &Path) -> Result<Self> {
+        let ruleset = match landlock_abi_version() {
+            Some(abi) => Some(build_ruleset(config, mount_path, abi)?),
+            None => {
+                tracing::warn!(
+                    "Landlock is not available on this kernel; sandbox filesystem rules will not be enforced"
+                );
+                None
+            }
+        };
+
+        let network = match config.network.mode {
+            NetworkMode::Allow => NetworkIsolation::None,
+            NetworkMode::Localhost => NetworkIsolation::Namespace { loopback: true },
+            NetworkMode::Deny if seccomp_supported() => NetworkIsolation::BlockInet,
+            NetworkMode::Deny => NetworkIsolation::Namespace { loopback: false },
+        };
+
+        if network != NetworkIsolation::None && !config.network.allow_hosts.is_empty() {
+            tracing::warn!(
+                "sandbox.network.allow_hosts is not supported on Linux and will be ignored"
+            );
+        }
+
+        let userns = match network {
+            NetworkIsolation::Namespace { .. } => Some(UserNamespaceMaps::for_current_user()),
+            _ => None,
+        };
+
+        Ok(Self {
+            ruleset,
+            network,
+            userns,
+        })
+    }
+
+    /// Applies the sandbox to the current process.
+    ///
+    /// Intended to be called from `pre_exec`: it only issues syscalls on data
+    /// prepared by [`LinuxSandbox::prepare`] and is async-signal-safe.
+    pub fn apply(&self) -> io::Result<()> {
+        if let Some(maps) = &self.userns {
+            maps.unshare(libc::CLONE_NEWNET)?;
+            if let NetworkIsolation::Namespace { loopback: true } = self.network {
+                bring_up_loopback()?;
+            }
+        }
+
+        if self.ruleset.is_none() && self.network != NetworkIsolation::BlockInet {
+            return Ok(());
+        }
+
+        // SAFETY: prctl with PR_SET_NO_NEW_PRIVS takes only integer arguments.
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if self.network == NetworkIsolation::BlockInet {
+            install_inet_seccomp_filter()?;
+        }
+
+        if let Some(ruleset) = &self.ruleset {
+            // SAFETY: landlock_restrict_self takes a ruleset fd and flags.
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_restrict_self,
+                    ruleset.as_raw_fd(),
+                    0 as libc::c_uint,
+                )
+            };
+            if ret != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the Landlock ABI version supported by the running kernel, or
+/// `None` if Landlock is unavailable or disabled.
+pub fn landlock_abi_version() -> Option<u32> {
+    // SAFETY: Querying the ABI version passes a null attribute pointer and
+    // size 0, as documented for LANDLOCK_CREATE_RULESET_VERSION.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<LandlockRulesetAttr>(),
+            0usize,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if ret < 1 {
+        None
+    } else {
+        Some(ret as u32)
+    }
+}
+
+fn handled_access_fs(abi: u32) -> u64 {
+    let mut access = ACCESS_FS_ABI_V1;
+    if abi >= 2 {
+        access |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        access |= ACCESS_FS_TRUNCATE;
+    }
+    access
+}
+
+fn build_ruleset(config: &SandboxConfig, mount_path: &Path, abi: u32) -> Result<OwnedFd> {
+    let handled = handled_access_fs(abi);
+    let write_access = handled & !ACCESS_READ;
+
+    let attr = LandlockRulesetAttr {
+        handled_access_fs: handled,
+    };
+    // SAFETY: attr is a valid landlock_ruleset_attr for the given size.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const LandlockRulesetAttr,
+            std::mem::size_of::<LandlockRulesetAttr>(),
+            0 as libc::c_uint,
+        )
+    };
+    if fd < 0 {
+        return Err(TreebeardError::Config(format!(
+            "Failed to create Landlock ruleset: {}",
+            io::Error::last_os_error()
+        )));
+    }
+    // SAFETY: The syscall returned a new file descriptor that we now own.
+    let ruleset = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+
+    let denied: Vec<PathBuf> = config
+        .deny_read
+        .iter()
+        .filter_map(|p| resolve_denied_path(&expand_tilde(p)))
+        .collect();
+
+    let rules = read_rules(Path::new("/"), &denied);
+    for (path, access) in &rules {
+        add_path_rule(&ruleset, path, *access & handled);
+    }
+
+    let canonical_mount_path = mount_path
+        .canonicalize()
+        .unwrap_or_else(|_| mount_path.to_path_buf());
+    add_path_rule(&ruleset, &canonical_mount_path, handled);
+
+    let mut writable: Vec<PathBuf> = ALWAYS_WRITABLE.iter().map(PathBuf::from).collect();
+    writable.push(std::env::temp_dir());
+    writable.extend(config.allow_write.iter().map(|p| expand_tilde(p)));
+    for path in &writable {
+        add_path_rule(&ruleset, path, write_access);
+    }
+
+    tracing::debug!(
+        "Prepared Landlock ruleset (ABI v{}) with {} read rules, mount path {:?}",
+        abi,
+        rules.len(),
+        canonical_mount_path
+    );
+
+    Ok(ruleset)
+}
+
+/// Canonicalizes a `deny_read` entry, including one that doesn't exist yet.
+fn resolve_denied_path(path: &Path) -> Option<PathBuf> {
+    if let Ok(canonical) = path.canonicalize() {
+        return Some(canonical);
+    }
+
+    let (ancestor, canonical) = path
+        .ancestors()
+        .skip(1)
+        .find_map(|ancestor| Some((ancestor, ancestor.canonicalize().ok()?)))?;
+    let missing = path.strip_prefix(ancestor).ok()?;
+    tracing::warn!(
+        "sandbox.deny_read entry {:?} doesn't exist yet; new entries in {:?} won't be readable in the sandbox",
+        path,
+        canonical
+    );
+    Some(canonical.join(missing))
+}
+
+/// Computes the read rules that allow everything under `root` except the
+/// `denied` paths.
+///
+/// Subtrees that contain no denied path get full read access. Directories
+/// on the way to a denied path only get `READ_DIR`, so their entries can be
+/// listed but the denied entry's contents cannot be read. Symlinks are
+/// skipped; access through them is governed by the rules on their targets.
+fn read_rules(root: &Path, denied: &[PathBuf]) -> Vec<(PathBuf, u64)> {
+    let mut rules = Vec::new();
+    collect_read_rules(root, denied, &mut rules);
+    rules
+}
+
+fn collect_read_rules(path: &Path, denied: &[PathBuf], rules: &mut Vec<(PathBuf, u64)>) {
+    if denied.iter().any(|d| d == path) {
+        return;
+    }
+    if !denied.iter().any(|d| d.starts_with(path)) {
+        rules.push((path.to_path_buf(), ACCESS_READ));
+        return;
+    }
+
+    rules.push((path.to_path_buf(), ACCESS_FS_READ_DIR));
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::debug!("Cannot scan {:?} for sandbox rules: {}", path, e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let is_symlink = entry.file_type().map(|t| t.is_symlink()).unwrap_or(true);
+        if !is_symlink {
+            collect_read_rules(&entry.path(), denied, rules);
+        }
+    }
+}
+
+/// Adds a path-beneath rule, skipping paths that cannot be opened.
+fn add_path_rule(ruleset: &OwnedFd, path: &Path, access: u64) {
+    let file = match OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::debug!("Skipping sandbox rule for {:?}: {}", path, e);
+            return;
+        }
+    };
+
+    let is_dir = file.metadata().map(|m| m.is_dir()).unwrap_or(false);
+    let access = if is_dir { access } else { access & ACCESS_FILE };
+    if access == 0 {
+        return;
+    }
+
+    let attr = LandlockPathBeneathAttr {
+        allowed_access: access,
+        parent_fd: file.as_raw_fd(),
+    };
+    // SAFETY: attr is a valid landlock_path_beneath_attr and the fd stays
+    // open for the duration of the call.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const LandlockPathBeneathAttr,
+            0 as libc::c_uint,
+        )
+    };
+    if ret != 0 {
+        tracing::debug!(
+            "Failed to add sandbox rule for {:?}: {}",
+            path,
+            io::Error::last_os_error()
+        );
+    }
+}
+
+fn write_proc_file(path: &CString, contents: &[u8]) -> io::Result<()> {
+    // SAFETY: path is a valid NUL-terminated string and contents a valid buffer.
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+        let err = io::Error::last_os_error();
+        libc::close(fd);
+        if written != contents.len() as isize {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+fn bring_up_loopback() -> io::Result<()> {
+    // SAFETY: ifreq is plain old data; we only set the name and flags fields
+    // before handing it to the SIOCSIFFLAGS ioctl.
+    unsafe {
+        let sock = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if sock < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut req: libc::ifreq = std::mem::zeroed();
+        for (dst, src) in req.ifr_name.iter_mut().zip(b"lo\0") {
+            *dst = *src as libc::c_char;
+        }
+        req.ifr_ifru.ifru_flags = libc::IFF_UP as libc::c_short;
+        let ret = libc::ioctl(sock, libc::SIOCSIFFLAGS, &req);
+        let err = io::Error::last_os_error();
+        libc::close(sock);
+        if ret != 0 {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xC000_003E;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+/// Syscalls numbered at or above this belong to the x32 ABI on x86_64 and
+/// would otherwise bypass the `socket` check.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+const fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+const fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// seccomp filter returning `EACCES` for IPv4/IPv6 socket creation.
+///
+/// Offsets index into `struct seccomp_data`: `nr` at 0, `arch` at 4 and the
+/// low word of `args[0]` at 16 (both supported targets are little-endian).
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+static INET_SECCOMP_FILTER: [libc::sock_filter; 12] = [
+    bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 4),
+    bpf_jump(
+        libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+        AUDIT_ARCH,
+        1,
+        0,
+    ),
+    bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+    bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0),
+    bpf_jump(
+        libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+        X32_SYSCALL_BIT,
+        0,
+        1,
+    ),
+    bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+    bpf_jump(
+        libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+        libc::SYS_socket as u32,
+        0,
+        4,
+    ),
+    bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 16),
+    bpf_jump(
+        libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+        libc::AF_INET as u32,
+        1,
+        0,
+    ),
+    bpf_jump(
+        libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+        libc::AF_INET6 as u32,
+        0,
+        1,
+    ),
+    bpf_stmt(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ERRNO | libc::EACCES as u32,
+    ),
+    bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+];
+
+fn seccomp_supported() -> bool {
+    cfg!(any(target_arch = "x86_64", target_arch = "aarch64"))
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn install_inet_seccomp_filter() -> io::Result<()> {
+    let prog = libc::sock_fprog {
+        len: INET_SECCOMP_FILTER.len() as u16,
+        filter: INET_SECCOMP_FILTER.as_ptr() as *mut libc::sock_filter,
+    };
+    // SAFETY: prog points to a static filter that outlives the call; the
+    // kernel copies it during PR_SET_SECCOMP.
+    let ret = unsafe {
+        libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn install_inet_seccomp_filter() -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SandboxNetworkConfig;
+
+    fn test_config(mode: NetworkMode) -> SandboxConfig {
+        SandboxConfig {
+            enabled: true,
+            deny_read: vec![],
+            allow_write: vec![],
+            network: SandboxNetworkConfig {
+                mode,
+                allow_hosts: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn test_read_rules_without_denied_paths_allows_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let rules = read_rules(temp.path(), &[]);
+
+        assert_eq!(rules, vec![(temp.path().to_path_buf(), ACCESS_READ)]);
+    }
+
+    #[test]
+    fn test_read_rules_excludes_denied_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("home/.ssh")).unwrap();
+        fs::create_dir_all(root.join("home/project")).unwrap();
+        fs::write(root.join("home/.bashrc"), "").unwrap();
+        fs::create_dir_all(root.join("etc")).unwrap();
+
+        let denied = vec![root.join("home/.ssh")];
+        let rules = read_rules(&root, &denied);
+
+        assert!(rules.contains(&(root.clone(), ACCESS_FS_READ_DIR)));
+        assert!(rules.contains(&(root.join("home"), ACCESS_FS_READ_DIR)));
+        assert!(rules.contains(&(root.join("home/project"), ACCESS_READ)));
+        assert!(rules.contains(&(root.join("home/.bashrc"), ACCESS_READ)));
+        assert!(rules.contains(&(root.join("etc"), ACCESS_READ)));
+        assert!(!rules
+            .iter()
+            .any(|(p, _)| p.starts_with(root.join("home/.ssh"))));
+    }
+
+    #[test]
+    fn test_read_rules_skips_symlinks_next_to_denied_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("secret")).unwrap();
+        std::os::unix::fs::symlink(root.join("secret"), root.join("link")).unwrap();
+
+        let denied = vec![root.join("secret")];
+        let rules = read_rules(&root, &denied);
+
+        assert!(!rules.iter().any(|(p, _)| p == &root.join("link")));
+    }
+
+    #[test]
+    fn test_read_rules_deny_missing_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("home")).unwrap();
+        fs::write(root.join("home/.bashrc"), "").unwrap();
+
+        let denied = resolve_denied_path(&root.join("home/.aws/credentials")).unwrap();
+        assert_eq!(denied, root.join("home/.aws/credentials"));
+
+        // The existing parent is only listable, so `.aws` created later isn't
+        // covered by any read rule
+        let rules = read_rules(&root, &[denied]);
+        assert!(rules.contains(&(root.join("home"), ACCESS_FS_READ_DIR)));
+        assert!(rules.contains(&(root.join("home/.bashrc"), ACCESS_READ)));
+        assert!(!rules
+            .iter()
+            .any(|(p, _)| p.starts_with(root.join("home/.aws"))));
+    }
+
+    #[test]
+    fn test_handled_access_fs_by_abi() {
+        assert_eq!(handled_access_fs(1), ACCESS_FS_ABI_V1);
+        assert_eq!(handled_access_fs(2), ACCESS_FS_ABI_V1 | ACCESS_FS_REFER);
+        assert_eq!(
+            handled_access_fs(3),
+            ACCESS_FS_ABI_V1 | ACCESS_FS_REFER | ACCESS_FS_TRUNCATE
+        );
+    }
+
+    #[test]
+    fn test_prepare_network_isolation() {
+        let mount = tempfile::tempdir().unwrap();
+
+        let allow = LinuxSandbox::prepare(&test_config(NetworkMode::Allow), mount.path()).unwrap();
+        assert_eq!(allow.network, NetworkIsolation::None);
+        assert!(allow.userns.is_none());
+
+        let localhost =
+            LinuxSandbox::prepare(&test_config(NetworkMode::Localhost), mount.path()).unwrap();
+        assert_eq!(
+            localhost.network,
+            NetworkIsolation::Namespace { loopback: true }
+        );
+        assert!(localhost.userns.is_some());
+
+        let deny = LinuxSandbox::prepare(&test_config(NetworkMode::Deny), mount.path()).unwrap();
+        assert_eq!(deny.network, NetworkIsolation::BlockInet);
+        assert!(deny.userns.is_none());
+    }
+
+    #[test]
+    fn test_user_namespace_maps_current_user() {
+        let maps = UserNamespaceMaps::for_current_user();
+        let uid = nix::unistd::getuid().as_raw();
+
+        assert_eq!(maps.uid_map, format!("{} {} 1\n", uid, uid).into_bytes());
+    }
+}
@@ -1,12 +1,22 @@
-//! Sandbox support for subprocess isolation using macOS sandbox-exec.
+//! Sandbox support for subprocess isolation.
 //!
-//! This module provides SBPL (Sandbox Profile Language) profile generation
-//! for restricting subprocess access to filesystem and network resources.
+//! On macOS this module provides SBPL (Sandbox Profile Language) profile
+//! generation for `sandbox-exec`. On Linux the [`linux`] backend enforces the
+//! same `SandboxConfig` with Landlock, seccomp and network namespaces.
 //! This is especially useful for AI coding tools that should not have access
 //! to sensitive data like SSH keys, AWS credentials, etc.
 
+#[cfg(target_os = "linux")]
+pub mod linux;
+
+#[cfg(target_os = "linux")]
+pub use linux::LinuxSandbox;
+
+#[cfg(target_os = "macos")]
 use crate::config::{expand_tilde, NetworkMode, SandboxConfig};
+#[cfg(target_os = "macos")]
 use crate::error::{Result, TreebeardError};
+#[cfg(target_os = "macos")]
 use std::path::Path;
 
 /// Validates a path for safe inclusion in an SBPL profile.
@@ -181,7 +191,7 @@ pub fn generate_sbpl_profile(config: &SandboxConfig, mount_path: &Path) -> Strin
     profile
 }
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "macos"))]
 mod tests {
     use super::*;
     use crate::config::{NetworkMode, SandboxConfig, SandboxNetworkConfig};
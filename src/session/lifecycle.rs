@@ -1,21 +1,35 @@
 use std::path::PathBuf;
-use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 
 use crate::cleanup::{self, CleanupContext};
-use crate::config::{Config, SandboxConfig};
+use crate::config::{Config, LimitsConfig, SandboxConfig};
 use crate::error::{Result, TreebeardError};
 use crate::git::GitRepo;
-use crate::overlay::MutationTracker;
+use crate::overlay::{MutationJournal, MutationTracker};
+use crate::session::store::{
+    attach_process, detach_process, find_active_session, get_mutation_journal_path,
+};
 use crate::shell;
+use crate::watcher::AutoCommitFailures;
+use std::time::Duration;
 use tokio::task::JoinHandle;
 
+/// How often the session owner re-checks for attached processes before cleanup.
+const ATTACHED_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Spawn the shell (or command) and wait for it to exit.
+///
+/// If `repo_path` is given, the child's PID is registered as attached to the
+/// session for `branch_name` while it runs, so `list` reports the session as
+/// active and the session owner defers cleanup until it exits.
 pub async fn run_shell_session(
     shell_path: &std::path::Path,
     branch_name: &str,
     command: Option<&[String]>,
     sandbox_config: Option<&SandboxConfig>,
+    limits_config: Option<&LimitsConfig>,
     mount_path: Option<&std::path::Path>,
+    repo_path: Option<&std::path::Path>,
 ) -> Result<i32> {
     let is_test_mode = std::env::var("TREEBEARD_TEST_MODE").is_ok();
 
@@ -35,6 +49,7 @@ pub async fn run_shell_session(
         branch_name,
         command,
         sandbox_config,
+        limits_config,
         mount_path,
     ) {
         Ok(c) => c,
@@ -44,12 +59,32 @@ pub async fn run_shell_session(
         }
     };
 
+    let attached = match (repo_path, child.id()) {
+        (Some(repo_path), Some(pid)) => match attach_process(repo_path, branch_name, pid) {
+            Ok(()) => Some((repo_path, pid)),
+            Err(e) => {
+                tracing::warn!("Failed to record attached process: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+
     let status = child
         .wait()
         .await
         .map_err(|e| TreebeardError::Config(format!("Failed to wait for subprocess: {}", e)))?;
 
     shell::restore_foreground();
+    if let Some(mount_path) = mount_path {
+        shell::release_session_limits(mount_path, branch_name);
+    }
+
+    if let Some((repo_path, pid)) = attached {
+        if let Err(e) = detach_process(repo_path, branch_name, pid) {
+            tracing::warn!("Failed to release attached process: {}", e);
+        }
+    }
 
     let subprocess_name = match command {
         Some(cmd) if !cmd.is_empty() => &cmd[0],
@@ -88,6 +123,44 @@ fn spawn_watcher_monitor(watcher_handle: JoinHandle<()>) {
     });
 }
 
+/// Wait until no other processes are attached to the session.
+///
+/// The FUSE mount lives in this process, so the session owner must outlive
+/// every `treebeard attach` shell. Ctrl+C stops waiting and proceeds to cleanup.
+pub(crate) async fn wait_for_attached_processes(repo_path: &std::path::Path, branch_name: &str) {
+    let mut announced = false;
+
+    loop {
+        let remaining = match find_active_session(repo_path, branch_name) {
+            Ok(Some(session)) => session.ref_count(),
+            Ok(None) => 0,
+            Err(e) => {
+                tracing::warn!("Failed to check attached processes: {}", e);
+                0
+            }
+        };
+        if remaining == 0 {
+            return;
+        }
+
+        if !announced {
+            println!(
+                "Waiting for {} attached process(es) to exit before cleaning up (Ctrl+C to stop waiting)...",
+                remaining
+            );
+            announced = true;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(ATTACHED_POLL_INTERVAL) => {}
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("\nNo longer waiting for attached processes");
+                return;
+            }
+        }
+    }
+}
+
 /// Create a critical cleanup closure for FUSE unmounting.
 /// This ensures the filesystem is properly unmounted even on failures.
 fn create_critical_cleanup(mount_path: Option<PathBuf>) -> impl FnOnce() -> Result<()> {
@@ -141,13 +214,22 @@ pub fn build_cleanup_context(
     mount_path: Option<std::path::PathBuf>,
     worktree_repo: &GitRepo,
     base_commit: &str,
-    failure_count: Arc<AtomicUsize>,
+    failure_count: Arc<AutoCommitFailures>,
 ) -> cleanup::CleanupContext {
-    let mutation_map = {
+    let mut mutation_map = {
         let guard = mutations.read();
         guard.clone()
     };
 
+    // Files handed back to the lower layer by `treebeard sync --passthrough`
+    // are still in the tracker, but no longer differ from the main repo.
+    let forgotten = get_mutation_journal_path(repo.workdir(), branch_name)
+        .and_then(|journal_path| MutationJournal::load_forgotten(&journal_path));
+    match forgotten {
+        Ok(forgotten) => mutation_map.retain(|path, _| !forgotten.contains(path)),
+        Err(e) => tracing::warn!("Failed to read mutation journal: {}", e),
+    }
+
     cleanup::CleanupContext {
         mount_path,
         worktree_path: worktree_path.to_path_buf(),
@@ -157,7 +239,7 @@ pub fn build_cleanup_context(
         config: config.clone(),
         mutations: mutation_map,
         base_commit: base_commit.to_string(),
-        auto_commit_failure_count: failure_count,
+        auto_commit_failures: failure_count,
     }
 }
 
@@ -166,8 +248,9 @@ pub fn build_cleanup_context(
 /// This function orchestrates the session lifecycle:
 /// 1. Monitors the watcher task for errors
 /// 2. Runs the shell session
-/// 3. Performs cleanup (sync, squash, worktree removal)
-/// 4. Displays exit messages to the user
+/// 3. Waits for any processes started with `treebeard attach` to exit
+/// 4. Performs cleanup (sync, squash, worktree removal)
+/// 5. Displays exit messages to the user
 #[allow(clippy::too_many_arguments)]
 pub async fn run_shell_and_cleanup(
     shell_path: &std::path::Path,
@@ -180,7 +263,7 @@ pub async fn run_shell_and_cleanup(
     command: Option<&[String]>,
     worktree_repo: &GitRepo,
     base_commit: &str,
-    failure_count: Arc<AtomicUsize>,
+    failure_count: Arc<AutoCommitFailures>,
     watcher_handle: JoinHandle<()>,
 ) -> Result<i32> {
     // Monitor watcher task for errors (runs in background)
@@ -192,10 +275,14 @@ pub async fn run_shell_and_cleanup(
         branch_name,
         command,
         Some(&config.sandbox),
+        Some(&config.limits),
         mount_path.as_deref(),
+        Some(repo.workdir()),
     )
     .await?;
 
+    wait_for_attached_processes(repo.workdir(), branch_name).await;
+
     // Build cleanup context and perform cleanup
     let ctx = build_cleanup_context(
         worktree_path,
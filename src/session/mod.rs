@@ -2,6 +2,9 @@ pub mod lifecycle;
 pub mod store;
 pub mod types;
 
-pub use lifecycle::run_shell_and_cleanup;
-pub use store::{add_active_session, load_active_sessions, remove_active_session};
-pub use types::{ActiveSession, SessionDisplay, SessionStatus};
+pub use lifecycle::{run_shell_and_cleanup, run_shell_session};
+pub use store::{
+    add_active_session, find_active_session, get_mutation_journal_path, load_active_sessions,
+    load_base_snapshots, new_session_id, remove_active_session,
+};
+pub use types::{ActiveSession, SessionDisplay};
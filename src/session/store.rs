@@ -1,15 +1,50 @@
 use crate::config::get_config_dir;
 use crate::error::{Result, TreebeardError};
+use crate::git::CommitSession;
+use crate::overlay::{BaseSnapshots, BaseStore};
 use crate::session::types::ActiveSession;
 use fs2::FileExt;
 use std::fs::OpenOptions;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub fn get_session_state_path() -> Result<std::path::PathBuf> {
     let config_dir = get_config_dir()?;
     Ok(config_dir.join("active_sessions.json"))
 }
 
+/// Path of the overlay mutation journal for a branch, stored alongside the
+/// session state as `journals/<repo>/<branch>.jsonl`.
+pub fn get_mutation_journal_path(repo_path: &Path, branch_name: &str) -> Result<PathBuf> {
+    let repo_name = repo_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let config_dir = get_config_dir()?;
+    Ok(config_dir
+        .join("journals")
+        .join(repo_name)
+        .join(format!("{}.jsonl", branch_name)))
+}
+
+/// Loads the copy-up bases recorded next to a branch's mutation journal.
+///
+/// Errors are logged and yield an empty set, which only disables conflict
+/// detection for the sync.
+pub fn load_base_snapshots(repo_path: &Path, branch_name: &str) -> BaseSnapshots {
+    let result = get_mutation_journal_path(repo_path, branch_name)
+        .and_then(|journal_path| BaseSnapshots::load(&BaseStore::dir_for_journal(&journal_path)));
+    match result {
+        Ok(bases) => bases,
+        Err(e) => {
+            tracing::warn!(
+                "Failed to load copy-up bases, conflicts will not be detected: {}",
+                e
+            );
+            BaseSnapshots::default()
+        }
+    }
+}
+
 pub fn load_active_sessions() -> Result<Vec<ActiveSession>> {
     let sessions_path = get_session_state_path()?;
 
@@ -38,18 +73,38 @@ pub fn load_active_sessions() -> Result<Vec<ActiveSession>> {
     result
 }
 
+/// A new session id: when the session started, and a random suffix.
+pub fn new_session_id() -> String {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    format!(
+        "{}-{:08x}",
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ"),
+        hasher.finish() as u32
+    )
+}
+
+/// Record `session`, whose worktree at `worktree_path` is mounted at
+/// `mount_path`, as active.
 pub fn add_active_session(
     repo_path: &std::path::Path,
-    branch_name: &str,
     worktree_path: &std::path::Path,
     mount_path: &std::path::Path,
+    commit_session: &CommitSession,
 ) -> Result<()> {
+    let branch_name = commit_session.branch.as_str();
     let session = ActiveSession {
         repo_path: repo_path.to_string_lossy().to_string(),
         branch_name: branch_name.to_string(),
         worktree_path: worktree_path.to_string_lossy().to_string(),
         mount_path: mount_path.to_string_lossy().to_string(),
         start_time: chrono::Utc::now(),
+        attached_pids: Vec::new(),
+        base_commit: Some(commit_session.base_commit.clone()),
+        session_id: Some(commit_session.id.clone()),
+        command: commit_session.command.clone(),
     };
 
     modify_sessions_atomic(|sessions| {
@@ -62,6 +117,63 @@ pub fn add_active_session(
     Ok(())
 }
 
+/// Find the session for `branch_name` in the repository at `repo_path`.
+pub fn find_active_session(repo_path: &Path, branch_name: &str) -> Result<Option<ActiveSession>> {
+    let repo_path_str = repo_path.to_string_lossy().to_string();
+    Ok(load_active_sessions()?
+        .into_iter()
+        .find(|s| s.repo_path == repo_path_str && s.branch_name == branch_name))
+}
+
+/// Register a process as attached to a session, pruning PIDs that have exited.
+pub fn attach_process(repo_path: &Path, branch_name: &str, pid: u32) -> Result<()> {
+    let repo_path_str = repo_path.to_string_lossy().to_string();
+
+    modify_sessions_atomic(|sessions| {
+        let session = sessions
+            .iter_mut()
+            .find(|s| s.repo_path == repo_path_str && s.branch_name == branch_name)
+            .ok_or_else(|| {
+                TreebeardError::Config(format!("No active session for branch '{}'", branch_name))
+            })?;
+
+        session.attached_pids = session.live_attached_pids();
+        if !session.attached_pids.contains(&pid) {
+            session.attached_pids.push(pid);
+        }
+        tracing::debug!(
+            "Attached pid {} to '{}' ({} attached)",
+            pid,
+            branch_name,
+            session.attached_pids.len()
+        );
+        Ok(())
+    })
+}
+
+/// Unregister a process from a session.
+///
+/// Returns the number of processes still attached after removing `pid`.
+/// Returns 0 if the session no longer exists.
+pub fn detach_process(repo_path: &Path, branch_name: &str, pid: u32) -> Result<usize> {
+    let repo_path_str = repo_path.to_string_lossy().to_string();
+    let mut remaining = 0;
+
+    modify_sessions_atomic(|sessions| {
+        if let Some(session) = sessions
+            .iter_mut()
+            .find(|s| s.repo_path == repo_path_str && s.branch_name == branch_name)
+        {
+            session.attached_pids.retain(|p| *p != pid);
+            session.attached_pids = session.live_attached_pids();
+            remaining = session.attached_pids.len();
+        }
+        Ok(())
+    })?;
+
+    Ok(remaining)
+}
+
 fn modify_sessions_atomic<F>(mutator: F) -> Result<()>
 where
     F: FnOnce(&mut Vec<ActiveSession>) -> Result<()>,
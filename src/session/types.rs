@@ -1,3 +1,4 @@
+use crate::git::CommitSession;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::time::Duration;
@@ -9,17 +10,81 @@ pub struct ActiveSession {
     pub worktree_path: String,
     pub mount_path: String,
     pub start_time: chrono::DateTime<chrono::Utc>,
+    /// PIDs of processes running inside the mount: the session's own shell
+    /// plus any shells or commands started with `treebeard attach`.
+    #[serde(default)]
+    pub attached_pids: Vec<u32>,
+    /// HEAD of the branch when the session was first created. `resume` keeps
+    /// it so squash-on-exit covers the whole session. `None` for sessions
+    /// recorded by older versions.
+    #[serde(default)]
+    pub base_commit: Option<String>,
+    /// Identifies the session in its commits' trailers. `resume` keeps it.
+    /// `None` for sessions recorded by older versions.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// The command the session runs, empty for an interactive shell
+    #[serde(default)]
+    pub command: Vec<String>,
 }
 
 impl ActiveSession {
+    /// The session as recorded in the trailers of its commits. Fields not
+    /// recorded by older versions are left empty.
+    pub fn commit_session(&self) -> CommitSession {
+        CommitSession {
+            id: self.session_id.clone().unwrap_or_default(),
+            branch: self.branch_name.clone(),
+            command: self.command.clone(),
+            base_commit: self.base_commit.clone().unwrap_or_default(),
+        }
+    }
+
     pub fn is_healthy(&self) -> bool {
         Path::new(&self.mount_path).exists() && Path::new(&self.worktree_path).exists()
     }
+
+    /// Attached PIDs whose processes are still running.
+    pub fn live_attached_pids(&self) -> Vec<u32> {
+        self.attached_pids
+            .iter()
+            .copied()
+            .filter(|pid| is_process_alive(*pid))
+            .collect()
+    }
+
+    /// Number of live processes attached to this session. The session is torn
+    /// down once this drops to zero.
+    pub fn ref_count(&self) -> usize {
+        self.live_attached_pids().len()
+    }
+
+    pub fn status(&self) -> SessionStatus {
+        if !self.is_healthy() {
+            SessionStatus::Stale
+        } else if self.ref_count() > 0 {
+            SessionStatus::Active
+        } else {
+            SessionStatus::Idle
+        }
+    }
+}
+
+/// Returns true if a process with the given PID exists.
+pub fn is_process_alive(pid: u32) -> bool {
+    let Ok(raw) = i32::try_from(pid) else {
+        return false;
+    };
+    match nix::sys::signal::kill(nix::unistd::Pid::from_raw(raw), None) {
+        Ok(()) => true,
+        // The process exists but belongs to another user
+        Err(nix::errno::Errno::EPERM) => true,
+        Err(_) => false,
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SessionStatus {
-    #[allow(dead_code)] // Planned: detect when shell is actively running
     Active,
     Idle,
     Stale,
@@ -50,4 +115,72 @@ pub struct SessionDisplay {
     pub mount_status: String,
     pub dirty_files: usize,
     pub age: Duration,
+    /// Formatted CPU and RSS usage, for sessions running in a cgroup
+    pub usage: Option<(String, String)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_with(mount_path: &Path, worktree_path: &Path, pids: Vec<u32>) -> ActiveSession {
+        ActiveSession {
+            repo_path: "/repo".to_string(),
+            branch_name: "feature".to_string(),
+            worktree_path: worktree_path.to_string_lossy().to_string(),
+            mount_path: mount_path.to_string_lossy().to_string(),
+            start_time: chrono::Utc::now(),
+            attached_pids: pids,
+            base_commit: None,
+            session_id: None,
+            command: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_is_process_alive() {
+        assert!(is_process_alive(std::process::id()));
+        assert!(!is_process_alive(u32::MAX));
+    }
+
+    #[test]
+    fn test_ref_count_ignores_exited_processes() {
+        let temp = tempfile::tempdir().unwrap();
+        let session = session_with(temp.path(), temp.path(), vec![std::process::id(), u32::MAX]);
+
+        assert_eq!(session.live_attached_pids(), vec![std::process::id()]);
+        assert_eq!(session.ref_count(), 1);
+    }
+
+    #[test]
+    fn test_status_reflects_attached_processes() {
+        let temp = tempfile::tempdir().unwrap();
+
+        let idle = session_with(temp.path(), temp.path(), vec![]);
+        assert_eq!(idle.status(), SessionStatus::Idle);
+
+        let active = session_with(temp.path(), temp.path(), vec![std::process::id()]);
+        assert_eq!(active.status(), SessionStatus::Active);
+
+        let stale = session_with(
+            &temp.path().join("missing"),
+            temp.path(),
+            vec![std::process::id()],
+        );
+        assert_eq!(stale.status(), SessionStatus::Stale);
+    }
+
+    #[test]
+    fn test_session_without_attached_pids_deserializes() {
+        let json = r#"{
+            "repo_path": "/repo",
+            "branch_name": "feature",
+            "worktree_path": "/wt",
+            "mount_path": "/mnt",
+            "start_time": "2024-01-01T00:00:00Z"
+        }"#;
+        let session: ActiveSession = serde_json::from_str(json).unwrap();
+        assert!(session.attached_pids.is_empty());
+        assert!(session.base_commit.is_none());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::config::SandboxConfig;
+use crate::config::{LimitsConfig, SandboxConfig};
 use crate::error::Result;
 use nix::sys::signal::{self, SigHandler, Signal};
 use nix::unistd::{setpgid, tcsetpgrp, Pid};
@@ -7,27 +7,30 @@ use tokio::process::Command as TokioCommand;
 
 use tracing::debug;
 
+#[cfg(target_os = "linux")]
+use crate::cgroup::SessionCgroup;
+#[cfg(target_os = "linux")]
+use crate::overlay::backend::MountNamespace;
 #[cfg(target_os = "macos")]
 use crate::sandbox::generate_sbpl_profile;
+#[cfg(target_os = "linux")]
+use crate::sandbox::LinuxSandbox;
 
-/// Spawns a subprocess in the given working directory.
-///
-/// On macOS, if sandbox configuration is provided and enabled, the subprocess
-/// will run inside a sandbox-exec sandbox with restricted filesystem and network access.
+/// Builds the command for a session subprocess.
 ///
-/// # Arguments
-/// * `working_dir` - The directory to run the subprocess in
-/// * `branch_name` - The branch name (set as TREEBEARD_BRANCH env var)
-/// * `command` - Optional command to run (defaults to user's shell)
-/// * `sandbox_config` - Optional sandbox configuration for macOS
-/// * `mount_path` - The FUSE mount path (used for sandbox write permissions)
-pub fn spawn_subprocess_async(
+/// If sandbox configuration is provided and enabled, the subprocess runs with
+/// restricted filesystem and network access: inside sandbox-exec on macOS, and
+/// under Landlock/seccomp/network namespaces on Linux. The child is always
+/// placed in its own process group so it can be signalled as a unit, and on
+/// Linux that group joins the session's cgroup when `[limits]` is enabled.
+fn build_subprocess_command(
     working_dir: &Path,
     branch_name: &str,
     command: Option<&[String]>,
     sandbox_config: Option<&SandboxConfig>,
+    limits_config: Option<&LimitsConfig>,
     mount_path: Option<&Path>,
-) -> Result<tokio::process::Child> {
+) -> Result<TokioCommand> {
     let (program, args) = match command {
         Some(cmd) if !cmd.is_empty() => (
             cmd[0].clone(),
@@ -50,8 +53,38 @@ pub fn spawn_subprocess_async(
         .map(|c| c.enabled && mount_path.is_some())
         .unwrap_or(false);
 
-    #[cfg(not(target_os = "macos"))]
-    let use_sandbox = false;
+    #[cfg(target_os = "linux")]
+    let linux_sandbox = match (sandbox_config, mount_path) {
+        (Some(config), Some(mount)) if config.enabled => {
+            debug!("Sandbox enabled, preparing Linux sandbox");
+            Some(LinuxSandbox::prepare(config, mount)?)
+        }
+        _ => None,
+    };
+
+    #[cfg(target_os = "linux")]
+    let cgroup = match (limits_config, mount_path) {
+        (Some(config), Some(mount)) if config.is_enabled() => {
+            let cgroup = SessionCgroup::prepare(config, mount, branch_name)?;
+            if let Some(cgroup) = &cgroup {
+                debug!("Session cgroup: {}", cgroup.path().display());
+            }
+            cgroup
+        }
+        _ => None,
+    };
+
+    // overlayfs mounts are only visible inside the namespace that holds them
+    #[cfg(target_os = "linux")]
+    let mount_namespace = match mount_path {
+        Some(mount) => MountNamespace::for_mount(mount, working_dir)?,
+        None => None,
+    };
+
+    #[cfg(not(target_os = "linux"))]
+    if limits_config.is_some_and(|c| c.has_limits()) {
+        tracing::warn!("[limits] is only supported on Linux and will be ignored");
+    }
 
     // Build the actual command, potentially wrapping with sandbox-exec
     #[cfg(target_os = "macos")]
@@ -74,40 +107,139 @@ pub fn spawn_subprocess_async(
     #[cfg(not(target_os = "macos"))]
     let (final_program, final_args) = (program, cmd_args);
 
-    // SAFETY: We're setting up the child to be in its own process group
-    // and making it the foreground process group of the terminal.
-    // This ensures Ctrl+C goes to the subprocess, not to treebeard.
+    let mut cmd = TokioCommand::new(&final_program);
+    cmd.current_dir(working_dir)
+        .args(&final_args)
+        .env("TREEBEARD_ACTIVE", "1")
+        .env("TREEBEARD_BRANCH", branch_name);
+
+    // SAFETY: The closure only calls async-signal-safe functions (setpgid, the
+    // cgroup.procs write and the raw syscalls made to join the overlay's
+    // namespace and by the Linux sandbox) between fork and exec.
     unsafe {
-        let mut cmd = TokioCommand::new(&final_program);
-        cmd.current_dir(working_dir)
-            .args(&final_args)
-            .stdin(std::process::Stdio::inherit())
-            .stdout(std::process::Stdio::inherit())
-            .stderr(std::process::Stdio::inherit())
-            .env("TREEBEARD_ACTIVE", "1")
-            .env("TREEBEARD_BRANCH", branch_name)
-            .pre_exec(|| {
-                // Put the child in its own process group
-                let pid = Pid::from_raw(0); // 0 means "this process"
-                setpgid(pid, pid).map_err(std::io::Error::other)?;
-                Ok(())
-            });
-
-        let child = cmd.spawn().map_err(crate::error::TreebeardError::Io)?;
-
-        // Make the child's process group the foreground process group of the terminal.
-        // This ensures Ctrl+C signals go to the subprocess, not to us.
-        if let Some(pid) = child.id() {
-            let child_pid = Pid::from_raw(pid as i32);
-            // tcsetpgrp may fail if stdin isn't a terminal (e.g., piped input in tests).
-            // This is acceptable; the subprocess will still run, just without foreground control.
-            if let Err(e) = tcsetpgrp(std::io::stdin(), child_pid) {
-                debug!("Failed to set child process group as foreground: {}", e);
+        cmd.pre_exec(move || {
+            // Put the child in its own process group
+            let pid = Pid::from_raw(0); // 0 means "this process"
+            setpgid(pid, pid).map_err(std::io::Error::other)?;
+
+            // Join the session cgroup before the sandbox moves the child into
+            // a user namespace that may not be allowed to
+            #[cfg(target_os = "linux")]
+            if let Some(cgroup) = &cgroup {
+                cgroup.join()?;
+            }
+
+            // Enter the overlay's namespace while we can still join it; the
+            // sandbox may move the child into a nested user namespace
+            #[cfg(target_os = "linux")]
+            if let Some(namespace) = &mount_namespace {
+                namespace.enter()?;
             }
-        }
 
-        Ok(child)
+            // Restrict the child before it execs the target program
+            #[cfg(target_os = "linux")]
+            if let Some(sandbox) = &linux_sandbox {
+                sandbox.apply()?;
+            }
+
+            Ok(())
+        });
     }
+
+    Ok(cmd)
+}
+
+/// Spawns a subprocess in the given working directory.
+///
+/// If sandbox configuration is provided and enabled, the subprocess runs with
+/// restricted filesystem and network access: inside sandbox-exec on macOS, and
+/// under Landlock/seccomp/network namespaces on Linux.
+///
+/// # Arguments
+/// * `working_dir` - The directory to run the subprocess in
+/// * `branch_name` - The branch name (set as TREEBEARD_BRANCH env var)
+/// * `command` - Optional command to run (defaults to user's shell)
+/// * `sandbox_config` - Optional sandbox configuration
+/// * `limits_config` - Optional resource limits (Linux only)
+/// * `mount_path` - The FUSE mount path (used for sandbox write permissions
+///   and to name the session cgroup)
+pub fn spawn_subprocess_async(
+    working_dir: &Path,
+    branch_name: &str,
+    command: Option<&[String]>,
+    sandbox_config: Option<&SandboxConfig>,
+    limits_config: Option<&LimitsConfig>,
+    mount_path: Option<&Path>,
+) -> Result<tokio::process::Child> {
+    let mut cmd = build_subprocess_command(
+        working_dir,
+        branch_name,
+        command,
+        sandbox_config,
+        limits_config,
+        mount_path,
+    )?;
+    cmd.stdin(std::process::Stdio::inherit())
+        .stdout(std::process::Stdio::inherit())
+        .stderr(std::process::Stdio::inherit());
+
+    let child = cmd.spawn().map_err(crate::error::TreebeardError::Io)?;
+
+    // Make the child's process group the foreground process group of the terminal.
+    // This ensures Ctrl+C signals go to the subprocess, not to us.
+    if let Some(pid) = child.id() {
+        let child_pid = Pid::from_raw(pid as i32);
+        // tcsetpgrp may fail if stdin isn't a terminal (e.g., piped input in tests).
+        // This is acceptable; the subprocess will still run, just without foreground control.
+        if let Err(e) = tcsetpgrp(std::io::stdin(), child_pid) {
+            debug!("Failed to set child process group as foreground: {}", e);
+        }
+    }
+
+    Ok(child)
+}
+
+/// Spawns a subprocess with no terminal, for detached sessions.
+///
+/// Stdin is closed and stdout/stderr are both written to `log_file`. The
+/// sandbox, limits and environment are the same as for `spawn_subprocess_async`.
+pub fn spawn_detached_subprocess(
+    working_dir: &Path,
+    branch_name: &str,
+    command: &[String],
+    sandbox_config: Option<&SandboxConfig>,
+    limits_config: Option<&LimitsConfig>,
+    mount_path: Option<&Path>,
+    log_file: std::fs::File,
+) -> Result<tokio::process::Child> {
+    let stderr_file = log_file
+        .try_clone()
+        .map_err(crate::error::TreebeardError::Io)?;
+
+    let mut cmd = build_subprocess_command(
+        working_dir,
+        branch_name,
+        Some(command),
+        sandbox_config,
+        limits_config,
+        mount_path,
+    )?;
+    cmd.stdin(std::process::Stdio::null())
+        .stdout(log_file)
+        .stderr(stderr_file);
+
+    cmd.spawn().map_err(crate::error::TreebeardError::Io)
+}
+
+/// Removes the session's cgroup once its last process has exited. Does
+/// nothing if limits are not in use or other processes are still running in
+/// the session.
+pub fn release_session_limits(mount_path: &Path, branch_name: &str) {
+    #[cfg(target_os = "linux")]
+    crate::cgroup::remove_session_cgroup(mount_path, branch_name);
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = (mount_path, branch_name);
 }
 
 /// Restore treebeard as the foreground process group after the shell exits
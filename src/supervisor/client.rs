@@ -0,0 +1,153 @@
+use crate::error::{Result, TreebeardError};
+use crate::supervisor::protocol::{DetachedSession, Request, Response};
+use crate::supervisor::{get_supervisor_log_path, get_supervisor_socket_path};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a freshly spawned supervisor to accept connections.
+const SUPERVISOR_START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bound on a single request. Starting a session mounts the overlay and
+/// runs post_create hooks, so this is deliberately generous.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+fn connect() -> Option<UnixStream> {
+    let socket_path = get_supervisor_socket_path().ok()?;
+    UnixStream::connect(socket_path).ok()
+}
+
+/// Returns true if a supervisor is accepting connections.
+pub fn is_running() -> bool {
+    connect().is_some()
+}
+
+/// Send a request to a running supervisor and return its response.
+///
+/// `Response::Error` is converted into an `Err`.
+pub fn send_request(request: &Request) -> Result<Response> {
+    let stream = connect().ok_or_else(|| {
+        TreebeardError::Config("The treebeard supervisor is not running".to_string())
+    })?;
+    exchange(stream, request)
+}
+
+fn exchange(mut stream: UnixStream, request: &Request) -> Result<Response> {
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(TreebeardError::Io)?;
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line).map_err(|e| {
+        TreebeardError::Config(format!("Failed to send request to supervisor: {}", e))
+    })?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(|e| TreebeardError::Config(format!("Failed to read supervisor reply: {}", e)))?;
+    if reply.is_empty() {
+        return Err(TreebeardError::Config(
+            "Supervisor closed the connection without replying".to_string(),
+        ));
+    }
+
+    match serde_json::from_str::<Response>(&reply)? {
+        Response::Error { message } => Err(TreebeardError::Config(message)),
+        response => Ok(response),
+    }
+}
+
+/// Connect to the supervisor, starting one in the background if needed.
+pub fn ensure_running() -> Result<()> {
+    if is_running() {
+        return Ok(());
+    }
+
+    let exe = std::env::current_exe().map_err(TreebeardError::Io)?;
+    let log_path = get_supervisor_log_path()?;
+    if let Some(parent) = log_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            TreebeardError::Config(format!("Failed to create config directory: {}", e))
+        })?;
+    }
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|e| TreebeardError::Config(format!("Failed to open supervisor log: {}", e)))?;
+    let log_err = log.try_clone().map_err(TreebeardError::Io)?;
+
+    let mut cmd = std::process::Command::new(exe);
+    cmd.arg("supervise")
+        .stdin(std::process::Stdio::null())
+        .stdout(log)
+        .stderr(log_err);
+
+    // SAFETY: setsid is async-signal-safe. It detaches the supervisor from our
+    // session and terminal so it survives the shell that started it.
+    unsafe {
+        cmd.pre_exec(|| {
+            nix::unistd::setsid().map_err(std::io::Error::other)?;
+            Ok(())
+        });
+    }
+
+    cmd.spawn()
+        .map_err(|e| TreebeardError::Config(format!("Failed to start supervisor: {}", e)))?;
+
+    let deadline = Instant::now() + SUPERVISOR_START_TIMEOUT;
+    while Instant::now() < deadline {
+        if is_running() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    Err(TreebeardError::Config(format!(
+        "Supervisor did not start in time. See {}",
+        log_path.display()
+    )))
+}
+
+/// Sessions known to the supervisor for the repository at `repo_path`.
+///
+/// Returns an empty list if no supervisor is running.
+pub fn list_sessions(repo_path: &Path) -> Result<Vec<DetachedSession>> {
+    let Some(stream) = connect() else {
+        return Ok(Vec::new());
+    };
+
+    match exchange(stream, &Request::List)? {
+        Response::Sessions { sessions } => Ok(sessions
+            .into_iter()
+            .filter(|s| s.repo_path == repo_path)
+            .collect()),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// Look up a single detached session.
+pub fn get_session(repo_path: &Path, branch_name: &str) -> Result<DetachedSession> {
+    let no_session =
+        || TreebeardError::Config(format!("No detached session for branch '{}'", branch_name));
+
+    let stream = connect().ok_or_else(no_session)?;
+    match exchange(
+        stream,
+        &Request::Get {
+            repo_path: repo_path.to_path_buf(),
+            branch_name: branch_name.to_string(),
+        },
+    )? {
+        Response::Session { session } => Ok(*session),
+        other => Err(unexpected(other)),
+    }
+}
+
+pub(crate) fn unexpected(response: Response) -> TreebeardError {
+    TreebeardError::Config(format!("Unexpected supervisor response: {:?}", response))
+}
@@ -0,0 +1,42 @@
+//! Supervisor for detached sessions.
+//!
+//! `treebeard branch <name> --detach -- <cmd>` hands the session to a
+//! long-lived per-user supervisor process instead of tying it to the
+//! terminal. The supervisor owns the FUSE mount, the auto-commit watcher and
+//! the child process, and answers requests from `list`, `logs`, `stop` and
+//! `finish` over a Unix socket in the config directory.
+//!
+//! The protocol is one JSON request per connection followed by one JSON
+//! response, each terminated by a newline.
+
+pub mod client;
+pub mod protocol;
+pub mod server;
+
+use crate::config::get_config_dir;
+use crate::error::Result;
+use std::path::{Path, PathBuf};
+
+/// Path of the supervisor's Unix socket.
+pub fn get_supervisor_socket_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("supervisor.sock"))
+}
+
+/// Path of the supervisor's own log, which receives the output of overlay
+/// setup and cleanup for every detached session.
+pub fn get_supervisor_log_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("supervisor.log"))
+}
+
+/// Path of the output log for a detached session, stored as
+/// `logs/<repo>/<branch>.log` in the config directory.
+pub fn get_session_log_path(repo_path: &Path, branch_name: &str) -> Result<PathBuf> {
+    let repo_name = repo_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    Ok(get_config_dir()?
+        .join("logs")
+        .join(repo_name)
+        .join(format!("{}.log", branch_name)))
+}
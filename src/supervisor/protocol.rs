@@ -0,0 +1,176 @@
+use crate::config::{Config, SyncConfig};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Everything the supervisor needs to take over a session whose branch and
+/// worktree have already been created by the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartRequest {
+    pub repo_path: PathBuf,
+    pub branch_name: String,
+    pub worktree_path: PathBuf,
+    pub base_commit: String,
+    pub command: Vec<String>,
+    pub config: Config,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Mount the overlay, start the watcher and spawn the command.
+    Start { request: Box<StartRequest> },
+    /// Return every session the supervisor knows about.
+    List,
+    /// Return a single session.
+    Get {
+        repo_path: PathBuf,
+        branch_name: String,
+    },
+    /// Send SIGTERM to a running session's process group.
+    Stop {
+        repo_path: PathBuf,
+        branch_name: String,
+    },
+    /// Drop an exited session once `finish` has cleaned it up.
+    Forget {
+        repo_path: PathBuf,
+        branch_name: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Session { session: Box<DetachedSession> },
+    Sessions { sessions: Vec<DetachedSession> },
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DetachedState {
+    Running,
+    /// The command has exited and the overlay is unmounted. The worktree and
+    /// mutation journal are kept until `treebeard finish` runs.
+    Exited {
+        code: i32,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetachedSession {
+    pub repo_path: PathBuf,
+    pub branch_name: String,
+    pub worktree_path: PathBuf,
+    pub mount_path: PathBuf,
+    pub base_commit: String,
+    pub command: Vec<String>,
+    /// Identifies the session in its commits' trailers
+    #[serde(default)]
+    pub session_id: String,
+    pub pid: u32,
+    pub log_path: PathBuf,
+    pub state: DetachedState,
+    /// Whether the supervisor already squashed the auto-commits on exit
+    /// (`on_exit = "squash"`).
+    pub squashed: bool,
+    pub auto_commit_failures: usize,
+    /// Of `auto_commit_failures`, those where signing the commit failed
+    #[serde(default)]
+    pub auto_commit_signing_failures: usize,
+    pub start_time: chrono::DateTime<chrono::Utc>,
+    /// Sync settings captured at start, applied by `finish`
+    pub sync: SyncConfig,
+}
+
+impl DetachedSession {
+    pub fn is_running(&self) -> bool {
+        self.state == DetachedState::Running
+    }
+
+    pub fn matches(&self, repo_path: &std::path::Path, branch_name: &str) -> bool {
+        self.repo_path == repo_path && self.branch_name == branch_name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_round_trip() {
+        let request = Request::Stop {
+            repo_path: PathBuf::from("/repo"),
+            branch_name: "feature".to_string(),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"stop","repo_path":"/repo","branch_name":"feature"}"#
+        );
+
+        match serde_json::from_str::<Request>(&json).unwrap() {
+            Request::Stop {
+                repo_path,
+                branch_name,
+            } => {
+                assert_eq!(repo_path, PathBuf::from("/repo"));
+                assert_eq!(branch_name, "feature");
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_start_request_carries_config() {
+        let mut config = Config::default();
+        config.cleanup.on_exit = crate::config::OnExitBehavior::Squash;
+        let request = Request::Start {
+            request: Box::new(StartRequest {
+                repo_path: PathBuf::from("/repo"),
+                branch_name: "feature".to_string(),
+                worktree_path: PathBuf::from("/wt/feature"),
+                base_commit: "abc123".to_string(),
+                command: vec!["make".to_string(), "test".to_string()],
+                config,
+            }),
+        };
+
+        let json = serde_json::to_string(&request).unwrap();
+        match serde_json::from_str::<Request>(&json).unwrap() {
+            Request::Start { request } => {
+                assert_eq!(request.command, vec!["make", "test"]);
+                assert_eq!(
+                    request.config.cleanup.on_exit,
+                    crate::config::OnExitBehavior::Squash
+                );
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_detached_state_serialization() {
+        assert_eq!(
+            serde_json::to_string(&DetachedState::Running).unwrap(),
+            r#"{"status":"running"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&DetachedState::Exited { code: 3 }).unwrap(),
+            r#"{"status":"exited","code":3}"#
+        );
+    }
+
+    #[test]
+    fn test_error_response_round_trip() {
+        let json = serde_json::to_string(&Response::Error {
+            message: "boom".to_string(),
+        })
+        .unwrap();
+        match serde_json::from_str::<Response>(&json).unwrap() {
+            Response::Error { message } => assert_eq!(message, "boom"),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+}
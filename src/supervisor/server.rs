@@ -0,0 +1,501 @@
+use crate::cleanup::{squash_branch_commits, SquashContext};
+use crate::config::OnExitBehavior;
+use crate::error::{Result, TreebeardError};
+use crate::git::{CommitSession, GitRepo};
+use crate::hooks;
+use crate::overlay::{perform_fuse_cleanup, setup_overlay_and_watcher};
+use crate::session::lifecycle::wait_for_attached_processes;
+use crate::session::store::{attach_process, detach_process};
+use crate::session::{add_active_session, new_session_id, remove_active_session};
+use crate::shell;
+use crate::supervisor::protocol::{
+    DetachedSession, DetachedState, Request, Response, StartRequest,
+};
+use crate::supervisor::{get_session_log_path, get_supervisor_socket_path};
+use crate::watcher::AutoCommitFailures;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use parking_lot::Mutex;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+
+/// The supervisor exits once it has had no sessions for this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for the watcher's final auto-commit after unmounting.
+const WATCHER_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long running sessions get to exit when the supervisor is terminated.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Sessions = Arc<Mutex<Vec<DetachedSession>>>;
+
+/// Run the supervisor until it is idle or receives SIGTERM/SIGINT.
+///
+/// This is the body of the hidden `treebeard supervise` command, which
+/// `branch --detach` starts on demand.
+pub async fn run_supervisor() -> Result<()> {
+    let socket_path = get_supervisor_socket_path()?;
+
+    if std::os::unix::net::UnixStream::connect(&socket_path).is_ok() {
+        return Err(TreebeardError::Config(
+            "A treebeard supervisor is already running".to_string(),
+        ));
+    }
+
+    if let Some(parent) = socket_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            TreebeardError::Config(format!("Failed to create config directory: {}", e))
+        })?;
+    }
+    // Nothing is listening, so any existing socket file is left over from a
+    // supervisor that did not shut down cleanly.
+    remove_socket(&socket_path);
+
+    let listener = UnixListener::bind(&socket_path).map_err(|e| {
+        TreebeardError::Config(format!(
+            "Failed to bind supervisor socket {}: {}",
+            socket_path.display(),
+            e
+        ))
+    })?;
+    std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600))
+        .map_err(TreebeardError::Io)?;
+
+    tracing::info!("Supervisor listening on {}", socket_path.display());
+
+    let sessions: Sessions = Arc::new(Mutex::new(Vec::new()));
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let mut sigterm = signal(SignalKind::terminate()).map_err(TreebeardError::Io)?;
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let mut idle_since = Instant::now();
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let sessions = sessions.clone();
+                    let in_flight = in_flight.clone();
+                    in_flight.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, &sessions).await {
+                            tracing::warn!("Supervisor request failed: {}", e);
+                        }
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+                Err(e) => tracing::warn!("Failed to accept supervisor connection: {}", e),
+            },
+            _ = tick.tick() => {
+                let busy = !sessions.lock().is_empty() || in_flight.load(Ordering::SeqCst) > 0;
+                if busy {
+                    idle_since = Instant::now();
+                } else if idle_since.elapsed() >= IDLE_TIMEOUT {
+                    tracing::info!("No detached sessions left, supervisor exiting");
+                    break;
+                }
+            }
+            _ = sigterm.recv() => {
+                shutdown(&sessions).await;
+                break;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                shutdown(&sessions).await;
+                break;
+            }
+        }
+    }
+
+    remove_socket(&socket_path);
+    Ok(())
+}
+
+fn remove_socket(socket_path: &Path) {
+    if let Err(e) = std::fs::remove_file(socket_path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to remove supervisor socket: {}", e);
+        }
+    }
+}
+
+/// Stop every running session and give their exit handling a chance to
+/// unmount before the supervisor goes away.
+async fn shutdown(sessions: &Sessions) {
+    let running: Vec<u32> = sessions
+        .lock()
+        .iter()
+        .filter(|s| s.is_running())
+        .map(|s| s.pid)
+        .collect();
+    if running.is_empty() {
+        return;
+    }
+
+    tracing::info!("Stopping {} detached session(s)", running.len());
+    for pid in running {
+        signal_process_group(pid);
+    }
+
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while Instant::now() < deadline && sessions.lock().iter().any(|s| s.is_running()) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Send SIGTERM to the process group led by `pid`. Detached children are
+/// spawned as group leaders, so this also reaches anything they started.
+fn signal_process_group(pid: u32) {
+    let Ok(raw) = i32::try_from(pid) else {
+        return;
+    };
+    if let Err(e) = kill(Pid::from_raw(-raw), Signal::SIGTERM) {
+        tracing::debug!("Failed to signal process group {}: {}", pid, e);
+    }
+}
+
+async fn handle_connection(stream: UnixStream, sessions: &Sessions) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+
+    let mut line = String::new();
+    BufReader::new(reader)
+        .read_line(&mut line)
+        .await
+        .map_err(TreebeardError::Io)?;
+    if line.is_empty() {
+        // A liveness probe that connected and hung up
+        return Ok(());
+    }
+
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => dispatch(request, sessions).await,
+        Err(e) => Response::Error {
+            message: format!("Invalid supervisor request: {}", e),
+        },
+    };
+
+    let mut reply = serde_json::to_vec(&response)?;
+    reply.push(b'\n');
+    writer.write_all(&reply).await.map_err(TreebeardError::Io)
+}
+
+async fn dispatch(request: Request, sessions: &Sessions) -> Response {
+    let result = match request {
+        Request::Start { request } => {
+            start_session(*request, sessions)
+                .await
+                .map(|session| Response::Session {
+                    session: Box::new(session),
+                })
+        }
+        Request::List => Ok(Response::Sessions {
+            sessions: sessions.lock().clone(),
+        }),
+        Request::Get {
+            repo_path,
+            branch_name,
+        } => find_session(sessions, &repo_path, &branch_name).map(|session| Response::Session {
+            session: Box::new(session),
+        }),
+        Request::Stop {
+            repo_path,
+            branch_name,
+        } => stop_session(sessions, &repo_path, &branch_name).map(|()| Response::Ok),
+        Request::Forget {
+            repo_path,
+            branch_name,
+        } => forget_session(sessions, &repo_path, &branch_name).map(|()| Response::Ok),
+    };
+
+    result.unwrap_or_else(|e| Response::Error {
+        // The client wraps the message in TreebeardError::Config again
+        message: match e {
+            TreebeardError::Config(message) => message,
+            other => other.to_string(),
+        },
+    })
+}
+
+fn find_session(
+    sessions: &Sessions,
+    repo_path: &Path,
+    branch_name: &str,
+) -> Result<DetachedSession> {
+    sessions
+        .lock()
+        .iter()
+        .find(|s| s.matches(repo_path, branch_name))
+        .cloned()
+        .ok_or_else(|| {
+            TreebeardError::Config(format!("No detached session for branch '{}'", branch_name))
+        })
+}
+
+fn stop_session(sessions: &Sessions, repo_path: &Path, branch_name: &str) -> Result<()> {
+    let session = find_session(sessions, repo_path, branch_name)?;
+    if !session.is_running() {
+        return Err(TreebeardError::Config(format!(
+            "Session for '{}' has already exited. Run: treebeard finish {}",
+            branch_name, branch_name
+        )));
+    }
+
+    tracing::info!("Stopping detached session '{}'", branch_name);
+    signal_process_group(session.pid);
+    Ok(())
+}
+
+fn forget_session(sessions: &Sessions, repo_path: &Path, branch_name: &str) -> Result<()> {
+    let session = find_session(sessions, repo_path, branch_name)?;
+    if session.is_running() {
+        return Err(TreebeardError::Config(format!(
+            "Session for '{}' is still running",
+            branch_name
+        )));
+    }
+
+    sessions
+        .lock()
+        .retain(|s| !s.matches(repo_path, branch_name));
+    Ok(())
+}
+
+async fn start_session(request: StartRequest, sessions: &Sessions) -> Result<DetachedSession> {
+    if let Ok(existing) = find_session(sessions, &request.repo_path, &request.branch_name) {
+        return Err(TreebeardError::Config(if existing.is_running() {
+            format!(
+                "A detached session for '{}' is already running",
+                request.branch_name
+            )
+        } else {
+            format!(
+                "A detached session for '{}' has exited and is waiting to be finished. Run: treebeard finish {}",
+                request.branch_name, request.branch_name
+            )
+        }));
+    }
+
+    let config = &request.config;
+    let commit_session = CommitSession {
+        id: new_session_id(),
+        branch: request.branch_name.clone(),
+        command: request.command.clone(),
+        base_commit: request.base_commit.clone(),
+    };
+    let failure_count = Arc::new(AutoCommitFailures::default());
+    let overlay = setup_overlay_and_watcher(
+        &commit_session,
+        &request.worktree_path,
+        &request.repo_path,
+        config,
+        failure_count.clone(),
+    )?;
+
+    if let Err(e) = add_active_session(
+        &request.repo_path,
+        &request.worktree_path,
+        &overlay.mount_path,
+        &commit_session,
+    ) {
+        tracing::warn!("Failed to save session state: {}", e);
+    }
+
+    if !config.hooks.post_create.is_empty() {
+        let hook_context = hooks::HookContext::new(
+            &request.branch_name,
+            &overlay.mount_path,
+            &request.worktree_path,
+            &request.repo_path,
+        );
+        if let Err(e) = hooks::run_hooks(
+            &config.hooks.post_create,
+            &hook_context,
+            &overlay.mount_path,
+        )
+        .await
+        {
+            tracing::warn!("post_create hook failed: {}", e);
+        }
+    }
+
+    let log_path = get_session_log_path(&request.repo_path, &request.branch_name)?;
+    let child = match open_session_log(&log_path).and_then(|log_file| {
+        shell::spawn_detached_subprocess(
+            &overlay.mount_path,
+            &request.branch_name,
+            &request.command,
+            Some(&config.sandbox),
+            Some(&config.limits),
+            Some(&overlay.mount_path),
+            log_file,
+        )
+    }) {
+        Ok(child) => child,
+        Err(e) => {
+            if let Err(e) = remove_active_session(&request.repo_path, &request.branch_name) {
+                tracing::warn!("Failed to remove session state: {}", e);
+            }
+            perform_fuse_cleanup(&overlay.mount_path);
+            return Err(e);
+        }
+    };
+    let pid = child.id().unwrap_or_default();
+
+    if let Err(e) = attach_process(&request.repo_path, &request.branch_name, pid) {
+        tracing::warn!("Failed to record attached process: {}", e);
+    }
+
+    let session = DetachedSession {
+        repo_path: request.repo_path.clone(),
+        branch_name: request.branch_name.clone(),
+        worktree_path: request.worktree_path.clone(),
+        mount_path: overlay.mount_path.clone(),
+        base_commit: request.base_commit.clone(),
+        command: request.command.clone(),
+        session_id: commit_session.id.clone(),
+        pid,
+        log_path,
+        state: DetachedState::Running,
+        squashed: false,
+        auto_commit_failures: 0,
+        auto_commit_signing_failures: 0,
+        start_time: chrono::Utc::now(),
+        sync: request.config.sync.clone(),
+    };
+    sessions.lock().push(session.clone());
+
+    tracing::info!(
+        "Started detached session '{}' (pid {}): {}",
+        request.branch_name,
+        pid,
+        request.command.join(" ")
+    );
+
+    tokio::spawn(supervise_child(
+        sessions.clone(),
+        request,
+        child,
+        overlay.mount_path,
+        overlay.worktree_repo,
+        overlay.watcher_handle,
+        failure_count,
+    ));
+
+    Ok(session)
+}
+
+fn open_session_log(log_path: &Path) -> Result<std::fs::File> {
+    if let Some(parent) = log_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to create log directory {}: {}",
+                parent.display(),
+                e
+            ))
+        })?;
+    }
+    std::fs::File::create(log_path).map_err(|e| {
+        TreebeardError::Config(format!(
+            "Failed to create session log {}: {}",
+            log_path.display(),
+            e
+        ))
+    })
+}
+
+/// Wait for a detached session's command to exit, then tear down the mount.
+///
+/// Exit handling mirrors the foreground flow up to the point where it needs
+/// the user: attached processes are waited for, the overlay is unmounted and
+/// `on_exit = "squash"` is applied. Sync and worktree removal are deferred to
+/// `treebeard finish`, as is the squash decision when `on_exit = "prompt"`.
+async fn supervise_child(
+    sessions: Sessions,
+    request: StartRequest,
+    mut child: tokio::process::Child,
+    mount_path: PathBuf,
+    worktree_repo: GitRepo,
+    watcher_handle: JoinHandle<()>,
+    failure_count: Arc<AutoCommitFailures>,
+) {
+    let pid = child.id().unwrap_or_default();
+    let branch_name = &request.branch_name;
+
+    let code = match child.wait().await {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to wait for detached session '{}': {}",
+                branch_name,
+                e
+            );
+            1
+        }
+    };
+    tracing::info!(
+        "Detached session '{}' exited with status {}",
+        branch_name,
+        code
+    );
+
+    if let Err(e) = detach_process(&request.repo_path, branch_name, pid) {
+        tracing::warn!("Failed to release attached process: {}", e);
+    }
+    wait_for_attached_processes(&request.repo_path, branch_name).await;
+    shell::release_session_limits(&mount_path, branch_name);
+
+    if let Err(e) = remove_active_session(&request.repo_path, branch_name) {
+        tracing::warn!("Failed to remove session state: {}", e);
+    }
+
+    perform_fuse_cleanup(&mount_path);
+
+    // Unmounting closes the mutation channel; let the watcher make its final
+    // commit before squashing.
+    if tokio::time::timeout(WATCHER_DRAIN_TIMEOUT, watcher_handle)
+        .await
+        .is_err()
+    {
+        tracing::warn!("Watcher for '{}' did not finish in time", branch_name);
+    }
+
+    let on_exit = request.config.cleanup.on_exit;
+    let squashed = on_exit == OnExitBehavior::Squash && {
+        let squash_ctx = SquashContext {
+            repo: worktree_repo,
+            branch_name: branch_name.clone(),
+            on_exit: &on_exit,
+            squash_message: request
+                .config
+                .commit
+                .get_squash_commit_message()
+                .replace("{branch}", branch_name),
+            base_commit: request.base_commit.clone(),
+            interactive: false,
+        };
+        match squash_branch_commits(&squash_ctx) {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!("Squash error: {}", e);
+                false
+            }
+        }
+    };
+
+    let mut sessions = sessions.lock();
+    if let Some(session) = sessions
+        .iter_mut()
+        .find(|s| s.matches(&request.repo_path, branch_name))
+    {
+        session.state = DetachedState::Exited { code };
+        session.squashed = squashed;
+        session.auto_commit_failures = failure_count.count();
+        session.auto_commit_signing_failures = failure_count.signing_count();
+    }
+}
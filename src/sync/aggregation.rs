@@ -1,11 +1,10 @@
 use super::files::CompiledPatterns;
 use super::types::{AggregateResult, ChangeItem, ChangeType, DirectoryChange, FileChange};
 use crate::config::SyncConfig;
+use crate::git::is_ignored;
 use crate::overlay::MutationType;
 use std::collections::{HashMap, HashSet};
-use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
 
 fn find_top_ignored_ancestor(path: &Path, ignored_dirs: &HashSet<PathBuf>) -> Option<PathBuf> {
     let mut top_ignored = None;
@@ -45,57 +44,40 @@ pub fn get_gitignored_files(
         return Ok(HashSet::new());
     }
 
-    let mut child = Command::new("git")
-        .args(["check-ignore", "--stdin", "-z"])
-        .current_dir(repo_path)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .map_err(|e| {
-            tracing::warn!("Failed to spawn git check-ignore: {}", e);
-            GitCheckIgnoreError {
-                message: format!("failed to spawn git: {}", e),
-            }
-        })?;
-
-    {
-        let stdin = child.stdin.as_mut().ok_or_else(|| {
-            tracing::warn!("Failed to get stdin for git check-ignore");
-            GitCheckIgnoreError {
-                message: "failed to get stdin pipe".to_string(),
-            }
-        })?;
-        for path in &files {
-            stdin
-                .write_all(path.as_os_str().as_encoded_bytes())
-                .map_err(|e| {
-                    tracing::warn!("Failed to write to git check-ignore stdin: {}", e);
-                    GitCheckIgnoreError {
-                        message: format!("failed to write to stdin: {}", e),
-                    }
-                })?;
-            stdin.write_all(b"\0").map_err(|e| {
-                tracing::warn!("Failed to write null byte to git check-ignore stdin: {}", e);
-                GitCheckIgnoreError {
-                    message: format!("failed to write to stdin: {}", e),
-                }
-            })?;
-        }
-    }
-
-    let output = child.wait_with_output().map_err(|e| {
-        tracing::warn!("Failed to wait for git check-ignore: {}", e);
+    let check_ignore_error = |context: &str, e: &dyn std::fmt::Display| {
+        tracing::warn!("Failed to check ignored files: {}: {}", context, e);
         GitCheckIgnoreError {
-            message: format!("failed to wait for git process: {}", e),
+            message: format!("{}: {}", context, e),
         }
-    })?;
+    };
+    let repo =
+        gix::open(repo_path).map_err(|e| check_ignore_error("failed to open repository", &e))?;
+    let index = repo
+        .index_or_empty()
+        .map_err(|e| check_ignore_error("failed to read the index", &e))?;
+    let mut excludes = repo
+        .excludes(
+            &index,
+            None,
+            gix::worktree::stack::state::ignore::Source::WorktreeThenIdMappingIfNotSkipped,
+        )
+        .map_err(|e| check_ignore_error("failed to read ignore rules", &e))?;
 
     let mut ignored = HashSet::new();
-    for path_bytes in output.stdout.split(|&b| b == 0) {
-        if !path_bytes.is_empty() {
-            if let Ok(path_str) = std::str::from_utf8(path_bytes) {
-                ignored.insert(PathBuf::from(path_str));
-            }
+    for path in files {
+        // Like `git check-ignore`, tracked files are never reported
+        let git_path = gix::path::into_bstr(path.as_path());
+        if index.entry_by_path(git_path.as_ref()).is_some() {
+            continue;
+        }
+        let is_dir = repo_path
+            .join(path)
+            .symlink_metadata()
+            .is_ok_and(|m| m.is_dir());
+        if is_ignored(&mut excludes, path, is_dir)
+            .map_err(|e| check_ignore_error("failed to match ignore rules", &e))?
+        {
+            ignored.insert(path.clone());
         }
     }
 
@@ -189,7 +171,7 @@ pub fn aggregate_changes(
     }
 
     let dir_candidate_vec: Vec<PathBuf> = dir_candidates.into_iter().collect();
-    // For directory grouping, we can tolerate git check-ignore failures - files will just
+    // For directory grouping, we can tolerate ignore check failures - files will just
     // be shown individually rather than grouped by directory. This is a minor UX degradation.
     let ignored_dirs = get_gitignored_files(repo_path, &dir_candidate_vec).unwrap_or_else(|e| {
         tracing::debug!(
@@ -235,6 +217,7 @@ pub fn aggregate_changes(
                 modified_count,
                 added_count,
                 deleted_count,
+                conflict_count: 0,
             }));
         }
     }
@@ -264,6 +247,43 @@ mod tests {
         assert!(result.unwrap().is_empty());
     }
 
+    #[test]
+    fn test_get_gitignored_files_matches_git_check_ignore() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(args)
+                .current_dir(repo_path)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        git(&["init", "-q"]);
+        std::fs::write(repo_path.join(".gitignore"), "build/\n*.log\n").unwrap();
+        std::fs::create_dir(repo_path.join("build")).unwrap();
+        std::fs::write(repo_path.join("kept.log"), "tracked anyway").unwrap();
+        git(&["add", "-f", "kept.log"]);
+
+        let files: Vec<PathBuf> = [
+            "build",
+            "build/out.o",
+            "debug.log",
+            "kept.log",
+            "src/main.rs",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let ignored = get_gitignored_files(repo_path, &files).unwrap();
+
+        let expected: HashSet<PathBuf> = ["build", "build/out.o", "debug.log"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(ignored, expected);
+    }
+
     #[test]
     fn test_get_gitignored_files_nonexistent_repo() {
         // Running git check-ignore in a non-git directory should fail
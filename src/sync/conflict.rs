@@ -0,0 +1,450 @@
+use crate::error::{Result, TreebeardError};
+use crate::overlay::base::hash_file;
+use crate::overlay::BaseSnapshots;
+use crate::sync::display::format_diff;
+use crate::sync::files::detect_binary;
+use crate::sync::ops::sync_single_file;
+use crate::sync::types::{ChangeItem, ChangeType, FileChange};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns true if the main repo's copy of `file` has changed since the
+/// session copied it up, and differs from the worktree's version.
+fn is_conflict(
+    file: &FileChange,
+    repo_path: &Path,
+    worktree_path: &Path,
+    bases: &BaseSnapshots,
+) -> bool {
+    if !matches!(file.change_type, ChangeType::Modified | ChangeType::Deleted) {
+        return false;
+    }
+    let repo_file = repo_path.join(&file.path);
+    if bases.matches_base(&file.path, &repo_file) != Some(false) {
+        return false;
+    }
+
+    let theirs = hash_file(&repo_file).ok();
+    let ours = hash_file(&worktree_path.join(&file.path)).ok();
+    theirs != ours
+}
+
+/// Marks files whose main repo version diverged from the recorded base as
+/// [`ChangeType::Conflicted`]. Returns the number of conflicts found.
+pub fn mark_conflicts(
+    items: &mut [ChangeItem],
+    repo_path: &Path,
+    worktree_path: &Path,
+    bases: &BaseSnapshots,
+) -> usize {
+    if bases.is_empty() {
+        return 0;
+    }
+
+    let mut count = 0;
+    for item in items.iter_mut() {
+        match item {
+            ChangeItem::File(file) => {
+                if is_conflict(file, repo_path, worktree_path, bases) {
+                    file.change_type = ChangeType::Conflicted;
+                    count += 1;
+                }
+            }
+            ChangeItem::Directory(dir) => {
+                for file in dir.files.iter_mut() {
+                    if is_conflict(file, repo_path, worktree_path, bases) {
+                        match file.change_type {
+                            ChangeType::Modified => dir.modified_count -= 1,
+                            ChangeType::Deleted => dir.deleted_count -= 1,
+                            _ => {}
+                        }
+                        file.change_type = ChangeType::Conflicted;
+                        dir.conflict_count += 1;
+                        count += 1;
+                    }
+                }
+            }
+        }
+    }
+    count
+}
+
+/// Paths of every conflicted file in `items`.
+pub fn conflicted_paths(items: &[ChangeItem]) -> Vec<PathBuf> {
+    items
+        .iter()
+        .flat_map(|item| match item {
+            ChangeItem::File(file) => std::slice::from_ref(file).iter(),
+            ChangeItem::Directory(dir) => dir.files.iter(),
+        })
+        .filter(|f| f.change_type == ChangeType::Conflicted)
+        .map(|f| f.path.clone())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    pub text: String,
+    /// Number of regions where both sides changed the same lines. These are
+    /// written with `<<<<<<<`/`=======`/`>>>>>>>` markers.
+    pub conflicts: usize,
+}
+
+/// A changed region of the base: `base[old.0..old.1]` became
+/// `side[new.0..new.1]`.
+struct Hunk {
+    old: (usize, usize),
+    new: (usize, usize),
+}
+
+fn hunks(base: &[&str], side: &[&str]) -> Vec<Hunk> {
+    capture_diff_slices(Algorithm::Myers, base, side)
+        .into_iter()
+        .filter_map(|op| match op {
+            DiffOp::Equal { .. } => None,
+            DiffOp::Delete {
+                old_index,
+                old_len,
+                new_index,
+            } => Some(Hunk {
+                old: (old_index, old_index + old_len),
+                new: (new_index, new_index),
+            }),
+            DiffOp::Insert {
+                old_index,
+                new_index,
+                new_len,
+            } => Some(Hunk {
+                old: (old_index, old_index),
+                new: (new_index, new_index + new_len),
+            }),
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => Some(Hunk {
+                old: (old_index, old_index + old_len),
+                new: (new_index, new_index + new_len),
+            }),
+        })
+        .collect()
+}
+
+/// Applies `hunks` (all within `start..end`) to that region of `base`.
+fn apply_region(base: &[&str], side: &[&str], hunks: &[&Hunk], start: usize, end: usize) -> String {
+    let mut out = String::new();
+    let mut pos = start;
+    for hunk in hunks {
+        out.extend(base[pos..hunk.old.0].iter().copied());
+        out.extend(side[hunk.new.0..hunk.new.1].iter().copied());
+        pos = hunk.old.1;
+    }
+    out.extend(base[pos..end].iter().copied());
+    out
+}
+
+fn push_terminated(out: &mut String, text: &str) {
+    out.push_str(text);
+    if !text.is_empty() && !text.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Line-based three-way merge of `ours` and `theirs` against `base`.
+///
+/// Changes made on only one side are taken as-is. Overlapping changes that
+/// differ are kept as conflict markers for the user to edit.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let our_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let our_hunks = hunks(&base_lines, &our_lines);
+    let their_hunks = hunks(&base_lines, &their_lines);
+
+    let mut text = String::new();
+    let mut conflicts = 0;
+    let mut pos = 0;
+    let (mut i, mut j) = (0, 0);
+
+    while i < our_hunks.len() || j < their_hunks.len() {
+        let start = match (our_hunks.get(i), their_hunks.get(j)) {
+            (Some(a), Some(b)) => a.old.0.min(b.old.0),
+            (Some(a), None) => a.old.0,
+            (None, Some(b)) => b.old.0,
+            (None, None) => unreachable!(),
+        };
+
+        // Grow the region until no hunk from either side touches it
+        let mut end = start;
+        let (mut ours_in, mut theirs_in) = (Vec::new(), Vec::new());
+        loop {
+            if let Some(a) = our_hunks.get(i).filter(|a| a.old.0 <= end) {
+                end = end.max(a.old.1);
+                ours_in.push(a);
+                i += 1;
+            } else if let Some(b) = their_hunks.get(j).filter(|b| b.old.0 <= end) {
+                end = end.max(b.old.1);
+                theirs_in.push(b);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        text.extend(base_lines[pos..start].iter().copied());
+        let our_region = apply_region(&base_lines, &our_lines, &ours_in, start, end);
+        let their_region = apply_region(&base_lines, &their_lines, &theirs_in, start, end);
+
+        if theirs_in.is_empty() || our_region == their_region {
+            text.push_str(&our_region);
+        } else if ours_in.is_empty() {
+            text.push_str(&their_region);
+        } else {
+            conflicts += 1;
+            text.push_str("<<<<<<< worktree\n");
+            push_terminated(&mut text, &our_region);
+            text.push_str("=======\n");
+            push_terminated(&mut text, &their_region);
+            text.push_str(">>>>>>> main repo\n");
+        }
+        pos = end;
+    }
+    text.extend(base_lines[pos..].iter().copied());
+
+    MergeResult { text, conflicts }
+}
+
+/// How the user resolved a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The worktree version was written to the main repo
+    KeptWorktree,
+    /// The main repo was left as it is
+    KeptMainRepo,
+    /// A merge was written to the main repo, with this many conflict regions
+    Merged(usize),
+    /// The user went back without choosing
+    Unresolved,
+}
+
+/// Reads a file for display and merging. Returns `None` if it is missing,
+/// binary, too large or not UTF-8.
+fn read_text(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.len() > super::MAX_DIFF_FILE_SIZE {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if detect_binary(&bytes) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn print_side_diff(label: &str, base: &str, side: Option<&str>) {
+    println!("--- base → {}", label);
+    match side {
+        None => println!("(deleted)"),
+        Some(side) => {
+            let diff = format_diff(base, side);
+            let lines: Vec<&str> = diff.lines().collect();
+            for line in lines.iter().take(super::MAX_DIFF_LINES) {
+                println!("{}", line);
+            }
+            if lines.len() > super::MAX_DIFF_LINES {
+                println!("... ({} more lines)", lines.len() - super::MAX_DIFF_LINES);
+            }
+        }
+    }
+    println!();
+}
+
+/// Shows a conflicted file with base/ours/theirs diffs and applies the
+/// user's choice immediately.
+pub fn resolve_conflict(
+    file: &FileChange,
+    repo_path: &Path,
+    worktree_path: &Path,
+    bases: &BaseSnapshots,
+) -> Result<Resolution> {
+    let worktree_file = worktree_path.join(&file.path);
+    let repo_file = repo_path.join(&file.path);
+
+    let base = bases
+        .content(&file.path)
+        .filter(|b| !detect_binary(b))
+        .and_then(|b| String::from_utf8(b).ok());
+    let ours = read_text(&worktree_file);
+    let theirs = read_text(&repo_file);
+
+    println!("{} — CONFLICT\n", file.path.display());
+    println!("This file changed in the main repo after the session started.\n");
+
+    let can_merge = match &base {
+        Some(base) => {
+            let ours_missing = !worktree_file.exists();
+            let theirs_missing = !repo_file.exists();
+            if (ours.is_none() && !ours_missing) || (theirs.is_none() && !theirs_missing) {
+                println!("Cannot display diff for binary or large files.\n");
+                false
+            } else {
+                print_side_diff("worktree (ours)", base, ours.as_deref());
+                print_side_diff("main repo (theirs)", base, theirs.as_deref());
+                ours.is_some() && theirs.is_some()
+            }
+        }
+        None => {
+            println!("The original version was not kept (binary or larger than 100 KiB).\n");
+            false
+        }
+    };
+
+    print!("[o] Keep worktree version  [t] Keep main repo version  ");
+    if can_merge {
+        print!("[m] Merge  ");
+    }
+    println!("[q] Back: ");
+
+    let mut input = String::new();
+    std::io::stdin()
+        .read_line(&mut input)
+        .map_err(|e| TreebeardError::Config(format!("Failed to read input: {}", e)))?;
+
+    match input.trim().to_lowercase().as_str() {
+        "o" => {
+            sync_single_file(file, repo_path, worktree_path)?;
+            println!("\n✓ Kept worktree version of {}", file.path.display());
+            Ok(Resolution::KeptWorktree)
+        }
+        "t" => {
+            println!("\n✓ Kept main repo version of {}", file.path.display());
+            Ok(Resolution::KeptMainRepo)
+        }
+        "m" if can_merge => {
+            let (Some(base), Some(ours), Some(theirs)) = (base, ours, theirs) else {
+                return Ok(Resolution::Unresolved);
+            };
+            let merged = merge3(&base, &ours, &theirs);
+            fs::write(&repo_file, &merged.text).map_err(|e| {
+                TreebeardError::Config(format!(
+                    "Failed to write merged file {}: {}",
+                    repo_file.display(),
+                    e
+                ))
+            })?;
+            if merged.conflicts == 0 {
+                println!("\n✓ Merged {} into main repo", file.path.display());
+            } else {
+                println!(
+                    "\n! Merged {} into main repo with {} conflict(s) marked by <<<<<<<",
+                    file.path.display(),
+                    merged.conflicts
+                );
+            }
+            Ok(Resolution::Merged(merged.conflicts))
+        }
+        _ => Ok(Resolution::Unresolved),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay::BaseStore;
+    use crate::sync::types::DirectoryChange;
+
+    #[test]
+    fn test_merge3_takes_changes_from_both_sides() {
+        let base = "a\nb\nc\nd\ne\n";
+        let ours = "A\nb\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\n";
+
+        let merged = merge3(base, ours, theirs);
+        assert_eq!(merged.text, "A\nb\nc\nd\nE\n");
+        assert_eq!(merged.conflicts, 0);
+
+        let same = merge3(base, ours, ours);
+        assert_eq!(same.text, ours);
+        assert_eq!(same.conflicts, 0);
+    }
+
+    #[test]
+    fn test_merge3_marks_overlapping_changes() {
+        let merged = merge3("KEY=1\nOTHER=x\n", "KEY=2\nOTHER=x\n", "KEY=3\nOTHER=x\n");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.text,
+            "<<<<<<< worktree\nKEY=2\n=======\nKEY=3\n>>>>>>> main repo\nOTHER=x\n"
+        );
+
+        let no_newline = merge3("x", "y", "z");
+        assert_eq!(no_newline.conflicts, 1);
+        assert!(no_newline.text.contains("y\n=======\nz\n"));
+    }
+
+    #[test]
+    fn test_mark_conflicts() {
+        let temp = tempfile::tempdir().unwrap();
+        let repo = temp.path().join("repo");
+        let worktree = temp.path().join("worktree");
+        let base_file = temp.path().join("base");
+        fs::create_dir_all(repo.join("cache")).unwrap();
+        fs::create_dir_all(worktree.join("cache")).unwrap();
+        fs::write(&base_file, "KEY=1\n").unwrap();
+
+        let store_dir = temp.path().join("branch.base");
+        let store = BaseStore::open(&store_dir).unwrap();
+        let base_metadata = fs::metadata(&base_file).unwrap();
+        for path in [".env", "untouched.env", "same.env", "cache/a"] {
+            store
+                .record(Path::new(path), &base_metadata, &base_file)
+                .unwrap();
+        }
+
+        // Changed on both sides
+        fs::write(repo.join(".env"), "KEY=3\n").unwrap();
+        fs::write(worktree.join(".env"), "KEY=2\n").unwrap();
+        // Only changed in the worktree
+        fs::write(repo.join("untouched.env"), "KEY=1\n").unwrap();
+        fs::write(worktree.join("untouched.env"), "KEY=2\n").unwrap();
+        // Both sides made the same change
+        fs::write(repo.join("same.env"), "KEY=2\n").unwrap();
+        fs::write(worktree.join("same.env"), "KEY=2\n").unwrap();
+        // Deleted in the main repo
+        fs::write(worktree.join("cache/a"), "KEY=2\n").unwrap();
+
+        let file = |path: &str| FileChange {
+            path: PathBuf::from(path),
+            change_type: ChangeType::Modified,
+        };
+        let mut items = vec![
+            ChangeItem::File(file(".env")),
+            ChangeItem::File(file("untouched.env")),
+            ChangeItem::File(file("same.env")),
+            ChangeItem::Directory(DirectoryChange {
+                path: PathBuf::from("cache"),
+                files: vec![file("cache/a"), file("cache/b")],
+                modified_count: 2,
+                added_count: 0,
+                deleted_count: 0,
+                conflict_count: 0,
+            }),
+        ];
+
+        let bases = BaseSnapshots::load(&store_dir).unwrap();
+        assert_eq!(mark_conflicts(&mut items, &repo, &worktree, &bases), 2);
+        assert_eq!(
+            conflicted_paths(&items),
+            vec![PathBuf::from(".env"), PathBuf::from("cache/a")]
+        );
+        match &items[3] {
+            ChangeItem::Directory(dir) => {
+                assert_eq!(dir.modified_count, 1);
+                assert_eq!(dir.conflict_count, 1);
+            }
+            ChangeItem::File(_) => panic!("expected directory"),
+        }
+    }
+}
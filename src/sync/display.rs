@@ -52,7 +52,7 @@ pub fn show_file_preview(
         ChangeType::Deleted => {
             show_deleted_file(&file.path)?;
         }
-        ChangeType::Modified => {
+        ChangeType::Modified | ChangeType::Conflicted => {
             if !both_files_exist(repo_file, worktree_file) {
                 return Ok(PreviewResult::Skipped);
             }
@@ -1,7 +1,8 @@
-use crate::config::SyncConfig;
+use crate::config::{SyncConfig, SyncPolicy};
 use crate::error::{Result, TreebeardError};
-use crate::overlay::MutationType;
+use crate::overlay::{BaseSnapshots, MutationType};
 use crate::sync::aggregation::{aggregate_changes, filter_syncable_items};
+use crate::sync::conflict::{mark_conflicts, resolve_conflict, Resolution};
 use crate::sync::display::show_file_preview;
 use crate::sync::ops::{sync_all, sync_single_file};
 use crate::sync::tui::{install_panic_hook, run_interactive_selection};
@@ -10,9 +11,11 @@ use std::path::{Path, PathBuf};
 
 pub mod aggregation;
 pub mod config;
+pub mod conflict;
 pub mod display;
 pub mod files;
 pub mod ops;
+pub mod policy;
 pub mod tui;
 pub mod types;
 
@@ -23,12 +26,30 @@ pub const MAX_LINE_LENGTH: usize = 200;
 
 pub use crate::sync::types::{ChangeItem, FileChange, SyncResult};
 
+/// Offers changed ignored files for sync back to the main repo.
+///
+/// `bases` holds the content of copied-up files as the session first saw
+/// them; files whose main repo version has changed since are shown as
+/// conflicts instead of being overwritten.
 pub fn run_sync_flow(
     mutations: &HashMap<PathBuf, MutationType>,
     repo_path: &Path,
     worktree_path: &Path,
     sync_config: &SyncConfig,
+    bases: &BaseSnapshots,
 ) -> Result<SyncResult> {
+    let policy = sync_config.get_sync_policy();
+    if policy != SyncPolicy::Prompt {
+        return policy::run_policy_sync(
+            policy,
+            mutations,
+            repo_path,
+            worktree_path,
+            sync_config,
+            bases,
+        );
+    }
+
     install_panic_hook();
 
     let aggregate_result = aggregate_changes(mutations, repo_path, worktree_path);
@@ -45,7 +66,7 @@ pub fn run_sync_flow(
         return Ok(SyncResult::Skipped);
     }
 
-    let filtered = match filter_syncable_items(aggregate_result.items, repo_path, sync_config) {
+    let mut filtered = match filter_syncable_items(aggregate_result.items, repo_path, sync_config) {
         Ok(f) => f,
         Err(e) => {
             // Git check-ignore failed - we couldn't determine which files are gitignored.
@@ -85,18 +106,22 @@ pub fn run_sync_flow(
         return Ok(SyncResult::Skipped);
     }
 
+    let conflicts = mark_conflicts(&mut filtered.items_to_show, repo_path, worktree_path, bases);
+
     if filtered.items_to_show.len() == 1 {
         if let ChangeItem::File(file) = &filtered.items_to_show[0] {
-            return handle_single_file(file, repo_path, worktree_path);
+            return handle_single_file(file, repo_path, worktree_path, bases);
         }
     }
 
     run_sync_summary(
         &filtered.items_to_show,
         &filtered.auto_skipped,
+        conflicts,
         repo_path,
         worktree_path,
         sync_config,
+        bases,
     )
 }
 
@@ -104,12 +129,23 @@ fn handle_single_file(
     file: &FileChange,
     repo_path: &Path,
     worktree_path: &Path,
+    bases: &BaseSnapshots,
 ) -> Result<SyncResult> {
     use crate::cleanup::prompt_yes_no;
     use crate::sync::display::PreviewResult;
+    use crate::sync::types::ChangeType;
 
     println!("1 ignored file was modified:\n");
 
+    if file.change_type == ChangeType::Conflicted {
+        return Ok(
+            match resolve_conflict(file, repo_path, worktree_path, bases)? {
+                Resolution::KeptWorktree | Resolution::Merged(_) => SyncResult::Synced(1),
+                Resolution::KeptMainRepo | Resolution::Unresolved => SyncResult::Skipped,
+            },
+        );
+    }
+
     let worktree_file = worktree_path.join(&file.path);
     let repo_file = repo_path.join(&file.path);
 
@@ -130,9 +166,11 @@ fn handle_single_file(
 fn run_sync_summary(
     items: &[ChangeItem],
     auto_skipped: &[ChangeItem],
+    conflicts: usize,
     repo_path: &Path,
     worktree_path: &Path,
     sync_config: &SyncConfig,
+    bases: &BaseSnapshots,
 ) -> Result<SyncResult> {
     println!("The following ignored files were modified:\n");
 
@@ -173,6 +211,14 @@ fn run_sync_summary(
         }
     }
 
+    if conflicts > 0 {
+        println!(
+            "\n  {} file(s) also changed in the main repo since the session started.",
+            conflicts
+        );
+        println!("  [a]ll leaves them as they are; use [s]elect to resolve them.");
+    }
+
     println!("\nSync changes back to main repo? [a]ll / [s]elect interactive / [N]one (N): ");
 
     let mut input = String::new();
@@ -182,7 +228,7 @@ fn run_sync_summary(
     let choice = input.trim().to_lowercase();
     match choice.as_str() {
         "a" => sync_all(items, repo_path, worktree_path),
-        "s" => run_interactive_selection(items, repo_path, worktree_path, sync_config),
+        "s" => run_interactive_selection(items, repo_path, worktree_path, sync_config, bases),
         "" | "n" => {
             println!("\nNo files synced.");
             Ok(SyncResult::Skipped)
@@ -1,5 +1,7 @@
 use crate::error::{Result, TreebeardError};
-use crate::sync::types::{ChangeItem, DirectoryChange, FileChange, SyncProgress, SyncResult};
+use crate::sync::types::{
+    ChangeItem, ChangeType, DirectoryChange, FileChange, SyncProgress, SyncResult,
+};
 use std::fs;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
@@ -17,6 +19,12 @@ fn sync_items_internal(items: &[ChangeItem], repo_path: &Path, worktree_path: &P
 
     for item in items {
         match item {
+            ChangeItem::File(file) if file.change_type == ChangeType::Conflicted => {
+                println!(
+                    "  ! {} - changed in main repo, not synced",
+                    file.path.display()
+                );
+            }
             ChangeItem::File(file) => match sync_single_file(file, repo_path, worktree_path) {
                 Ok(()) => {
                     synced_files.push(file.path.clone());
@@ -30,6 +38,7 @@ fn sync_items_internal(items: &[ChangeItem], repo_path: &Path, worktree_path: &P
             },
             ChangeItem::Directory(dir) => {
                 let result = sync_directory_with_progress(dir, repo_path, worktree_path);
+                let attempted = result.len();
                 for (path, res) in result {
                     match res {
                         Ok(()) => {
@@ -40,14 +49,21 @@ fn sync_items_internal(items: &[ChangeItem], repo_path: &Path, worktree_path: &P
                         }
                     }
                 }
-                total_count += dir.files.len();
+                total_count += attempted;
                 println!(
                     "  ✓ {} ({} files - {} synced, {} failed)",
                     dir.path.display(),
                     dir.files.len(),
-                    synced_files.len() + failed_files.len() - total_count + dir.files.len(),
+                    synced_files.len() + failed_files.len() - total_count + attempted,
                     failed_files.len()
                 );
+                if dir.conflict_count > 0 {
+                    println!(
+                        "  ! {} - {} file(s) changed in main repo, not synced",
+                        dir.path.display(),
+                        dir.conflict_count
+                    );
+                }
             }
         }
     }
@@ -81,6 +97,16 @@ fn create_sync_result(stats: SyncStats, done_message: &str) -> Result<SyncResult
     }
 }
 
+/// Sync `items` without any prompting or summary, returning per-file results.
+pub fn sync_items(items: &[ChangeItem], repo_path: &Path, worktree_path: &Path) -> SyncProgress {
+    let stats = sync_items_internal(items, repo_path, worktree_path);
+    SyncProgress {
+        synced_files: stats.synced_files,
+        failed_files: stats.failed_files,
+        total_count: stats.total_count,
+    }
+}
+
 pub fn sync_all(
     items: &[ChangeItem],
     repo_path: &Path,
@@ -91,13 +117,23 @@ pub fn sync_all(
     create_sync_result(stats, "Synced")
 }
 
+/// Copies the worktree version of `file` over the main repo's, or removes
+/// it from the main repo if it was deleted.
+///
+/// Conflicted files are handled the same way; callers only pass them here
+/// once the user has chosen to keep the worktree version.
 pub fn sync_single_file(file: &FileChange, repo_path: &Path, worktree_path: &Path) -> Result<()> {
-    use crate::sync::types::ChangeType;
     let worktree_file = worktree_path.join(&file.path);
     let repo_file = repo_path.join(&file.path);
 
-    match file.change_type {
-        ChangeType::Deleted => match fs::remove_file(&repo_file) {
+    let deleted = match file.change_type {
+        ChangeType::Deleted => true,
+        ChangeType::Conflicted => worktree_file.symlink_metadata().is_err(),
+        ChangeType::Added | ChangeType::Modified => false,
+    };
+
+    if deleted {
+        match fs::remove_file(&repo_file) {
             Ok(()) => {}
             Err(e) if e.kind() == ErrorKind::NotFound => {}
             Err(e) => {
@@ -107,26 +143,25 @@ pub fn sync_single_file(file: &FileChange, repo_path: &Path, worktree_path: &Pat
                     e
                 )))
             }
-        },
-        ChangeType::Added | ChangeType::Modified => {
-            if let Some(parent) = repo_file.parent() {
-                fs::create_dir_all(parent).map_err(|e| {
-                    TreebeardError::Config(format!(
-                        "Failed to create directory {}: {}",
-                        parent.display(),
-                        e
-                    ))
-                })?;
-            }
-            fs::copy(&worktree_file, &repo_file).map_err(|e| {
+        }
+    } else {
+        if let Some(parent) = repo_file.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
                 TreebeardError::Config(format!(
-                    "Failed to copy {} to {}: {}",
-                    worktree_file.display(),
-                    repo_file.display(),
+                    "Failed to create directory {}: {}",
+                    parent.display(),
                     e
                 ))
             })?;
         }
+        fs::copy(&worktree_file, &repo_file).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to copy {} to {}: {}",
+                worktree_file.display(),
+                repo_file.display(),
+                e
+            ))
+        })?;
     }
     Ok(())
 }
@@ -138,6 +173,7 @@ fn sync_directory_with_progress(
 ) -> Vec<(PathBuf, Result<()>)> {
     dir.files
         .iter()
+        .filter(|file| file.change_type != ChangeType::Conflicted)
         .map(|file| {
             let path = file.path.clone();
             let result = sync_single_file(file, repo_path, worktree_path);
@@ -0,0 +1,339 @@
+use crate::config::{SyncConfig, SyncPolicy};
+use crate::error::{Result, TreebeardError};
+use crate::overlay::{BaseSnapshots, MutationType};
+use crate::sync::aggregation::{aggregate_changes, filter_syncable_items};
+use crate::sync::conflict::{conflicted_paths, mark_conflicts};
+use crate::sync::files::CompiledPatterns;
+use crate::sync::ops::sync_items;
+use crate::sync::types::{ChangeItem, ChangeType, SyncResult};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FailedFile {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Machine-readable outcome of a non-interactive sync, written as JSON to
+/// `sync_report` when configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncReport {
+    pub policy: SyncPolicy,
+    pub synced: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub failed: Vec<FailedFile>,
+    /// Files changed in the main repo since the session copied them up.
+    /// These are never synced by a policy.
+    pub conflicts: Vec<PathBuf>,
+    pub symlinks_skipped: usize,
+    /// Set when `git check-ignore` failed and nothing could be synced safely
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SyncReport {
+    fn new(policy: SyncPolicy) -> Self {
+        Self {
+            policy,
+            synced: Vec::new(),
+            skipped: Vec::new(),
+            failed: Vec::new(),
+            conflicts: Vec::new(),
+            symlinks_skipped: 0,
+            error: None,
+        }
+    }
+}
+
+fn item_files(item: &ChangeItem) -> Vec<PathBuf> {
+    match item {
+        ChangeItem::File(file) => vec![file.path.clone()],
+        ChangeItem::Directory(dir) => dir.files.iter().map(|f| f.path.clone()).collect(),
+    }
+}
+
+/// Split `items` into those the policy syncs and those it skips.
+pub fn select_items(
+    policy: SyncPolicy,
+    items: Vec<ChangeItem>,
+    sync_config: &SyncConfig,
+) -> (Vec<ChangeItem>, Vec<ChangeItem>) {
+    match policy {
+        SyncPolicy::All => (items, Vec::new()),
+        SyncPolicy::None | SyncPolicy::Prompt => (Vec::new(), items),
+        SyncPolicy::Patterns => {
+            let include_patterns = CompiledPatterns::new(&sync_config.get_sync_always_include());
+            items.into_iter().partition(|item| match item {
+                ChangeItem::File(file) => include_patterns.matches(&file.path),
+                ChangeItem::Directory(dir) => include_patterns.matches(&dir.path),
+            })
+        }
+    }
+}
+
+/// Sync changed ignored files according to a non-interactive `policy`.
+///
+/// Nothing here reads from stdin, so this is safe to run from CI and agent
+/// orchestrators. Items matching `sync_always_skip` are never synced, and
+/// neither are conflicts; those are listed in the report for a human.
+pub fn run_policy_sync(
+    policy: SyncPolicy,
+    mutations: &HashMap<PathBuf, MutationType>,
+    repo_path: &Path,
+    worktree_path: &Path,
+    sync_config: &SyncConfig,
+    bases: &BaseSnapshots,
+) -> Result<SyncResult> {
+    let mut report = SyncReport::new(policy);
+    let aggregate_result = aggregate_changes(mutations, repo_path, worktree_path);
+    report.symlinks_skipped = aggregate_result.symlinks_skipped;
+
+    let result = if aggregate_result.items.is_empty() {
+        println!("No ignored files were modified.");
+        SyncResult::Skipped
+    } else {
+        match filter_syncable_items(aggregate_result.items, repo_path, sync_config) {
+            Err(e) => {
+                eprintln!("Warning: Could not determine which files are gitignored.");
+                eprintln!("         Reason: {}", e);
+                report.error = Some(e.to_string());
+                SyncResult::GitCheckFailed
+            }
+            Ok(mut filtered) => {
+                report
+                    .skipped
+                    .extend(filtered.auto_skipped.iter().flat_map(item_files));
+
+                mark_conflicts(&mut filtered.items_to_show, repo_path, worktree_path, bases);
+                report.conflicts = conflicted_paths(&filtered.items_to_show);
+                if !report.conflicts.is_empty() {
+                    eprintln!(
+                        "Warning: {} file(s) changed in the main repo since the session started and were not synced:",
+                        report.conflicts.len()
+                    );
+                    for path in &report.conflicts {
+                        eprintln!("  ! {}", path.display());
+                    }
+                }
+
+                let (mut to_sync, skipped) =
+                    select_items(policy, filtered.items_to_show, sync_config);
+                report.skipped.extend(skipped.iter().flat_map(item_files));
+                to_sync.retain(|item| {
+                    !matches!(item, ChangeItem::File(f) if f.change_type == ChangeType::Conflicted)
+                });
+
+                if to_sync.is_empty() {
+                    println!(
+                        "Sync policy '{}': no files synced ({} skipped)",
+                        policy,
+                        report.skipped.len()
+                    );
+                    SyncResult::Skipped
+                } else {
+                    println!("Sync policy '{}': syncing changed ignored files...", policy);
+                    let progress = sync_items(&to_sync, repo_path, worktree_path);
+                    report.synced = progress.synced_files.clone();
+                    report.failed = progress
+                        .failed_files
+                        .iter()
+                        .map(|(path, error)| FailedFile {
+                            path: path.clone(),
+                            error: error.clone(),
+                        })
+                        .collect();
+
+                    println!(
+                        "Synced {} file(s), skipped {}, failed {}",
+                        report.synced.len(),
+                        report.skipped.len(),
+                        report.failed.len()
+                    );
+                    if progress.failed_files.is_empty() {
+                        SyncResult::Synced(progress.synced_files.len())
+                    } else {
+                        SyncResult::Partial(progress)
+                    }
+                }
+            }
+        }
+    };
+
+    if let Some(report_path) = sync_config.get_sync_report_path() {
+        write_report(&report, &report_path)?;
+        println!("Sync report written to {}", report_path.display());
+    }
+
+    Ok(result)
+}
+
+fn write_report(report: &SyncReport, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            TreebeardError::Config(format!(
+                "Failed to create directory {}: {}",
+                parent.display(),
+                e
+            ))
+        })?;
+    }
+
+    let json = serde_json::to_string_pretty(report)?;
+    std::fs::write(path, json).map_err(|e| {
+        TreebeardError::Config(format!(
+            "Failed to write sync report {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::types::FileChange;
+    use std::process::Command;
+
+    fn file(path: &str) -> ChangeItem {
+        ChangeItem::File(FileChange {
+            path: PathBuf::from(path),
+            change_type: ChangeType::Modified,
+        })
+    }
+
+    fn paths(items: &[ChangeItem]) -> Vec<String> {
+        items.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_select_items_by_policy() {
+        let config = SyncConfig {
+            sync_always_include: Some(vec![".env*".to_string()]),
+            ..Default::default()
+        };
+        let items = || vec![file(".env"), file("cache.bin")];
+
+        let (sync, skip) = select_items(SyncPolicy::All, items(), &config);
+        assert_eq!(paths(&sync), vec![".env", "cache.bin"]);
+        assert!(skip.is_empty());
+
+        let (sync, skip) = select_items(SyncPolicy::None, items(), &config);
+        assert!(sync.is_empty());
+        assert_eq!(skip.len(), 2);
+
+        let (sync, skip) = select_items(SyncPolicy::Patterns, items(), &config);
+        assert_eq!(paths(&sync), vec![".env"]);
+        assert_eq!(paths(&skip), vec!["cache.bin"]);
+    }
+
+    #[test]
+    fn test_run_policy_sync_writes_report() {
+        let repo = tempfile::tempdir().unwrap();
+        let worktree = tempfile::tempdir().unwrap();
+        let report_path = repo.path().join("reports/sync.json");
+
+        Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(repo.path())
+            .status()
+            .unwrap();
+        std::fs::write(repo.path().join(".gitignore"), "*.env\n*.log\n").unwrap();
+        std::fs::write(worktree.path().join("app.env"), "KEY=1").unwrap();
+        std::fs::write(worktree.path().join("debug.log"), "noise").unwrap();
+
+        let mut mutations = HashMap::new();
+        mutations.insert(PathBuf::from("app.env"), MutationType::Created);
+        mutations.insert(PathBuf::from("debug.log"), MutationType::Created);
+
+        let config = SyncConfig {
+            sync_always_skip: Some(vec!["*.log".to_string()]),
+            sync_report: Some(report_path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        let result = run_policy_sync(
+            SyncPolicy::All,
+            &mutations,
+            repo.path(),
+            worktree.path(),
+            &config,
+            &BaseSnapshots::default(),
+        )
+        .unwrap();
+
+        assert_eq!(result, SyncResult::Synced(1));
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("app.env")).unwrap(),
+            "KEY=1"
+        );
+        assert!(!repo.path().join("debug.log").exists());
+
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(report["policy"], "all");
+        assert_eq!(report["synced"], serde_json::json!(["app.env"]));
+        assert_eq!(report["skipped"], serde_json::json!(["debug.log"]));
+        assert_eq!(report["failed"], serde_json::json!([]));
+        assert_eq!(report["conflicts"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_run_policy_sync_skips_conflicts() {
+        use crate::overlay::BaseStore;
+
+        let repo = tempfile::tempdir().unwrap();
+        let worktree = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let report_path = state.path().join("sync.json");
+
+        Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(repo.path())
+            .status()
+            .unwrap();
+        std::fs::write(repo.path().join(".gitignore"), "*.env\n").unwrap();
+        std::fs::write(state.path().join("base"), "KEY=1\n").unwrap();
+        std::fs::write(repo.path().join("app.env"), "KEY=3\n").unwrap();
+        std::fs::write(worktree.path().join("app.env"), "KEY=2\n").unwrap();
+
+        let store_dir = state.path().join("branch.base");
+        let base_file = state.path().join("base");
+        BaseStore::open(&store_dir)
+            .unwrap()
+            .record(
+                Path::new("app.env"),
+                &std::fs::metadata(&base_file).unwrap(),
+                &base_file,
+            )
+            .unwrap();
+
+        let mut mutations = HashMap::new();
+        mutations.insert(PathBuf::from("app.env"), MutationType::CopiedUp);
+        let config = SyncConfig {
+            sync_report: Some(report_path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        let result = run_policy_sync(
+            SyncPolicy::All,
+            &mutations,
+            repo.path(),
+            worktree.path(),
+            &config,
+            &BaseSnapshots::load(&store_dir).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(result, SyncResult::Skipped);
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("app.env")).unwrap(),
+            "KEY=3\n"
+        );
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(report["conflicts"], serde_json::json!(["app.env"]));
+        assert_eq!(report["synced"], serde_json::json!([]));
+    }
+}
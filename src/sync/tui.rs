@@ -1,9 +1,11 @@
 use crate::config::SyncConfig;
 use crate::error::{Result, TreebeardError};
+use crate::overlay::BaseSnapshots;
 use crate::sync::config::save_skip_pattern;
+use crate::sync::conflict::{resolve_conflict, Resolution};
 use crate::sync::display::PreviewResult;
 use crate::sync::files::CompiledPatterns;
-use crate::sync::types::{ChangeItem, DirectoryChange, FileChange, SyncResult};
+use crate::sync::types::{ChangeItem, ChangeType, DirectoryChange, FileChange, SyncResult};
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use std::collections::HashSet;
@@ -124,13 +126,20 @@ enum ActionResult {
     Exit(SyncResult),
 }
 
+fn is_conflict(item: &ChangeItem) -> bool {
+    matches!(item, ChangeItem::File(f) if f.change_type == ChangeType::Conflicted)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn execute_selection_action(
     action: SelectionAction,
     items: &[ChangeItem],
     cursor: &mut usize,
     selected: &mut HashSet<usize>,
+    resolved: &mut HashSet<usize>,
     repo_path: &Path,
     worktree_path: &Path,
+    bases: &BaseSnapshots,
     ui: &mut SyncUI,
 ) -> Result<ActionResult> {
     use crate::sync::ops::sync_selected;
@@ -145,6 +154,10 @@ fn execute_selection_action(
             }
         }
         SelectionAction::ToggleSelection => {
+            // Conflicts are resolved from their detail view, not selected
+            if is_conflict(&items[*cursor]) {
+                return Ok(ActionResult::Continue);
+            }
             if selected.contains(cursor) {
                 selected.remove(cursor);
             } else {
@@ -152,12 +165,13 @@ fn execute_selection_action(
             }
         }
         SelectionAction::ToggleSelectAll => {
-            if selected.len() == items.len() {
+            let selectable: Vec<usize> = (0..items.len())
+                .filter(|&i| !is_conflict(&items[i]))
+                .collect();
+            if selected.len() == selectable.len() {
                 selected.clear();
             } else {
-                for i in 0..items.len() {
-                    selected.insert(i);
-                }
+                selected.extend(selectable);
             }
         }
         SelectionAction::Done => {
@@ -172,9 +186,17 @@ fn execute_selection_action(
         SelectionAction::ViewDetails => {
             ui.exit_raw_mode();
             let should_sync = match &items[*cursor] {
+                ChangeItem::File(file) if file.change_type == ChangeType::Conflicted => {
+                    if resolve_conflict(file, repo_path, worktree_path, bases)?
+                        != Resolution::Unresolved
+                    {
+                        resolved.insert(*cursor);
+                    }
+                    false
+                }
                 ChangeItem::File(file) => display_file_diff(file, repo_path, worktree_path)?,
                 ChangeItem::Directory(dir) => {
-                    display_directory_summary(dir, repo_path, worktree_path, ui)?
+                    display_directory_summary(dir, repo_path, worktree_path, bases, ui)?
                 }
             };
             if should_sync {
@@ -186,7 +208,7 @@ fn execute_selection_action(
             if let ChangeItem::Directory(dir) = &items[*cursor] {
                 if dir.files.len() <= super::MAX_VIEWABLE_FILES {
                     ui.exit_raw_mode();
-                    match run_directory_file_selection(dir, repo_path, worktree_path, ui) {
+                    match run_directory_file_selection(dir, repo_path, worktree_path, bases, ui) {
                         Ok(SyncResult::Synced(count)) => {
                             ui.enter_raw_mode()?;
                             println!("\n✓ Marked {} files for sync", count);
@@ -233,6 +255,7 @@ pub fn run_interactive_selection(
     repo_path: &Path,
     worktree_path: &Path,
     sync_config: &SyncConfig,
+    bases: &BaseSnapshots,
 ) -> Result<SyncResult> {
     let mut ui = SyncUI::new();
     ui.enter_raw_mode()?;
@@ -244,15 +267,16 @@ pub fn run_interactive_selection(
             ChangeItem::File(file) => include_patterns.matches(&file.path),
             ChangeItem::Directory(dir) => include_patterns.matches(&dir.path),
         };
-        if should_preselect {
+        if should_preselect && !is_conflict(item) {
             selected.insert(idx);
         }
     }
 
+    let mut resolved: HashSet<usize> = HashSet::new();
     let mut cursor = 0;
 
     loop {
-        display_selection_menu(items, &selected, cursor);
+        display_selection_menu(items, &selected, &resolved, cursor);
 
         loop {
             if let Some(ev) = ui.poll_input(Duration::from_millis(100))? {
@@ -262,8 +286,10 @@ pub fn run_interactive_selection(
                         items,
                         &mut cursor,
                         &mut selected,
+                        &mut resolved,
                         repo_path,
                         worktree_path,
+                        bases,
                         &mut ui,
                     )?;
                     if let ActionResult::Exit(sync_result) = result {
@@ -278,8 +304,10 @@ pub fn run_interactive_selection(
                         items,
                         &mut cursor,
                         &mut selected,
+                        &mut resolved,
                         repo_path,
                         worktree_path,
+                        bases,
                         &mut ui,
                     )?;
                     if let ActionResult::Exit(sync_result) = result {
@@ -292,7 +320,12 @@ pub fn run_interactive_selection(
     }
 }
 
-fn display_selection_menu(items: &[ChangeItem], selected: &HashSet<usize>, cursor: usize) {
+fn display_selection_menu(
+    items: &[ChangeItem],
+    selected: &HashSet<usize>,
+    resolved: &HashSet<usize>,
+    cursor: usize,
+) {
     let _ = crossterm::execute!(
         std::io::stdout(),
         crossterm::terminal::Clear(crossterm::terminal::ClearType::All),
@@ -308,9 +341,27 @@ fn display_selection_menu(items: &[ChangeItem], selected: &HashSet<usize>, curso
         let checkbox = if is_selected { "[x]" } else { "[ ]" };
 
         match item {
+            ChangeItem::File(file) if file.change_type == ChangeType::Conflicted => {
+                let status = if resolved.contains(&idx) {
+                    "conflict resolved"
+                } else {
+                    "conflict"
+                };
+                println!("  {} [!] {:?} ({})", marker, file.path.display(), status);
+            }
             ChangeItem::File(file) => {
                 println!("  {} {} {:?}", marker, checkbox, file.path.display());
             }
+            ChangeItem::Directory(dir) if dir.conflict_count > 0 => {
+                println!(
+                    "  {} {} {:?} ({} files, {} conflicts)",
+                    marker,
+                    checkbox,
+                    dir.path.display(),
+                    dir.files.len(),
+                    dir.conflict_count
+                );
+            }
             ChangeItem::Directory(dir) => {
                 println!(
                     "  {} {} {:?} ({} files)",
@@ -326,6 +377,9 @@ fn display_selection_menu(items: &[ChangeItem], selected: &HashSet<usize>, curso
     println!(
         "\n[↑↓] navigate  [space] toggle  [enter] view diff  [a] select all  [d] done  [q] quit"
     );
+    if items.iter().any(is_conflict) {
+        println!("[!] changed in main repo too: press [enter] to resolve");
+    }
     if items.len() == 1
         || (!items.is_empty()
             && matches!(&items[cursor], ChangeItem::Directory(d) if d.files.len() <= 50))
@@ -360,6 +414,7 @@ fn display_directory_summary(
     dir: &DirectoryChange,
     repo_path: &Path,
     worktree_path: &Path,
+    bases: &BaseSnapshots,
     ui: &mut SyncUI,
 ) -> Result<bool> {
     use crate::sync::config::save_include_pattern;
@@ -372,6 +427,12 @@ fn display_directory_summary(
     println!("    Modified:  {} files", dir.modified_count);
     println!("    Added:     {} files", dir.added_count);
     println!("    Deleted:   {} files", dir.deleted_count);
+    if dir.conflict_count > 0 {
+        println!(
+            "    Conflict:  {} files (changed in main repo too, not synced)",
+            dir.conflict_count
+        );
+    }
 
     let largest_changes: Vec<&FileChange> = dir.files.iter().take(3).collect();
     if !largest_changes.is_empty() {
@@ -438,7 +499,7 @@ fn display_directory_summary(
         }
         "v" if dir.files.len() <= super::MAX_VIEWABLE_FILES => {
             ui.enter_raw_mode()?;
-            match run_directory_file_selection(dir, repo_path, worktree_path, ui) {
+            match run_directory_file_selection(dir, repo_path, worktree_path, bases, ui) {
                 Ok(SyncResult::Synced(count)) => {
                     ui.exit_raw_mode();
                     println!("\n✓ Marked {} files for sync", count);
@@ -475,11 +536,13 @@ fn run_directory_file_selection(
     dir: &DirectoryChange,
     repo_path: &Path,
     worktree_path: &Path,
+    bases: &BaseSnapshots,
     ui: &mut SyncUI,
 ) -> Result<SyncResult> {
     ui.exit_raw_mode();
 
     let mut selected: HashSet<usize> = HashSet::new();
+    let mut resolved: HashSet<usize> = HashSet::new();
     let mut cursor = 0;
     let page_size = 15;
     let mut page = 0;
@@ -509,7 +572,17 @@ fn run_directory_file_selection(
             let is_cursor = absolute_idx == cursor;
             let is_selected = selected.contains(&absolute_idx);
             let marker = if is_cursor { ">" } else { " " };
-            let checkbox = if is_selected { "[x]" } else { "[ ]" };
+            let checkbox = if file.change_type == ChangeType::Conflicted {
+                if resolved.contains(&absolute_idx) {
+                    "[=]"
+                } else {
+                    "[!]"
+                }
+            } else if is_selected {
+                "[x]"
+            } else {
+                "[ ]"
+            };
 
             println!(
                 "  {} {} {} {:?}",
@@ -555,7 +628,9 @@ fn run_directory_file_selection(
                                     }
                                 }
                             }
-                            KeyCode::Char(' ') => {
+                            KeyCode::Char(' ')
+                                if dir.files[cursor].change_type != ChangeType::Conflicted =>
+                            {
                                 if selected.contains(&cursor) {
                                     selected.remove(&cursor);
                                 } else {
@@ -563,12 +638,13 @@ fn run_directory_file_selection(
                                 }
                             }
                             KeyCode::Char('a') => {
-                                if selected.len() == dir.files.len() {
+                                let selectable: Vec<usize> = (0..dir.files.len())
+                                    .filter(|&i| dir.files[i].change_type != ChangeType::Conflicted)
+                                    .collect();
+                                if selected.len() == selectable.len() {
                                     selected.clear();
                                 } else {
-                                    for i in 0..dir.files.len() {
-                                        selected.insert(i);
-                                    }
+                                    selected.extend(selectable);
                                 }
                             }
                             KeyCode::Char('d') => {
@@ -578,9 +654,13 @@ fn run_directory_file_selection(
                             KeyCode::Enter => {
                                 ui.exit_raw_mode();
                                 let file = &dir.files[cursor];
-                                let should_sync =
-                                    display_file_diff(file, repo_path, worktree_path)?;
-                                if should_sync {
+                                if file.change_type == ChangeType::Conflicted {
+                                    if resolve_conflict(file, repo_path, worktree_path, bases)?
+                                        != Resolution::Unresolved
+                                    {
+                                        resolved.insert(cursor);
+                                    }
+                                } else if display_file_diff(file, repo_path, worktree_path)? {
                                     selected.insert(cursor);
                                 }
                                 ui.enter_raw_mode()?;
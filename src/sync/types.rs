@@ -6,6 +6,9 @@ pub enum ChangeType {
     Modified,
     Added,
     Deleted,
+    /// Changed in the session, and also changed in the main repo since the
+    /// session copied it up. Never synced without an explicit resolution.
+    Conflicted,
 }
 
 impl ChangeType {
@@ -14,6 +17,7 @@ impl ChangeType {
             ChangeType::Modified => "modified",
             ChangeType::Added => "added",
             ChangeType::Deleted => "deleted",
+            ChangeType::Conflicted => "conflict",
         }
     }
 
@@ -22,6 +26,7 @@ impl ChangeType {
             ChangeType::Modified => "~",
             ChangeType::Added => "+",
             ChangeType::Deleted => "-",
+            ChangeType::Conflicted => "!",
         }
     }
 }
@@ -49,6 +54,7 @@ pub struct DirectoryChange {
     pub modified_count: usize,
     pub added_count: usize,
     pub deleted_count: usize,
+    pub conflict_count: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -1,5 +1,5 @@
 use crate::config::HooksConfig;
-use crate::error::Result;
+use crate::error::{Result, TreebeardError};
 use crate::git::GitRepo;
 use crate::hooks::{self, HookContext};
 use std::collections::HashSet;
@@ -9,6 +9,48 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::UnboundedReceiver;
 
+/// Auto-commits that failed during a session.
+///
+/// Failures to sign a commit are also counted separately, since they need a
+/// different fix than other git errors.
+#[derive(Debug, Default)]
+pub struct AutoCommitFailures {
+    failed: AtomicUsize,
+    signing: AtomicUsize,
+}
+
+impl AutoCommitFailures {
+    /// Counts carried over from an earlier record of the session.
+    pub fn from_counts(failed: usize, signing: usize) -> Self {
+        Self {
+            failed: AtomicUsize::new(failed),
+            signing: AtomicUsize::new(signing),
+        }
+    }
+
+    /// Count a failed auto-commit.
+    pub fn record(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count an auto-commit that failed with `error`.
+    pub fn record_error(&self, error: &TreebeardError) {
+        self.record();
+        if matches!(error, TreebeardError::Signing(_)) {
+            self.signing.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// How many of the failures were failures to sign.
+    pub fn signing_count(&self) -> usize {
+        self.signing.load(Ordering::Relaxed)
+    }
+}
+
 /// Configuration for generating commit messages.
 #[derive(Clone)]
 pub struct CommitConfig {
@@ -87,7 +129,7 @@ pub async fn watch_and_commit(
     repo: &GitRepo,
     debounce_ms: u64,
     commit_message: &str,
-    failure_count: Arc<AtomicUsize>,
+    failures: Arc<AutoCommitFailures>,
 ) -> Result<()> {
     tracing::debug!(
         "watch_and_commit() started, debounce={}ms, repo.workdir={:?}",
@@ -99,7 +141,7 @@ pub async fn watch_and_commit(
         mutation_rx,
         repo,
         debounce_ms,
-        failure_count,
+        failures,
         CommitMode::Simple(commit_message.to_string()),
     )
     .await
@@ -114,7 +156,7 @@ pub async fn watch_and_commit_with_hooks(
     repo: &GitRepo,
     debounce_ms: u64,
     commit_config: CommitConfig,
-    failure_count: Arc<AtomicUsize>,
+    failures: Arc<AutoCommitFailures>,
 ) -> Result<()> {
     tracing::debug!(
         "watch_and_commit_with_hooks() started, debounce={}ms, repo.workdir={:?}",
@@ -126,7 +168,7 @@ pub async fn watch_and_commit_with_hooks(
         mutation_rx,
         repo,
         debounce_ms,
-        failure_count,
+        failures,
         CommitMode::WithHooks(commit_config),
     )
     .await
@@ -137,7 +179,7 @@ async fn watch_and_commit_internal(
     mut mutation_rx: UnboundedReceiver<PathBuf>,
     repo: &GitRepo,
     debounce_ms: u64,
-    failure_count: Arc<AtomicUsize>,
+    failures: Arc<AutoCommitFailures>,
     commit_mode: CommitMode,
 ) -> Result<()> {
     let debounce_duration = Duration::from_millis(debounce_ms);
@@ -169,13 +211,13 @@ async fn watch_and_commit_internal(
                         last_event = Some(Instant::now());
                     }
                     None => {
-                        // Channel closed, FUSE filesystem shutting down
+                        // Channel closed, FUSE filesystem shutting down. The final
+                        // commit stages everything, picking up changes the overlay
+                        // doesn't report, such as a chmod.
                         tracing::debug!("Mutation channel closed, performing final commit if needed");
-                        if !pending_paths.is_empty() {
-                            match &commit_mode {
-                                CommitMode::Simple(msg) => do_commit(repo.clone(), msg, &pending_paths, failure_count.clone()).await,
-                                CommitMode::WithHooks(config) => do_commit_with_hooks(repo.clone(), config, &pending_paths, failure_count.clone()).await,
-                            }
+                        match &commit_mode {
+                            CommitMode::Simple(msg) => do_commit(repo.clone(), msg, None, failures.clone()).await,
+                            CommitMode::WithHooks(config) => do_commit_with_hooks(repo.clone(), config, &pending_paths, failures.clone()).await,
                         }
                         break;
                     }
@@ -187,8 +229,8 @@ async fn watch_and_commit_internal(
                 // Debounce timer expired
                 if !pending_paths.is_empty() {
                     match &commit_mode {
-                        CommitMode::Simple(msg) => do_commit(repo.clone(), msg, &pending_paths, failure_count.clone()).await,
-                        CommitMode::WithHooks(config) => do_commit_with_hooks(repo.clone(), config, &pending_paths, failure_count.clone()).await,
+                        CommitMode::Simple(msg) => do_commit(repo.clone(), msg, Some(&pending_paths), failures.clone()).await,
+                        CommitMode::WithHooks(config) => do_commit_with_hooks(repo.clone(), config, &pending_paths, failures.clone()).await,
                     }
                     pending_paths.clear();
                     last_event = None;
@@ -205,38 +247,46 @@ enum CommitMode {
     WithHooks(CommitConfig),
 }
 
+/// Commit `paths`, or everything that changed in the worktree if None.
 async fn do_commit(
     repo: Arc<GitRepo>,
     commit_message: &str,
-    paths: &HashSet<PathBuf>,
-    failure_count: Arc<AtomicUsize>,
+    paths: Option<&HashSet<PathBuf>>,
+    failures: Arc<AutoCommitFailures>,
 ) {
-    // paths is logged for debugging but not passed to stage_and_commit because
-    // git add -A stages all changes, which is simpler and handles edge cases
-    // like files created then deleted within the debounce window.
-    tracing::debug!(
-        "Debounce timer expired, committing {} changed paths",
-        paths.len()
-    );
+    let paths: Option<Vec<PathBuf>> = paths.map(|paths| paths.iter().cloned().collect());
+    match &paths {
+        Some(paths) => tracing::debug!(
+            "Debounce timer expired, committing {} changed paths",
+            paths.len()
+        ),
+        None => tracing::debug!("Committing all changes"),
+    }
     // Auto-commit is best-effort. Failures are logged but don't interrupt the
     // user's work; they can manually commit if needed.
     let message = commit_message.to_string();
-    let count = failure_count.clone();
     tokio::task::spawn_blocking(move || {
-        if let Err(e) = repo.stage_and_commit(&message) {
+        let result = match paths {
+            Some(paths) => repo.commit_paths(&paths, &message),
+            None => repo.stage_and_commit(&message),
+        };
+        if let Err(e) = result {
             tracing::warn!("Auto-commit failed: {}", e);
-            count.fetch_add(1, Ordering::Relaxed);
+            failures.record_error(&e);
         }
     })
     .await
     .ok();
 }
 
+/// Commit with a message from the commit_message hook. The hook is given
+/// the staged diff, so this stages everything with git rather than just
+/// `paths`.
 async fn do_commit_with_hooks(
     repo: Arc<GitRepo>,
     commit_config: &CommitConfig,
     paths: &HashSet<PathBuf>,
-    failure_count: Arc<AtomicUsize>,
+    failures: Arc<AutoCommitFailures>,
 ) {
     tracing::debug!(
         "Debounce timer expired, committing {} changed paths (with hooks)",
@@ -244,7 +294,6 @@ async fn do_commit_with_hooks(
     );
 
     let config = commit_config.clone();
-    let count = failure_count.clone();
 
     // Stage changes and get the diff in a blocking task
     let stage_result = tokio::task::spawn_blocking({
@@ -261,12 +310,12 @@ async fn do_commit_with_hooks(
         }
         Ok(Err(e)) => {
             tracing::warn!("Failed to stage changes: {}", e);
-            count.fetch_add(1, Ordering::Relaxed);
+            failures.record_error(&e);
             return;
         }
         Err(e) => {
             tracing::warn!("Task panicked while staging: {}", e);
-            count.fetch_add(1, Ordering::Relaxed);
+            failures.record();
             return;
         }
     };
@@ -288,11 +337,11 @@ async fn do_commit_with_hooks(
         }
         Ok(Err(e)) => {
             tracing::warn!("Auto-commit failed: {}", e);
-            count.fetch_add(1, Ordering::Relaxed);
+            failures.record_error(&e);
         }
         Err(e) => {
             tracing::warn!("Task panicked while committing: {}", e);
-            count.fetch_add(1, Ordering::Relaxed);
+            failures.record();
         }
     }
 }
@@ -1,6 +1,7 @@
 //! Edge case tests for error handling.
 
 use crate::shared::common::{get_treebeard_path, TestWorkspace};
+use std::fs;
 use std::process::Command;
 
 #[test]
@@ -174,6 +175,58 @@ fn test_err_branch_with_already_existing_branch() {
     workspace.restore_dir();
 }
 
+#[test]
+fn test_err_branch_from_unknown_rev_leaves_changes() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+    workspace.switch_to_repo();
+
+    fs::write(workspace.repo_path.join("README.md"), "# Edited\n").unwrap();
+    fs::write(workspace.repo_path.join("notes.txt"), "untracked").unwrap();
+
+    for start in [["--from", "no-such-rev"], ["--from-pr", "999"]] {
+        let output = Command::new(&treebeard_path)
+            .args(["branch", "err-bad-start", "--no-shell"])
+            .args(start)
+            .current_dir(&workspace.repo_path)
+            .env("TREEBEARD_TEST_MODE", "1")
+            .output()
+            .expect("Failed to run treebeard");
+
+        assert!(
+            !output.status.success(),
+            "{:?} should fail for a start point that doesn't exist",
+            start
+        );
+
+        // Nothing was stashed or created
+        assert_eq!(
+            fs::read_to_string(workspace.repo_path.join("README.md")).unwrap(),
+            "# Edited\n"
+        );
+        assert!(workspace.repo_path.join("notes.txt").exists());
+        let stashes = Command::new("git")
+            .args(["stash", "list"])
+            .current_dir(&workspace.repo_path)
+            .output()
+            .expect("Failed to run git");
+        assert!(
+            stashes.stdout.is_empty(),
+            "{:?} left a stash: {}",
+            start,
+            String::from_utf8_lossy(&stashes.stdout)
+        );
+        assert!(!Command::new("git")
+            .args(["rev-parse", "--verify", "--quiet", "err-bad-start"])
+            .current_dir(&workspace.repo_path)
+            .status()
+            .expect("Failed to run git")
+            .success());
+    }
+
+    workspace.restore_dir();
+}
+
 #[test]
 fn test_err_empty_branch_name() {
     let treebeard_path = get_treebeard_path();
@@ -200,3 +253,203 @@ fn test_err_empty_branch_name() {
 
     workspace.restore_dir();
 }
+
+#[test]
+fn test_err_attach_without_session() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+    workspace.switch_to_repo();
+
+    let output = Command::new(&treebeard_path)
+        .args(["attach", "no-such-session", "true"])
+        .current_dir(&workspace.repo_path)
+        .env("TREEBEARD_TEST_MODE", "1")
+        .output()
+        .expect("Failed to run treebeard");
+
+    assert!(
+        !output.status.success(),
+        "Attach should fail when no session is running"
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("No active session"),
+        "Error message should mention the missing session. stderr: {}",
+        stderr
+    );
+
+    workspace.restore_dir();
+}
+
+#[test]
+fn test_err_detach_without_command() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+    workspace.switch_to_repo();
+
+    let output = Command::new(&treebeard_path)
+        .args(["branch", "detached-no-cmd", "--detach"])
+        .current_dir(&workspace.repo_path)
+        .env("TREEBEARD_TEST_MODE", "1")
+        .output()
+        .expect("Failed to run treebeard");
+
+    assert!(
+        !output.status.success(),
+        "--detach without a command should fail"
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--detach requires a command"),
+        "Error message should explain that a command is required. stderr: {}",
+        stderr
+    );
+
+    workspace.restore_dir();
+}
+
+#[test]
+fn test_err_stop_and_finish_without_detached_session() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+    workspace.switch_to_repo();
+
+    for subcommand in ["stop", "finish", "logs"] {
+        let output = Command::new(&treebeard_path)
+            .args([subcommand, "no-such-session"])
+            .current_dir(&workspace.repo_path)
+            .env("TREEBEARD_TEST_MODE", "1")
+            .output()
+            .expect("Failed to run treebeard");
+
+        assert!(
+            !output.status.success(),
+            "{} should fail when there is no detached session",
+            subcommand
+        );
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("no-such-session"),
+            "{} error should name the branch. stderr: {}",
+            subcommand,
+            stderr
+        );
+    }
+
+    workspace.restore_dir();
+}
+
+#[test]
+fn test_err_stats_without_session() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+    workspace.switch_to_repo();
+
+    let output = Command::new(&treebeard_path)
+        .args(["stats", "no-such-session", "--json"])
+        .current_dir(&workspace.repo_path)
+        .env("TREEBEARD_TEST_MODE", "1")
+        .output()
+        .expect("Failed to run treebeard");
+
+    assert!(
+        !output.status.success(),
+        "stats should fail when there is no session"
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("No active session for branch 'no-such-session'"),
+        "Error should name the branch. stderr: {}",
+        stderr
+    );
+
+    workspace.restore_dir();
+}
+
+#[test]
+fn test_err_reload_without_session() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+    workspace.switch_to_repo();
+
+    let output = Command::new(&treebeard_path)
+        .args(["reload", "no-such-session"])
+        .current_dir(&workspace.repo_path)
+        .env("TREEBEARD_TEST_MODE", "1")
+        .output()
+        .expect("Failed to run treebeard");
+
+    assert!(
+        !output.status.success(),
+        "reload should fail when there is no session"
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("No active session for branch 'no-such-session'"),
+        "Error should name the branch. stderr: {}",
+        stderr
+    );
+
+    workspace.restore_dir();
+}
+
+#[test]
+fn test_err_resume_without_worktree() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+    workspace.switch_to_repo();
+
+    let output = Command::new(&treebeard_path)
+        .args(["resume", "never-created"])
+        .current_dir(&workspace.repo_path)
+        .env("TREEBEARD_TEST_MODE", "1")
+        .output()
+        .expect("Failed to run treebeard");
+
+    assert!(
+        !output.status.success(),
+        "Resume should fail when the branch has no worktree"
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("No worktree found"),
+        "Error message should mention the missing worktree. stderr: {}",
+        stderr
+    );
+
+    workspace.restore_dir();
+}
+
+#[test]
+fn test_err_diff_without_worktree() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+    workspace.switch_to_repo();
+
+    let output = Command::new(&treebeard_path)
+        .args(["diff", "never-created", "--stat"])
+        .current_dir(&workspace.repo_path)
+        .env("TREEBEARD_TEST_MODE", "1")
+        .output()
+        .expect("Failed to run treebeard");
+
+    assert!(
+        !output.status.success(),
+        "Diff should fail when the branch has no worktree"
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("No worktree found"),
+        "Error message should mention the missing worktree. stderr: {}",
+        stderr
+    );
+
+    workspace.restore_dir();
+}
@@ -127,3 +127,28 @@ fn test_non_tty_allowed_cleanup_command() {
         String::from_utf8_lossy(&output.stderr)
     );
 }
+
+#[test]
+fn test_non_tty_allowed_branch_command_with_sync_policy() {
+    let treebeard_path = get_treebeard_path();
+    let workspace = TestWorkspace::new();
+
+    let output = Command::new(&treebeard_path)
+        .args(["branch", "test-sync-no-tty", "--sync=none", "--", "true"])
+        .stdin(std::process::Stdio::null())
+        .current_dir(&workspace.repo_path)
+        .output()
+        .expect("Failed to run treebeard");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "treebeard branch with a command and --sync should succeed without TTY. stderr: {}",
+        stderr
+    );
+    assert!(
+        !stderr.contains("interactive terminal"),
+        "Should not require a TTY. stderr: {}",
+        stderr
+    );
+}
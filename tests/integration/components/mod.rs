@@ -1,3 +1,4 @@
 mod hooks;
+mod overlayfs;
 mod sandbox;
 mod watcher;
@@ -0,0 +1,116 @@
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::Path;
+use tokio::time::{sleep, timeout, Duration};
+use treebeard::overlay::backend::{backend, MountRequest};
+use treebeard::overlay::whiteout::Whiteout;
+use treebeard::overlay::{join_mount_namespace, perform_fuse_cleanup, MutationType};
+use treebeard::{InodeCacheConfig, OverlayBackendKind};
+
+/// Run a shell snippet inside the session mount at `mount_point`.
+async fn run_in_mount(mount_point: &Path, script: &str) -> String {
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c").arg(script).current_dir(mount_point);
+    join_mount_namespace(&mut cmd, mount_point).expect("Failed to join mount namespace");
+    let output = cmd.output().await.expect("Failed to run shell");
+    assert!(
+        output.status.success(),
+        "Shell failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Mount, write through and unmount a kernel overlayfs session
+#[tokio::test]
+async fn test_overlayfs_mount_tracks_mutations_and_unmounts() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::env::set_var("TREEBEARD_DATA_DIR", temp_dir.path());
+
+    let lower = temp_dir.path().join("repo");
+    let upper = temp_dir
+        .path()
+        .join("worktrees")
+        .join("repo")
+        .join("branch");
+    let mount_point = temp_dir.path().join("mounts").join("repo").join("branch");
+    fs::create_dir_all(&lower).unwrap();
+    fs::create_dir_all(&upper).unwrap();
+    fs::write(lower.join("edit.txt"), "lower").unwrap();
+    fs::write(lower.join("delete.txt"), "lower").unwrap();
+
+    let overlayfs = backend(OverlayBackendKind::Overlayfs).unwrap();
+    let request = MountRequest {
+        mount_point: &mount_point,
+        upper_layer: &upper,
+        lower_layer: &lower,
+        ttl_secs: 1,
+        passthrough_patterns: Vec::new(),
+        journal_path: None,
+        inode_cache: &InodeCacheConfig::default(),
+    };
+    let (mutations, mut rx) = match overlayfs.mount(&request) {
+        Ok(mounted) => mounted,
+        Err(e) => {
+            eprintln!("Skipping: overlayfs unavailable: {}", e);
+            std::env::remove_var("TREEBEARD_DATA_DIR");
+            return;
+        }
+    };
+
+    let merged = run_in_mount(
+        &mount_point,
+        "echo upper > edit.txt && echo new > new.txt && rm delete.txt && cat edit.txt && ls",
+    )
+    .await;
+    assert_eq!(merged, "upper\nedit.txt\nnew.txt\n");
+
+    // Mutations arrive from the upper layer watcher asynchronously
+    for _ in 0..50 {
+        if mutations.read().len() == 3 {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    {
+        let mutations = mutations.read();
+        assert_eq!(
+            mutations.get(Path::new("./edit.txt")),
+            Some(&MutationType::CopiedUp)
+        );
+        assert_eq!(
+            mutations.get(Path::new("./new.txt")),
+            Some(&MutationType::Created)
+        );
+        assert_eq!(
+            mutations.get(Path::new("./delete.txt")),
+            Some(&MutationType::Deleted)
+        );
+    }
+
+    // Writes land in the upper layer and leave the lower layer untouched
+    assert_eq!(fs::read_to_string(upper.join("new.txt")).unwrap(), "new\n");
+    assert_eq!(fs::read_to_string(lower.join("edit.txt")).unwrap(), "lower");
+    assert!(lower.join("delete.txt").exists());
+    assert!(Whiteout::is_kernel_whiteout(&upper.join("delete.txt")));
+
+    // The mount lives in a private namespace, so it's invisible from here
+    assert!(!mount_point.join("new.txt").exists());
+
+    let cleanup = perform_fuse_cleanup(&mount_point);
+    assert!(cleanup.unmount_succeeded);
+    assert!(cleanup.directory_removed);
+    let closed = timeout(Duration::from_secs(5), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await;
+    assert!(closed.is_ok(), "Mutation channel should close on unmount");
+    assert!(!overlayfs.owns_mount(&mount_point));
+    assert!(!temp_dir
+        .path()
+        .join("worktrees/repo/.branch.overlayfs-work")
+        .exists());
+
+    std::env::remove_var("TREEBEARD_DATA_DIR");
+}
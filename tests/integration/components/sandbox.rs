@@ -33,17 +33,17 @@ fn test_expand_tilde_relative_path() {
 }
 
 #[test]
-fn test_sandbox_config_default_enabled_on_macos() {
+fn test_sandbox_config_default_enabled_on_macos_and_linux() {
     let config = SandboxConfig::default();
-    #[cfg(target_os = "macos")]
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
     assert!(
         config.enabled,
-        "Sandbox should be enabled by default on macOS"
+        "Sandbox should be enabled by default on macOS and Linux"
     );
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
     assert!(
         !config.enabled,
-        "Sandbox should be disabled by default on non-macOS"
+        "Sandbox should be disabled by default on other platforms"
     );
 }
 
@@ -98,7 +98,7 @@ fn test_config_includes_sandbox_defaults() {
     let config = load_config().expect("Failed to load config");
 
     // Sandbox config should have defaults
-    #[cfg(target_os = "macos")]
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
     assert!(config.sandbox.enabled);
     assert!(!config.sandbox.deny_read.is_empty());
     assert!(config.sandbox.allow_write.is_empty());
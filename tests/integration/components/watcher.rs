@@ -2,11 +2,11 @@ use crate::shared::common::create_test_repo;
 
 use std::fs;
 use std::path::PathBuf;
-use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::time::{sleep, Duration};
 use treebeard::git::GitRepo;
+use treebeard::watcher::AutoCommitFailures;
 
 /// Test that the watcher commits when it receives mutation signals via channel
 #[tokio::test]
@@ -31,7 +31,7 @@ async fn test_watcher_commits_on_channel_signal() {
 
     let worktree_repo = GitRepo::from_path(&worktree_path).expect("Failed to get worktree repo");
 
-    let failure_count = Arc::new(AtomicUsize::new(0));
+    let failure_count = Arc::new(AutoCommitFailures::default());
 
     // Spawn the watcher task
     // 200ms debounce is short enough for fast tests but long enough to batch signals
@@ -109,7 +109,7 @@ async fn test_watcher_debounces_rapid_signals() {
 
     let worktree_repo = GitRepo::from_path(&worktree_path).expect("Failed to get worktree repo");
 
-    let failure_count = Arc::new(AtomicUsize::new(0));
+    let failure_count = Arc::new(AutoCommitFailures::default());
 
     // Spawn the watcher task
     // 300ms debounce: longer than inter-signal delays (50ms) to test batching
@@ -187,7 +187,7 @@ async fn test_watcher_handles_channel_closure() {
 
     let worktree_repo = GitRepo::from_path(&worktree_path).expect("Failed to get worktree repo");
 
-    let failure_count = Arc::new(AtomicUsize::new(0));
+    let failure_count = Arc::new(AutoCommitFailures::default());
 
     // Spawn the watcher task
     let watcher_handle = tokio::spawn(async move {
@@ -3,7 +3,7 @@ use crate::shared::common::TestConfigContext;
 use std::fs;
 use std::path::Path;
 use tempfile::TempDir;
-use treebeard::load_config;
+use treebeard::{load_config, SyncPolicy};
 
 /// Test that glob pattern matching works correctly with ./ prefix stripping.
 /// Exact-match patterns like ".env" need prefix stripping to match paths
@@ -203,3 +203,29 @@ fn test_config_sync_always_include_default() {
         "sync_always_include should default to empty"
     );
 }
+
+/// Test that sync_policy defaults to prompt and parses from TOML
+#[test]
+fn test_config_sync_policy() {
+    let _ctx = TestConfigContext::new();
+
+    let config = load_config().expect("Failed to load config");
+    assert_eq!(config.sync.get_sync_policy(), SyncPolicy::Prompt);
+    assert!(config.sync.get_sync_report_path().is_none());
+
+    let parsed: treebeard::Config = toml::from_str(
+        r#"
+[sync]
+sync_policy = "patterns"
+sync_report = "/tmp/treebeard-sync.json"
+"#,
+    )
+    .expect("Failed to parse config TOML");
+    assert_eq!(parsed.sync.get_sync_policy(), SyncPolicy::Patterns);
+    assert_eq!(
+        parsed.sync.get_sync_report_path(),
+        Some(std::path::PathBuf::from("/tmp/treebeard-sync.json"))
+    );
+
+    assert!(toml::from_str::<treebeard::Config>("[sync]\nsync_policy = \"some\"\n").is_err());
+}
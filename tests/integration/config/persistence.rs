@@ -1,6 +1,6 @@
 use crate::shared::common::TestConfigContext;
 
-use treebeard::{load_config, save_config};
+use treebeard::{load_config, save_config, OverlayBackendKind};
 
 /// Test that save_config persists sync_always_skip patterns correctly
 #[test]
@@ -150,3 +150,57 @@ fn test_config_round_trip_with_sync_fields() {
         config.sync.get_sync_always_include()
     );
 }
+
+/// Test that the overlay backend defaults to FUSE and can be set from TOML
+#[test]
+fn test_config_parses_overlay_backend_from_toml() {
+    let parsed: treebeard::Config = toml::from_str("").expect("Failed to parse empty config");
+    assert_eq!(parsed.get_overlay_backend(), OverlayBackendKind::Fuse);
+
+    let parsed: treebeard::Config =
+        toml::from_str("overlay_backend = \"overlayfs\"\n").expect("Failed to parse config TOML");
+    assert_eq!(parsed.get_overlay_backend(), OverlayBackendKind::Overlayfs);
+
+    assert!(toml::from_str::<treebeard::Config>("overlay_backend = \"aufs\"\n").is_err());
+}
+
+/// Test that the inode cache section parses and falls back to its defaults
+#[test]
+fn test_config_parses_inode_cache_from_toml() {
+    let parsed: treebeard::Config = toml::from_str("").expect("Failed to parse empty config");
+    assert_eq!(parsed.inode_cache.get_capacity(), 10000);
+    assert!(parsed.inode_cache.get_pin_referenced());
+
+    let parsed: treebeard::Config =
+        toml::from_str("[inode_cache]\ncapacity = 250000\npin_referenced = false\n")
+            .expect("Failed to parse config TOML");
+    assert_eq!(parsed.inode_cache.get_capacity(), 250000);
+    assert!(!parsed.inode_cache.get_pin_referenced());
+}
+
+/// Test that commit identities parse from TOML and round-trip
+#[test]
+fn test_config_parses_commit_identity_from_toml() {
+    let parsed: treebeard::Config = toml::from_str("").expect("Failed to parse empty config");
+    assert!(parsed.commit.author.is_none());
+    assert!(parsed.commit.get_trailers());
+
+    let parsed: treebeard::Config = toml::from_str(
+        "[commit]\nauthor = \"agent <agent@local>\"\ncommitter = \"Jo Dev <jo@example.com>\"\ntrailers = false\n",
+    )
+    .expect("Failed to parse config TOML");
+    let author = parsed.commit.author.clone().unwrap();
+    assert_eq!(author.name, "agent");
+    assert_eq!(author.email, "agent@local");
+    assert_eq!(
+        parsed.commit.committer.as_ref().unwrap().to_string(),
+        "Jo Dev <jo@example.com>"
+    );
+    assert!(!parsed.commit.get_trailers());
+
+    let serialized = toml::to_string(&parsed).expect("Failed to serialize config");
+    assert!(serialized.contains("author = \"agent <agent@local>\""));
+
+    assert!(toml::from_str::<treebeard::Config>("[commit]\nauthor = \"agent\"\n").is_err());
+    assert!(toml::from_str::<treebeard::Config>("[commit]\nauthor = \"<a@b>\"\n").is_err());
+}
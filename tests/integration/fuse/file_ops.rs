@@ -0,0 +1,253 @@
+#![cfg(target_os = "macos")]
+
+use crate::shared::fuse_helpers::FuseTestSession;
+
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+fn c_path(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).unwrap()
+}
+
+/// statfs reports the upper layer's filesystem instead of zeroes
+#[test]
+fn test_statfs_reports_real_usage() {
+    let Some(session) = FuseTestSession::new("statfs") else {
+        return;
+    };
+
+    let path = c_path(&session.mountpoint);
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::statvfs(path.as_ptr(), &mut stat) }, 0);
+
+    assert!(stat.f_blocks > 0, "statfs reported no blocks");
+    assert!(stat.f_bsize > 0, "statfs reported no block size");
+    assert!(stat.f_bavail <= stat.f_blocks);
+}
+
+/// access checks the backing file's mode, and checking doesn't copy up
+#[test]
+fn test_access_checks_file_mode() {
+    let Some(session) = FuseTestSession::with_lower_layer_setup("access", |lower| {
+        fs::write(lower.join("data.txt"), "data").unwrap();
+        fs::set_permissions(lower.join("data.txt"), fs::Permissions::from_mode(0o644)).unwrap();
+        fs::write(lower.join("run.sh"), "#!/bin/sh").unwrap();
+        fs::set_permissions(lower.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    }) else {
+        return;
+    };
+
+    let access = |name: &str, mask: libc::c_int| {
+        let path = c_path(&session.mountpoint.join(name));
+        unsafe { libc::access(path.as_ptr(), mask) == 0 }
+    };
+
+    assert!(access("data.txt", libc::R_OK | libc::W_OK));
+    assert!(!access("data.txt", libc::X_OK));
+    assert!(access("run.sh", libc::X_OK));
+    assert!(!access("missing.txt", libc::F_OK));
+
+    assert!(
+        !session.upper_layer.join("data.txt").exists(),
+        "access(W_OK) shouldn't copy the file up"
+    );
+}
+
+/// fsync and fsyncdir succeed for files and directories written through the mount
+#[test]
+fn test_fsync_file_and_directory() {
+    let Some(session) = FuseTestSession::with_lower_layer_setup("fsync", |lower| {
+        fs::create_dir(lower.join("db")).unwrap();
+        fs::write(lower.join("db/app.db"), "data").unwrap();
+    }) else {
+        return;
+    };
+
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(session.mountpoint.join("db/app.db"))
+        .unwrap();
+    file.write_all(b" more").unwrap();
+    file.sync_data().expect("fdatasync failed");
+    file.sync_all().expect("fsync failed");
+
+    File::open(session.mountpoint.join("db"))
+        .unwrap()
+        .sync_all()
+        .expect("fsync on directory failed");
+
+    assert_eq!(
+        fs::read_to_string(session.upper_layer.join("db/app.db")).unwrap(),
+        "data more"
+    );
+    assert_eq!(
+        fs::read_to_string(session.lower_layer.join("db/app.db")).unwrap(),
+        "data"
+    );
+}
+
+/// Preallocating space in a lower-layer file copies it up first
+#[test]
+fn test_fallocate_copies_up() {
+    let Some(session) = FuseTestSession::with_lower_layer_setup("fallocate", |lower| {
+        fs::write(lower.join("data.bin"), "data").unwrap();
+    }) else {
+        return;
+    };
+
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(session.mountpoint.join("data.bin"))
+        .unwrap();
+    let mut store = libc::fstore_t {
+        fst_flags: libc::F_ALLOCATEALL,
+        fst_posmode: libc::F_PEOFPOSMODE,
+        fst_offset: 0,
+        fst_length: 1024 * 1024,
+        fst_bytesalloc: 0,
+    };
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) } == -1 {
+        let e = std::io::Error::last_os_error();
+        eprintln!(
+            "Skipping - F_PREALLOCATE not forwarded by this macFUSE: {}",
+            e
+        );
+        return;
+    }
+
+    assert!(session.upper_layer.join("data.bin").exists());
+    assert_eq!(
+        fs::read(session.lower_layer.join("data.bin")).unwrap(),
+        b"data"
+    );
+    // Preallocation doesn't change the size
+    assert_eq!(file.metadata().unwrap().len(), 4);
+}
+
+/// SEEK_DATA and SEEK_HOLE find the data and trailing hole of a file
+#[test]
+fn test_lseek_data_and_hole() {
+    let Some(session) = FuseTestSession::with_lower_layer_setup("lseek", |lower| {
+        fs::write(lower.join("data.bin"), vec![1u8; 8192]).unwrap();
+    }) else {
+        return;
+    };
+
+    let file = File::open(session.mountpoint.join("data.bin")).unwrap();
+    let data = unsafe { libc::lseek(file.as_raw_fd(), 100, libc::SEEK_DATA) };
+    if data == -1 {
+        let e = std::io::Error::last_os_error();
+        eprintln!("Skipping - SEEK_DATA not supported through macFUSE: {}", e);
+        return;
+    }
+    assert_eq!(data, 100);
+
+    let hole = unsafe { libc::lseek(file.as_raw_fd(), 0, libc::SEEK_HOLE) };
+    assert_eq!(hole, 8192, "the only hole in a dense file is at its end");
+}
+
+/// Copying onto a lower-layer file copies up the destination but not the source.
+///
+/// macOS has no `copy_file_range()`, so this goes through reads and writes;
+/// the unit tests in `overlay::file_ops` cover the Linux request.
+#[test]
+fn test_copy_into_lower_file_copies_up_destination() {
+    let Some(session) = FuseTestSession::with_lower_layer_setup("copy-range", |lower| {
+        fs::write(lower.join("src.txt"), "new content").unwrap();
+        fs::write(lower.join("dest.txt"), "old content").unwrap();
+    }) else {
+        return;
+    };
+
+    fs::copy(
+        session.mountpoint.join("src.txt"),
+        session.mountpoint.join("dest.txt"),
+    )
+    .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(session.mountpoint.join("dest.txt")).unwrap(),
+        "new content"
+    );
+    assert_eq!(
+        fs::read_to_string(session.lower_layer.join("dest.txt")).unwrap(),
+        "old content"
+    );
+    assert!(session.upper_layer.join("dest.txt").exists());
+    assert!(!session.upper_layer.join("src.txt").exists());
+}
+
+/// FIFOs created through the mount land in the upper layer and carry data
+#[test]
+fn test_mkfifo_in_upper_layer() {
+    let Some(session) = FuseTestSession::new("mkfifo") else {
+        return;
+    };
+
+    let fifo = session.mountpoint.join("events");
+    assert_eq!(unsafe { libc::mkfifo(c_path(&fifo).as_ptr(), 0o600) }, 0);
+
+    assert!(fs::metadata(&fifo).unwrap().file_type().is_fifo());
+    assert!(fs::symlink_metadata(session.upper_layer.join("events"))
+        .unwrap()
+        .file_type()
+        .is_fifo());
+    assert!(!session.lower_layer.join("events").exists());
+
+    let writer_path = fifo.clone();
+    let writer = std::thread::spawn(move || {
+        fs::OpenOptions::new()
+            .write(true)
+            .open(writer_path)
+            .unwrap()
+            .write_all(b"ready")
+            .unwrap();
+    });
+    let mut received = String::new();
+    File::open(&fifo)
+        .unwrap()
+        .read_to_string(&mut received)
+        .unwrap();
+    writer.join().unwrap();
+    assert_eq!(received, "ready");
+}
+
+/// Dev servers can listen on Unix sockets inside the mount
+#[test]
+fn test_unix_socket_in_mount() {
+    let Some(session) = FuseTestSession::new("mksock") else {
+        return;
+    };
+
+    let socket = session.mountpoint.join("dev.sock");
+    let listener = match UnixListener::bind(&socket) {
+        Ok(l) => l,
+        // sun_path only holds about 100 bytes on macOS
+        Err(e) if e.raw_os_error() == Some(libc::ENAMETOOLONG) => {
+            eprintln!("Skipping - mount point path too long for a socket: {}", e);
+            return;
+        }
+        Err(e) => panic!("Failed to bind socket in mount: {}", e),
+    };
+    assert!(fs::metadata(&socket).unwrap().file_type().is_socket());
+    assert!(session.upper_layer.join("dev.sock").exists());
+
+    let client = std::thread::spawn(move || {
+        UnixStream::connect(&socket)
+            .unwrap()
+            .write_all(b"ping")
+            .unwrap();
+    });
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut received = String::new();
+    stream.read_to_string(&mut received).unwrap();
+    client.join().unwrap();
+    assert_eq!(received, "ping");
+}
@@ -121,3 +121,88 @@ fn test_fuse_toctou_copy_up_source_deleted() {
 
     eprintln!("✓ TOCTOU copy_up handling test completed");
 }
+
+/// Walks a 200k-file tree through a mount whose inode cache holds far fewer
+/// inodes, while keeping one file open. The open file's inode is still
+/// referenced by the kernel, so it must survive every eviction.
+///
+/// Ignored by default because creating and walking the tree takes a while:
+///
+/// ```sh
+/// cargo test --test integration inode_cache_walk -- --ignored --nocapture
+/// ```
+#[test]
+#[ignore]
+fn test_fuse_real_inode_cache_walk_of_200k_files() {
+    use crate::shared::fuse_helpers::{
+        check_macfuse_installed, determine_mount_point, MountCleanup,
+    };
+    use fuser::Session;
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+    use treebeard::overlay::{OverlayFs, TreebeardFs};
+    use treebeard::InodeCacheConfig;
+
+    const DIRS: usize = 200;
+    const FILES_PER_DIR: usize = 1000;
+
+    if !check_macfuse_installed() {
+        eprintln!("Skipping real FUSE test - macFUSE not installed");
+        return;
+    }
+
+    let lower_dir = tempfile::tempdir().unwrap();
+    let upper_dir = tempfile::tempdir().unwrap();
+    for d in 0..DIRS {
+        let dir = lower_dir.path().join(format!("dir{}", d));
+        fs::create_dir(&dir).unwrap();
+        for f in 0..FILES_PER_DIR {
+            fs::write(dir.join(format!("file{}", f)), format!("{}/{}", d, f)).unwrap();
+        }
+    }
+
+    let overlay = OverlayFs::new(
+        upper_dir.path().to_path_buf(),
+        lower_dir.path().to_path_buf(),
+        None,
+        0,
+        vec![],
+    )
+    .unwrap()
+    .with_inode_cache(&InodeCacheConfig {
+        capacity: Some(1000),
+        pin_referenced: Some(true),
+    });
+
+    let mountpoint = determine_mount_point("inode-cache-walk").unwrap();
+    let _cleanup = MountCleanup::new(mountpoint.clone());
+    let _handle = Session::new(TreebeardFs::from(overlay), &mountpoint, &[])
+        .unwrap()
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(500));
+
+    let mut held = fs::File::open(mountpoint.join("dir0").join("file0")).unwrap();
+
+    let started = Instant::now();
+    let mut files = 0;
+    for entry in walkdir::WalkDir::new(&mountpoint) {
+        let entry = entry.unwrap();
+        entry.metadata().unwrap();
+        if entry.file_type().is_file() {
+            files += 1;
+        }
+    }
+    eprintln!("Walked {} files in {:?}", files, started.elapsed());
+    assert_eq!(files, DIRS * FILES_PER_DIR);
+
+    // The open file's inode must still resolve after all the evictions
+    held.metadata().unwrap();
+    let mut content = String::new();
+    held.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "0/0");
+    assert_eq!(
+        fs::read_to_string(mountpoint.join("dir199").join("file999")).unwrap(),
+        "199/999"
+    );
+}
@@ -1,7 +1,9 @@
 mod cow;
+mod file_ops;
 mod inodes;
 mod lookup;
 mod mount;
 mod passthrough;
 mod real_ops;
+mod throughput;
 mod whiteouts;
@@ -1653,3 +1653,219 @@ fn test_inode_consistency_multiple_operations() {
     drop(handle);
     eprintln!("Multiple operations sequence test completed");
 }
+
+/// Rename replaces an existing destination
+///
+/// Editors and build tools save atomically by writing a temp file and
+/// renaming it over the target. The destination must be replaced whether it
+/// lives in the lower layer, the upper layer, or was deleted earlier.
+#[test]
+fn test_rename_over_existing_file() {
+    if !check_macfuse_installed() {
+        eprintln!("Skipping real FUSE test - macFUSE not installed");
+        return;
+    }
+
+    let test_name = "rename-over";
+    let mountpoint = match determine_mount_point(test_name) {
+        Ok(mp) => mp,
+        Err(e) => {
+            eprintln!("Failed to determine mount point: {}", e);
+            return;
+        }
+    };
+
+    let upper_layer = tempfile::tempdir().unwrap().keep();
+    let lower_layer = tempfile::tempdir().unwrap().keep();
+
+    fs::write(lower_layer.join("config.json"), "lower config").unwrap();
+    fs::write(lower_layer.join("moved.txt"), "moved from lower").unwrap();
+    fs::write(lower_layer.join("deleted.txt"), "deleted in session").unwrap();
+    fs::write(upper_layer.join("target.txt"), "upper target").unwrap();
+
+    let fs_instance =
+        match TreebeardFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("Failed to create TreebeardFs: {}", e);
+                return;
+            }
+        };
+
+    let _cleanup = MountCleanup::new(mountpoint.clone());
+    let session = match Session::new(fs_instance, &mountpoint, &[]) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to create FUSE session: {}", e);
+            return;
+        }
+    };
+
+    let handle = match session.spawn() {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("Failed to spawn FUSE session: {}", e);
+            return;
+        }
+    };
+
+    thread::sleep(Duration::from_millis(500));
+
+    // Atomic save over a lower-layer file
+    fs::write(mountpoint.join(".config.json.tmp"), "saved config").unwrap();
+    fs::rename(
+        mountpoint.join(".config.json.tmp"),
+        mountpoint.join("config.json"),
+    )
+    .expect("REGRESSION: rename over a lower-layer file failed");
+    assert_eq!(
+        fs::read_to_string(mountpoint.join("config.json")).unwrap(),
+        "saved config"
+    );
+    assert!(!mountpoint.join(".config.json.tmp").exists());
+    assert_eq!(
+        fs::read_to_string(lower_layer.join("config.json")).unwrap(),
+        "lower config",
+        "Lower layer must not be modified"
+    );
+    eprintln!("✓ Temp file renamed over lower-layer file");
+
+    // Lower-layer file renamed over an upper-layer file
+    fs::rename(mountpoint.join("moved.txt"), mountpoint.join("target.txt"))
+        .expect("REGRESSION: rename over an upper-layer file failed");
+    assert_eq!(
+        fs::read_to_string(mountpoint.join("target.txt")).unwrap(),
+        "moved from lower"
+    );
+    assert!(
+        !mountpoint.join("moved.txt").exists(),
+        "REGRESSION: old name of a renamed lower-layer file is still visible"
+    );
+    let entries: Vec<String> = fs::read_dir(&mountpoint)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    assert!(!entries.contains(&"moved.txt".to_string()));
+    eprintln!("✓ Lower-layer file renamed over upper-layer file");
+
+    // Rename onto a name deleted earlier in the session
+    fs::remove_file(mountpoint.join("deleted.txt")).unwrap();
+    fs::write(mountpoint.join("new.txt"), "recreated").unwrap();
+    fs::rename(mountpoint.join("new.txt"), mountpoint.join("deleted.txt"))
+        .expect("REGRESSION: rename onto a whited-out name failed");
+    assert_eq!(
+        fs::read_to_string(mountpoint.join("deleted.txt")).unwrap(),
+        "recreated"
+    );
+    eprintln!("✓ File renamed onto whited-out name");
+
+    drop(handle);
+    eprintln!("✓ rename replaces existing destinations");
+}
+
+/// Rename honours RENAME_EXCL and RENAME_SWAP
+///
+/// These are the `renamex_np()` equivalents of Linux's `RENAME_NOREPLACE`
+/// and `RENAME_EXCHANGE`. Older macFUSE versions don't pass `RENAME_SWAP`
+/// to the filesystem, so that half is skipped when it is unsupported.
+#[test]
+fn test_rename_exclusive_and_swap() {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    if !check_macfuse_installed() {
+        eprintln!("Skipping real FUSE test - macFUSE not installed");
+        return;
+    }
+
+    let test_name = "rename-flags";
+    let mountpoint = match determine_mount_point(test_name) {
+        Ok(mp) => mp,
+        Err(e) => {
+            eprintln!("Failed to determine mount point: {}", e);
+            return;
+        }
+    };
+
+    let upper_layer = tempfile::tempdir().unwrap().keep();
+    let lower_layer = tempfile::tempdir().unwrap().keep();
+
+    fs::write(lower_layer.join("a.txt"), "A").unwrap();
+    fs::write(upper_layer.join("b.txt"), "B").unwrap();
+
+    let fs_instance =
+        match TreebeardFs::new(upper_layer.clone(), lower_layer.clone(), None, 1, vec![]) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("Failed to create TreebeardFs: {}", e);
+                return;
+            }
+        };
+
+    let _cleanup = MountCleanup::new(mountpoint.clone());
+    let session = match Session::new(fs_instance, &mountpoint, &[]) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to create FUSE session: {}", e);
+            return;
+        }
+    };
+
+    let handle = match session.spawn() {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("Failed to spawn FUSE session: {}", e);
+            return;
+        }
+    };
+
+    thread::sleep(Duration::from_millis(500));
+
+    let renamex = |from: &str, to: &str, flags: libc::c_uint| -> std::io::Result<()> {
+        let from = CString::new(mountpoint.join(from).as_os_str().as_bytes()).unwrap();
+        let to = CString::new(mountpoint.join(to).as_os_str().as_bytes()).unwrap();
+        // SAFETY: Both paths are null-terminated CStrings that outlive the call.
+        if unsafe { libc::renamex_np(from.as_ptr(), to.as_ptr(), flags) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    };
+
+    match renamex("b.txt", "a.txt", libc::RENAME_EXCL) {
+        Err(e) => assert_eq!(
+            e.raw_os_error(),
+            Some(libc::EEXIST),
+            "RENAME_EXCL over an existing file should fail with EEXIST"
+        ),
+        Ok(()) => panic!("REGRESSION: RENAME_EXCL replaced an existing file"),
+    }
+    assert_eq!(fs::read_to_string(mountpoint.join("a.txt")).unwrap(), "A");
+    assert_eq!(fs::read_to_string(mountpoint.join("b.txt")).unwrap(), "B");
+    eprintln!("✓ RENAME_EXCL refuses to replace");
+
+    renamex("b.txt", "c.txt", libc::RENAME_EXCL).expect("RENAME_EXCL to a new name failed");
+    assert_eq!(fs::read_to_string(mountpoint.join("c.txt")).unwrap(), "B");
+    eprintln!("✓ RENAME_EXCL renames to a free name");
+
+    match renamex("a.txt", "c.txt", libc::RENAME_SWAP) {
+        Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => {
+            eprintln!("Skipping RENAME_SWAP - this macFUSE version doesn't pass rename flags");
+            drop(handle);
+            return;
+        }
+        result => result.expect("RENAME_SWAP failed"),
+    }
+    assert_eq!(fs::read_to_string(mountpoint.join("a.txt")).unwrap(), "B");
+    assert_eq!(fs::read_to_string(mountpoint.join("c.txt")).unwrap(), "A");
+    assert_eq!(
+        fs::read_to_string(lower_layer.join("a.txt")).unwrap(),
+        "A",
+        "Lower layer must not be modified"
+    );
+    eprintln!("✓ RENAME_SWAP exchanges both names");
+
+    drop(handle);
+    eprintln!("✓ rename honours RENAME_EXCL and RENAME_SWAP");
+}
@@ -0,0 +1,221 @@
+#![cfg(target_os = "macos")]
+
+//! Throughput benchmarks for concurrent requests through a real mount.
+//!
+//! Each benchmark mounts the same lower layer with an increasing number of
+//! worker threads and drives it from several client threads. They're ignored
+//! by default because they take a while and the numbers depend on the machine:
+//!
+//! ```sh
+//! cargo test --test integration throughput -- --ignored --nocapture
+//! ```
+
+use crate::shared::fuse_helpers::{check_macfuse_installed, determine_mount_point, MountCleanup};
+
+use fuser::{BackgroundSession, Session};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use treebeard::overlay::TreebeardFs;
+
+const WORKER_COUNTS: [usize; 4] = [1, 2, 4, 8];
+const CLIENT_THREADS: usize = 8;
+const DIRS: usize = 8;
+const FILES_PER_DIR: usize = 32;
+const FILE_SIZE: usize = 512 * 1024;
+const LOOKUP_ROUNDS: usize = 20;
+
+/// A mounted overlay that is unmounted on drop.
+struct BenchMount {
+    mountpoint: PathBuf,
+    _handle: BackgroundSession,
+    _cleanup: MountCleanup,
+    _upper_dir: tempfile::TempDir,
+}
+
+/// Mounts `lower_layer` with `worker_threads` workers and a zero TTL, so every
+/// stat goes to the filesystem instead of the kernel's attribute cache.
+fn mount(test_name: &str, lower_layer: &Path, worker_threads: usize) -> Option<BenchMount> {
+    let mountpoint = match determine_mount_point(&format!("{}-{}", test_name, worker_threads)) {
+        Ok(mp) => mp,
+        Err(e) => {
+            eprintln!("Failed to determine mount point: {}", e);
+            return None;
+        }
+    };
+    let upper_dir = tempfile::tempdir().ok()?;
+
+    let fs_instance = match TreebeardFs::new(
+        upper_dir.path().to_path_buf(),
+        lower_layer.to_path_buf(),
+        None,
+        0,
+        vec![],
+    ) {
+        Ok(f) => f.with_worker_threads(worker_threads),
+        Err(e) => {
+            eprintln!("Failed to create TreebeardFs: {}", e);
+            return None;
+        }
+    };
+
+    let cleanup = MountCleanup::new(mountpoint.clone());
+    let session = match Session::new(fs_instance, &mountpoint, &[]) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to create FUSE session: {}", e);
+            return None;
+        }
+    };
+    let handle = match session.spawn() {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("Failed to spawn FUSE session: {}", e);
+            return None;
+        }
+    };
+
+    // Give filesystem time to mount
+    thread::sleep(Duration::from_millis(500));
+
+    Some(BenchMount {
+        mountpoint,
+        _handle: handle,
+        _cleanup: cleanup,
+        _upper_dir: upper_dir,
+    })
+}
+
+/// Fills the lower layer with `DIRS` directories of `FILES_PER_DIR` files.
+fn populate_lower_layer(lower_layer: &Path) -> Vec<PathBuf> {
+    let content: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    let mut files = Vec::new();
+    for d in 0..DIRS {
+        let dir = PathBuf::from(format!("dir{}", d));
+        fs::create_dir_all(lower_layer.join(&dir)).unwrap();
+        for f in 0..FILES_PER_DIR {
+            let file = dir.join(format!("file{}.bin", f));
+            fs::write(lower_layer.join(&file), &content).unwrap();
+            files.push(file);
+        }
+    }
+    files
+}
+
+/// Reads every file straight from the lower layer, so each run finds them in
+/// the host's page cache and the first run isn't the only one reading from
+/// disk.
+fn warm_page_cache(lower_layer: &Path, files: &[PathBuf]) {
+    let mut buf = Vec::with_capacity(FILE_SIZE);
+    for file in files {
+        buf.clear();
+        fs::File::open(lower_layer.join(file))
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+    }
+}
+
+/// Splits `files` between `CLIENT_THREADS` threads that each run `op` on
+/// their share, and returns the total number of operations and elapsed time.
+fn run_clients<F>(mountpoint: &Path, files: &[PathBuf], op: F) -> (usize, Duration)
+where
+    F: Fn(&Path) -> usize + Send + Sync + 'static,
+{
+    let op = Arc::new(op);
+    let start = Instant::now();
+    let clients: Vec<_> = (0..CLIENT_THREADS)
+        .map(|client| {
+            let op = Arc::clone(&op);
+            let paths: Vec<PathBuf> = files
+                .iter()
+                .skip(client)
+                .step_by(CLIENT_THREADS)
+                .map(|file| mountpoint.join(file))
+                .collect();
+            thread::spawn(move || paths.iter().map(|path| op(path)).sum::<usize>())
+        })
+        .collect();
+    let ops = clients.into_iter().map(|c| c.join().unwrap()).sum();
+    (ops, start.elapsed())
+}
+
+fn print_results(label: &str, unit: &str, results: &[(usize, f64)]) {
+    let baseline = results[0].1;
+    eprintln!("\n{} ({} client threads)", label, CLIENT_THREADS);
+    eprintln!("{:>8} {:>14} {:>8}", "workers", unit, "speedup");
+    for (workers, rate) in results {
+        eprintln!("{:>8} {:>14.1} {:>7.2}x", workers, rate, rate / baseline);
+    }
+}
+
+/// Concurrent reads of distinct lower-layer files scale with worker threads
+#[test]
+#[ignore = "benchmark; run with --ignored --nocapture"]
+fn bench_concurrent_reads() {
+    if !check_macfuse_installed() {
+        eprintln!("Skipping real FUSE test - macFUSE not installed");
+        return;
+    }
+
+    let lower_dir = tempfile::tempdir().unwrap();
+    let files = populate_lower_layer(lower_dir.path());
+
+    let mut results = Vec::new();
+    for workers in WORKER_COUNTS {
+        // A fresh mount per run, so no run reads from the previous one's
+        // cache of the mount. The lower files stay in the host's page cache
+        // across runs, so every run starts with them there.
+        let Some(mount) = mount("bench-reads", lower_dir.path(), workers) else {
+            return;
+        };
+        warm_page_cache(lower_dir.path(), &files);
+
+        let (bytes, elapsed) = run_clients(&mount.mountpoint, &files, |path| {
+            let mut buf = Vec::with_capacity(FILE_SIZE);
+            fs::File::open(path).unwrap().read_to_end(&mut buf).unwrap();
+            assert_eq!(buf.len(), FILE_SIZE);
+            buf.len()
+        });
+        results.push((
+            workers,
+            bytes as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0),
+        ));
+    }
+
+    print_results("Concurrent reads", "MiB/s", &results);
+}
+
+/// Concurrent lookups of distinct paths scale with worker threads
+#[test]
+#[ignore = "benchmark; run with --ignored --nocapture"]
+fn bench_concurrent_lookups() {
+    if !check_macfuse_installed() {
+        eprintln!("Skipping real FUSE test - macFUSE not installed");
+        return;
+    }
+
+    let lower_dir = tempfile::tempdir().unwrap();
+    let files = populate_lower_layer(lower_dir.path());
+
+    let mut results = Vec::new();
+    for workers in WORKER_COUNTS {
+        let Some(mount) = mount("bench-lookups", lower_dir.path(), workers) else {
+            return;
+        };
+        warm_page_cache(lower_dir.path(), &files);
+
+        let (lookups, elapsed) = run_clients(&mount.mountpoint, &files, |path| {
+            for _ in 0..LOOKUP_ROUNDS {
+                assert!(fs::metadata(path).unwrap().is_file());
+            }
+            LOOKUP_ROUNDS
+        });
+        results.push((workers, lookups as f64 / elapsed.as_secs_f64()));
+    }
+
+    print_results("Concurrent lookups", "lookups/s", &results);
+}
@@ -1,7 +1,8 @@
 #![cfg(target_os = "macos")]
 
 use crate::shared::fuse_helpers::{
-    check_macfuse_installed, determine_mount_point, MountCleanup, TEST_SETUP_DELAY_MS,
+    check_macfuse_installed, determine_mount_point, FuseTestSession, MountCleanup,
+    TEST_SETUP_DELAY_MS,
 };
 
 use fuser::Session;
@@ -282,3 +283,66 @@ fn test_diagnostic_deletion_scenarios() {
 
     drop(handle);
 }
+
+/// `rm -rf build && mkdir build` leaves an empty directory instead of merging
+/// the lower layer's old contents back in
+#[test]
+fn test_recreated_directory_is_opaque() {
+    let Some(session) = FuseTestSession::with_lower_layer_setup("opaque-dir", |lower| {
+        fs::create_dir_all(lower.join("build/obj")).unwrap();
+        fs::write(lower.join("build/app"), "old binary").unwrap();
+        fs::write(lower.join("build/obj/main.o"), "old object").unwrap();
+    }) else {
+        return;
+    };
+
+    let build = session.mountpoint.join("build");
+    // Modified in the session, so it's been copied up when removed
+    fs::write(build.join("app"), "new binary").unwrap();
+
+    // Twice, like successive clean builds
+    for round in 0..2 {
+        fs::remove_dir_all(&build).unwrap();
+        assert!(!build.exists(), "build still visible after rm -rf");
+
+        fs::create_dir(&build).unwrap();
+        let entries: Vec<_> = fs::read_dir(&build)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert!(
+            entries.is_empty(),
+            "recreated build/ shows {:?} in round {}",
+            entries,
+            round
+        );
+        assert!(!build.join("app").exists());
+        assert!(!build.join("obj/main.o").exists());
+
+        fs::write(build.join("app"), "rebuilt").unwrap();
+        assert_eq!(fs::read_to_string(build.join("app")).unwrap(), "rebuilt");
+    }
+
+    assert!(session.upper_layer.join("build/.wh..wh..opq").exists());
+    assert!(!session.upper_layer.join(".wh.build").exists());
+    assert_eq!(
+        fs::read_to_string(session.lower_layer.join("build/app")).unwrap(),
+        "old binary"
+    );
+    assert!(session.lower_layer.join("build/obj/main.o").exists());
+}
+
+/// rmdir of a directory whose lower-layer entries are still visible fails
+#[test]
+fn test_rmdir_non_empty_merged_directory() {
+    let Some(session) = FuseTestSession::with_lower_layer_setup("rmdir-not-empty", |lower| {
+        fs::create_dir(lower.join("src")).unwrap();
+        fs::write(lower.join("src/lib.rs"), "").unwrap();
+    }) else {
+        return;
+    };
+
+    let err = fs::remove_dir(session.mountpoint.join("src")).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::ENOTEMPTY));
+    assert!(session.mountpoint.join("src/lib.rs").exists());
+}
@@ -0,0 +1,92 @@
+use crate::shared::common::create_test_repo;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use treebeard::config::CommitConfig;
+use treebeard::git::{CommitSession, GitRepo};
+
+fn git(repo_path: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .output()
+        .expect("Failed to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Author, committer and `Treebeard-*` trailers of HEAD
+fn head_attribution(repo_path: &Path) -> String {
+    git(
+        repo_path,
+        &[
+            "log",
+            "-1",
+            "--format=%an <%ae>|%cn <%ce>|%(trailers:key=Treebeard-Session,key=Treebeard-Branch,key=Treebeard-Command,key=Treebeard-Base,separator=%x2C)",
+        ],
+    )
+}
+
+fn agent_repo(repo_path: &Path, trailers: bool) -> GitRepo {
+    let config = CommitConfig {
+        author: Some("agent <agent@local>".parse().unwrap()),
+        trailers: Some(trailers),
+        ..Default::default()
+    };
+    let session = CommitSession {
+        id: "session-1".to_string(),
+        branch: "feature".to_string(),
+        command: vec!["make".to_string(), "check".to_string()],
+        base_commit: git(repo_path, &["rev-parse", "HEAD"]),
+    };
+    GitRepo::from_path(repo_path)
+        .expect("Failed to discover repo")
+        .with_attribution(config.get_attribution(&session))
+}
+
+/// Auto-commits made by git and in-process, and the squash commit, are all
+/// attributed and carry the trailers
+#[test]
+fn test_session_commits_carry_identity_and_trailers() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let base_commit = git(&repo_path, &["rev-parse", "HEAD"]);
+    let repo = agent_repo(&repo_path, true);
+    let expected = format!(
+        "agent <agent@local>|Test User <test@example.com>|\
+         Treebeard-Session: session-1,Treebeard-Branch: feature,\
+         Treebeard-Command: make check,Treebeard-Base: {}",
+        base_commit
+    );
+
+    fs::write(repo_path.join("first.txt"), "first").unwrap();
+    repo.stage_and_commit("Auto").expect("Failed to commit");
+    assert_eq!(head_attribution(&repo_path), expected);
+
+    fs::write(repo_path.join("second.txt"), "second").unwrap();
+    repo.commit_paths(&[PathBuf::from("./second.txt")], "Auto")
+        .expect("Failed to commit paths");
+    assert_eq!(head_attribution(&repo_path), expected);
+    assert_eq!(git(&repo_path, &["log", "-1", "--format=%s"]), "Auto");
+
+    repo.squash_commits(&base_commit, "Squashed")
+        .expect("Failed to squash");
+    assert_eq!(head_attribution(&repo_path), expected);
+    assert_eq!(git(&repo_path, &["log", "-1", "--format=%s"]), "Squashed");
+}
+
+/// Turning trailers off leaves the message as given
+#[test]
+fn test_trailers_can_be_disabled() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = agent_repo(&repo_path, false);
+
+    fs::write(repo_path.join("file.txt"), "content").unwrap();
+    repo.commit_paths(&[PathBuf::from("./file.txt")], "Auto")
+        .expect("Failed to commit paths");
+    assert_eq!(
+        head_attribution(&repo_path),
+        "agent <agent@local>|Test User <test@example.com>|"
+    );
+    assert_eq!(git(&repo_path, &["log", "-1", "--format=%B"]), "Auto");
+}
@@ -1,5 +1,6 @@
 use crate::shared::common::create_test_repo;
 
+use std::process::Command;
 use treebeard::git::GitRepo;
 
 #[test]
@@ -32,3 +33,101 @@ fn test_duplicate_branch_creation() {
     let result = repo.create_branch("test-branch");
     assert!(result.is_err(), "Should fail to create duplicate branch");
 }
+
+fn git(repo_path: &std::path::Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .output()
+        .expect("Failed to run git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn test_create_branch_from_resolved_rev() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    let first = git(&repo_path, &["rev-parse", "HEAD"]);
+    git(&repo_path, &["tag", "v1"]);
+    git(&repo_path, &["commit", "--allow-empty", "-m", "Second"]);
+
+    // Annotated and lightweight tags, branches and relative revs all peel to commits
+    git(&repo_path, &["tag", "-a", "v2", "-m", "Release", "HEAD"]);
+    assert_eq!(repo.resolve_commit("v1").unwrap(), first);
+    assert_eq!(repo.resolve_commit("HEAD~1").unwrap(), first);
+    assert_eq!(
+        repo.resolve_commit("v2").unwrap(),
+        git(&repo_path, &["rev-parse", "HEAD"])
+    );
+    assert!(repo.resolve_commit("no-such-rev").is_err());
+
+    let commit = repo.resolve_commit("v1").unwrap();
+    repo.create_branch_at("from-v1", &commit)
+        .expect("Failed to create branch");
+    assert_eq!(git(&repo_path, &["rev-parse", "from-v1"]), first);
+}
+
+#[test]
+fn test_find_fetched_pull_request_ref() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    assert!(repo.find_pull_request_ref(7).is_err());
+
+    git(
+        &repo_path,
+        &["update-ref", "refs/remotes/upstream/pr/7", "HEAD"],
+    );
+    assert_eq!(
+        repo.find_pull_request_ref(7).unwrap(),
+        "refs/remotes/upstream/pr/7"
+    );
+
+    // origin wins over other remotes, and refs/pull over both
+    git(
+        &repo_path,
+        &["update-ref", "refs/remotes/origin/pr/7", "HEAD"],
+    );
+    assert_eq!(
+        repo.find_pull_request_ref(7).unwrap(),
+        "refs/remotes/origin/pr/7"
+    );
+    git(&repo_path, &["update-ref", "refs/pull/7/head", "HEAD"]);
+    assert_eq!(repo.find_pull_request_ref(7).unwrap(), "refs/pull/7/head");
+
+    // Other pull requests don't match
+    assert!(repo.find_pull_request_ref(70).is_err());
+}
+
+#[test]
+fn test_tracked_paths_missing_from_start_rev() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+    let start = git(&repo_path, &["rev-parse", "HEAD"]);
+
+    std::fs::create_dir_all(repo_path.join("newdir")).unwrap();
+    std::fs::write(repo_path.join("added.txt"), "added").unwrap();
+    std::fs::write(repo_path.join("newdir/a.txt"), "added").unwrap();
+    std::fs::write(repo_path.join("README.md"), "changed").unwrap();
+    git(&repo_path, &["add", "."]);
+    git(&repo_path, &["commit", "-m", "Add files"]);
+
+    // Only files HEAD tracks and the start rev doesn't, not changed ones
+    let mut paths = repo.tracked_paths_missing_from(&start).unwrap();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            std::path::PathBuf::from("added.txt"),
+            std::path::PathBuf::from("newdir/a.txt")
+        ]
+    );
+    assert!(repo.tracked_paths_missing_from("HEAD").unwrap().is_empty());
+}
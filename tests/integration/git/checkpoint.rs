@@ -0,0 +1,107 @@
+use crate::shared::common::create_test_repo;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use treebeard::git::GitRepo;
+
+fn git(repo_path: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .output()
+        .expect("Failed to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Checkpoints are listed newest first with the paths each one changed
+#[test]
+fn test_list_checkpoints() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+    let base_commit = repo.get_head().unwrap();
+
+    assert!(repo.list_checkpoints(&base_commit).unwrap().is_empty());
+
+    fs::create_dir_all(repo_path.join("src")).unwrap();
+    fs::write(repo_path.join("src/lib.rs"), "fn a() {}\n").unwrap();
+    repo.stage_and_commit("First").unwrap();
+    fs::write(repo_path.join("README.md"), "# Changed\n").unwrap();
+    fs::remove_file(repo_path.join("src/lib.rs")).unwrap();
+    repo.stage_and_commit("Second").unwrap();
+
+    let checkpoints = repo.list_checkpoints(&base_commit).unwrap();
+    let subjects: Vec<_> = checkpoints.iter().map(|c| c.subject.as_str()).collect();
+    assert_eq!(subjects, ["Second", "First"]);
+    assert_eq!(checkpoints[0].id, repo.get_head().unwrap());
+    assert_eq!(
+        checkpoints[0].changes,
+        vec![
+            ('M', PathBuf::from("README.md")),
+            ('D', PathBuf::from("src/lib.rs"))
+        ]
+    );
+    assert_eq!(
+        checkpoints[1].changes,
+        vec![('A', PathBuf::from("src/lib.rs"))]
+    );
+
+    let base = repo.checkpoint(&base_commit).unwrap();
+    assert_eq!(base.id, base_commit);
+    assert!(base.changes.is_empty());
+}
+
+/// Rolling back resets tracked and untracked files but leaves ignored files
+/// and whiteout markers, and can be undone
+#[test]
+fn test_rollback_to_checkpoint() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    fs::write(repo_path.join(".gitignore"), "*.log\n").unwrap();
+    git(&repo_path, &["add", "."]);
+    git(&repo_path, &["commit", "-m", "Setup"]);
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+    let base_commit = repo.get_head().unwrap();
+
+    fs::write(repo_path.join("kept.txt"), "v1\n").unwrap();
+    repo.stage_and_commit("First").unwrap();
+    let first = repo.get_head().unwrap();
+    fs::write(repo_path.join("kept.txt"), "v2\n").unwrap();
+    fs::write(repo_path.join("later.txt"), "later\n").unwrap();
+    repo.stage_and_commit("Second").unwrap();
+    let second = repo.get_head().unwrap();
+
+    // Changes the watcher hasn't committed yet
+    fs::write(repo_path.join("pending.txt"), "pending\n").unwrap();
+    fs::write(repo_path.join("build.log"), "ignored\n").unwrap();
+    fs::write(repo_path.join(".wh.README.md"), "").unwrap();
+
+    let (target, changed) = repo.rollback_to(&base_commit, &first[..7]).unwrap();
+    assert_eq!(target, first);
+    assert_eq!(
+        changed,
+        vec![
+            PathBuf::from("kept.txt"),
+            PathBuf::from("later.txt"),
+            PathBuf::from("pending.txt")
+        ]
+    );
+    assert_eq!(repo.get_head().unwrap(), first);
+    assert_eq!(
+        fs::read_to_string(repo_path.join("kept.txt")).unwrap(),
+        "v1\n"
+    );
+    assert!(!repo_path.join("later.txt").exists());
+    assert!(!repo_path.join("pending.txt").exists());
+    assert!(repo_path.join("build.log").exists());
+    assert!(repo_path.join(".wh.README.md").exists());
+
+    // The commits left behind can be rolled forward to again
+    repo.rollback_to(&base_commit, &second).unwrap();
+    assert!(repo_path.join("later.txt").exists());
+
+    // Commits from before the session aren't checkpoints
+    let result = repo.rollback_to(&base_commit, "HEAD~3");
+    assert!(result.is_err());
+    assert_eq!(repo.get_head().unwrap(), second);
+}
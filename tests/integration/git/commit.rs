@@ -0,0 +1,117 @@
+use crate::shared::common::create_test_repo;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use treebeard::git::GitRepo;
+
+fn git(repo_path: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .output()
+        .expect("Failed to run git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn paths(paths: &[&str]) -> Vec<PathBuf> {
+    paths.iter().map(PathBuf::from).collect()
+}
+
+/// Only the reported paths are committed, and the index matches the commit
+#[test]
+fn test_commit_paths_stages_only_reported_paths() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    fs::write(repo_path.join("reported.txt"), "reported\n").unwrap();
+    fs::write(repo_path.join("unreported.txt"), "unreported\n").unwrap();
+    fs::write(repo_path.join("README.md"), "# Changed\n").unwrap();
+
+    repo.commit_paths(&paths(&["./reported.txt", "./README.md"]), "Auto")
+        .expect("Failed to commit paths");
+
+    assert_eq!(git(&repo_path, &["log", "-1", "--format=%s"]), "Auto\n");
+    assert_eq!(
+        git(&repo_path, &["show", "--name-only", "--format=", "HEAD"]),
+        "README.md\nreported.txt\n"
+    );
+    assert_eq!(git(&repo_path, &["show", "HEAD:README.md"]), "# Changed\n");
+    assert_eq!(
+        git(&repo_path, &["status", "--porcelain"]),
+        "?? unreported.txt\n"
+    );
+}
+
+/// Deletions, new directories, ignored files and executable bits
+#[test]
+fn test_commit_paths_handles_deletes_directories_and_ignores() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    fs::write(repo_path.join(".gitignore"), "*.log\n").unwrap();
+    fs::write(repo_path.join("old.txt"), "old\n").unwrap();
+    git(&repo_path, &["add", "."]);
+    git(&repo_path, &["commit", "-m", "Setup"]);
+
+    fs::remove_file(repo_path.join("old.txt")).unwrap();
+    fs::create_dir_all(repo_path.join("src/nested")).unwrap();
+    fs::write(repo_path.join("src/nested/lib.rs"), "fn main() {}\n").unwrap();
+    fs::write(repo_path.join("src/build.log"), "ignored\n").unwrap();
+    fs::write(repo_path.join("run.sh"), "#!/bin/sh\n").unwrap();
+    fs::set_permissions(repo_path.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(repo_path.join("debug.log"), "ignored\n").unwrap();
+
+    repo.commit_paths(
+        &paths(&["./old.txt", "./src", "./run.sh", "./debug.log"]),
+        "Auto",
+    )
+    .expect("Failed to commit paths");
+
+    assert_eq!(
+        git(&repo_path, &["ls-tree", "-r", "--name-only", "HEAD"]),
+        ".gitignore\nREADME.md\nrun.sh\nsrc/nested/lib.rs\n"
+    );
+    assert!(git(&repo_path, &["ls-tree", "HEAD", "run.sh"]).starts_with("100755"));
+    assert_eq!(git(&repo_path, &["status", "--porcelain"]), "");
+
+    // The tree is unchanged, so nothing is committed
+    let head = git(&repo_path, &["rev-parse", "HEAD"]);
+    repo.commit_paths(&paths(&["./run.sh"]), "Again")
+        .expect("Failed to commit paths");
+    assert_eq!(git(&repo_path, &["rev-parse", "HEAD"]), head);
+}
+
+/// Commit hooks need `git commit`, which stages everything
+#[test]
+fn test_commit_paths_runs_hooks_through_git() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    let hook = repo_path.join(".git/hooks/pre-commit");
+    fs::write(
+        &hook,
+        "#!/bin/sh\ntouch \"$(git rev-parse --git-dir)/hook-ran\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+    fs::write(repo_path.join("reported.txt"), "reported\n").unwrap();
+    fs::write(repo_path.join("unreported.txt"), "unreported\n").unwrap();
+
+    repo.commit_paths(&paths(&["./reported.txt"]), "Auto")
+        .expect("Failed to commit paths");
+
+    assert!(repo_path.join(".git/hook-ran").exists());
+    assert_eq!(
+        git(&repo_path, &["show", "--name-only", "--format=", "HEAD"]),
+        "reported.txt\nunreported.txt\n"
+    );
+}
@@ -1,5 +1,9 @@
+mod attribution;
 mod branch;
+mod checkpoint;
+mod commit;
 mod repo;
+mod signing;
 mod squash;
 mod stash;
 mod worktree;
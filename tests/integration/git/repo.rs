@@ -1,5 +1,10 @@
 use crate::shared::common::create_test_repo;
 
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+
 use treebeard::git::GitRepo;
 
 #[test]
@@ -21,3 +26,51 @@ fn test_repo_name() {
         "Repo name should match directory name"
     );
 }
+
+#[test]
+fn test_stage_and_commit_skips_whiteout_markers() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    fs::create_dir(repo_path.join("build")).unwrap();
+    fs::write(repo_path.join("notes.txt"), "notes\n").unwrap();
+    fs::write(repo_path.join(".wh.README.md"), "").unwrap();
+    fs::write(repo_path.join("build/.wh..wh..opq"), "").unwrap();
+    fs::remove_file(repo_path.join("README.md")).unwrap();
+
+    repo.stage_and_commit("checkpoint").unwrap();
+
+    let output = Command::new("git")
+        .args(["ls-files"])
+        .current_dir(&repo_path)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "notes.txt\n");
+}
+
+#[test]
+fn test_stage_and_commit_records_kernel_whiteouts_as_deletions() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    // Kernel overlayfs leaves a 0/0 character device where a file was deleted
+    fs::remove_file(repo_path.join("README.md")).unwrap();
+    let path = CString::new(repo_path.join("README.md").as_os_str().as_bytes()).unwrap();
+    if unsafe { libc::mknod(path.as_ptr(), libc::S_IFCHR, 0) } != 0 {
+        eprintln!(
+            "Skipping - can't create character devices: {}",
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+    fs::write(repo_path.join("notes.txt"), "notes\n").unwrap();
+
+    repo.stage_and_commit("checkpoint").unwrap();
+
+    let output = Command::new("git")
+        .args(["ls-files"])
+        .current_dir(&repo_path)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "notes.txt\n");
+}
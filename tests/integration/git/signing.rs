@@ -0,0 +1,83 @@
+use crate::shared::common::create_test_repo;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use treebeard::config::SigningPolicy;
+use treebeard::git::{CommitSigning, GitRepo};
+use treebeard::TreebeardError;
+
+fn git(repo_path: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .output()
+        .expect("Failed to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// A repo that asks for signed commits with a signing program that always fails
+fn repo_requiring_signatures(policy: SigningPolicy) -> (tempfile::TempDir, PathBuf, GitRepo) {
+    let (temp_dir, repo_path) = create_test_repo();
+    git(&repo_path, &["config", "commit.gpgSign", "true"]);
+    git(&repo_path, &["config", "gpg.program", "false"]);
+    let repo = GitRepo::from_path(&repo_path)
+        .expect("Failed to discover repo")
+        .with_signing(CommitSigning {
+            policy,
+            ..Default::default()
+        });
+    (temp_dir, repo_path, repo)
+}
+
+#[test]
+fn test_auto_policy_reports_signing_failures() {
+    let (_temp_dir, repo_path, repo) = repo_requiring_signatures(SigningPolicy::Auto);
+
+    fs::write(repo_path.join("file.txt"), "content").unwrap();
+    let result = repo.stage_and_commit("Auto");
+    assert!(
+        matches!(result, Err(TreebeardError::Signing(_))),
+        "Expected a signing error, got {:?}",
+        result
+    );
+}
+
+#[test]
+fn test_final_only_signs_just_the_squash_commit() {
+    let (_temp_dir, repo_path, repo) = repo_requiring_signatures(SigningPolicy::FinalOnly);
+    let base_commit = repo.get_head().unwrap();
+
+    fs::write(repo_path.join("first.txt"), "first").unwrap();
+    repo.stage_and_commit("First")
+        .expect("Auto-commits shouldn't be signed");
+    fs::write(repo_path.join("second.txt"), "second").unwrap();
+    repo.commit_paths(&[PathBuf::from("./second.txt")], "Second")
+        .expect("Auto-commits shouldn't be signed");
+    let head = repo.get_head().unwrap();
+
+    let result = repo.squash_commits(&base_commit, "Squashed");
+    assert!(
+        matches!(result, Err(TreebeardError::Signing(_))),
+        "Expected a signing error, got {:?}",
+        result
+    );
+    assert_eq!(
+        repo.get_head().unwrap(),
+        head,
+        "Squash should be rolled back"
+    );
+}
+
+#[test]
+fn test_none_policy_never_signs() {
+    let (_temp_dir, repo_path, repo) = repo_requiring_signatures(SigningPolicy::None);
+    let base_commit = repo.get_head().unwrap();
+
+    fs::write(repo_path.join("file.txt"), "content").unwrap();
+    repo.stage_and_commit("Auto").expect("Failed to commit");
+    repo.squash_commits(&base_commit, "Squashed")
+        .expect("Failed to squash");
+    assert_eq!(git(&repo_path, &["log", "-1", "--format=%s"]), "Squashed");
+}
@@ -2,7 +2,9 @@ use crate::shared::common::create_test_repo;
 
 use std::path::PathBuf;
 use std::process::Command;
+use treebeard::cleanup::{squash_branch_commits, SquashContext};
 use treebeard::git::GitRepo;
+use treebeard::OnExitBehavior;
 
 /// Regression test: squash_commits must use the worktree's GitRepo, not the main repo,
 /// otherwise git commands run in the wrong directory and fail silently.
@@ -21,6 +23,7 @@ fn test_squash_commits_in_worktree() {
 
     let worktree_repo =
         GitRepo::from_path(&worktree_path).expect("Failed to create GitRepo for worktree");
+    let base_commit = worktree_repo.get_head().expect("Failed to get base commit");
 
     std::fs::write(worktree_path.join("file1.txt"), "content 1").expect("Failed to write file1");
     worktree_repo
@@ -34,7 +37,7 @@ fn test_squash_commits_in_worktree() {
         .expect("Failed to get HEAD");
     let head_before = String::from_utf8_lossy(&output.stdout).trim().to_string();
 
-    let result = worktree_repo.squash_commits(branch_name, "Squashed commit");
+    let result = worktree_repo.squash_commits(&base_commit, "Squashed commit");
     assert!(
         result.is_ok(),
         "squash_commits should succeed when using worktree repo: {:?}",
@@ -93,6 +96,7 @@ fn test_squash_commits_rollback_on_failure() {
 
     let worktree_repo =
         GitRepo::from_path(&worktree_path).expect("Failed to create GitRepo for worktree");
+    let base_commit = worktree_repo.get_head().expect("Failed to get base commit");
 
     std::fs::write(worktree_path.join("file1.txt"), "content 1").expect("Failed to write file1");
     worktree_repo
@@ -126,7 +130,7 @@ fn test_squash_commits_rollback_on_failure() {
     std::fs::write(index_path.join("index"), b"corrupted index data")
         .expect("Failed to corrupt index");
 
-    let result = worktree_repo.squash_commits(branch_name, "Squashed commit");
+    let result = worktree_repo.squash_commits(&base_commit, "Squashed commit");
     assert!(
         result.is_err(),
         "squash_commits should fail with corrupted index"
@@ -141,3 +145,146 @@ fn test_squash_commits_rollback_on_failure() {
         "HEAD should be rolled back to original position after commit failure"
     );
 }
+
+#[test]
+fn test_squash_commits_collapses_everything_since_base() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    let branch_name = "test-squash-base";
+    repo.create_branch(branch_name)
+        .expect("Failed to create branch");
+
+    let worktree_path = repo_path.join(".treebeard-worktree-base");
+    repo.create_worktree(branch_name, &worktree_path)
+        .expect("Failed to create worktree");
+
+    let worktree_repo =
+        GitRepo::from_path(&worktree_path).expect("Failed to create GitRepo for worktree");
+    let base_commit = worktree_repo.get_head().expect("Failed to get base commit");
+
+    for i in 1..=3 {
+        std::fs::write(worktree_path.join(format!("file{}.txt", i)), "content")
+            .expect("Failed to write file");
+        worktree_repo
+            .stage_and_commit(&format!("Commit {}", i))
+            .expect("Failed to commit");
+    }
+    assert_eq!(
+        worktree_repo
+            .get_commit_count_since(branch_name, &base_commit)
+            .unwrap(),
+        3
+    );
+
+    worktree_repo
+        .squash_commits(&base_commit, "Squashed commit")
+        .expect("Failed to squash");
+
+    assert_eq!(
+        worktree_repo
+            .get_commit_count_since(branch_name, &base_commit)
+            .unwrap(),
+        1
+    );
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD~1"])
+        .current_dir(&worktree_path)
+        .output()
+        .expect("Failed to get parent");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), base_commit);
+
+    // Nothing to do once HEAD is the base
+    let squashed = worktree_repo.get_head().unwrap();
+    worktree_repo
+        .squash_commits(&squashed, "Squashed again")
+        .expect("Squashing nothing should succeed");
+    assert_eq!(worktree_repo.get_head().unwrap(), squashed);
+}
+
+/// The squash commit has HEAD's content, plus anything still staged, as with
+/// `git reset --soft` and `git commit`.
+#[test]
+fn test_squash_commits_keeps_content_and_staged_changes() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&repo_path)
+            .output()
+            .expect("Failed to run git");
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+
+    let base_commit = repo.get_head().unwrap();
+    std::fs::write(repo_path.join("first.txt"), "first").unwrap();
+    repo.stage_and_commit("First").unwrap();
+    std::fs::remove_file(repo_path.join("README.md")).unwrap();
+    repo.stage_and_commit("Second").unwrap();
+    let tree = git(&["rev-parse", "HEAD^{tree}"]);
+
+    repo.squash_commits(&base_commit, "Squashed").unwrap();
+    assert_eq!(git(&["rev-parse", "HEAD^{tree}"]), tree);
+    assert_eq!(git(&["rev-parse", "HEAD~1"]), base_commit);
+    assert_eq!(git(&["log", "-1", "--format=%B"]), "Squashed");
+    assert_eq!(git(&["status", "--porcelain"]), "");
+
+    let squashed = repo.get_head().unwrap();
+    std::fs::write(repo_path.join("third.txt"), "third").unwrap();
+    repo.stage_and_commit("Third").unwrap();
+    std::fs::write(repo_path.join("staged.txt"), "staged").unwrap();
+    git(&["add", "staged.txt"]);
+
+    repo.squash_commits(&squashed, "Squashed again").unwrap();
+    assert_eq!(
+        git(&["show", "--name-only", "--format=", "HEAD"]),
+        "staged.txt\nthird.txt"
+    );
+    assert_eq!(git(&["status", "--porcelain"]), "");
+}
+
+/// With a non-interactive sync policy, `on_exit = "prompt"` keeps the
+/// auto-commits instead of waiting for an answer.
+#[test]
+fn test_squash_prompt_keeps_commits_when_not_interactive() {
+    let (_temp_dir, repo_path) = create_test_repo();
+    let repo = GitRepo::from_path(&repo_path).expect("Failed to discover repo");
+
+    let branch_name = "test-squash-unattended";
+    repo.create_branch(branch_name)
+        .expect("Failed to create branch");
+
+    let worktree_path = repo_path.join(".treebeard-worktree-unattended");
+    repo.create_worktree(branch_name, &worktree_path)
+        .expect("Failed to create worktree");
+
+    let worktree_repo =
+        GitRepo::from_path(&worktree_path).expect("Failed to create GitRepo for worktree");
+    let base_commit = worktree_repo.get_head().expect("Failed to get base commit");
+    for i in 1..=2 {
+        std::fs::write(worktree_path.join(format!("file{}.txt", i)), "content")
+            .expect("Failed to write file");
+        worktree_repo
+            .stage_and_commit(&format!("Commit {}", i))
+            .expect("Failed to commit");
+    }
+
+    let ctx = SquashContext {
+        repo: worktree_repo.clone(),
+        branch_name: branch_name.to_string(),
+        on_exit: &OnExitBehavior::Prompt,
+        squash_message: "Squashed commit".to_string(),
+        base_commit: base_commit.clone(),
+        interactive: false,
+    };
+    squash_branch_commits(&ctx).expect("Squash step should succeed");
+
+    assert_eq!(
+        worktree_repo
+            .get_commit_count_since(branch_name, &base_commit)
+            .unwrap(),
+        2
+    );
+}